@@ -0,0 +1,73 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use crate::geometry::Rect;
+use crate::{format, i18n, png};
+
+// --- `average-color` Subcommand ---
+//
+// Samples the average color of a region without saving anything, for colors that are
+// noisy at the pixel level, like a blurred or dithered background.
+
+/// Options for sampling a color.
+pub struct AverageOptions {
+    /// The region to sample; selected with slurp when `None`.
+    pub geometry: Option<Rect>,
+    pub copy: bool,
+    pub json: bool,
+}
+
+#[derive(Serialize)]
+struct Sample {
+    hex: String,
+    rgb: [u8; 3],
+    geometry: String,
+}
+
+/// Selects a region and prints its average color.
+pub async fn average_color(options: AverageOptions) -> Result<()> {
+    let geometry = match options.geometry {
+        Some(rect) => rect,
+        None => match crate::region_mode().await? {
+            Some(rect) => rect,
+            None => {
+                eprintln!("{}", i18n::tr("Action cancelled."));
+                return Ok(());
+            }
+        },
+    };
+
+    let image = crate::capture_geometry_to_buffer(&geometry, format::ImageFormat::Png, None, None).await?;
+    let rgb = average(&image)?;
+    let hex = format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2]);
+
+    if options.json {
+        println!("{}", serde_json::to_string(&Sample { hex: hex.clone(), rgb, geometry: geometry.printed() })?);
+    } else {
+        println!("{}\trgb({}, {}, {})", hex, rgb[0], rgb[1], rgb[2]);
+    }
+    if options.copy {
+        crate::copy_text_to_clipboard(&hex).await?;
+    }
+    Ok(())
+}
+
+/// Returns the mean color of a PNG image, weighting every pixel by its alpha.
+fn average(image: &[u8]) -> Result<[u8; 3]> {
+    let mut sums = [0u64; 3];
+    let mut weight = 0u64;
+    png::for_each_row(image, &mut |row| {
+        for pixel in row.chunks_exact(4) {
+            let alpha = pixel[3] as u64;
+            for (sum, &channel) in sums.iter_mut().zip(pixel) {
+                *sum += channel as u64 * alpha;
+            }
+            weight += alpha;
+        }
+    })
+    .context("Failed to read the captured image")?;
+
+    if weight == 0 {
+        anyhow::bail!("The selected region is fully transparent");
+    }
+    Ok(sums.map(|sum| ((sum + weight / 2) / weight) as u8))
+}
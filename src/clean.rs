@@ -0,0 +1,147 @@
+use std::collections::HashSet;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, TimeDelta};
+use crate::{history, save, search, trash};
+
+// --- `clean` Subcommand ---
+
+/// Which captures `luminashot clean` keeps. A capture is kept if any rule keeps it.
+#[derive(Debug, Clone, Copy)]
+pub struct Retention {
+    /// Keep captures younger than this.
+    pub older_than: Option<TimeDelta>,
+    /// Keep this many of the newest captures.
+    pub keep_last: Option<usize>,
+    pub keep_favorites: bool,
+}
+
+/// A screenshot on disk or a history entry, considered for removal.
+struct Candidate {
+    time: DateTime<Local>,
+    path: Option<String>,
+    favorite: bool,
+    /// Index into the loaded history, `None` for files the history doesn't know about.
+    history_index: Option<usize>,
+}
+
+/// Parses an age like `30d`, `12h`, `2w` or `90m`.
+pub fn parse_age(value: &str) -> Result<TimeDelta, String> {
+    let invalid = || format!("`{}` is not a valid age, expected a number followed by s, m, h, d or w", value);
+
+    let split = value.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let (number, unit) = value.split_at(split);
+    let number: i64 = number.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    number.checked_mul(seconds).and_then(TimeDelta::try_seconds).ok_or_else(invalid)
+}
+
+/// Deletes screenshots and history entries that no retention rule keeps. Screenshots are
+/// moved to the trash unless `purge` is set.
+///
+/// Only files LuminaShot created are touched: those in the history, and files in the
+/// screenshots directory carrying LuminaShot's name.
+pub async fn clean(retention: Retention, dry_run: bool, purge: bool) -> Result<()> {
+    if retention.older_than.is_none() && retention.keep_last.is_none() {
+        anyhow::bail!("Nothing to clean: give --older-than and/or --keep-last");
+    }
+
+    let entries = history::load().await?;
+    let mut candidates: Vec<Candidate> = entries
+    .iter()
+    .enumerate()
+    .map(|(index, entry)| Candidate {
+        time: entry.time,
+        path: entry.path.clone(),
+        favorite: entry.favorite,
+        history_index: Some(index),
+    })
+    .collect();
+    candidates.extend(untracked_screenshots(&entries).await?);
+    candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.time));
+
+    let now = Local::now();
+    let mut removed_entries = HashSet::new();
+    let (mut deleted, mut kept) = (0, 0);
+
+    for (rank, candidate) in candidates.iter().enumerate() {
+        let file_exists = candidate.path.as_ref().is_some_and(|path| std::path::Path::new(path).exists());
+        let keep = retention.older_than.is_some_and(|age| now - candidate.time < age)
+        || retention.keep_last.is_some_and(|count| rank < count)
+        || (retention.keep_favorites && candidate.favorite);
+
+        if keep && (file_exists || candidate.path.is_none()) {
+            kept += 1;
+            continue;
+        }
+
+        // Entries whose file has already been deleted by hand are dropped from the history too.
+        if let (Some(path), true) = (&candidate.path, file_exists) {
+            if dry_run {
+                println!("Would delete {}", path);
+            } else {
+                println!("{}", trash::remove(path, purge).await?);
+            }
+            deleted += 1;
+        }
+        if let Some(index) = candidate.history_index {
+            removed_entries.insert(index);
+        }
+    }
+
+    if !dry_run && !removed_entries.is_empty() {
+        let remaining: Vec<history::Entry> = entries
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| !removed_entries.contains(index))
+        .map(|(_, entry)| entry)
+        .collect();
+        history::rewrite(&remaining).await?;
+        search::prune_index(&remaining.iter().map(|entry| entry.sha256.as_str()).collect()).await?;
+    }
+
+    let verb = if dry_run { "Would delete" } else { "Deleted" };
+    println!("{} {} screenshot(s) and {} history entries, kept {}.", verb, deleted, removed_entries.len(), kept);
+    Ok(())
+}
+
+/// Finds LuminaShot screenshots in the screenshots directory that aren't in the history,
+/// such as captures made before the history existed. Their age is taken from the file.
+async fn untracked_screenshots(entries: &[history::Entry]) -> Result<Vec<Candidate>> {
+    let dir = save::screenshots_dir();
+    let tracked: HashSet<&str> = entries.iter().filter_map(|entry| entry.path.as_deref()).collect();
+
+    let mut read_dir = match tokio::fs::read_dir(&dir).await {
+        Ok(read_dir) => read_dir,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", dir)),
+    };
+
+    let mut candidates = Vec::new();
+    while let Some(file) = read_dir.next_entry().await? {
+        let path = file.path().to_string_lossy().into_owned();
+        let name = file.file_name().to_string_lossy().into_owned();
+        // `--ocr-sidecar` text files are removed along with their screenshot.
+        if !name.contains("-luminashot") || name.ends_with(".txt") || tracked.contains(path.as_str()) {
+            continue;
+        }
+
+        let metadata = file.metadata().await?;
+        if !metadata.is_file() {
+            continue;
+        }
+        candidates.push(Candidate {
+            time: DateTime::<Local>::from(metadata.modified()?),
+            path: Some(path),
+            favorite: false,
+            history_index: None,
+        });
+    }
+    Ok(candidates)
+}
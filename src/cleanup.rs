@@ -0,0 +1,52 @@
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use tokio::signal::unix::{signal, SignalKind};
+
+// --- Signal Handling ---
+
+/// Waits for SIGINT or SIGTERM and returns the name of the signal received.
+///
+/// Racing this against the main future means the main future is dropped on shutdown,
+/// which kills any children spawned with `kill_on_drop` and runs the cleanup guards below.
+pub async fn shutdown_signal() -> Result<&'static str> {
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+
+    tokio::select! {
+        _ = interrupt.recv() => Ok("SIGINT"),
+        _ = terminate.recv() => Ok("SIGTERM"),
+    }
+}
+
+/// Returns the conventional exit code for a process terminated by `signal`.
+pub fn exit_code(signal: &str) -> i32 {
+    match signal {
+        "SIGTERM" => 128 + libc::SIGTERM,
+        _ => 128 + libc::SIGINT,
+    }
+}
+
+// --- Cleanup Guards ---
+
+/// A temporary file that is removed when the guard is dropped, including when the
+/// future owning it is dropped because of a signal. Moving the file into place before
+/// the guard goes out of scope makes the removal a no-op.
+pub struct TempPath {
+    path: PathBuf,
+}
+
+impl TempPath {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
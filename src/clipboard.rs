@@ -0,0 +1,269 @@
+use std::fs::File;
+use std::io::{BufRead, Read, Write};
+use std::process::Stdio;
+use std::sync::OnceLock;
+use std::time::Duration;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+use crate::wayland::{self, Arg};
+use crate::{jpeg, png, process};
+
+// --- Several Types on the Clipboard ---
+//
+// wl-copy offers one piece of data, so offering the image and its text at once, for the
+// pasting application to pick from, takes a clipboard owner of our own. With
+// `--clipboard-backend native` every copy goes through it, offering a PNG screenshot as
+// JPEG as well and the file it was saved to as a `text/uri-list`, so image editors,
+// chat apps and file managers each paste what they understand. It is another
+// luminashot, started with `SERVE_ARG`, that takes over the clipboard through the data
+// control protocol and answers requests for any of the types until something else is
+// copied, just like wl-copy does in the background.
+//
+// It is handed its data on stdin: for each item, a line with its space-separated MIME
+// types, a line with its length, and the bytes. Once it owns the clipboard, it answers
+// with a line saying `ok`, or the reason it couldn't. An item whose first type is
+// `image/png` is converted when it is asked for as `image/jpeg`, so only the applications
+// that want JPEG wait for it to be encoded.
+//
+// wl-copy can't let go of the clipboard after a while either, so a `--clipboard-hold` in
+// seconds is served by the owner as well: it exits once the time is up, which clears the
+// clipboard unless a clipboard manager has kept a copy.
+
+/// Marks a process as a clipboard owner started by `offer`. Not meant to be typed by users.
+pub const SERVE_ARG: &str = "--serve-clipboard";
+/// Makes the owner exit after the first paste, like `wl-copy --paste-once`.
+const PASTE_ONCE_ARG: &str = "--paste-once";
+/// Makes the owner exit after the number of seconds that follows.
+const HOLD_ARG: &str = "--hold";
+
+/// What puts copies on the clipboard.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Default)]
+pub enum Backend {
+    /// wl-copy, offering the image in the format it was captured in
+    #[default]
+    WlCopy,
+    /// luminashot itself, offering the image as PNG and JPEG and its file as a URI
+    Native,
+}
+
+static BACKEND: OnceLock<Backend> = OnceLock::new();
+
+/// Sets what puts copies on the clipboard. Only the first call has an effect.
+pub fn set_backend(backend: Backend) {
+    let _ = BACKEND.set(backend);
+}
+
+/// Returns what puts copies on the clipboard.
+pub fn backend() -> Backend {
+    BACKEND.get().copied().unwrap_or_default()
+}
+
+/// Returns whether copies take a clipboard owner of our own, rather than wl-copy.
+pub fn needs_owner() -> bool {
+    backend() == Backend::Native || matches!(hold(), Hold::For(_))
+}
+
+/// How long a copied screenshot stays on the clipboard after luminashot exits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hold {
+    /// Until another application copies something.
+    Forever,
+    /// Until it has been pasted once.
+    UntilPaste,
+    /// Until the time is up, or something else is copied.
+    For(Duration),
+}
+
+static HOLD: OnceLock<Hold> = OnceLock::new();
+
+/// Sets how long copies stay on the clipboard. Only the first call has an effect.
+pub fn set_hold(hold: Hold) {
+    let _ = HOLD.set(hold);
+}
+
+/// Returns how long copies stay on the clipboard.
+pub fn hold() -> Hold {
+    HOLD.get().copied().unwrap_or(Hold::Forever)
+}
+
+/// Parses `forever`, `until-paste` or a positive number of seconds.
+pub fn parse_hold(value: &str) -> Result<Hold, String> {
+    match value {
+        "forever" => Ok(Hold::Forever),
+        "until-paste" => Ok(Hold::UntilPaste),
+        _ => match value.parse::<f64>() {
+            Ok(secs) if secs.is_finite() && secs > 0.0 => Ok(Hold::For(Duration::from_secs_f64(secs))),
+            _ => Err(format!("`{}` is not forever, until-paste or a number of seconds", value)),
+        },
+    }
+}
+
+/// The types text is offered as; X11 applications ask for the last three.
+pub const TEXT_TYPES: &[&str] = &["text/plain;charset=utf-8", "text/plain", "UTF8_STRING", "STRING", "TEXT"];
+
+/// The types a file is offered as, for file managers.
+pub const URI_TYPES: &[&str] = &["text/uri-list"];
+
+/// The data control managers, newest first. Their requests and events are the same.
+const MANAGERS: &[&str] = &["ext_data_control_manager_v1", "zwlr_data_control_manager_v1"];
+
+/// One piece of data, offered as any of several types.
+pub struct Item<'a> {
+    pub mime_types: &'a [&'a str],
+    pub data: &'a [u8],
+}
+
+/// Puts the items on the clipboard for as long as `--clipboard-hold` says. With
+/// `wait_for_paste`, returns the process that exits once something has been pasted, which
+/// also clears the clipboard.
+pub async fn offer(items: &[Item<'_>], wait_for_paste: bool) -> Result<Option<Child>> {
+    let hold = hold();
+    let exe = std::env::current_exe().context("Failed to locate the luminashot executable")?;
+    let mut command = Command::new(exe);
+    command.arg(SERVE_ARG);
+    if wait_for_paste || hold == Hold::UntilPaste {
+        command.arg(PASTE_ONCE_ARG);
+    }
+    if let Hold::For(duration) = hold {
+        command.arg(HOLD_ARG).arg(duration.as_secs_f64().to_string());
+    }
+    let mut child = command
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    // Its errors are answered on stdout; stderr would keep a piped caller's output open.
+    .stderr(Stdio::null())
+    // Outlives this process unless it waits for the paste, and doesn't get the terminal's Ctrl+C.
+    .process_group(0)
+    .kill_on_drop(wait_for_paste)
+    .spawn()
+    .context("Failed to start the clipboard owner")?;
+
+    let mut payload = Vec::new();
+    for item in items {
+        payload.extend_from_slice(format!("{}\n{}\n", item.mime_types.join(" "), item.data.len()).as_bytes());
+        payload.extend_from_slice(item.data);
+    }
+    let mut stdin = child.stdin.take().context("The clipboard owner has no stdin")?;
+    process::io("the clipboard owner", stdin.write_all(&payload)).await?;
+    drop(stdin);
+
+    let stdout = child.stdout.take().context("The clipboard owner has no stdout")?;
+    let mut reply = String::new();
+    process::io("the clipboard owner", BufReader::new(stdout).read_line(&mut reply)).await?;
+    match reply.trim() {
+        "ok" => Ok(wait_for_paste.then_some(child)),
+        "" => anyhow::bail!("The clipboard owner exited without taking over the clipboard"),
+        reason => anyhow::bail!("{}", reason),
+    }
+}
+
+/// Runs the clipboard owner, with the arguments after `SERVE_ARG`.
+pub fn serve(args: &[std::ffi::OsString]) -> Result<()> {
+    let paste_once = args.iter().any(|arg| arg == PASTE_ONCE_ARG);
+    let hold = args
+    .iter()
+    .position(|arg| arg == HOLD_ARG)
+    .and_then(|index| args.get(index + 1)?.to_str()?.parse::<f64>().ok());
+    let mut stdout = std::io::stdout();
+    let (mut connection, source, device, items) = match take_over() {
+        Ok(owner) => owner,
+        Err(err) => {
+            let _ = writeln!(stdout, "{:#}", err);
+            std::process::exit(1);
+        }
+    };
+    writeln!(stdout, "ok")?;
+
+    if let Some(secs) = hold {
+        // Closing the connection destroys the data source, and with it the selection.
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_secs_f64(secs));
+            std::process::exit(0);
+        });
+    }
+
+    let mut converted: Option<Vec<u8>> = None;
+    loop {
+        let mut event = connection.read_event()?;
+        match (event.object, event.opcode) {
+            (object, 0) if object == source => {
+                let mime_type = event.string()?;
+                let fd = connection.take_fd()?;
+                let Some((types, data)) = items.iter().find(|(types, _)| types.contains(&mime_type)) else {
+                    continue;
+                };
+                let data = match mime_type == "image/jpeg" && types[0] == "image/png" {
+                    true => converted.get_or_insert_with(|| to_jpeg(data)).clone(),
+                    false => data.clone(),
+                };
+                // Pasting applications may stop reading halfway, which is not our problem.
+                let writer = std::thread::spawn(move || {
+                    let _ = File::from(fd).write_all(&data);
+                });
+                if paste_once {
+                    let _ = writer.join();
+                    return Ok(());
+                }
+            }
+            // Another application took over the clipboard.
+            (object, 1) if object == source => return Ok(()),
+            // The data control manager went away.
+            (object, 2) if object == device => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+/// Reads the items from stdin and makes them the clipboard's contents. Returns the
+/// connection with the IDs of the data source and the data device.
+#[allow(clippy::type_complexity)]
+fn take_over() -> Result<(wayland::Connection, u32, u32, Vec<(Vec<String>, Vec<u8>)>)> {
+    let items = read_items().context("Failed to read what to put on the clipboard")?;
+
+    let mut connection = wayland::Connection::connect()?;
+    let globals = connection.globals()?;
+    let manager = MANAGERS
+    .iter()
+    .find_map(|name| globals.iter().find(|global| global.interface == *name))
+    .context("The compositor doesn't support the data control protocol, which offering several types needs")?
+    .clone();
+    let seat = globals.iter().find(|global| global.interface == "wl_seat").context("The compositor has no seat")?.clone();
+    let manager = connection.bind(&manager, 1)?;
+    let seat = connection.bind(&seat, 1)?;
+
+    let source = connection.new_id();
+    connection.request(manager, 0, &[Arg::Uint(source)])?;
+    for (types, _) in &items {
+        for mime_type in types {
+            connection.request(source, 0, &[Arg::Str(mime_type)])?;
+        }
+    }
+    let device = connection.new_id();
+    connection.request(manager, 1, &[Arg::Uint(device), Arg::Uint(seat)])?;
+    connection.request(device, 0, &[Arg::Uint(source)])?;
+    connection.roundtrip(|_, _| Ok(()))?;
+    Ok((connection, source, device, items))
+}
+
+/// Converts a PNG image to JPEG, or returns nothing for the paste if that fails.
+fn to_jpeg(data: &[u8]) -> Vec<u8> {
+    png::decode(data).and_then(|image| jpeg::encode(&image)).unwrap_or_default()
+}
+
+fn read_items() -> Result<Vec<(Vec<String>, Vec<u8>)>> {
+    let mut stdin = std::io::stdin().lock();
+    let mut items = Vec::new();
+    loop {
+        let mut types = String::new();
+        if stdin.read_line(&mut types)? == 0 {
+            return Ok(items);
+        }
+        let mut length = String::new();
+        stdin.read_line(&mut length)?;
+        let mut data = vec![0; length.trim().parse().context("Malformed item length")?];
+        stdin.read_exact(&mut data)?;
+        items.push((types.split_whitespace().map(String::from).collect(), data));
+    }
+}
@@ -0,0 +1,129 @@
+use anyhow::{Context, Result};
+use crate::image::Image;
+use crate::{font, format, history, i18n, png, save};
+
+// --- `collage` Subcommand ---
+//
+// Puts the most recent saved screenshots side by side in a grid, oldest first, each
+// labelled with when it was taken and how, for before/after comparisons in bug reports.
+// Screenshots are scaled down to fit a cell, and only PNG screenshots can be used.
+
+/// Longest side of a screenshot in the collage.
+const CELL_SIZE: u32 = 960;
+/// Space around and between the screenshots.
+const PADDING: u32 = 24;
+/// Size of a font pixel in the labels.
+const LABEL_SCALE: u32 = 2;
+/// Space between a screenshot and its label.
+const LABEL_GAP: u32 = 8;
+const BACKGROUND: [u8; 4] = [0x1e, 0x1e, 0x2e, 0xff];
+const LABEL_COLOR: [u8; 4] = [0xcd, 0xd6, 0xf4, 0xff];
+
+/// Options for making a collage.
+pub struct CollageOptions {
+    /// How many of the newest screenshots to use.
+    pub last: usize,
+    /// Columns of the grid; about as many as rows when `None`.
+    pub columns: Option<usize>,
+    pub copy: bool,
+}
+
+/// A scaled-down screenshot and its label.
+struct Cell {
+    image: Image,
+    label: String,
+}
+
+/// Makes a collage of the newest screenshots and saves it next to them.
+pub async fn collage(options: CollageOptions) -> Result<()> {
+    let mut entries: Vec<(history::Entry, String)> = history::load()
+    .await?
+    .into_iter()
+    .rev()
+    .filter_map(|entry| {
+        let path = entry.path.clone()?;
+        let usable = format::ImageFormat::from_path(&path) == format::ImageFormat::Png && std::path::Path::new(&path).is_file();
+        usable.then_some((entry, path))
+    })
+    .take(options.last)
+    .collect();
+    if entries.is_empty() {
+        anyhow::bail!("No saved PNG screenshot in the history yet");
+    }
+    if entries.len() < options.last {
+        eprintln!("Warning: only {} saved PNG screenshot(s) in the history", entries.len());
+    }
+    entries.reverse();
+
+    let mut cells = Vec::new();
+    for (entry, path) in &entries {
+        let data = tokio::fs::read(path).await.with_context(|| format!("Failed to read {}", path))?;
+        let image = png::decode_scaled(&data, CELL_SIZE).with_context(|| format!("Failed to read {}", path))?;
+        let mut label = format!("{}  {}", entry.time.format("%Y-%m-%d %H:%M:%S"), entry.mode);
+        if let Some(title) = &entry.window_title {
+            label.push_str("  ");
+            label.push_str(title);
+        }
+        cells.push(Cell { image, label });
+    }
+
+    let columns = options.columns.unwrap_or_else(|| (cells.len() as f64).sqrt().ceil() as usize).clamp(1, cells.len());
+    let collage = render(&cells, columns);
+    let outcome = save::save_buffer_to_file(&collage, format::ImageFormat::Png, "-collage", false).await?;
+    let path = match &outcome {
+        save::SaveOutcome::Saved(path) => path,
+        save::SaveOutcome::Diverted { path, full_dir } => {
+            eprintln!("{}: {}", i18n::tr("Warning"), i18n::tr_with("Not enough space in {dir}, saved the collage to {path} instead.", &[("dir", full_dir.clone()), ("path", path.clone())]));
+            path
+        }
+        save::SaveOutcome::NoSpace { full_dir } => anyhow::bail!("{}", i18n::tr_with("Not enough space in {dir} to save the collage", &[("dir", full_dir.clone())])),
+        save::SaveOutcome::Stdout => unreachable!("only save_buffer_to_path writes to stdout"),
+    };
+    println!("{}", path);
+
+    if options.copy {
+        crate::copy_to_clipboard(&collage, format::ImageFormat::Png, Some(path), None, false).await?;
+    }
+    Ok(())
+}
+
+/// Lays the cells out in a grid with `columns` columns and encodes it as a PNG.
+fn render(cells: &[Cell], columns: usize) -> Vec<u8> {
+    let rows = cells.len().div_ceil(columns);
+    let cell_width = cells.iter().map(|cell| cell.image.width).max().unwrap_or(1);
+    let cell_height = cells.iter().map(|cell| cell.image.height).max().unwrap_or(1);
+    let label_height = font::HEIGHT * LABEL_SCALE;
+    let row_height = cell_height + LABEL_GAP + label_height;
+
+    let width = columns as u32 * (cell_width + PADDING) + PADDING;
+    let height = rows as u32 * (row_height + PADDING) + PADDING;
+    let mut pixels: Vec<u8> = BACKGROUND.repeat(width as usize * height as usize);
+
+    for (index, cell) in cells.iter().enumerate() {
+        let left = PADDING + (index % columns) as u32 * (cell_width + PADDING);
+        let top = PADDING + (index / columns) as u32 * (row_height + PADDING);
+        // Smaller screenshots are centered in their cell, with the labels in a line.
+        let x = left + (cell_width - cell.image.width) / 2;
+        let y = top + (cell_height - cell.image.height) / 2;
+        for (row, source) in cell.image.pixels.chunks_exact(cell.image.width as usize * 4).enumerate() {
+            let start = ((y as usize + row) * width as usize + x as usize) * 4;
+            for (target, pixel) in pixels[start..start + source.len()].chunks_exact_mut(4).zip(source.chunks_exact(4)) {
+                let alpha = pixel[3] as u32;
+                for (channel, &value) in target[..3].iter_mut().zip(pixel) {
+                    *channel = ((value as u32 * alpha + *channel as u32 * (255 - alpha) + 127) / 255) as u8;
+                }
+            }
+        }
+
+        // Labels that are too long for the cell are cut short.
+        let fits = (cell_width / (font::ADVANCE * LABEL_SCALE)).max(1) as usize;
+        let label: String = cell.label.chars().take(fits).collect();
+        font::draw_text(&mut pixels, width, left, top + cell_height + LABEL_GAP, LABEL_SCALE, &label, LABEL_COLOR);
+    }
+
+    let mut encoder = png::Encoder::new(width, height, false);
+    for row in pixels.chunks_exact(width as usize * 4) {
+        encoder.push_row(row);
+    }
+    encoder.finish()
+}
@@ -0,0 +1,278 @@
+use std::ffi::OsString;
+use std::path::Path;
+use anyhow::{Context, Result};
+use crate::geometry::Rect;
+use crate::{process, save, snapshot, HyprlandClient, HyprlandMonitor};
+
+// --- grimblast, grimshot and hyprshot Compatibility ---
+//
+// Keybindings and scripts written for grimblast, grimshot or hyprshot keep working when
+// LuminaShot is run as `luminashot grimblast copy area`, or through a symlink named after
+// the tool. Their command lines are translated into LuminaShot's before parsing, so the
+// config file still applies. As with the originals, grimblast and grimshot command lines
+// show no notification unless `--notify` is given, and hyprshot ones do unless `--silent` is.
+
+/// Programs whose command lines are understood.
+const TOOLS: &[&str] = &["grimblast", "grimshot", "hyprshot"];
+
+/// Programs `check` looks for, and whether LuminaShot needs them.
+const CHECKED_PROGRAMS: &[(&str, bool)] = &[
+    ("grim", true),
+    ("slurp", true),
+    ("hyprctl", true),
+    ("wl-copy", true),
+    ("notify-send", false),
+    ("hyprpicker", false),
+];
+
+/// The modes of hyprshot's `-m`; any other value names a monitor.
+const HYPRSHOT_MODES: &[&str] = &["output", "window", "region", "active"];
+
+/// Translates a grimblast, grimshot or hyprshot command line into LuminaShot arguments,
+/// and returns any other command line unchanged. Returns `None` if there is nothing left
+/// to do, after `check`, `usage` or `--help`.
+pub async fn translate(args: Vec<OsString>) -> Result<Option<Vec<OsString>>> {
+    let program = args.first().and_then(|arg| Path::new(arg).file_name()).and_then(|name| name.to_str()).unwrap_or_default();
+    let (tool, rest) = if TOOLS.contains(&program) {
+        (program.to_string(), &args[1..])
+    } else if let Some(tool) = args.get(1).and_then(|arg| arg.to_str()).filter(|arg| TOOLS.contains(arg)) {
+        (tool.to_string(), &args[2..])
+    } else {
+        return Ok(Some(args));
+    };
+    let rest = rest
+    .iter()
+    .map(|arg| arg.to_str().with_context(|| format!("{} arguments must be valid UTF-8", tool)))
+    .collect::<Result<Vec<_>>>()?;
+
+    let translated = match tool.as_str() {
+        "hyprshot" => hyprshot(&rest).await?,
+        _ => grim_script(&tool, &rest).await?,
+    };
+    Ok(translated.map(|translated| {
+        let mut args = vec![args[0].clone()];
+        args.extend(translated.into_iter().map(OsString::from));
+        args
+    }))
+}
+
+/// Translates a grimblast or grimshot command line.
+async fn grim_script(tool: &str, args: &[&str]) -> Result<Option<Vec<String>>> {
+    let mut translated: Vec<String> = Vec::new();
+    let mut notify = false;
+    // Options come first, like in the original scripts.
+    let mut words = args.iter().copied();
+    let mut action = None;
+    while let Some(arg) = words.next() {
+        match arg {
+            "-n" | "--notify" => notify = true,
+            "-w" | "--wait" => translated.extend(["--delay".into(), value(arg, None, &mut words)?.into()]),
+            "-f" | "--freeze" if tool == "grimblast" => translated.push("--freeze".into()),
+            "-c" | "--cursor" => eprintln!("Warning: {} is ignored, LuminaShot never captures the cursor", arg),
+            "-s" | "--scale" if tool == "grimblast" => {
+                value(arg, None, &mut words)?;
+                eprintln!("Warning: {} is ignored, LuminaShot always captures at the monitors' own scale", arg);
+            }
+            option if option.starts_with('-') => anyhow::bail!("The {} option {} is not supported", tool, option),
+            word => {
+                action = Some(word);
+                break;
+            }
+        }
+    }
+    let subject = words.next().unwrap_or("screen");
+    let file = words.next();
+
+    match action {
+        Some("check") => {
+            check();
+            return Ok(None);
+        }
+        Some("usage") | None => {
+            grim_script_usage(tool);
+            return Ok(None);
+        }
+        Some("copy") => translated.push("--copy".into()),
+        Some("save") => translated.push("--save".into()),
+        Some("copysave") if tool == "grimblast" => translated.extend(["--copy".into(), "--save".into()]),
+        Some("savecopy") if tool == "grimshot" => translated.extend(["--copy".into(), "--save".into()]),
+        Some("edit") if tool == "grimblast" => {
+            translated.extend(["--save".into(), "--edit".into()]);
+            if let Ok(editor) = std::env::var("GRIMBLAST_EDITOR") {
+                translated.extend(["--editor".into(), editor]);
+            }
+        }
+        Some(action) => anyhow::bail!("Unknown {} action {}; see `{} usage`", tool, action, tool),
+    }
+
+    match subject {
+        "area" | "anything" => translated.extend(["--mode".into(), "region".into()]),
+        "window" if tool == "grimshot" => translated.extend(["--mode".into(), "window".into()]),
+        "output" => translated.extend(["--mode".into(), "monitor".into()]),
+        "active" => translated.extend(active_window().await?),
+        "screen" => {
+            let monitors: Vec<HyprlandMonitor> = snapshot::hyprctl_json("monitors").await?;
+            let mut screen: Option<Rect> = None;
+            for monitor in &monitors {
+                let rect = monitor.rect()?;
+                screen = Some(match screen {
+                    Some(screen) => screen.union(&rect)?,
+                    None => rect,
+                });
+            }
+            let screen = screen.context("There are no monitors")?;
+            translated.extend(["--geometry".into(), screen.to_string()]);
+        }
+        subject => anyhow::bail!("Unknown {} target {}; see `{} usage`", tool, subject, tool),
+    }
+
+    if let (Some(file), true) = (file, translated.iter().any(|arg| arg == "--save")) {
+        translated.extend(["--file".into(), file.into()]);
+    }
+    if !notify {
+        translated.push("--silent".into());
+    }
+    Ok(Some(translated))
+}
+
+/// Translates a hyprshot command line. hyprshot saves and copies by default, and shows a
+/// notification unless `--silent` is given.
+async fn hyprshot(args: &[&str]) -> Result<Option<Vec<String>>> {
+    let mut translated: Vec<String> = Vec::new();
+    let mut modes = Vec::new();
+    let (mut folder, mut filename) = (None, None);
+    let (mut clipboard_only, mut raw) = (false, false);
+    let mut command = Vec::new();
+
+    let mut args = args.iter().copied();
+    while let Some(arg) = args.next() {
+        let (option, inline) = match arg.split_once('=') {
+            Some((option, inline)) if option.starts_with("--") => (option, Some(inline)),
+            _ => (arg, None),
+        };
+        match option {
+            "-h" | "--help" => {
+                hyprshot_usage();
+                return Ok(None);
+            }
+            "-m" | "--mode" => modes.push(value(option, inline, &mut args)?),
+            "-o" | "--output-folder" => folder = Some(value(option, inline, &mut args)?),
+            "-f" | "--filename" => filename = Some(value(option, inline, &mut args)?),
+            "-D" | "--delay" => translated.extend(["--delay".into(), value(option, inline, &mut args)?.into()]),
+            "-t" | "--notif-timeout" => translated.extend(["--notify-timeout".into(), value(option, inline, &mut args)?.into()]),
+            "-z" | "--freeze" => translated.push("--freeze".into()),
+            "-s" | "--silent" => translated.push("--silent".into()),
+            "-r" | "--raw" => raw = true,
+            "--clipboard-only" => clipboard_only = true,
+            // LuminaShot always says what it is doing.
+            "-d" | "--debug" => {}
+            "--" => {
+                command = args.by_ref().collect();
+                break;
+            }
+            option => anyhow::bail!("The hyprshot option {} is not supported; see `hyprshot --help`", option),
+        }
+    }
+
+    let active = modes.contains(&"active");
+    let mode = modes.iter().copied().find(|mode| ["output", "window", "region"].contains(mode));
+    let monitor = modes.iter().find(|mode| !HYPRSHOT_MODES.contains(mode));
+    match (mode, monitor) {
+        (Some("window"), _) if active => translated.extend(active_window().await?),
+        (Some("window"), _) => translated.extend(["--mode".into(), "window".into()]),
+        (Some("region"), _) => translated.extend(["--mode".into(), "region".into()]),
+        (Some("output") | None, Some(name)) => translated.extend(["--geometry".into(), monitor_rect(|monitor| monitor.name == *name, name).await?.to_string()]),
+        (Some("output"), None) if active => translated.extend(["--geometry".into(), monitor_rect(|monitor| monitor.focused, "focused").await?.to_string()]),
+        (Some("output"), None) => translated.extend(["--mode".into(), "monitor".into()]),
+        _ => anyhow::bail!("hyprshot needs a mode, like -m region; see `hyprshot --help`"),
+    }
+
+    if raw {
+        translated.extend(["--file".into(), "-".into()]);
+        return Ok(Some(translated));
+    }
+    translated.push("--copy".into());
+    if !clipboard_only {
+        translated.push("--save".into());
+        let folder = folder.map(String::from).or_else(|| std::env::var("HYPRSHOT_DIR").ok());
+        if folder.is_some() || filename.is_some() {
+            let folder = folder.unwrap_or_else(save::screenshots_dir);
+            let filename = filename.map(String::from).unwrap_or_else(|| chrono::Local::now().format("%Y-%m-%d-%H%M%S_hyprshot.png").to_string());
+            translated.extend(["--file".into(), format!("{}/{}", folder.trim_end_matches('/'), filename)]);
+        }
+    }
+    if !command.is_empty() {
+        translated.extend(["--edit".into(), "--editor".into(), command.join(" ")]);
+    }
+    Ok(Some(translated))
+}
+
+/// Returns the option's value, given after `=` or as the next argument.
+fn value<'a>(option: &str, inline: Option<&'a str>, args: &mut impl Iterator<Item = &'a str>) -> Result<&'a str> {
+    inline.or_else(|| args.next()).with_context(|| format!("{} needs a value", option))
+}
+
+/// Returns the arguments capturing the focused window.
+async fn active_window() -> Result<Vec<String>> {
+    // Of the process's windows, the focused one is picked.
+    let window: HyprlandClient = snapshot::hyprctl_json("activewindow").await.context("No window is focused")?;
+    Ok(vec!["--mode".into(), "window".into(), "--pid".into(), window.pid.to_string()])
+}
+
+/// Returns the area of the first monitor `matches` accepts.
+async fn monitor_rect(matches: impl Fn(&HyprlandMonitor) -> bool, description: &str) -> Result<Rect> {
+    let monitors: Vec<HyprlandMonitor> = snapshot::hyprctl_json("monitors").await?;
+    monitors
+    .iter()
+    .find(|monitor| matches(monitor))
+    .with_context(|| format!("There is no {} monitor", description))?
+    .rect()
+}
+
+/// Prints which of the programs LuminaShot relies on are installed, like `grimblast check`.
+fn check() {
+    println!("Checking if required tools are installed. If something is missing, install it to your system and make it available in PATH...");
+    for (program, required) in CHECKED_PROGRAMS {
+        let state = match (process::in_path(program), required) {
+            (true, _) => "OK",
+            (false, true) => "NOT FOUND",
+            (false, false) => "NOT FOUND (optional)",
+        };
+        println!("   {}: {}", program, state);
+    }
+}
+
+/// Prints the command line understood for grimblast or grimshot.
+fn grim_script_usage(tool: &str) {
+    let (actions, targets, options) = match tool {
+        "grimblast" => ("copy|save|copysave|edit", "active|screen|output|area", "[--notify] [--cursor] [--freeze] [--wait N] [--scale <scale>]"),
+        _ => ("copy|save|savecopy", "active|screen|output|area|window|anything", "[--notify] [--cursor] [--wait N]"),
+    };
+    println!("Usage:");
+    println!("  {} {} ({}) [{}] [FILE|-]", tool, options, actions, targets);
+    println!("  {} check", tool);
+    println!("  {} usage", tool);
+    println!();
+    println!("LuminaShot understands this command line for compatibility. FILE defaults to a new");
+    println!("file in the screenshots directory, and - writes the screenshot to stdout.");
+}
+
+/// Prints the options understood for hyprshot.
+fn hyprshot_usage() {
+    println!("Usage: hyprshot [options ..] [-m [mode] ..] -- [command]");
+    println!();
+    println!("  -h, --help                show this help");
+    println!("  -m, --mode                output, window, region, active or a monitor name");
+    println!("  -o, --output-folder       folder to save the screenshot in");
+    println!("  -f, --filename            file name of the screenshot");
+    println!("  -D, --delay               seconds to wait after selecting before capturing");
+    println!("  -z, --freeze              freeze the screen while selecting (requires hyprpicker)");
+    println!("  -d, --debug               accepted for compatibility");
+    println!("  -s, --silent              don't show a notification");
+    println!("  -r, --raw                 write the image to stdout instead of saving or copying it");
+    println!("  -t, --notif-timeout       notification timeout in milliseconds");
+    println!("  --clipboard-only          only copy the screenshot, without saving it");
+    println!("  -- [command]              open the saved screenshot with command");
+    println!();
+    println!("LuminaShot understands this command line for compatibility.");
+}
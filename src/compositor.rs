@@ -0,0 +1,274 @@
+use std::sync::Mutex;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+use crate::{dirs, i18n, process, snapshot};
+
+// --- Temporary Hyprland Option Overrides ---
+//
+// Overridden options are also written to a journal in the runtime directory before they
+// are changed. Guards restore them on drop, but if luminashot is killed or crashes, the
+// next capture finds the journal of the dead process and puts the old values back.
+
+/// The value of a Hyprland option as reported by `hyprctl getoption -j`.
+#[derive(Deserialize, Debug)]
+struct HyprlandOption {
+    int: Option<i64>,
+    float: Option<f64>,
+    str: Option<String>,
+    custom: Option<String>,
+}
+
+impl HyprlandOption {
+    /// Returns the value in the form `hyprctl keyword` accepts.
+    fn value(self) -> Option<String> {
+        self.str
+        .or(self.custom)
+        .or(self.int.map(|n| n.to_string()))
+        .or(self.float.map(|n| n.to_string()))
+    }
+}
+
+/// An option waiting to be restored, as kept in the journal.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SavedOption {
+    name: String,
+    previous: String,
+}
+
+/// The overrides of this process that haven't been restored yet, oldest first.
+static JOURNAL: Mutex<Vec<SavedOption>> = Mutex::new(Vec::new());
+
+/// A Hyprland option changed for the duration of a capture. The previous value is put
+/// back when the guard is dropped, including when the capture is interrupted by a signal.
+pub struct OptionOverride {
+    name: String,
+    previous: String,
+}
+
+impl Drop for OptionOverride {
+    fn drop(&mut self) {
+        // Drop can't await, and the restore must happen before the process exits.
+        let restored = std::process::Command::new("hyprctl")
+        .args(["keyword", &self.name, &self.previous])
+        .stdout(std::process::Stdio::null())
+        .status();
+        if !restored.is_ok_and(|status| status.success()) {
+            eprintln!("Warning: Failed to restore Hyprland option {} to `{}`", self.name, self.previous);
+        }
+
+        let mut journal = JOURNAL.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(index) = journal.iter().rposition(|saved| saved.name == self.name) {
+            journal.remove(index);
+        }
+        write_journal(&journal);
+    }
+}
+
+/// Sets a Hyprland option and returns a guard that restores its previous value.
+pub async fn override_option(name: &str, value: &str) -> Result<OptionOverride> {
+    let previous = get_option(name).await?;
+    replace_option(name, previous, value).await
+}
+
+/// Overrides the first of several names for the same option that this Hyprland version knows,
+/// for options that were renamed between releases.
+pub async fn override_renamed_option(names: &[&str], value: &str) -> Result<OptionOverride> {
+    for name in names {
+        if let Ok(previous) = get_option(name).await {
+            return replace_option(name, previous, value).await;
+        }
+    }
+    anyhow::bail!("Hyprland has none of the options {}", names.join(", "));
+}
+
+/// Journals an option's current value, then changes it.
+async fn replace_option(name: &str, previous: String, value: &str) -> Result<OptionOverride> {
+    {
+        let mut journal = JOURNAL.lock().unwrap_or_else(|err| err.into_inner());
+        journal.push(SavedOption { name: name.to_string(), previous: previous.clone() });
+        write_journal(&journal);
+    }
+    // The guard exists before the option is changed, so a failed change still cleans up.
+    let guard = OptionOverride { name: name.to_string(), previous };
+    set_keyword(name, value).await?;
+    Ok(guard)
+}
+
+/// Restores options left overridden by luminashot processes that didn't get to clean up.
+pub async fn restore_stale_overrides() {
+    // Left for the next capture under Hyprland, which is where they were overridden.
+    if !is_hyprland() {
+        return;
+    }
+    let Ok(dir) = std::fs::read_dir(dirs::runtime_dir()) else {
+        return;
+    };
+
+    for file in dir.flatten() {
+        let name = file.file_name().to_string_lossy().into_owned();
+        let Some(pid) = name.strip_prefix("luminashot-overrides-").and_then(|rest| rest.strip_suffix(".json")) else {
+            continue;
+        };
+        let Ok(pid) = pid.parse::<libc::pid_t>() else {
+            continue;
+        };
+        // SAFETY: signal 0 only checks for the process, it doesn't deliver anything.
+        if unsafe { libc::kill(pid, 0) } == 0 {
+            continue;
+        }
+
+        let saved: Vec<SavedOption> = std::fs::read(file.path())
+        .ok()
+        .and_then(|contents| serde_json::from_slice(&contents).ok())
+        .unwrap_or_default();
+        // Newest first, so an option overridden twice ends up with its original value.
+        for option in saved.iter().rev() {
+            match set_keyword(&option.name, &option.previous).await {
+                Ok(()) => eprintln!("Restored Hyprland option {} to `{}` after an interrupted capture.", option.name, option.previous),
+                Err(err) => eprintln!("Warning: {:#}", err),
+            }
+        }
+        let _ = std::fs::remove_file(file.path());
+    }
+}
+
+fn journal_path() -> String {
+    format!("{}/luminashot-overrides-{}.json", dirs::runtime_dir(), std::process::id())
+}
+
+/// Writes this process's pending overrides to its journal, removing it once there are none.
+fn write_journal(journal: &[SavedOption]) {
+    let result = if journal.is_empty() {
+        match std::fs::remove_file(journal_path()) {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    } else {
+        serde_json::to_vec(journal)
+        .map_err(std::io::Error::from)
+        .and_then(|contents| std::fs::write(journal_path(), contents))
+    };
+    if let Err(err) = result {
+        eprintln!("Warning: Could not update {}: {}", journal_path(), err);
+    }
+}
+
+/// Reads an option holding a size for each side, such as `general:gaps_in`, as `[top, right, bottom, left]`.
+///
+/// Like CSS margins, one value applies to all sides, two to vertical and horizontal, and three
+/// to top, horizontal and bottom.
+pub async fn get_sides_option(name: &str) -> Result<[i32; 4]> {
+    let value = get_option(name).await?;
+    let sides = value
+    .split(|c: char| c == ',' || c.is_whitespace())
+    .filter(|part| !part.is_empty())
+    .map(|part| part.parse::<f64>().map(|n| n.round() as i32))
+    .collect::<Result<Vec<_>, _>>()
+    .with_context(|| format!("Hyprland option {} has an unexpected value `{}`", name, value))?;
+
+    match sides[..] {
+        [all] => Ok([all; 4]),
+        [vertical, horizontal] => Ok([vertical, horizontal, vertical, horizontal]),
+        [top, horizontal, bottom] => Ok([top, horizontal, bottom, horizontal]),
+        [top, right, bottom, left] => Ok([top, right, bottom, left]),
+        _ => anyhow::bail!("Hyprland option {} has an unexpected value `{}`", name, value),
+    }
+}
+
+/// Reads the current value of a Hyprland option.
+pub async fn get_option(name: &str) -> Result<String> {
+    require_hyprland()?;
+    let output = process::output(
+        Command::new("hyprctl")
+        .arg("getoption")
+        .arg(name)
+        .arg("-j")
+    ).await?;
+
+    let option: HyprlandOption = serde_json::from_slice(&output.stdout)
+    .with_context(|| format!("Failed to read Hyprland option {}", name))?;
+    option.value().with_context(|| format!("Hyprland option {} has no value", name))
+}
+
+/// Sets a Hyprland option at runtime.
+async fn set_keyword(name: &str, value: &str) -> Result<()> {
+    require_hyprland()?;
+    let output = process::output(
+        Command::new("hyprctl")
+        .arg("keyword")
+        .arg(name)
+        .arg(value)
+    ).await?;
+
+    let reply = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() || reply.trim() != "ok" {
+        anyhow::bail!("Failed to set Hyprland option {}: {}", name, reply.trim());
+    }
+    Ok(())
+}
+
+// --- Dispatchers ---
+
+/// Runs a dispatcher when dropped, to undo a temporary change such as switching workspaces.
+pub struct Revert {
+    dispatcher: String,
+    argument: String,
+}
+
+impl Revert {
+    pub fn new(dispatcher: &str, argument: String) -> Revert {
+        Revert { dispatcher: dispatcher.to_string(), argument }
+    }
+}
+
+impl Drop for Revert {
+    fn drop(&mut self) {
+        let reverted = std::process::Command::new("hyprctl")
+        .args(["dispatch", &self.dispatcher, &self.argument])
+        .stdout(std::process::Stdio::null())
+        .status();
+        if !reverted.is_ok_and(|status| status.success()) {
+            eprintln!("Warning: Failed to run `hyprctl dispatch {} {}`", self.dispatcher, self.argument);
+        }
+        snapshot::invalidate();
+    }
+}
+
+/// Runs a Hyprland dispatcher, such as `focuswindow address:0x1234`.
+pub async fn dispatch(dispatcher: &str, argument: &str) -> Result<()> {
+    require_hyprland()?;
+    let output = process::output(
+        Command::new("hyprctl")
+        .arg("dispatch")
+        .arg(dispatcher)
+        .arg(argument)
+    ).await?;
+
+    // Even a failed dispatcher may have changed something.
+    snapshot::invalidate();
+    let reply = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() || reply.trim() != "ok" {
+        anyhow::bail!("hyprctl dispatch {} failed: {}", dispatcher, reply.trim());
+    }
+    Ok(())
+}
+
+// --- Detecting Hyprland ---
+//
+// Outside of Hyprland, hyprctl only prints a complaint that nothing can be parsed, so
+// everything that asks Hyprland checks first and fails with an explanation instead. Region
+// and monitor captures, which slurp and grim can do on their own, still work.
+
+/// Returns true if luminashot runs in a Hyprland session.
+pub fn is_hyprland() -> bool {
+    std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some_and(|signature| !signature.is_empty())
+}
+
+/// Fails with an explanation unless luminashot runs in a Hyprland session.
+pub fn require_hyprland() -> Result<()> {
+    if !is_hyprland() {
+        anyhow::bail!("{}", i18n::tr("Hyprland is not running (HYPRLAND_INSTANCE_SIGNATURE is not set), and this needs it. On other compositors only --mode region, --mode monitor and --geometry work."));
+    }
+    Ok(())
+}
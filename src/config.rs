@@ -0,0 +1,500 @@
+use std::ffi::OsString;
+use anyhow::{Context, Result};
+use clap::error::ErrorKind;
+use clap::{Arg, ArgAction, Command, Subcommand};
+use crate::{dirs, template, wizard};
+
+// --- Configuration File ---
+//
+// The config file uses the same `key = value` syntax as hyprland.conf. Every key is the
+// long name of a command-line option, so anything that can be passed as a flag can be
+// given a default here:
+//
+//     # ~/.config/luminashot/config.conf
+//     mode = region
+//     copy = true
+//     notify-timeout = 3000
+//
+// Entries are turned into arguments placed before the real command line, so flags given
+// on the command line always win. Options of a subcommand go in a section named after it:
+//
+//     [clean]
+//     older-than = 30d
+//     keep-favorites = true
+//
+// Every option can also be set through an environment variable named after it, like
+// `LUMINASHOT_NOTIFY_TIMEOUT=3000` or `LUMINASHOT_CLEAN_OLDER_THAN=30d`, for wrappers and
+// systemd units. These override the config file, and are overridden by the command line.
+
+/// Actions of the `config` subcommand.
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    /// Write a commented config file listing every option and its default
+    Init {
+        #[arg(long, help = "Overwrite an existing config file")]
+        force: bool,
+
+        #[arg(short, long, help = "Ask about the most common settings and fill in the answers")]
+        interactive: bool,
+    },
+    /// Print the effective configuration: the config file's values, and defaults for the rest
+    Show,
+    /// Check the config file for unknown keys, invalid values and template typos
+    Validate,
+}
+
+/// Options whose values are templates, with the placeholders each one supports.
+pub type TemplateOptions<'a> = &'a [(&'a str, &'a [&'a str])];
+
+/// A single `key = value` line from the config file.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub line: usize,
+    /// The `[section]` the entry appears in, `None` for the top of the file.
+    pub section: Option<String>,
+    pub key: String,
+    pub value: String,
+}
+
+/// Prefix of the environment variables that set options.
+const ENV_PREFIX: &str = "LUMINASHOT_";
+
+/// Returns the path of the config file.
+pub fn config_path() -> Result<String> {
+    Ok(format!("{}/luminashot/config.conf", dirs::config_home()?))
+}
+
+/// Returns the command line with the defaults of the config file and the environment
+/// inserted after the program name.
+pub fn args_with_config(command: &Command, mut args: Vec<OsString>) -> Result<Vec<OsString>> {
+
+    // The first argument naming a subcommand is where that subcommand's options start.
+    let subcommand = args
+    .iter()
+    .enumerate()
+    .skip(1)
+    .find_map(|(index, arg)| command.find_subcommand(arg).map(|sub| (index, sub.get_name().to_string())));
+
+    // `config validate` has to work even when the file itself is broken.
+    if subcommand.as_ref().is_some_and(|(_, name)| name == "config") {
+        return Ok(args);
+    }
+
+    let mut global_args = Vec::new();
+    let mut subcommand_args = Vec::new();
+    let config = match config_path() {
+        Ok(path) => read_config(&path)?.map(|contents| (path, contents)),
+        Err(_) => None,
+    };
+    // Each entry with where it comes from, for its errors.
+    let mut entries = Vec::new();
+    if let Some((path, contents)) = config {
+        for entry in parse(&contents).with_context(|| format!("Invalid config file {}", path))? {
+            entries.push((format!("{}:{}", path, entry.line), entry));
+        }
+    }
+    // A variable replaces what the config file sets the option to rather than following
+    // it, since a switch the file turns on can't be turned off by a later argument.
+    for (variable, entry) in env_entries(command) {
+        entries.retain(|(_, known)| known.section != entry.section || known.key != entry.key);
+        entries.push((variable, entry));
+    }
+
+    for (location, entry) in &entries {
+        match &entry.section {
+            None => global_args.extend(entry_to_args(command, entry).with_context(|| location.clone())?),
+            Some(section) => {
+                let sub = command.find_subcommand(section).with_context(|| format!("{}: unknown section `[{}]`", location, section))?;
+                let sub_args = entry_to_args(sub, entry).with_context(|| location.clone())?;
+                if subcommand.as_ref().is_some_and(|(_, name)| name == sub.get_name()) {
+                    subcommand_args.extend(sub_args);
+                }
+            }
+        }
+    }
+
+    if let Some((index, _)) = subcommand {
+        args.splice(index + 1..index + 1, subcommand_args);
+    }
+    args.splice(1..1, global_args);
+    Ok(args)
+}
+
+/// Returns the options set through environment variables, with the variable each comes
+/// from. Variables set to nothing count as unset.
+fn env_entries(command: &Command) -> Vec<(String, Entry)> {
+    let mut entries = Vec::new();
+    for (section, command) in sections(command) {
+        for arg in options(command) {
+            let key = arg.get_long().unwrap_or_default();
+            let variable = env_name(section, key);
+            if let Some(value) = std::env::var(&variable).ok().filter(|value| !value.is_empty()) {
+                let entry = Entry { line: 0, section: section.map(String::from), key: key.to_string(), value };
+                entries.push((variable, entry));
+            }
+        }
+    }
+    entries
+}
+
+/// Returns the environment variable that sets an option: `LUMINASHOT_NOTIFY_TIMEOUT` for
+/// `--notify-timeout`, and `LUMINASHOT_CLEAN_OLDER_THAN` for `--older-than` of `clean`.
+fn env_name(section: Option<&str>, key: &str) -> String {
+    let name = match section {
+        Some(section) => format!("{}-{}", section, key),
+        None => key.to_string(),
+    };
+    format!("{}{}", ENV_PREFIX, name.to_ascii_uppercase().replace('-', "_"))
+}
+
+/// Reads the config file, returning `None` if there isn't one.
+fn read_config(path: &str) -> Result<Option<String>> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("Failed to read {}", path)),
+    }
+}
+
+/// Parses the `key = value` lines of a config file. Blank lines and `#` comments are ignored.
+pub fn parse(contents: &str) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut section = None;
+
+    for (index, raw) in contents.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            section = Some(name.trim().to_string());
+            continue;
+        }
+
+        let (key, value) = line
+        .split_once('=')
+        .with_context(|| format!("line {}: expected `key = value`, found `{}`", index + 1, line))?;
+
+        entries.push(Entry {
+            line: index + 1,
+            section: section.clone(),
+            key: key.trim().replace('_', "-"),
+            value: unquote(value.trim()).to_string(),
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Converts a config entry into the command-line arguments it stands for.
+fn entry_to_args(command: &Command, entry: &Entry) -> Result<Vec<OsString>> {
+    let arg = command
+    .get_arguments()
+    .find(|arg| arg.get_long() == Some(entry.key.as_str()))
+    .with_context(|| format!("unknown option `{}`", entry.key))?;
+
+    if arg.get_action().takes_values() {
+        return Ok(vec![format!("--{}={}", entry.key, entry.value).into()]);
+    }
+
+    match entry.value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Ok(vec![format!("--{}", entry.key).into()]),
+        "false" | "no" | "off" | "0" => Ok(vec![]),
+        _ => anyhow::bail!("`{}` is a switch and expects true or false, found `{}`", entry.key, entry.value),
+    }
+}
+
+/// Strips one pair of matching quotes, so values with leading or trailing spaces can be written.
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(inner) = value.strip_prefix(quote).and_then(|v| v.strip_suffix(quote)) {
+            return inner;
+        }
+    }
+    value
+}
+
+// --- `config` Subcommand ---
+
+/// Writes a config file with every option commented out at its default value. With
+/// `interactive`, the answers to the setup wizard's questions are filled in.
+pub fn init(command: &Command, force: bool, interactive: bool) -> Result<()> {
+    let path = config_path()?;
+    if !force && std::path::Path::new(&path).exists() {
+        anyhow::bail!("{} already exists; pass --force to overwrite it", path);
+    }
+    let answers = if interactive { wizard::ask(command)? } else { Vec::new() };
+    let out = template(command, &answers);
+
+    if let Some(parent) = std::path::Path::new(&path).parent() {
+        std::fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    std::fs::write(&path, out).with_context(|| format!("Failed to write {}", path))?;
+    println!("Wrote {}", path);
+    Ok(())
+}
+
+/// Returns the contents `config init` writes, with the answers filled in and every other
+/// option commented out at its default.
+fn template(command: &Command, answers: &[Entry]) -> String {
+    let mut out = String::from(
+        "# LuminaShot configuration\n\
+        #\n\
+        # Every key is the long name of a command-line option, and flags given on the command\n\
+        # line always win over the values here. Uncomment a line to change its default.\n",
+    );
+    for (section, command) in sections(command) {
+        if let Some(name) = section {
+            out.push_str(&format!("\n[{}]\n", name));
+        }
+        for arg in options(command) {
+            let help = arg.get_help().map(|help| help.to_string()).unwrap_or_default();
+            let key = arg.get_long().unwrap_or_default();
+            match answers.iter().rev().find(|entry| entry.section.as_deref() == section && entry.key == key) {
+                Some(answer) => out.push_str(&format!("\n# {}\n{} = {}\n", help, key, quote(&answer.value))),
+                None => match default_value(arg) {
+                    // An empty value would be rejected once uncommented, so options without
+                    // a default are only described.
+                    value if value.is_empty() => out.push_str(&format!("\n# {} (`{}`, no default)\n", help, key)),
+                    value => out.push_str(&format!("\n# {}\n# {} = {}\n", help, key, quote(&value))),
+                },
+            }
+        }
+    }
+    out
+}
+
+/// Prints the value every option ends up with once the config file is applied.
+pub fn show(command: &Command, templates: TemplateOptions) -> Result<()> {
+    let path = config_path()?;
+    let mut entries = match read_config(&path)? {
+        Some(contents) => parse(&contents).with_context(|| format!("Invalid config file {}", path))?,
+        None => Vec::new(),
+    };
+    for problem in problems(command, &entries, &path, templates) {
+        eprintln!("Warning: {}", problem);
+    }
+    entries.extend(env_entries(command).into_iter().map(|(_, entry)| entry));
+
+    println!("# Effective configuration, from {}, {}* variables and the built-in defaults", path, ENV_PREFIX);
+    for (section, command) in sections(command) {
+        if let Some(name) = section {
+            println!("\n[{}]", name);
+        }
+        for arg in options(command) {
+            let key = arg.get_long().unwrap_or_default();
+            // Later entries override earlier ones, just like repeated flags.
+            let value = entries
+            .iter()
+            .rev()
+            .find(|entry| entry.section.as_deref() == section && entry.key == key)
+            .map(|entry| entry.value.clone())
+            .unwrap_or_else(|| default_value(arg));
+
+            if value.is_empty() {
+                println!("# {} =", key);
+            } else {
+                println!("{} = {}", key, quote(&value));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks every entry of the config file, printing all problems with their line numbers.
+pub fn validate(command: &Command, templates: TemplateOptions) -> Result<()> {
+    let path = config_path()?;
+    let Some(contents) = read_config(&path)? else {
+        println!("No config file at {}; the built-in defaults are used.", path);
+        return Ok(());
+    };
+    let entries = parse(&contents).with_context(|| format!("Invalid config file {}", path))?;
+
+    let problems = problems(command, &entries, &path, templates);
+    if problems.is_empty() {
+        println!("{} is valid ({} settings).", path, entries.len());
+        return Ok(());
+    }
+    for problem in &problems {
+        eprintln!("{}", problem);
+    }
+    anyhow::bail!("Found {} problems in {}", problems.len(), path);
+}
+
+/// Returns a `path:line: message` description of every invalid entry.
+fn problems(command: &Command, entries: &[Entry], path: &str, templates: TemplateOptions) -> Vec<String> {
+    entries
+    .iter()
+    .filter_map(|entry| check_entry(command, entry, templates).err().map(|err| format!("{}:{}: {:#}", path, entry.line, err)))
+    .collect()
+}
+
+/// Checks that an entry names a real option and has a value that option accepts.
+fn check_entry(command: &Command, entry: &Entry, templates: TemplateOptions) -> Result<()> {
+    let command = match &entry.section {
+        None => command,
+        Some(section) => command.find_subcommand(section).with_context(|| format!("unknown section `[{}]`", section))?,
+    };
+    let args = entry_to_args(command, entry)?;
+
+    if entry.section.is_none() {
+        if let Some((_, supported)) = templates.iter().find(|(key, _)| *key == entry.key) {
+            if let Some(name) = template::placeholders(&entry.value).into_iter().find(|name| !supported.contains(name)) {
+                anyhow::bail!("unknown placeholder `{{{}}}` in `{}`; supported: {}", name, entry.key, supported.iter().map(|name| format!("{{{}}}", name)).collect::<Vec<_>>().join(", "));
+            }
+        }
+    }
+
+    // Let clap check the value itself. Other errors, like a missing positional argument,
+    // come from parsing a lone option and don't mean the entry is wrong.
+    let argv = std::iter::once(OsString::from(command.get_name())).chain(args);
+    if let Err(err) = command.clone().try_get_matches_from(argv) {
+        if matches!(err.kind(), ErrorKind::InvalidValue | ErrorKind::ValueValidation) {
+            let message = err.render().to_string();
+            let message: Vec<&str> = message.lines().take_while(|line| !line.is_empty()).map(str::trim).collect();
+            anyhow::bail!("{}", message.join(" ").trim_start_matches("error: "));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the top-level command and every subcommand with options, keyed by their section name.
+fn sections(command: &Command) -> Vec<(Option<&str>, &Command)> {
+    let subcommands = command
+    .get_subcommands()
+    .filter(|sub| sub.get_name() != "config" && options(sub).next().is_some())
+    .map(|sub| (Some(sub.get_name()), sub));
+    std::iter::once((None, command)).chain(subcommands).collect()
+}
+
+/// Returns the options that can be set in the config file.
+fn options(command: &Command) -> impl Iterator<Item = &Arg> {
+    command
+    .get_arguments()
+    .filter(|arg| arg.get_long().is_some() && !arg.is_hide_set())
+    .filter(|arg| !matches!(arg.get_action(), ArgAction::Help | ArgAction::HelpShort | ArgAction::HelpLong | ArgAction::Version))
+}
+
+/// Returns an option's default as it would be written in the config file. Several
+/// values are joined with the option's delimiter, just as they would be on the command line.
+pub fn default_value(arg: &Arg) -> String {
+    if !arg.get_action().takes_values() {
+        return "false".to_string();
+    }
+    let delimiter = arg.get_value_delimiter().unwrap_or(',').to_string();
+    arg.get_default_values().iter().map(|value| value.to_string_lossy()).collect::<Vec<_>>().join(&delimiter)
+}
+
+/// Quotes values whose surrounding spaces would otherwise be trimmed.
+fn quote(value: &str) -> String {
+    if value.trim() != value {
+        return format!("\"{}\"", value);
+    }
+    value.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::value_parser;
+
+    const TEMPLATES: TemplateOptions = &[("filename", &["date", "mode"])];
+
+    fn command() -> Command {
+        Command::new("luminashot")
+        .arg(Arg::new("mode").long("mode").value_parser(["region", "output"]).default_value("region"))
+        .arg(Arg::new("copy").long("copy").action(ArgAction::SetTrue))
+        .arg(Arg::new("notify-timeout").long("notify-timeout").value_parser(value_parser!(u32)))
+        .arg(Arg::new("filename").long("filename"))
+        .subcommand(
+            Command::new("clean")
+            .arg(Arg::new("older-than").long("older-than").required(true))
+            .arg(Arg::new("dry-run").long("dry-run").action(ArgAction::SetTrue)),
+        )
+        .subcommand(Command::new("config").arg(Arg::new("force").long("force").action(ArgAction::SetTrue)))
+    }
+
+    fn check(contents: &str) -> Vec<String> {
+        problems(&command(), &parse(contents).unwrap(), "config.conf", TEMPLATES)
+    }
+
+    #[test]
+    fn parses_entries_and_sections() {
+        let entries = parse("# comment\n\nmode = output\n  notify_timeout=3000  \nfilename = \" spaced \"\n[clean]\nolder-than = '30d'\n").unwrap();
+        let found: Vec<(usize, Option<&str>, &str, &str)> = entries
+        .iter()
+        .map(|entry| (entry.line, entry.section.as_deref(), entry.key.as_str(), entry.value.as_str()))
+        .collect();
+        assert_eq!(found, [
+            (3, None, "mode", "output"),
+            (4, None, "notify-timeout", "3000"),
+            (5, None, "filename", " spaced "),
+            (7, Some("clean"), "older-than", "30d"),
+        ]);
+
+        let err = parse("mode = region\ncopy\n").unwrap_err().to_string();
+        assert_eq!(err, "line 2: expected `key = value`, found `copy`");
+        assert_eq!(unquote("\"mixed'"), "\"mixed'");
+    }
+
+    #[test]
+    fn turns_entries_into_arguments() {
+        let entry = |key: &str, value: &str| Entry { line: 1, section: None, key: key.to_string(), value: value.to_string() };
+        let command = command();
+        assert_eq!(entry_to_args(&command, &entry("mode", "output")).unwrap(), ["--mode=output"]);
+        assert_eq!(entry_to_args(&command, &entry("copy", "Yes")).unwrap(), ["--copy"]);
+        assert!(entry_to_args(&command, &entry("copy", "off")).unwrap().is_empty());
+        assert!(entry_to_args(&command, &entry("copy", "maybe")).is_err());
+        assert!(entry_to_args(&command, &entry("colour", "red")).is_err());
+    }
+
+    #[test]
+    fn validates_entries() {
+        assert!(check("mode = output\ncopy = true\nnotify-timeout = 3000\nfilename = {date}-{mode}\n[clean]\nolder-than = 30d\ndry-run = no\n").is_empty());
+
+        let problems = check("mode = window\nnotify-timeout = soon\ncopy = sure\nfilename = {dat}\ncolour = red\n[clean]\nolder = 30d\n[paint]\nbrush = big\n");
+        assert_eq!(problems.len(), 7, "{:#?}", problems);
+        assert!(problems[0].starts_with("config.conf:1: invalid value 'window' for '--mode <mode>'"), "{}", problems[0]);
+        assert!(problems[1].starts_with("config.conf:2: invalid value 'soon'"), "{}", problems[1]);
+        assert_eq!(problems[2], "config.conf:3: `copy` is a switch and expects true or false, found `sure`");
+        assert_eq!(problems[3], "config.conf:4: unknown placeholder `{dat}` in `filename`; supported: {date}, {mode}");
+        assert_eq!(problems[4], "config.conf:5: unknown option `colour`");
+        assert_eq!(problems[5], "config.conf:7: unknown option `older`");
+        assert_eq!(problems[6], "config.conf:9: unknown section `[paint]`");
+    }
+
+    #[test]
+    fn lists_sections_with_options() {
+        let command = command();
+        let sections: Vec<Option<&str>> = sections(&command).into_iter().map(|(section, _)| section).collect();
+        assert_eq!(sections, [None, Some("clean")]);
+        assert_eq!(env_name(None, "notify-timeout"), "LUMINASHOT_NOTIFY_TIMEOUT");
+        assert_eq!(env_name(Some("clean"), "older-than"), "LUMINASHOT_CLEAN_OLDER_THAN");
+    }
+
+    #[test]
+    fn init_writes_valid_defaults() {
+        use clap::CommandFactory;
+
+        let command = crate::Cli::command();
+        let out = template(&command, &[]);
+        assert!(out.contains("\n# notify-actions = edit,reveal,delete,retake\n"), "{}", out);
+
+        // Each option is its help line followed by a commented `key = value` line.
+        let uncommented: Vec<String> = out
+        .split("\n\n")
+        .flat_map(|block| {
+            let lines: Vec<&str> = block.lines().collect();
+            let value_line = lines.len() == 2 && lines.iter().all(|line| line.starts_with("# "));
+            lines.into_iter().enumerate().map(move |(index, line)| match value_line && index == 1 {
+                true => line.trim_start_matches("# ").to_string(),
+                false => line.to_string(),
+            })
+        })
+        .collect();
+        let entries = parse(&uncommented.join("\n")).unwrap();
+        assert!(entries.len() > 50, "{}", entries.len());
+        let problems = problems(&command, &entries, "config.conf", crate::TEMPLATE_OPTIONS);
+        assert!(problems.is_empty(), "{:#?}", problems);
+    }
+}
@@ -0,0 +1,97 @@
+use anyhow::Result;
+use crate::{dirs, format, i18n, notify, png};
+
+// --- Confirm Before Keeping ---
+//
+// `--confirm` shows the capture in a notification with Save, Copy, Edit and Discard
+// buttons before anything is written or copied, so misfires never reach the screenshots
+// folder. Clicking the notification itself does what the command line asked for, and
+// dismissing it discards the capture. `luminashot gui` asks the same way after annotating,
+// with Upload instead of Edit.
+
+/// Largest side of the preview sent with the notification.
+const PREVIEW_SIZE: u32 = 512;
+
+/// What to do with a capture.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Choice {
+    /// Save and copy as the command line says.
+    Keep,
+    Save,
+    Copy,
+    /// Save, then open the editor.
+    Edit,
+    /// Save, then upload and copy the URL.
+    Upload,
+    Discard,
+}
+
+impl Choice {
+    fn key(self) -> &'static str {
+        match self {
+            Choice::Keep => "default",
+            Choice::Save => "save",
+            Choice::Copy => "copy",
+            Choice::Edit => "edit",
+            Choice::Upload => "upload",
+            Choice::Discard => "discard",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Choice::Keep => "Keep",
+            Choice::Save => "Save",
+            Choice::Copy => "Copy",
+            Choice::Edit => "Edit",
+            Choice::Upload => "Upload",
+            Choice::Discard => "Discard",
+        }
+    }
+}
+
+/// Asks what to do with a capture, offering `choices` besides keeping it, and waits until the
+/// user has decided. Without a notification server that shows buttons, the capture is kept.
+pub async fn ask(image: &[u8], image_format: format::ImageFormat, mode_label: &str, choices: &[Choice], limit: std::time::Duration) -> Result<Choice> {
+    // Other formats can't be decoded here, so the server is pointed at a file instead.
+    let (preview, icon_file) = match image_format {
+        format::ImageFormat::Png => (png::decode_scaled(image, PREVIEW_SIZE).ok(), None),
+        _ => {
+            let path = format!("{}/luminashot-confirm.{}", dirs::runtime_dir(), image_format.extension());
+            tokio::fs::write(&path, image).await?;
+            (None, Some(path))
+        }
+    };
+
+    let delivered = notify::show(&notify::Notification {
+        summary: i18n::tr_with("LuminaShot - {mode} Mode", &[("mode", i18n::tr(mode_label))]),
+        body: i18n::tr("Keep this screenshot?"),
+        icon: icon_file.clone().unwrap_or_else(|| "camera-photo".into()),
+        image: preview,
+        actions: std::iter::once(Choice::Keep)
+        .chain(choices.iter().copied())
+        .map(|choice| (choice.key().to_string(), i18n::tr(choice.label())))
+        .collect(),
+        timeout: Some(0),
+        ..Default::default()
+    })
+    .await;
+
+    let choice = match delivered {
+        Some(delivered) if delivered.supports_actions() => {
+            let action = delivered.wait_for_action(limit).await;
+            std::iter::once(Choice::Keep)
+            .chain(choices.iter().copied())
+            .find(|choice| action.as_deref() == Some(choice.key()))
+            .unwrap_or(Choice::Discard)
+        }
+        _ => {
+            eprintln!("Warning: Choosing needs a notification server that shows buttons, keeping the screenshot");
+            Choice::Keep
+        }
+    };
+    if let Some(path) = icon_file {
+        let _ = tokio::fs::remove_file(path).await;
+    }
+    Ok(choice)
+}
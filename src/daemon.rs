@@ -0,0 +1,262 @@
+use std::ffi::OsString;
+use std::io::Read;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::process::Stdio;
+use anyhow::{Context, Result};
+use tokio::io::AsyncWriteExt;
+use tokio::process::{Child, ChildStdin, Command};
+use crate::dbus::{self, Value};
+use crate::tray;
+
+// --- `daemon` Subcommand ---
+//
+// The daemon registers LuminaShot's actions as named global shortcuts through the
+// GlobalShortcuts portal, so they show up in the compositor's shortcut settings and are
+// bound there instead of with hand-written `exec` lines. On Hyprland they are listed by
+// `hyprctl globalshortcuts` and bound with `bind = SUPER, Print, global, <app-id>:capture-region`.
+//
+// Every activation runs a separate `luminashot` with the options the daemon was started
+// with, so a capture behaves exactly as if the keybinding had run it directly. To keep
+// process startup out of the way, the next one is always started ahead of time and waits
+// on its stdin to be handed its command line.
+
+const PORTAL: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const GLOBAL_SHORTCUTS: &str = "org.freedesktop.portal.GlobalShortcuts";
+const REQUEST: &str = "org.freedesktop.portal.Request";
+
+/// Marks a process as a standby started by the daemon. Not meant to be typed by users.
+pub const STANDBY_ARG: &str = "--daemon-standby";
+
+/// A shortcut the daemon registers.
+pub struct Shortcut {
+    pub id: &'static str,
+    pub description: &'static str,
+    /// Arguments of the `luminashot` command the shortcut runs.
+    pub args: &'static [&'static str],
+}
+
+pub const SHORTCUTS: &[Shortcut] = &[
+    Shortcut { id: "capture-region", description: "Capture region", args: &["--mode", "region"] },
+    Shortcut { id: "capture-window", description: "Capture window", args: &["--mode", "window"] },
+    Shortcut { id: "capture-monitor", description: "Capture monitor", args: &["--mode", "monitor"] },
+    Shortcut { id: "record-region", description: "Start or stop recording a region", args: &["record", "region", "--toggle"] },
+];
+
+/// Options for running the daemon.
+pub struct DaemonOptions<'a> {
+    /// Also show an icon in the system tray.
+    pub tray: bool,
+    /// Program the tray's recent captures are opened with.
+    pub viewer: &'a str,
+}
+
+/// Registers the shortcuts and runs captures when they are pressed, until interrupted.
+pub async fn daemon(options: DaemonOptions<'_>) -> Result<()> {
+    let mut bus = dbus::Connection::session().await?;
+    // Subscribe before the first call so no response can slip past.
+    bus.add_match(&format!("type='signal',interface='{}',member='Response'", REQUEST)).await?;
+    bus.add_match(&format!("type='signal',interface='{}',member='Activated'", GLOBAL_SHORTCUTS)).await?;
+
+    let mut tray = match options.tray {
+        true => Some(tray::Tray::register(&mut bus).await.context("Failed to add the tray icon")?),
+        false => None,
+    };
+    // With a tray icon, captures can still be started from it without the portal.
+    let session = match bind_shortcuts(&mut bus).await {
+        Ok(session) => Some(session),
+        Err(err) if tray.is_some() => {
+            eprintln!("Warning: {:#}", err);
+            None
+        }
+        Err(err) => return Err(err),
+    };
+
+    let exe = std::env::current_exe().context("Failed to find the luminashot executable")?;
+    let base_args = crate::args_before("daemon");
+    let mut standby = start_standby(&exe);
+    loop {
+        let message = bus.next_message().await?;
+        if let Some(tray) = &mut tray {
+            match tray.handle(&mut bus, &message).await? {
+                Some(tray::Action::Run(shortcut)) => {
+                    launch(&exe, &base_args, &mut standby, shortcut).await;
+                    continue;
+                }
+                Some(tray::Action::Open(path)) => {
+                    if let Err(err) = crate::spawn_with_file(options.viewer, &path) {
+                        eprintln!("Warning: Failed to open {}: {:#}", path, err);
+                    }
+                    continue;
+                }
+                None => {}
+            }
+        }
+
+        if !message.is_signal(GLOBAL_SHORTCUTS, "Activated") || message.body.first().and_then(Value::as_str) != session.as_deref() {
+            continue;
+        }
+        let Some(shortcut) = message.body.get(1).and_then(Value::as_str).and_then(|id| SHORTCUTS.iter().find(|shortcut| shortcut.id == id)) else {
+            continue;
+        };
+        launch(&exe, &base_args, &mut standby, shortcut).await;
+    }
+}
+
+/// Opens a GlobalShortcuts session and binds the shortcuts in it. Returns the session handle.
+async fn bind_shortcuts(bus: &mut dbus::Connection) -> Result<String> {
+    let token = format!("luminashot{}", std::process::id());
+    let results = request(bus, "CreateSession", vec![Value::dict(vec![
+        ("handle_token".into(), Value::str(&token)),
+        ("session_handle_token".into(), Value::str(&token)),
+    ])])
+    .await
+    .context("Failed to open a GlobalShortcuts session; is xdg-desktop-portal running with a backend that supports it, like xdg-desktop-portal-hyprland?")?;
+    let session = results
+    .get("session_handle")
+    .and_then(Value::as_str)
+    .context("The GlobalShortcuts portal did not return a session")?
+    .to_string();
+
+    let shortcuts = SHORTCUTS
+    .iter()
+    .map(|shortcut| {
+        Value::Struct(vec![
+            Value::str(shortcut.id),
+            Value::dict(vec![("description".into(), Value::str(shortcut.description))]),
+        ])
+    })
+    .collect();
+    let results = request(bus, "BindShortcuts", vec![
+        Value::Path(session.clone()),
+        Value::Array("(sa{sv})".into(), shortcuts),
+        Value::str(""),
+        Value::dict(vec![("handle_token".into(), Value::str(format!("{}bind", token)))]),
+    ])
+    .await?;
+    print_bound(&results);
+    Ok(session)
+}
+
+/// Runs a shortcut's command line in the standby process, and starts the next standby.
+async fn launch(exe: &std::path::Path, base_args: &[OsString], standby: &mut Option<Standby>, shortcut: &Shortcut) {
+    let command_line: Vec<OsString> = std::iter::once(exe.to_path_buf().into_os_string())
+    .chain(base_args.iter().cloned())
+    .chain(shortcut.args.iter().map(OsString::from))
+    .collect();
+    // A standby process that died before the press only costs the head start.
+    let ran = match standby.take() {
+        Some(ready) => match ready.activate(&command_line).await {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                eprintln!("Warning: {:#}, starting {} anew", err, shortcut.id);
+                run(exe, &command_line)
+            }
+        },
+        None => run(exe, &command_line),
+    };
+    if let Err(err) = ran {
+        eprintln!("Warning: Failed to run {}: {:#}", shortcut.id, err);
+    }
+    *standby = start_standby(exe);
+}
+
+/// Calls a portal method and waits for the response to the request it starts.
+async fn request(bus: &mut dbus::Connection, method: &str, args: Vec<Value>) -> Result<Value> {
+    let reply = bus.call(PORTAL, PORTAL_PATH, GLOBAL_SHORTCUTS, method, args).await?;
+    let handle = reply.first().and_then(Value::as_str).context("The GlobalShortcuts portal did not return a request")?.to_string();
+
+    loop {
+        let message = bus.next_message().await?;
+        if !message.is_signal(REQUEST, "Response") || message.path.as_deref() != Some(handle.as_str()) {
+            continue;
+        }
+        return match message.body.first().and_then(Value::as_u32) {
+            Some(0) => Ok(message.body.get(1).cloned().unwrap_or_else(|| Value::dict(vec![]))),
+            Some(1) => anyhow::bail!("{} was cancelled", method),
+            _ => anyhow::bail!("The GlobalShortcuts portal refused {}", method),
+        };
+    }
+}
+
+/// Prints every registered shortcut with the trigger the compositor assigned to it.
+fn print_bound(results: &Value) {
+    let bound = results.get("shortcuts").and_then(Value::as_array).unwrap_or_default();
+    println!("Registered {} global shortcuts:", bound.len());
+    for shortcut in bound {
+        let Value::Struct(fields) = shortcut else { continue };
+        let id = fields.first().and_then(Value::as_str).unwrap_or_default();
+        let trigger = fields
+        .get(1)
+        .and_then(|properties| properties.get("trigger_description"))
+        .and_then(Value::as_str)
+        .filter(|trigger| !trigger.is_empty())
+        .unwrap_or("not bound");
+        println!("  {}\t{}", id, trigger);
+    }
+}
+
+/// A luminashot process started ahead of time, waiting to be handed a command line.
+struct Standby {
+    child: Child,
+    stdin: ChildStdin,
+}
+
+/// Starts the process the next shortcut will use. Without one, shortcuts start a new process.
+fn start_standby(exe: &std::path::Path) -> Option<Standby> {
+    let started = Command::new(exe)
+    .arg(STANDBY_ARG)
+    .stdin(Stdio::piped())
+    .spawn()
+    .context("Failed to start a standby luminashot process");
+    match started {
+        Ok(mut child) => {
+            let stdin = child.stdin.take()?;
+            Some(Standby { child, stdin })
+        }
+        Err(err) => {
+            eprintln!("Warning: {:#}", err);
+            None
+        }
+    }
+}
+
+impl Standby {
+    /// Hands the standby process its command line, which it then runs on its own.
+    async fn activate(self, command_line: &[OsString]) -> Result<()> {
+        let Standby { child, mut stdin } = self;
+        let message: Vec<u8> = command_line.iter().map(|arg| arg.as_bytes()).collect::<Vec<_>>().join(&0);
+        stdin.write_all(&message).await.context("The standby process exited")?;
+        drop(stdin);
+        reap(child);
+        Ok(())
+    }
+}
+
+/// In a standby process, waits for the daemon to hand over the command line to run.
+/// Returns `None` if the daemon exits instead.
+pub fn wait_for_activation() -> Result<Option<Vec<OsString>>> {
+    let mut message = Vec::new();
+    std::io::stdin().read_to_end(&mut message).context("Failed to read the command line from the daemon")?;
+    if message.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(message.split(|&byte| byte == 0).map(|arg| OsString::from_vec(arg.to_vec())).collect()))
+}
+
+/// Starts a command line in a new process without waiting for it.
+fn run(exe: &std::path::Path, command_line: &[OsString]) -> Result<()> {
+    let child = Command::new(exe)
+    .args(&command_line[1..])
+    .stdin(Stdio::null())
+    .spawn()?;
+    reap(child);
+    Ok(())
+}
+
+/// Waits for a child in the background, so finished captures don't linger as zombies.
+fn reap(mut child: Child) {
+    tokio::spawn(async move {
+        let _ = child.wait().await;
+    });
+}
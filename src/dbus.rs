@@ -0,0 +1,870 @@
+use std::collections::VecDeque;
+use std::os::fd::OwnedFd;
+use std::os::linux::net::SocketAddrExt;
+use anyhow::{Context, Result};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+use crate::dirs;
+
+// A minimal D-Bus client: just enough of the wire protocol to call methods on the
+// session bus, receive signals and answer calls made to objects we export.
+
+// --- Values ---
+
+/// A D-Bus value, tagged with enough type information to be marshalled.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Byte(u8),
+    Bool(bool),
+    I32(i32),
+    U32(u32),
+    I64(i64),
+    U64(u64),
+    Double(f64),
+    Str(String),
+    Path(String),
+    Signature(String),
+    /// An array with its element signature, so empty arrays can still be typed.
+    Array(String, Vec<Value>),
+    Struct(Vec<Value>),
+    DictEntry(Box<Value>, Box<Value>),
+    Variant(Box<Value>),
+}
+
+impl Value {
+    /// Returns the D-Bus type signature of this value.
+    pub fn signature(&self) -> String {
+        match self {
+            Value::Byte(_) => "y".into(),
+            Value::Bool(_) => "b".into(),
+            Value::I32(_) => "i".into(),
+            Value::U32(_) => "u".into(),
+            Value::I64(_) => "x".into(),
+            Value::U64(_) => "t".into(),
+            Value::Double(_) => "d".into(),
+            Value::Str(_) => "s".into(),
+            Value::Path(_) => "o".into(),
+            Value::Signature(_) => "g".into(),
+            Value::Array(element, _) => format!("a{}", element),
+            Value::Struct(fields) => format!("({})", fields.iter().map(Value::signature).collect::<String>()),
+            Value::DictEntry(key, value) => format!("{{{}{}}}", key.signature(), value.signature()),
+            Value::Variant(_) => "v".into(),
+        }
+    }
+
+    pub fn str(value: impl Into<String>) -> Value {
+        Value::Str(value.into())
+    }
+
+    /// Wraps a value in a variant.
+    pub fn variant(value: Value) -> Value {
+        Value::Variant(Box::new(value))
+    }
+
+    /// Builds an `a{sv}` dictionary.
+    pub fn dict(entries: Vec<(String, Value)>) -> Value {
+        Value::Array(
+            "{sv}".into(),
+            entries
+            .into_iter()
+            .map(|(key, value)| Value::DictEntry(Box::new(Value::Str(key)), Box::new(Value::variant(value))))
+            .collect(),
+        )
+    }
+
+    /// Builds an `as` array.
+    pub fn string_array<S: Into<String>>(items: impl IntoIterator<Item = S>) -> Value {
+        Value::Array("s".into(), items.into_iter().map(|item| Value::Str(item.into())).collect())
+    }
+
+    /// Builds an `ay` byte array.
+    pub fn bytes(bytes: &[u8]) -> Value {
+        Value::Array("y".into(), bytes.iter().map(|&b| Value::Byte(b)).collect())
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) | Value::Path(s) | Value::Signature(s) => Some(s),
+            Value::Variant(inner) => inner.as_str(),
+            _ => None,
+        }
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            Value::U32(n) => Some(*n),
+            Value::Variant(inner) => inner.as_u32(),
+            _ => None,
+        }
+    }
+
+    /// Returns the items of an array.
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(_, items) => Some(items),
+            Value::Variant(inner) => inner.as_array(),
+            _ => None,
+        }
+    }
+
+    /// Looks up `key` in an `a{sv}` dictionary, returning the value inside its variant.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.as_array()?.iter().find_map(|item| match item {
+            Value::DictEntry(k, value) if k.as_str() == Some(key) => match value.as_ref() {
+                Value::Variant(inner) => Some(inner.as_ref()),
+                other => Some(other),
+            },
+            _ => None,
+        })
+    }
+}
+
+// --- Marshalling ---
+
+fn alignment(code: u8) -> usize {
+    match code {
+        b'y' | b'g' | b'v' => 1,
+        b'n' | b'q' => 2,
+        b'x' | b't' | b'd' | b'(' | b'{' => 8,
+        _ => 4,
+    }
+}
+
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn pad(&mut self, align: usize) {
+        while !self.buf.len().is_multiple_of(align) {
+            self.buf.push(0);
+        }
+    }
+
+    fn u32(&mut self, n: u32) {
+        self.pad(4);
+        self.buf.extend_from_slice(&n.to_le_bytes());
+    }
+
+    fn string(&mut self, s: &str) {
+        self.u32(s.len() as u32);
+        self.buf.extend_from_slice(s.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn signature(&mut self, s: &str) {
+        self.buf.push(s.len() as u8);
+        self.buf.extend_from_slice(s.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn value(&mut self, value: &Value) {
+        match value {
+            Value::Byte(b) => self.buf.push(*b),
+            Value::Bool(b) => self.u32(*b as u32),
+            Value::I32(n) => {
+                self.pad(4);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::U32(n) => self.u32(*n),
+            Value::I64(n) => {
+                self.pad(8);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::U64(n) => {
+                self.pad(8);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Double(n) => {
+                self.pad(8);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Str(s) | Value::Path(s) => self.string(s),
+            Value::Signature(s) => self.signature(s),
+            Value::Array(element, items) => {
+                self.pad(4);
+                let len_pos = self.buf.len();
+                self.buf.extend_from_slice(&[0; 4]);
+                self.pad(alignment(element.as_bytes()[0]));
+                let start = self.buf.len();
+                for item in items {
+                    self.value(item);
+                }
+                let len = (self.buf.len() - start) as u32;
+                self.buf[len_pos..len_pos + 4].copy_from_slice(&len.to_le_bytes());
+            }
+            Value::Struct(fields) => {
+                self.pad(8);
+                for field in fields {
+                    self.value(field);
+                }
+            }
+            Value::DictEntry(key, value) => {
+                self.pad(8);
+                self.value(key);
+                self.value(value);
+            }
+            Value::Variant(inner) => {
+                self.signature(&inner.signature());
+                self.value(inner);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn align(&mut self, align: usize) {
+        self.pos = self.pos.div_ceil(align) * align;
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let bytes = self.buf.get(self.pos..self.pos + n).context("Truncated D-Bus message")?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        self.align(4);
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        self.align(8);
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.u32()? as usize;
+        let s = String::from_utf8(self.take(len)?.to_vec())?;
+        self.take(1)?;
+        Ok(s)
+    }
+
+    fn signature(&mut self) -> Result<String> {
+        let len = self.take(1)?[0] as usize;
+        let s = String::from_utf8(self.take(len)?.to_vec())?;
+        self.take(1)?;
+        Ok(s)
+    }
+
+    /// Reads all values described by a signature with any number of complete types.
+    fn values(&mut self, signature: &str) -> Result<Vec<Value>> {
+        split_signature(signature)?.iter().map(|t| self.value(t)).collect()
+    }
+
+    /// Reads a single value of a single complete type.
+    fn value(&mut self, signature: &str) -> Result<Value> {
+        let code = *signature.as_bytes().first().context("Malformed D-Bus signature")?;
+        Ok(match code {
+            b'y' => Value::Byte(self.take(1)?[0]),
+            b'b' => Value::Bool(self.u32()? != 0),
+            b'n' | b'q' => {
+                self.align(2);
+                let bytes = self.take(2)?;
+                Value::I32(i16::from_le_bytes([bytes[0], bytes[1]]) as i32)
+            }
+            b'i' => Value::I32(self.u32()? as i32),
+            b'u' | b'h' => Value::U32(self.u32()?),
+            b'x' => Value::I64(self.u64()? as i64),
+            b't' => Value::U64(self.u64()?),
+            b'd' => Value::Double(f64::from_bits(self.u64()?)),
+            b's' => Value::Str(self.string()?),
+            b'o' => Value::Path(self.string()?),
+            b'g' => Value::Signature(self.signature()?),
+            b'v' => {
+                let inner = self.signature()?;
+                Value::variant(self.value(&inner)?)
+            }
+            b'a' => {
+                let element = &signature[1..];
+                let len = self.u32()? as usize;
+                self.align(alignment(*element.as_bytes().first().context("Malformed D-Bus signature")?));
+                let end = self.pos + len;
+                let mut items = Vec::new();
+                while self.pos < end {
+                    let start = self.pos;
+                    items.push(self.value(element)?);
+                    // Elements like `()` take no bytes, and would repeat forever.
+                    if self.pos == start {
+                        anyhow::bail!("D-Bus array of empty elements");
+                    }
+                }
+                Value::Array(element.to_string(), items)
+            }
+            b'(' => {
+                self.align(8);
+                Value::Struct(self.values(contents(signature, ')')?)?)
+            }
+            b'{' => {
+                self.align(8);
+                let mut fields = self.values(contents(signature, '}')?)?.into_iter();
+                let key = fields.next().context("Empty D-Bus dict entry")?;
+                let value = fields.next().context("D-Bus dict entry without a value")?;
+                Value::DictEntry(Box::new(key), Box::new(value))
+            }
+            other => anyhow::bail!("Unsupported D-Bus type '{}'", other as char),
+        })
+    }
+}
+
+/// Returns what is between the brackets of a struct or dict entry signature.
+fn contents(signature: &str, close: char) -> Result<&str> {
+    signature
+    .get(1..)
+    .and_then(|rest| rest.strip_suffix(close))
+    .context("Malformed D-Bus signature")
+}
+
+/// Splits a signature into its complete types, e.g. `"sa{sv}u"` into `["s", "a{sv}", "u"]`.
+fn split_signature(signature: &str) -> Result<Vec<&str>> {
+    // Type codes are ASCII, so the types can be sliced out byte by byte.
+    if !signature.is_ascii() {
+        anyhow::bail!("Malformed D-Bus signature");
+    }
+    let bytes = signature.as_bytes();
+    let mut types = Vec::new();
+    let mut start = 0;
+
+    while start < bytes.len() {
+        let mut end = start;
+        while bytes.get(end) == Some(&b'a') {
+            end += 1;
+        }
+        if matches!(bytes.get(end).context("Malformed D-Bus signature")?, b'(' | b'{') {
+            let mut depth = 0;
+            loop {
+                match bytes.get(end).context("Unbalanced D-Bus signature")? {
+                    b'(' | b'{' => depth += 1,
+                    b')' | b'}' => depth -= 1,
+                    _ => {}
+                }
+                end += 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+        } else {
+            end += 1;
+        }
+        types.push(&signature[start..end]);
+        start = end;
+    }
+
+    Ok(types)
+}
+
+// --- Messages ---
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageType {
+    MethodCall = 1,
+    MethodReturn = 2,
+    Error = 3,
+    Signal = 4,
+}
+
+/// A D-Bus message with the header fields we care about.
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub kind: MessageType,
+    pub serial: u32,
+    pub path: Option<String>,
+    pub interface: Option<String>,
+    pub member: Option<String>,
+    pub error_name: Option<String>,
+    pub reply_serial: Option<u32>,
+    pub destination: Option<String>,
+    pub sender: Option<String>,
+    pub body: Vec<Value>,
+}
+
+impl Message {
+    fn new(kind: MessageType) -> Self {
+        Message {
+            kind,
+            serial: 0,
+            path: None,
+            interface: None,
+            member: None,
+            error_name: None,
+            reply_serial: None,
+            destination: None,
+            sender: None,
+            body: Vec::new(),
+        }
+    }
+
+    /// Returns true if this is a signal with the given interface and member.
+    pub fn is_signal(&self, interface: &str, member: &str) -> bool {
+        self.kind == MessageType::Signal
+        && self.interface.as_deref() == Some(interface)
+        && self.member.as_deref() == Some(member)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut body = Writer { buf: Vec::new() };
+        for value in &self.body {
+            body.value(value);
+        }
+        let signature: String = self.body.iter().map(Value::signature).collect();
+
+        let mut fields = Vec::new();
+        let mut field = |code: u8, value: Value| {
+            fields.push(Value::Struct(vec![Value::Byte(code), Value::variant(value)]));
+        };
+        if let Some(path) = &self.path {
+            field(1, Value::Path(path.clone()));
+        }
+        if let Some(interface) = &self.interface {
+            field(2, Value::str(interface));
+        }
+        if let Some(member) = &self.member {
+            field(3, Value::str(member));
+        }
+        if let Some(error_name) = &self.error_name {
+            field(4, Value::str(error_name));
+        }
+        if let Some(reply_serial) = self.reply_serial {
+            field(5, Value::U32(reply_serial));
+        }
+        if let Some(destination) = &self.destination {
+            field(6, Value::str(destination));
+        }
+        if let Some(sender) = &self.sender {
+            field(7, Value::str(sender));
+        }
+        if !signature.is_empty() {
+            field(8, Value::Signature(signature));
+        }
+
+        let mut header = Writer { buf: vec![b'l', self.kind as u8, 0, 1] };
+        header.u32(body.buf.len() as u32);
+        header.u32(self.serial);
+        header.value(&Value::Array("(yv)".into(), fields));
+        header.pad(8);
+        header.buf.extend_from_slice(&body.buf);
+        header.buf
+    }
+
+    fn decode(header: &[u8], body: &[u8]) -> Result<Message> {
+        let kind = match header[1] {
+            1 => MessageType::MethodCall,
+            2 => MessageType::MethodReturn,
+            3 => MessageType::Error,
+            4 => MessageType::Signal,
+            other => anyhow::bail!("Unknown D-Bus message type {}", other),
+        };
+
+        let mut reader = Reader { buf: header, pos: 8 };
+        let mut message = Message::new(kind);
+        message.serial = reader.u32()?;
+
+        let mut signature = String::new();
+        if let Value::Array(_, fields) = reader.value("a(yv)")? {
+            for field in fields {
+                let Value::Struct(parts) = field else { continue };
+                let (Value::Byte(code), value) = (&parts[0], &parts[1]) else { continue };
+                match code {
+                    1 => message.path = value.as_str().map(String::from),
+                    2 => message.interface = value.as_str().map(String::from),
+                    3 => message.member = value.as_str().map(String::from),
+                    4 => message.error_name = value.as_str().map(String::from),
+                    5 => message.reply_serial = value.as_u32(),
+                    6 => message.destination = value.as_str().map(String::from),
+                    7 => message.sender = value.as_str().map(String::from),
+                    8 => signature = value.as_str().unwrap_or_default().to_string(),
+                    _ => {}
+                }
+            }
+        }
+
+        message.body = Reader { buf: body, pos: 0 }.values(&signature)?;
+        Ok(message)
+    }
+}
+
+// --- Connection ---
+
+/// The largest message the specification allows, so a broken peer can't make us
+/// allocate without bound.
+const MAX_MESSAGE_LEN: usize = 128 * 1024 * 1024;
+
+/// A connection to the session bus.
+pub struct Connection {
+    stream: BufReader<UnixStream>,
+    serial: u32,
+    /// Messages received while waiting for a method reply, delivered by `next_message`.
+    queue: VecDeque<Message>,
+}
+
+impl Connection {
+    /// Connects and authenticates to the session bus.
+    pub async fn session() -> Result<Connection> {
+        let stream = connect_session_socket().await.context("Could not connect to the D-Bus session bus")?;
+        let mut stream = BufReader::new(stream);
+
+        // SASL EXTERNAL authentication with our uid, hex-encoded as ASCII.
+        // SAFETY: getuid never fails and has no preconditions.
+        let uid = unsafe { libc::getuid() }.to_string();
+        let hex_uid: String = uid.bytes().map(|b| format!("{:02x}", b)).collect();
+        stream.get_mut().write_all(format!("\0AUTH EXTERNAL {}\r\n", hex_uid).as_bytes()).await?;
+
+        let mut line = String::new();
+        stream.read_line(&mut line).await?;
+        if !line.starts_with("OK ") {
+            anyhow::bail!("D-Bus authentication failed: {}", line.trim());
+        }
+        stream.get_mut().write_all(b"BEGIN\r\n").await?;
+
+        let mut connection = Connection { stream, serial: 0, queue: VecDeque::new() };
+        connection.call("org.freedesktop.DBus", "/org/freedesktop/DBus", "org.freedesktop.DBus", "Hello", vec![]).await?;
+
+        Ok(connection)
+    }
+
+    /// Calls a method and waits for its reply. D-Bus errors are returned as `Err`.
+    pub async fn call(&mut self, destination: &str, path: &str, interface: &str, member: &str, args: Vec<Value>) -> Result<Vec<Value>> {
+        let mut message = Message::new(MessageType::MethodCall);
+        message.destination = Some(destination.into());
+        message.path = Some(path.into());
+        message.interface = Some(interface.into());
+        message.member = Some(member.into());
+        message.body = args;
+        let serial = self.send(message).await?;
+
+        loop {
+            let reply = self.read_message().await?;
+            if reply.reply_serial != Some(serial) {
+                self.queue.push_back(reply);
+                continue;
+            }
+            return match reply.kind {
+                MessageType::Error => {
+                    let detail = reply.body.first().and_then(Value::as_str).unwrap_or_default();
+                    anyhow::bail!("{}.{} failed: {} {}", interface, member, reply.error_name.unwrap_or_default(), detail)
+                }
+                _ => Ok(reply.body),
+            };
+        }
+    }
+
+    /// Subscribes to messages matching a match rule, e.g. signals from a single interface.
+    pub async fn add_match(&mut self, rule: &str) -> Result<()> {
+        self.call("org.freedesktop.DBus", "/org/freedesktop/DBus", "org.freedesktop.DBus", "AddMatch", vec![Value::str(rule)]).await?;
+        Ok(())
+    }
+
+    /// Asks the bus to give this connection a well-known name, like `org.kde.StatusNotifierItem-…`.
+    pub async fn request_name(&mut self, name: &str) -> Result<()> {
+        // DBUS_NAME_FLAG_DO_NOT_QUEUE: fail instead of waiting for the name.
+        let reply = self.call("org.freedesktop.DBus", "/org/freedesktop/DBus", "org.freedesktop.DBus", "RequestName", vec![Value::str(name), Value::U32(4)]).await?;
+        // 1 is DBUS_REQUEST_NAME_REPLY_PRIMARY_OWNER.
+        if reply.first().and_then(Value::as_u32) != Some(1) {
+            anyhow::bail!("The D-Bus name {} is already taken", name);
+        }
+        Ok(())
+    }
+
+    /// Answers a method call made to one of our objects.
+    pub async fn reply(&mut self, call: &Message, body: Vec<Value>) -> Result<()> {
+        let mut message = Message::new(MessageType::MethodReturn);
+        message.reply_serial = Some(call.serial);
+        message.destination = call.sender.clone();
+        message.body = body;
+        self.send(message).await?;
+        Ok(())
+    }
+
+    /// Answers a method call made to one of our objects with an error.
+    pub async fn reply_error(&mut self, call: &Message, name: &str, text: &str) -> Result<()> {
+        let mut message = Message::new(MessageType::Error);
+        message.reply_serial = Some(call.serial);
+        message.destination = call.sender.clone();
+        message.error_name = Some(name.into());
+        message.body = vec![Value::str(text)];
+        self.send(message).await?;
+        Ok(())
+    }
+
+    /// Returns the next incoming message that isn't a reply to one of our calls.
+    pub async fn next_message(&mut self) -> Result<Message> {
+        if let Some(message) = self.queue.pop_front() {
+            return Ok(message);
+        }
+        self.read_message().await
+    }
+
+    /// Sends a message, assigning it the next serial. Returns that serial.
+    pub async fn send(&mut self, mut message: Message) -> Result<u32> {
+        self.serial += 1;
+        message.serial = self.serial;
+        self.stream.get_mut().write_all(&message.encode()).await?;
+        Ok(self.serial)
+    }
+
+    async fn read_message(&mut self) -> Result<Message> {
+        read_message(&mut self.stream).await
+    }
+
+    /// Gives up the connection so another process can carry on with it. Returns the socket,
+    /// the serial of the last message sent, and the messages received but not handled yet,
+    /// in wire format.
+    pub async fn detach(mut self) -> Result<(OwnedFd, u32, Vec<u8>)> {
+        // A message read only partly would be cut in half, so it is finished first.
+        while !self.stream.buffer().is_empty() {
+            let message = self.read_message().await?;
+            self.queue.push_back(message);
+        }
+        let pending = self.queue.iter().flat_map(Message::encode).collect();
+        let socket = self.stream.into_inner().into_std()?;
+        Ok((socket.into(), self.serial, pending))
+    }
+
+    /// Carries on with a connection another process gave up with `detach`.
+    pub async fn adopt(socket: OwnedFd, serial: u32, mut pending: &[u8]) -> Result<Connection> {
+        let mut queue = VecDeque::new();
+        while !pending.is_empty() {
+            queue.push_back(read_message(&mut pending).await?);
+        }
+        let socket = std::os::unix::net::UnixStream::from(socket);
+        socket.set_nonblocking(true)?;
+        Ok(Connection { stream: BufReader::new(UnixStream::from_std(socket)?), serial, queue })
+    }
+}
+
+/// Reads one message in wire format.
+async fn read_message(reader: &mut (impl AsyncRead + Unpin)) -> Result<Message> {
+    let mut fixed = [0u8; 16];
+    reader.read_exact(&mut fixed).await.context("D-Bus connection closed")?;
+    if fixed[0] != b'l' {
+        anyhow::bail!("Big-endian D-Bus messages are not supported");
+    }
+
+    let body_len = u32::from_le_bytes(fixed[4..8].try_into()?) as usize;
+    let fields_len = u32::from_le_bytes(fixed[12..16].try_into()?) as usize;
+    let header_len = (16 + fields_len).div_ceil(8) * 8;
+    if header_len + body_len > MAX_MESSAGE_LEN {
+        anyhow::bail!("The D-Bus message is larger than the 128 MiB the specification allows");
+    }
+
+    let mut header = fixed.to_vec();
+    header.resize(header_len, 0);
+    reader.read_exact(&mut header[16..]).await?;
+    let mut body = vec![0u8; body_len];
+    reader.read_exact(&mut body).await?;
+
+    Message::decode(&header, &body)
+}
+
+/// Opens the socket named by `DBUS_SESSION_BUS_ADDRESS`, or the conventional `$XDG_RUNTIME_DIR/bus`.
+async fn connect_session_socket() -> Result<UnixStream> {
+    let address = std::env::var("DBUS_SESSION_BUS_ADDRESS")
+    .unwrap_or_else(|_| format!("unix:path={}/bus", dirs::runtime_dir()));
+
+    // An address may list several alternatives separated by ';'.
+    for candidate in address.split(';') {
+        let Some(params) = candidate.strip_prefix("unix:") else { continue };
+        for param in params.split(',') {
+            if let Some(path) = param.strip_prefix("path=") {
+                if let Ok(stream) = UnixStream::connect(unescape_address(path)).await {
+                    return Ok(stream);
+                }
+            } else if let Some(name) = param.strip_prefix("abstract=") {
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(unescape_address(name))?;
+                if let Ok(stream) = std::os::unix::net::UnixStream::connect_addr(&addr) {
+                    stream.set_nonblocking(true)?;
+                    return Ok(UnixStream::from_std(stream)?);
+                }
+            }
+        }
+    }
+
+    anyhow::bail!("No usable unix socket in bus address {}", address)
+}
+
+/// Decodes the `%xx` escapes allowed in D-Bus addresses.
+fn unescape_address(value: &str) -> String {
+    let mut out = Vec::new();
+    let bytes = value.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let digits = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(b) = digits.and_then(|digits| u8::from_str_radix(digits, 16).ok()) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marshal(values: &[Value]) -> Vec<u8> {
+        let mut writer = Writer { buf: Vec::new() };
+        for value in values {
+            writer.value(value);
+        }
+        writer.buf
+    }
+
+    fn unmarshal(bytes: &[u8], signature: &str) -> Result<Vec<Value>> {
+        Reader { buf: bytes, pos: 0 }.values(signature)
+    }
+
+    fn signal(member: &str, body: Vec<Value>) -> Message {
+        let mut message = Message::new(MessageType::Signal);
+        message.serial = 7;
+        message.path = Some("/org/freedesktop/Notifications".into());
+        message.interface = Some("org.freedesktop.Notifications".into());
+        message.member = Some(member.into());
+        message.sender = Some(":1.42".into());
+        message.body = body;
+        message
+    }
+
+    #[test]
+    fn values_round_trip() {
+        let values = vec![
+            Value::Byte(1),
+            Value::U64(u64::MAX),
+            Value::Bool(true),
+            Value::Byte(2),
+            Value::Double(-0.5),
+            Value::I32(-7),
+            Value::I64(i64::MIN),
+            Value::str("héllo"),
+            Value::Path("/a/b".into()),
+            Value::Signature("a{sv}".into()),
+            Value::dict(vec![
+                ("hint".into(), Value::Byte(3)),
+                ("list".into(), Value::string_array(["x", "y"])),
+                ("image".into(), Value::Struct(vec![Value::I32(1), Value::bytes(&[1, 2, 3])])),
+            ]),
+            Value::Array("(yt)".into(), vec![]),
+            Value::Array("(yt)".into(), vec![Value::Struct(vec![Value::Byte(9), Value::U64(10)])]),
+            Value::Array("ai".into(), vec![Value::Array("i".into(), vec![Value::I32(1)]), Value::Array("i".into(), vec![])]),
+            Value::variant(Value::variant(Value::U32(5))),
+        ];
+        let signature: String = values.iter().map(Value::signature).collect();
+        assert_eq!(unmarshal(&marshal(&values), &signature).unwrap(), values);
+        assert_eq!(values[10].get("hint"), Some(&Value::Byte(3)));
+    }
+
+    #[tokio::test]
+    async fn messages_round_trip() {
+        let message = signal("ActionInvoked", vec![Value::U32(3), Value::str("default")]);
+        let bytes = message.encode();
+
+        let decoded = read_message(&mut &bytes[..]).await.unwrap();
+        assert!(decoded.is_signal("org.freedesktop.Notifications", "ActionInvoked"));
+        assert_eq!(decoded.serial, 7);
+        assert_eq!(decoded.path, message.path);
+        assert_eq!(decoded.sender.as_deref(), Some(":1.42"));
+        assert_eq!(decoded.body, message.body);
+    }
+
+    #[tokio::test]
+    async fn rejects_truncated_messages() {
+        let bytes = signal("NotificationClosed", vec![Value::U32(3), Value::U32(2)]).encode();
+        for len in 0..bytes.len() {
+            assert!(read_message(&mut &bytes[..len]).await.is_err(), "{} of {} bytes", len, bytes.len());
+        }
+        let values = [Value::str("text"), Value::string_array(["a", "b"])];
+        let bytes = marshal(&values);
+        for len in 0..bytes.len() {
+            assert!(unmarshal(&bytes[..len], "sas").is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_messages() {
+        let mut big_endian = signal("Closed", vec![]).encode();
+        big_endian[0] = b'B';
+        assert!(read_message(&mut &big_endian[..]).await.is_err());
+
+        let mut unknown_type = signal("Closed", vec![]).encode();
+        unknown_type[1] = 9;
+        assert!(read_message(&mut &unknown_type[..]).await.is_err());
+
+        // A body length past the limit is refused before anything is allocated.
+        let mut oversized = signal("Closed", vec![]).encode();
+        oversized[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read_message(&mut &oversized[..]).await.is_err());
+
+        // A string that isn't UTF-8, and one without its terminating byte.
+        assert!(unmarshal(&[2, 0, 0, 0, 0xff, 0xfe, 0], "s").is_err());
+        assert!(unmarshal(&[2, 0, 0, 0, b'a', b'b'], "s").is_err());
+        // An array claiming more bytes than there are.
+        assert!(unmarshal(&[8, 0, 0, 0, 1, 0, 0, 0], "au").is_err());
+    }
+
+    #[test]
+    fn rejects_arrays_of_empty_elements() {
+        // Four bytes of a `()` array, which would never be used up. Decoded on a thread,
+        // so a regression fails the test instead of hanging it.
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let _ = sender.send(unmarshal(&[4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], "a()").is_err());
+        });
+        assert_eq!(receiver.recv_timeout(std::time::Duration::from_secs(5)), Ok(true));
+    }
+
+    #[test]
+    fn rejects_malformed_signatures() {
+        for signature in ["a", "(i", "i)", "{s", "a{", "(", "\u{e9}", "z", "{}", "{s}"] {
+            assert!(unmarshal(&[0; 64], signature).is_err(), "{:?}", signature);
+        }
+        assert_eq!(split_signature("sa{sv}a(ii)u").unwrap(), ["s", "a{sv}", "a(ii)", "u"]);
+        assert!(split_signature("aa").is_err());
+        assert!(split_signature("a{sv").is_err());
+    }
+
+    #[test]
+    fn unescapes_addresses() {
+        assert_eq!(unescape_address("/run/user/1000/bus"), "/run/user/1000/bus");
+        assert_eq!(unescape_address("/tmp/a%20b%2c"), "/tmp/a b,");
+        // Broken escapes are kept as they are.
+        assert_eq!(unescape_address("%zz%4"), "%zz%4");
+        assert_eq!(unescape_address("%\u{e9}x"), "%\u{e9}x");
+    }
+
+    #[tokio::test]
+    async fn detached_connections_keep_unread_messages() {
+        let (ours, mut peer) = UnixStream::pair().unwrap();
+        let mut connection = Connection { stream: BufReader::new(ours), serial: 5, queue: VecDeque::new() };
+        let mut sent = Vec::new();
+        for id in 1..=3 {
+            sent.extend(signal("ActionInvoked", vec![Value::U32(id), Value::str("open")]).encode());
+        }
+        peer.write_all(&sent).await.unwrap();
+
+        // The first read buffers the other two messages as well.
+        let first = connection.next_message().await.unwrap();
+        assert_eq!(first.body[0], Value::U32(1));
+        connection.queue.push_back(signal("Queued", vec![]));
+
+        let (socket, serial, pending) = connection.detach().await.unwrap();
+        let mut adopted = Connection::adopt(socket, serial, &pending).await.unwrap();
+        assert_eq!(adopted.serial, 5);
+        assert_eq!(adopted.next_message().await.unwrap().member.as_deref(), Some("Queued"));
+        for id in 2..=3 {
+            assert_eq!(adopted.next_message().await.unwrap().body[0], Value::U32(id));
+        }
+
+        // The adopted connection reads on from the same socket.
+        peer.write_all(&signal("NotificationClosed", vec![Value::U32(4)]).encode()).await.unwrap();
+        assert_eq!(adopted.next_message().await.unwrap().member.as_deref(), Some("NotificationClosed"));
+    }
+}
@@ -0,0 +1,555 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use crate::inflate::{CODE_LENGTH_ORDER, DISTANCE_BASE, DISTANCE_EXTRA, LENGTH_BASE, LENGTH_EXTRA, WINDOW_SIZE};
+
+// --- DEFLATE Compression (RFC 1950, RFC 1951) ---
+//
+// A streaming zlib compressor. Input is matched against the previous 32 KiB and written
+// out as dynamic Huffman blocks as it arrives, so no more than a block of it is held at
+// once, however large the image being encoded.
+
+/// Input collected before it is compressed as one block.
+const BLOCK_SIZE: usize = 128 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+/// How many earlier occurrences of the same three bytes are tried for a match.
+const MAX_CHAIN: usize = 16;
+/// A match at least this long is taken without looking for a longer one.
+const NICE_MATCH: usize = 64;
+const MAX_CODE_LENGTH: u8 = 15;
+const MAX_CODE_LENGTH_CODE_LENGTH: u8 = 7;
+
+/// Writes bits least significant bit first, as DEFLATE stores them.
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn bits(&mut self, value: u32, n: u32) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Writes a Huffman code, which is stored most significant bit first.
+    fn code(&mut self, (code, length): (u16, u8)) {
+        self.bits(code.reverse_bits() as u32 >> (16 - length as u32), length as u32);
+    }
+
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.bits(0, 8 - self.count);
+        }
+    }
+}
+
+/// A literal byte, or a back-reference of `length` bytes `distance` bytes back.
+#[derive(Clone, Copy)]
+enum Token {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+/// Compresses a stream into the zlib format, as found in PNG image data.
+pub struct ZlibEncoder {
+    /// The last `WINDOW_SIZE` bytes already compressed, followed by the input that isn't.
+    data: Vec<u8>,
+    /// Position in the whole stream of `data[0]`.
+    base: usize,
+    /// Index in `data` of the first byte not compressed yet.
+    pending: usize,
+    /// For every hash of three bytes, the stream position after their latest occurrence, or 0.
+    head: Vec<usize>,
+    /// For every position in the window, the same for the occurrence before it.
+    prev: Vec<usize>,
+    adler: (u32, u32),
+    out: BitWriter,
+}
+
+impl ZlibEncoder {
+    pub fn new() -> ZlibEncoder {
+        let mut out = BitWriter { out: Vec::new(), buffer: 0, count: 0 };
+        // Deflate with a 32 KiB window, default compression level.
+        out.out.extend_from_slice(&[0x78, 0x9c]);
+        ZlibEncoder {
+            data: Vec::with_capacity(WINDOW_SIZE + BLOCK_SIZE + MAX_MATCH),
+            base: 0,
+            pending: 0,
+            head: vec![0; 1 << HASH_BITS],
+            prev: vec![0; WINDOW_SIZE],
+            adler: (1, 0),
+            out,
+        }
+    }
+
+    pub fn write(&mut self, input: &[u8]) {
+        // Sums of up to 5552 bytes can't overflow before they are reduced.
+        for chunk in input.chunks(5552) {
+            let (mut a, mut b) = self.adler;
+            for &byte in chunk {
+                a += byte as u32;
+                b += a;
+            }
+            self.adler = (a % 65521, b % 65521);
+        }
+
+        self.data.extend_from_slice(input);
+        if self.data.len() - self.pending >= BLOCK_SIZE {
+            self.compress_pending(false);
+        }
+    }
+
+    /// Takes the compressed bytes written so far.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.out.out)
+    }
+
+    /// Compresses the rest of the input and returns the remaining compressed bytes.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.compress_pending(true);
+        self.out.align();
+        let (a, b) = self.adler;
+        self.out.out.extend_from_slice(&((b << 16) | a).to_be_bytes());
+        self.out.out
+    }
+
+    fn hash(&self, index: usize) -> usize {
+        let bytes = u32::from_le_bytes([self.data[index], self.data[index + 1], self.data[index + 2], 0]);
+        (bytes.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+    }
+
+    /// Records the three bytes at `index` so later input can refer back to them.
+    fn insert(&mut self, index: usize) {
+        if index + MIN_MATCH > self.data.len() {
+            return;
+        }
+        let hash = self.hash(index);
+        let position = self.base + index;
+        self.prev[position % WINDOW_SIZE] = self.head[hash];
+        self.head[hash] = position + 1;
+    }
+
+    /// Returns the longest earlier match for the input at `index`, as `(length, distance)`.
+    fn longest_match(&self, index: usize) -> (usize, usize) {
+        let max_length = MAX_MATCH.min(self.data.len() - index);
+        if max_length < MIN_MATCH {
+            return (0, 0);
+        }
+        let position = self.base + index;
+        let mut best = (0, 0);
+        let mut candidate = self.head[self.hash(index)];
+        for _ in 0..MAX_CHAIN {
+            let Some(earlier) = candidate.checked_sub(1) else { break };
+            if earlier >= position || position - earlier > WINDOW_SIZE || earlier < self.base {
+                break;
+            }
+
+            let start = earlier - self.base;
+            if self.data[start + best.0] == self.data[index + best.0] {
+                let length = self.data[start..start + max_length]
+                .iter()
+                .zip(&self.data[index..index + max_length])
+                .take_while(|(a, b)| a == b)
+                .count();
+                if length > best.0 {
+                    best = (length, position - earlier);
+                    if length >= NICE_MATCH.min(max_length) {
+                        break;
+                    }
+                }
+            }
+            candidate = self.prev[earlier % WINDOW_SIZE];
+        }
+        if best.0 < MIN_MATCH { (0, 0) } else { best }
+    }
+
+    fn compress_pending(&mut self, last: bool) {
+        let mut tokens = Vec::with_capacity(self.data.len() - self.pending);
+        let mut index = self.pending;
+        while index < self.data.len() {
+            let (length, distance) = self.longest_match(index);
+            if length == 0 {
+                tokens.push(Token::Literal(self.data[index]));
+                self.insert(index);
+                index += 1;
+            } else {
+                tokens.push(Token::Match { length: length as u16, distance: distance as u16 });
+                for skipped in index..index + length {
+                    self.insert(skipped);
+                }
+                index += length;
+            }
+        }
+        self.pending = index;
+        write_block(&mut self.out, &tokens, last);
+
+        // Only the window is needed for the input still to come.
+        if self.pending > WINDOW_SIZE {
+            let dropped = self.pending - WINDOW_SIZE;
+            self.data.drain(..dropped);
+            self.base += dropped;
+            self.pending = WINDOW_SIZE;
+        }
+    }
+}
+
+/// Returns the length code index and the distance code index of a match.
+fn match_codes(length: u16, distance: u16) -> (usize, usize) {
+    let length_index = LENGTH_BASE.partition_point(|&base| base <= length) - 1;
+    let distance_index = DISTANCE_BASE.partition_point(|&base| base <= distance) - 1;
+    (length_index, distance_index)
+}
+
+/// Writes `tokens` as one block with Huffman codes built for them.
+fn write_block(out: &mut BitWriter, tokens: &[Token], last: bool) {
+    let mut literal_counts = [0u32; 286];
+    let mut distance_counts = [0u32; 30];
+    literal_counts[256] = 1;
+    for &token in tokens {
+        match token {
+            Token::Literal(byte) => literal_counts[byte as usize] += 1,
+            Token::Match { length, distance } => {
+                let (length_index, distance_index) = match_codes(length, distance);
+                literal_counts[257 + length_index] += 1;
+                distance_counts[distance_index] += 1;
+            }
+        }
+    }
+    let literal_lengths = code_lengths(&literal_counts, MAX_CODE_LENGTH);
+    let distance_lengths = code_lengths(&distance_counts, MAX_CODE_LENGTH);
+    let literal_count = 257.max(literal_lengths.iter().rposition(|&length| length > 0).map_or(0, |last| last + 1));
+    let distance_count = 1.max(distance_lengths.iter().rposition(|&length| length > 0).map_or(0, |last| last + 1));
+
+    // The code lengths are themselves run-length encoded and Huffman coded.
+    let lengths: Vec<u8> = literal_lengths[..literal_count].iter().chain(&distance_lengths[..distance_count]).copied().collect();
+    let runs = run_lengths(&lengths);
+    let mut code_length_counts = [0u32; 19];
+    for &(symbol, _, _) in &runs {
+        code_length_counts[symbol as usize] += 1;
+    }
+    let code_length_lengths = code_lengths(&code_length_counts, MAX_CODE_LENGTH_CODE_LENGTH);
+    let code_length_count = 4.max(CODE_LENGTH_ORDER.iter().rposition(|&index| code_length_lengths[index] > 0).map_or(0, |last| last + 1));
+
+    out.bits(last as u32, 1);
+    out.bits(2, 2);
+    out.bits((literal_count - 257) as u32, 5);
+    out.bits((distance_count - 1) as u32, 5);
+    out.bits((code_length_count - 4) as u32, 4);
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        out.bits(code_length_lengths[index] as u32, 3);
+    }
+
+    let code_length_codes = canonical_codes(&code_length_lengths);
+    for &(symbol, extra, extra_bits) in &runs {
+        out.code(code_length_codes[symbol as usize]);
+        out.bits(extra as u32, extra_bits as u32);
+    }
+
+    let literal_codes = canonical_codes(&literal_lengths);
+    let distance_codes = canonical_codes(&distance_lengths);
+    for &token in tokens {
+        match token {
+            Token::Literal(byte) => out.code(literal_codes[byte as usize]),
+            Token::Match { length, distance } => {
+                let (length_index, distance_index) = match_codes(length, distance);
+                out.code(literal_codes[257 + length_index]);
+                out.bits((length - LENGTH_BASE[length_index]) as u32, LENGTH_EXTRA[length_index] as u32);
+                out.code(distance_codes[distance_index]);
+                out.bits((distance - DISTANCE_BASE[distance_index]) as u32, DISTANCE_EXTRA[distance_index] as u32);
+            }
+        }
+    }
+    out.code(literal_codes[256]);
+}
+
+/// Run-length encodes code lengths as `(symbol, extra bits value, extra bit count)`.
+fn run_lengths(lengths: &[u8]) -> Vec<(u8, u8, u8)> {
+    let mut runs = Vec::new();
+    let mut index = 0;
+    while index < lengths.len() {
+        let length = lengths[index];
+        let run = lengths[index..].iter().take_while(|&&other| other == length).count();
+        let mut remaining = run;
+        if length == 0 {
+            while remaining >= 11 {
+                let repeat = remaining.min(138);
+                runs.push((18, (repeat - 11) as u8, 7));
+                remaining -= repeat;
+            }
+            if remaining >= 3 {
+                runs.push((17, (remaining - 3) as u8, 3));
+                remaining = 0;
+            }
+        } else {
+            runs.push((length, 0, 0));
+            remaining -= 1;
+            while remaining >= 3 {
+                let repeat = remaining.min(6);
+                runs.push((16, (repeat - 3) as u8, 2));
+                remaining -= repeat;
+            }
+        }
+        runs.extend(std::iter::repeat_n((length, 0, 0), remaining));
+        index += run;
+    }
+    runs
+}
+
+/// Builds Huffman code lengths of at most `limit` bits for symbols occurring `counts` times.
+fn code_lengths(counts: &[u32], limit: u8) -> Vec<u8> {
+    let mut lengths = vec![0u8; counts.len()];
+    let used: Vec<usize> = (0..counts.len()).filter(|&symbol| counts[symbol] > 0).collect();
+    match used.len() {
+        0 => return lengths,
+        // A code needs two symbols to be complete, which some decoders insist on.
+        1 => {
+            lengths[used[0]] = 1;
+            lengths[if used[0] == 0 { 1 } else { 0 }] = 1;
+            return lengths;
+        }
+        _ => {}
+    }
+
+    // Rare symbols are made less rare until the longest code fits the limit.
+    let mut counts: Vec<u64> = counts.iter().map(|&count| count as u64).collect();
+    loop {
+        // Nodes are the used symbols, then the internal nodes in the order they are made.
+        let mut parents = vec![usize::MAX; used.len() * 2 - 1];
+        let mut heap: BinaryHeap<Reverse<(u64, usize)>> = used.iter().enumerate().map(|(node, &symbol)| Reverse((counts[symbol], node))).collect();
+        let mut next = used.len();
+        while let (Some(Reverse((a, left))), Some(Reverse((b, right)))) = (heap.pop(), heap.pop()) {
+            parents[left] = next;
+            parents[right] = next;
+            heap.push(Reverse((a + b, next)));
+            next += 1;
+        }
+
+        // Parents are made after their children, so depths can be filled in from the root.
+        let mut depths = vec![0u8; parents.len()];
+        for node in (0..parents.len() - 1).rev() {
+            depths[node] = depths[parents[node]] + 1;
+        }
+        if depths[..used.len()].iter().all(|&depth| depth <= limit) {
+            for (node, &symbol) in used.iter().enumerate() {
+                lengths[symbol] = depths[node];
+            }
+            return lengths;
+        }
+        for &symbol in &used {
+            counts[symbol] = counts[symbol].div_ceil(2);
+        }
+    }
+}
+
+/// Assigns canonical Huffman codes to code lengths, as `(code, length)`.
+fn canonical_codes(lengths: &[u8]) -> Vec<(u16, u8)> {
+    let mut length_counts = [0u16; 16];
+    for &length in lengths {
+        length_counts[length as usize] += 1;
+    }
+    length_counts[0] = 0;
+
+    let mut next_code = [0u16; 16];
+    let mut code = 0;
+    for length in 1..16 {
+        code = (code + length_counts[length - 1]) << 1;
+        next_code[length] = code;
+    }
+
+    lengths
+    .iter()
+    .map(|&length| {
+        if length == 0 {
+            return (0, 0);
+        }
+        let code = next_code[length as usize];
+        next_code[length as usize] += 1;
+        (code, length)
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inflate;
+
+    fn inflate_all(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        inflate::inflate(data, &mut |piece| {
+            out.extend_from_slice(piece);
+            Ok(())
+        })
+        .unwrap();
+        out
+    }
+
+    fn zlib_round_trip(input: &[u8]) {
+        let mut encoder = ZlibEncoder::new();
+        // Written in uneven pieces, the way PNG rows arrive.
+        for piece in input.chunks(1000) {
+            encoder.write(piece);
+        }
+        let compressed = encoder.finish();
+
+        let mut out = Vec::new();
+        inflate::zlib_decompress(&compressed, &mut |piece| {
+            out.extend_from_slice(piece);
+            Ok(())
+        })
+        .unwrap();
+        assert!(out == input, "{} bytes came back as {}", input.len(), out.len());
+    }
+
+    /// Bytes from a fixed linear congruential generator, which hardly compress.
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 12345u32;
+        (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect()
+    }
+
+    /// Writes a dynamic block of literals with the given literal/length code lengths, which
+    /// need not be ones `code_lengths` would pick.
+    fn literal_block(out: &mut BitWriter, literal_lengths: &[u8], literals: &[u8], last: bool) {
+        // A single distance code, and every code length code 5 bits long.
+        let lengths: Vec<u8> = literal_lengths.iter().chain(&[1]).copied().collect();
+        out.bits(last as u32, 1);
+        out.bits(2, 2);
+        out.bits((literal_lengths.len() - 257) as u32, 5);
+        out.bits(0, 5);
+        out.bits(19 - 4, 4);
+        for _ in 0..19 {
+            out.bits(5, 3);
+        }
+        let code_length_codes = canonical_codes(&[5; 19]);
+        for (symbol, extra, extra_bits) in run_lengths(&lengths) {
+            out.code(code_length_codes[symbol as usize]);
+            out.bits(extra as u32, extra_bits as u32);
+        }
+
+        let codes = canonical_codes(literal_lengths);
+        for &byte in literals {
+            out.code(codes[byte as usize]);
+        }
+        out.code(codes[256]);
+    }
+
+    fn stored_block(out: &mut BitWriter, data: &[u8], last: bool) {
+        out.bits(last as u32, 1);
+        out.bits(0, 2);
+        out.align();
+        out.out.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        out.out.extend_from_slice(&(!(data.len() as u16)).to_le_bytes());
+        out.out.extend_from_slice(data);
+    }
+
+    fn fixed_block(out: &mut BitWriter, literals: &[u8], last: bool) {
+        let mut lengths = [0u8; 288];
+        lengths[..144].fill(8);
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        lengths[280..].fill(8);
+        let codes = canonical_codes(&lengths);
+
+        out.bits(last as u32, 1);
+        out.bits(1, 2);
+        for &byte in literals {
+            out.code(codes[byte as usize]);
+        }
+        out.code(codes[256]);
+    }
+
+    #[test]
+    fn stored_block_after_dynamic_block() {
+        // A short end-of-block code next to 15-bit literal codes leaves the reader more
+        // than a byte ahead when the stored block starts.
+        let mut lengths = [0u8; 257];
+        lengths[256] = 1;
+        for (i, length) in (2..=15).enumerate() {
+            lengths[b'a' as usize + i] = length;
+        }
+        lengths[b'o' as usize] = 15;
+
+        let mut out = BitWriter { out: Vec::new(), buffer: 0, count: 0 };
+        literal_block(&mut out, &lengths, b"abcno", false);
+        stored_block(&mut out, b"stored", true);
+        assert_eq!(inflate_all(&out.out), b"abcnostored");
+    }
+
+    #[test]
+    fn mixed_block_types() {
+        let text = b"the quick brown fox jumps over the lazy dog; the quick brown fox jumps again";
+        let tokens: Vec<Token> = text
+        .iter()
+        .map(|&byte| Token::Literal(byte))
+        .chain([Token::Match { length: 258, distance: 4 }, Token::Match { length: 3, distance: text.len() as u16 + 258 }])
+        .collect();
+
+        let mut out = BitWriter { out: Vec::new(), buffer: 0, count: 0 };
+        write_block(&mut out, &tokens, false);
+        stored_block(&mut out, b"", false);
+        fixed_block(&mut out, b"fixed", false);
+        stored_block(&mut out, &noise(300), false);
+        write_block(&mut out, &[Token::Literal(b'!')], true);
+        out.align();
+
+        let mut expected = text.to_vec();
+        for _ in 0..258 {
+            expected.push(expected[expected.len() - 4]);
+        }
+        expected.extend_from_slice(&text[..3]);
+        expected.extend_from_slice(b"fixed");
+        expected.extend_from_slice(&noise(300));
+        expected.push(b'!');
+        assert_eq!(inflate_all(&out.out), expected);
+    }
+
+    #[test]
+    fn round_trips() {
+        zlib_round_trip(b"");
+        zlib_round_trip(b"a");
+        zlib_round_trip(&[0; 100_000]);
+        zlib_round_trip(&noise(3 * BLOCK_SIZE + 17));
+
+        // Repeats far enough apart to need the whole window, across block boundaries.
+        let mut repeated = noise(WINDOW_SIZE - 100);
+        repeated.extend_from_within(..);
+        repeated.extend_from_within(..);
+        repeated.extend_from_within(..);
+        zlib_round_trip(&repeated);
+    }
+
+    #[test]
+    fn adler32_trailer() {
+        let mut encoder = ZlibEncoder::new();
+        encoder.write(b"Wikipedia");
+        assert!(encoder.finish().ends_with(&0x11e60398u32.to_be_bytes()));
+    }
+
+    #[test]
+    fn code_lengths_respect_the_limit() {
+        // Fibonacci counts make the deepest possible tree.
+        let mut counts = vec![1u32, 1];
+        while counts.len() < 30 {
+            counts.push(counts[counts.len() - 1] + counts[counts.len() - 2]);
+        }
+        let lengths = code_lengths(&counts, 7);
+        assert!(lengths.iter().all(|&length| (1..=7).contains(&length)));
+        let kraft: f64 = lengths.iter().map(|&length| 0.5f64.powi(length as i32)).sum();
+        assert!(kraft <= 1.0);
+
+        assert_eq!(code_lengths(&[0, 5, 0], 15), [1, 1, 0]);
+        assert_eq!(code_lengths(&[0, 0], 15), [0, 0]);
+    }
+}
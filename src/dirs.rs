@@ -0,0 +1,122 @@
+use std::path::PathBuf;
+use anyhow::{Context, Result};
+
+// --- XDG Directory Resolution ---
+
+/// Returns the user's home directory.
+pub fn home_dir() -> Result<String> {
+    non_empty_var("HOME").context("HOME is not set")
+}
+
+/// Returns `$XDG_CONFIG_HOME`, falling back to `~/.config`.
+pub fn config_home() -> Result<String> {
+    match non_empty_var("XDG_CONFIG_HOME") {
+        Some(dir) => Ok(dir),
+        None => Ok(format!("{}/.config", home_dir()?)),
+    }
+}
+
+/// Returns `$XDG_DATA_HOME`, falling back to `~/.local/share`.
+pub fn data_home() -> Result<String> {
+    match non_empty_var("XDG_DATA_HOME") {
+        Some(dir) => Ok(dir),
+        None => Ok(format!("{}/.local/share", home_dir()?)),
+    }
+}
+
+/// Returns `$XDG_CACHE_HOME`, falling back to `~/.cache`.
+pub fn cache_home() -> Result<String> {
+    match non_empty_var("XDG_CACHE_HOME") {
+        Some(dir) => Ok(dir),
+        None => Ok(format!("{}/.cache", home_dir()?)),
+    }
+}
+
+/// Returns `$XDG_STATE_HOME`, falling back to `~/.local/state`.
+pub fn state_home() -> Result<String> {
+    match non_empty_var("XDG_STATE_HOME") {
+        Some(dir) => Ok(dir),
+        None => Ok(format!("{}/.local/state", home_dir()?)),
+    }
+}
+
+/// Returns the user's Pictures directory.
+///
+/// `XDG_PICTURES_DIR` is honored if it is exported, but it normally lives in
+/// `user-dirs.dirs`, which is where localized names like "Bilder" come from.
+pub fn pictures_dir() -> Result<String> {
+    if let Some(dir) = non_empty_var("XDG_PICTURES_DIR").or_else(|| user_dir("XDG_PICTURES_DIR")) {
+        return Ok(dir);
+    }
+
+    let home = home_dir().context("Could not determine the Pictures directory")?;
+    Ok(format!("{}/Pictures", home))
+}
+
+/// Returns the user's Videos directory, looked up the same way as the Pictures directory.
+pub fn videos_dir() -> Result<String> {
+    if let Some(dir) = non_empty_var("XDG_VIDEOS_DIR").or_else(|| user_dir("XDG_VIDEOS_DIR")) {
+        return Ok(dir);
+    }
+
+    let home = home_dir().context("Could not determine the Videos directory")?;
+    Ok(format!("{}/Videos", home))
+}
+
+/// Returns `$XDG_RUNTIME_DIR` for sockets and lock files, falling back to the temp directory.
+pub fn runtime_dir() -> String {
+    non_empty_var("XDG_RUNTIME_DIR").unwrap_or_else(temp_dir)
+}
+
+/// Returns the directory for temporary files, used when no better location is available.
+pub fn temp_dir() -> String {
+    std::env::temp_dir().to_string_lossy().into_owned()
+}
+
+/// Percent-encodes a path as it is written in `file://` URIs and `.trashinfo` files.
+pub fn encode_path(path: &str) -> String {
+    path.bytes()
+    .map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => (byte as char).to_string(),
+        _ => format!("%{:02X}", byte),
+    })
+    .collect()
+}
+
+/// Looks up a directory in `$XDG_CONFIG_HOME/user-dirs.dirs`, as written by `xdg-user-dirs-update`.
+fn user_dir(key: &str) -> Option<String> {
+    let path = PathBuf::from(config_home().ok()?).join("user-dirs.dirs");
+    let contents = std::fs::read_to_string(path).ok()?;
+
+    contents
+    .lines()
+    .map(str::trim)
+    .filter(|line| !line.starts_with('#'))
+    .filter_map(|line| line.split_once('='))
+    .find(|(name, _)| name.trim() == key)
+    .and_then(|(_, value)| parse_user_dir_value(value.trim()))
+}
+
+/// Parses a `user-dirs.dirs` value, which is either `"$HOME/relative"` or `"/absolute"`.
+fn parse_user_dir_value(value: &str) -> Option<String> {
+    let value = value.strip_prefix('"')?.strip_suffix('"')?;
+    let home = home_dir().ok()?;
+
+    let path = match value.strip_prefix("$HOME") {
+        Some(rest) => format!("{}{}", home, rest),
+        None if value.starts_with('/') => value.to_string(),
+        None => return None,
+    };
+
+    // A bare "$HOME/" means the directory has been disabled.
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() || trimmed == home.trim_end_matches('/') {
+        return None;
+    }
+
+    Some(trimmed.to_string())
+}
+
+fn non_empty_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
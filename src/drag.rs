@@ -0,0 +1,32 @@
+use std::process::Stdio;
+use anyhow::{Context, Result};
+use tokio::process::Command;
+use crate::process;
+
+// --- Drag and Drop ---
+//
+// `--drag` opens a small window showing the saved screenshot that can be dragged into a
+// browser's upload field or a chat. The window comes from ripdrag or dragon, whichever is
+// installed, and closes once the screenshot has been dropped somewhere.
+
+/// Drag sources that are tried in order, as program and arguments.
+const PROGRAMS: &[(&str, &[&str])] = &[
+    ("ripdrag", &["--and-exit"]),
+    ("dragon-drop", &["--and-exit"]),
+    ("dragon", &["--and-exit"]),
+];
+
+/// Opens a window to drag the file at `path` from, without waiting for it.
+pub fn drag(path: &str) -> Result<()> {
+    let (program, args) = PROGRAMS
+    .iter()
+    .find(|(program, _)| process::in_path(program))
+    .context("Dragging needs ripdrag or dragon to be installed")?;
+    Command::new(program)
+    .args(*args)
+    .arg(path)
+    .stdin(Stdio::null())
+    .spawn()
+    .with_context(|| format!("Failed to start {}", program))?;
+    Ok(())
+}
@@ -0,0 +1,185 @@
+use std::time::Instant;
+use anyhow::{Context, Result};
+use tokio::task::JoinSet;
+use tokio::time::Duration;
+use crate::compositor::OptionOverride;
+use crate::geometry::Rect;
+use crate::{filter, flash, format, history, i18n, montage, notify, save, sha256, snapshot, Cli, CaptureSummary, HyprlandMonitor};
+
+// --- Capturing Every Monitor ---
+//
+// `--each-output` saves one screenshot per monitor. Each monitor gets its own grim, and
+// its file is written as soon as that grim is done, so all of them run at the same time
+// and the whole desktop takes about as long as a single monitor. With `--montage`, they are
+// put together into a single screenshot instead.
+
+/// A monitor's screenshot, once captured and saved. Only what the summary and history
+/// need is kept, so the images themselves are freed as soon as they are written.
+struct Saved {
+    rect: Rect,
+    bytes: usize,
+    sha256: String,
+    path: String,
+    /// When the file was written.
+    done: Instant,
+}
+
+/// Captures and saves every monitor. `overrides` are undone once all of them are captured.
+pub async fn capture(cli: &Cli, overrides: Vec<OptionOverride>) -> Result<()> {
+    let monitors: Vec<HyprlandMonitor> = snapshot::hyprctl_json("monitors").await?;
+    let do_not_disturb = !cli.silent && !cli.ignore_dnd && notify::do_not_disturb(cli.dnd_command.as_deref()).await;
+
+    if cli.delay > 0.0 {
+        crate::countdown(Duration::from_secs_f64(cli.delay), cli.silent || do_not_disturb).await;
+    }
+    let hidden_cursor = if cli.hide_cursor { crate::hide_cursor().await } else { None };
+
+    crate::progress(&format!("Capturing {} monitors", monitors.len()));
+    let capture_started = Instant::now();
+
+    let (saved, failures) = if cli.montage {
+        (vec![save_montage(cli, &monitors).await?], Vec::new())
+    } else {
+        save_each(cli, &monitors).await?
+    };
+    drop(hidden_cursor);
+    drop(overrides);
+
+    let flash = (cli.flash && !cli.silent).then(|| tokio::spawn(flash::flash()));
+
+    if let Some(newest) = saved.last() {
+        save::update_latest_link(&cli.latest_link, &newest.path).await;
+    }
+
+    let mode = if cli.montage { "Montage" } else { "Monitor" };
+    for screenshot in &saved {
+        crate::progress(&format!("Saved {}", screenshot.path));
+        if cli.json {
+            let summary = CaptureSummary {
+                path: Some(&screenshot.path),
+                copied: false,
+                geometry: screenshot.rect.printed(),
+                mode,
+                window_title: None,
+                bytes: screenshot.bytes,
+                latency_ms: crate::TRIGGERED.get().map_or(0.0, |triggered| (screenshot.done - *triggered).as_secs_f64() * 1000.0),
+                capture_latency_ms: (screenshot.done - capture_started).as_secs_f64() * 1000.0,
+                timings: None,
+                palette: None,
+            };
+            println!("{}", serde_json::to_string(&summary)?);
+        }
+        history::record(&history::Entry {
+            time: chrono::Local::now(),
+            path: Some(screenshot.path.clone()),
+            mode: mode.to_string(),
+            geometry: screenshot.rect,
+            window_title: None,
+            sha256: screenshot.sha256.clone(),
+            favorite: false,
+            url: None,
+        }).await;
+    }
+
+    if !cli.silent && !do_not_disturb && !saved.is_empty() {
+        let dir = save::screenshots_dir();
+        notify::show(&notify::Notification {
+            summary: i18n::tr("LuminaShot - All Monitors"),
+            body: if cli.montage {
+                i18n::tr_with("Saved a montage of {count} monitors to {dir}", &[("count", monitors.len().to_string()), ("dir", dir)])
+            } else {
+                i18n::tr_with("Saved {count} screenshots to {dir}", &[("count", saved.len().to_string()), ("dir", dir)])
+            },
+            icon: saved[0].path.clone(),
+            ..Default::default()
+        }).await;
+    }
+
+    if let Some(flash) = flash {
+        if let Ok(Err(err)) = flash.await {
+            eprintln!("Warning: Could not flash the screen: {:#}", err);
+        }
+    }
+
+    if !failures.is_empty() {
+        anyhow::bail!("Failed to capture {} of {} monitors:\n{}", failures.len(), monitors.len(), failures.join("\n"));
+    }
+    Ok(())
+}
+
+/// Captures every monitor and saves each one as it's done. Returns the saved screenshots
+/// in the order of the monitors, and what went wrong with the others.
+async fn save_each(cli: &Cli, monitors: &[HyprlandMonitor]) -> Result<(Vec<Saved>, Vec<String>)> {
+    let mut tasks = JoinSet::new();
+    for (index, monitor) in monitors.iter().enumerate() {
+        let rect = monitor.rect()?;
+        let suffix = format!("-{}", monitor.name);
+        let (format, quality, overwrite) = (cli.format, cli.quality, cli.overwrite);
+        let edits = crate::edits(cli);
+        tasks.spawn(async move {
+            let saved = async {
+                let buffer = crate::capture_geometry_to_buffer(&rect, format, quality, None).await?;
+                let buffer = filter::apply(buffer, &edits)?;
+                let outcome = save::save_buffer_to_file(&buffer, format, &suffix, overwrite).await?;
+                Ok::<_, anyhow::Error>((buffer.len(), sha256::hex_digest(&buffer), outcome))
+            }
+            .await;
+            (index, rect, saved)
+        });
+    }
+
+    let mut saved = Vec::new();
+    let mut failures = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        let (index, rect, result) = joined?;
+        let name = &monitors[index].name;
+        match result {
+            Ok((bytes, sha256, outcome)) => match outcome.path() {
+                Some(path) => {
+                    if let save::SaveOutcome::Diverted { full_dir, .. } = &outcome {
+                        eprintln!("{}: {}", i18n::tr("Warning"), i18n::tr_with("Not enough space in {dir}, saved {name} to {path} instead.", &[("dir", full_dir.clone()), ("name", name.to_string()), ("path", path.to_string())]));
+                    }
+                    saved.push((index, Saved { rect, bytes, sha256, path: path.to_string(), done: Instant::now() }));
+                }
+                None => failures.push(format!("{}: not enough space to save it", name)),
+            },
+            Err(err) => failures.push(format!("{}: {:#}", name, err)),
+        }
+    }
+    saved.sort_by_key(|(index, _)| *index);
+    Ok((saved.into_iter().map(|(_, saved)| saved).collect(), failures))
+}
+
+/// Captures every monitor and saves them together as one montage.
+async fn save_montage(cli: &Cli, monitors: &[HyprlandMonitor]) -> Result<Saved> {
+    let mut tasks = JoinSet::new();
+    for (index, monitor) in monitors.iter().enumerate() {
+        let rect = monitor.rect()?;
+        let edits = crate::edits(cli);
+        tasks.spawn(async move {
+            let image = crate::capture_geometry_to_buffer(&rect, format::ImageFormat::Png, None, None).await;
+            (index, image.and_then(|image| filter::apply(image, &edits)).map(|image| montage::Shot { rect, image }))
+        });
+    }
+
+    let mut shots = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        let (index, shot) = joined?;
+        shots.push(shot.with_context(|| format!("Failed to capture {}", monitors[index].name))?);
+    }
+
+    let rect = montage::bounds(&shots)?;
+    let buffer = montage::compose(&shots, cli.montage_background)?;
+    drop(shots);
+    let outcome = save::save_buffer_to_file(&buffer, format::ImageFormat::Png, "-montage", cli.overwrite).await?;
+    let path = match outcome {
+        save::SaveOutcome::Saved(path) => path,
+        save::SaveOutcome::Diverted { path, full_dir } => {
+            eprintln!("{}: {}", i18n::tr("Warning"), i18n::tr_with("Not enough space in {dir}, saved the montage to {path} instead.", &[("dir", full_dir.clone()), ("path", path.clone())]));
+            path
+        }
+        save::SaveOutcome::NoSpace { full_dir } => anyhow::bail!("{}", i18n::tr_with("Not enough space in {dir} to save the montage", &[("dir", full_dir)])),
+        save::SaveOutcome::Stdout => unreachable!("only save_buffer_to_path writes to stdout"),
+    };
+    Ok(Saved { rect, bytes: buffer.len(), sha256: sha256::hex_digest(&buffer), path, done: Instant::now() })
+}
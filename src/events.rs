@@ -0,0 +1,40 @@
+use anyhow::{Context, Result};
+use tokio::io::{AsyncBufReadExt, BufReader, Lines};
+use tokio::net::UnixStream;
+use crate::dirs;
+
+// --- Hyprland Event Socket ---
+
+/// A connection to Hyprland's event socket, which announces every change as an
+/// `EVENT>>DATA` line.
+pub struct Events {
+    lines: Lines<BufReader<UnixStream>>,
+}
+
+impl Events {
+    /// Connects to the event socket of the running Hyprland instance.
+    pub async fn connect() -> Result<Events> {
+        let signature = std::env::var("HYPRLAND_INSTANCE_SIGNATURE").ok().context("HYPRLAND_INSTANCE_SIGNATURE is not set")?;
+        // Hyprland 0.40 moved its sockets from /tmp into the runtime directory.
+        let candidates = [
+            format!("{}/hypr/{}/.socket2.sock", dirs::runtime_dir(), signature),
+            format!("/tmp/hypr/{}/.socket2.sock", signature),
+        ];
+        for path in &candidates {
+            if let Ok(stream) = UnixStream::connect(path).await {
+                return Ok(Events { lines: BufReader::new(stream).lines() });
+            }
+        }
+        anyhow::bail!("Could not connect to Hyprland's event socket {}", candidates[0])
+    }
+
+    /// Waits for the next event and returns its name and data.
+    pub async fn next(&mut self) -> Result<(String, String)> {
+        loop {
+            let line = self.lines.next_line().await?.context("Hyprland closed its event socket")?;
+            if let Some((name, data)) = line.split_once(">>") {
+                return Ok((name.to_string(), data.to_string()));
+            }
+        }
+    }
+}
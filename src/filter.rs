@@ -0,0 +1,192 @@
+use std::time::Instant;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use crate::{format, png, timings};
+
+// --- Image Filters ---
+//
+// Adjustments and filters are applied to grim's image before it is saved or copied: the
+// adjustments first, then the filters in the order they were given, and finally any
+// color vision deficiency simulation, so it shows the finished image. They need the image
+// decoded, so they only work with PNG, and the image is re-encoded by LuminaShot rather
+// than by grim.
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Grayscale,
+    /// Invert the colors, which makes dark-mode screenshots printable.
+    Invert,
+    Sepia,
+}
+
+impl Filter {
+    /// Applies the filter to a row of RGBA pixels. Alpha is left alone.
+    fn apply(self, row: &mut [u8]) {
+        for pixel in row.chunks_exact_mut(4) {
+            let [r, g, b] = [pixel[0] as u32, pixel[1] as u32, pixel[2] as u32];
+            let rgb = match self {
+                // Rec. 601 luma, in 8-bit fixed point.
+                Filter::Grayscale => {
+                    let luma = ((77 * r + 150 * g + 29 * b) >> 8) as u8;
+                    [luma, luma, luma]
+                }
+                Filter::Invert => [255 - pixel[0], 255 - pixel[1], 255 - pixel[2]],
+                // The usual sepia tone matrix, in thousandths.
+                Filter::Sepia => [
+                    ((393 * r + 769 * g + 189 * b) / 1000).min(255) as u8,
+                    ((349 * r + 686 * g + 168 * b) / 1000).min(255) as u8,
+                    ((272 * r + 534 * g + 131 * b) / 1000).min(255) as u8,
+                ],
+            };
+            pixel[..3].copy_from_slice(&rgb);
+        }
+    }
+}
+
+/// A color vision deficiency to simulate.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Simulation {
+    /// No working red cones.
+    Protanopia,
+    /// No working green cones, the most common kind.
+    Deuteranopia,
+    /// No working blue cones.
+    Tritanopia,
+}
+
+impl Simulation {
+    /// The matrix for full severity from Machado, Oliveira and Fernandes (2009), which
+    /// applies to linear RGB.
+    fn matrix(self) -> [[f32; 3]; 3] {
+        match self {
+            Simulation::Protanopia => [
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ],
+            Simulation::Deuteranopia => [
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ],
+            Simulation::Tritanopia => [
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ],
+        }
+    }
+}
+
+/// Converts sRGB rows through a simulation matrix in linear light.
+struct Simulator {
+    matrix: [[f32; 3]; 3],
+    to_linear: [f32; 256],
+    /// Encodes linear values, indexed by the value scaled to `0..=SRGB_STEPS`.
+    to_srgb: Vec<u8>,
+}
+
+const SRGB_STEPS: usize = 4095;
+
+impl Simulator {
+    fn new(simulation: Simulation) -> Simulator {
+        let to_linear = std::array::from_fn(|value| {
+            let value = value as f32 / 255.0;
+            if value <= 0.04045 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) }
+        });
+        let to_srgb = (0..=SRGB_STEPS)
+        .map(|step| {
+            let value = step as f32 / SRGB_STEPS as f32;
+            let encoded = if value <= 0.0031308 { value * 12.92 } else { 1.055 * value.powf(1.0 / 2.4) - 0.055 };
+            (encoded * 255.0).round() as u8
+        })
+        .collect();
+        Simulator { matrix: simulation.matrix(), to_linear, to_srgb }
+    }
+
+    fn apply(&self, row: &mut [u8]) {
+        for pixel in row.chunks_exact_mut(4) {
+            let linear = [self.to_linear[pixel[0] as usize], self.to_linear[pixel[1] as usize], self.to_linear[pixel[2] as usize]];
+            for (channel, weights) in pixel.iter_mut().zip(&self.matrix) {
+                let value: f32 = weights.iter().zip(&linear).map(|(weight, value)| weight * value).sum();
+                *channel = self.to_srgb[(value.clamp(0.0, 1.0) * SRGB_STEPS as f32).round() as usize];
+            }
+        }
+    }
+}
+
+/// Brightness, contrast and gamma corrections, as factors where 1 changes nothing.
+#[derive(Debug, Clone, Copy)]
+pub struct Adjustments {
+    /// Scales every channel.
+    pub brightness: f64,
+    /// Scales the distance of every channel from mid-gray.
+    pub contrast: f64,
+    /// Above 1, brightens the midtones without touching black and white.
+    pub gamma: f64,
+}
+
+impl Adjustments {
+    fn is_identity(&self) -> bool {
+        self.brightness == 1.0 && self.contrast == 1.0 && self.gamma == 1.0
+    }
+
+    /// Returns the adjusted value of every possible channel value.
+    fn table(&self) -> [u8; 256] {
+        std::array::from_fn(|value| {
+            let value = value as f64 / 255.0 * self.brightness;
+            let value = ((value - 0.5) * self.contrast + 0.5).clamp(0.0, 1.0);
+            (value.powf(1.0 / self.gamma) * 255.0).round() as u8
+        })
+    }
+}
+
+/// Everything to do to an image before it is saved.
+#[derive(Debug, Clone)]
+pub struct Edits {
+    pub adjustments: Adjustments,
+    pub filters: Vec<Filter>,
+    pub simulate: Option<Simulation>,
+}
+
+impl Edits {
+    pub fn is_empty(&self) -> bool {
+        self.adjustments.is_identity() && self.filters.is_empty() && self.simulate.is_none()
+    }
+}
+
+/// Fails if `edits` can't be applied to images in `image_format`.
+pub fn check_format(image_format: format::ImageFormat, edits: &Edits) -> Result<()> {
+    if !edits.is_empty() && image_format != format::ImageFormat::Png {
+        anyhow::bail!("--filter, --simulate, --brightness, --contrast and --gamma only work with --format png");
+    }
+    Ok(())
+}
+
+/// Returns the PNG image with `edits` applied, or the image itself without any.
+pub fn apply(image: Vec<u8>, edits: &Edits) -> Result<Vec<u8>> {
+    if edits.is_empty() {
+        return Ok(image);
+    }
+    let started = Instant::now();
+    let adjusted = (!edits.adjustments.is_identity()).then(|| edits.adjustments.table());
+    let simulator = edits.simulate.map(Simulator::new);
+    let edited = png::transform(&image, &mut |row| {
+        if let Some(table) = &adjusted {
+            for pixel in row.chunks_exact_mut(4) {
+                for channel in &mut pixel[..3] {
+                    *channel = table[*channel as usize];
+                }
+            }
+        }
+        for filter in &edits.filters {
+            filter.apply(row);
+        }
+        if let Some(simulator) = &simulator {
+            simulator.apply(row);
+        }
+    })
+    .context("Failed to edit the image")?;
+    timings::add(timings::Stage::Filter, started.elapsed());
+    Ok(edited)
+}
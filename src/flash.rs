@@ -0,0 +1,33 @@
+use anyhow::{Context, Result};
+use tokio::time::{sleep, Duration};
+use crate::{compositor, dirs};
+
+// --- Capture Flash ---
+
+/// How long the screen stays brightened.
+const FLASH_DURATION: Duration = Duration::from_millis(150);
+
+/// Brightens every pixel most of the way towards white.
+const FLASH_SHADER: &str = r#"precision mediump float;
+varying vec2 v_texcoord;
+uniform sampler2D tex;
+
+void main() {
+    vec4 color = texture2D(tex, v_texcoord);
+    gl_FragColor = vec4(mix(color.rgb, vec3(1.0), 0.6), color.a);
+}
+"#;
+
+/// Briefly flashes the screen to confirm a capture, using a temporary Hyprland screen shader.
+///
+/// Must only run once grim has finished, or the flash ends up in the screenshot.
+pub async fn flash() -> Result<()> {
+    let shader_path = format!("{}/luminashot-flash.frag", dirs::runtime_dir());
+    tokio::fs::write(&shader_path, FLASH_SHADER)
+    .await
+    .with_context(|| format!("Failed to write {}", shader_path))?;
+
+    let _shader = compositor::override_option("decoration:screen_shader", &shader_path).await?;
+    sleep(FLASH_DURATION).await;
+    Ok(())
+}
@@ -0,0 +1,96 @@
+// --- Bitmap Font ---
+//
+// A 5x7 pixel font for the few labels LuminaShot draws into images itself. It only has
+// digits, capital letters and some punctuation; lowercase letters are drawn as capitals,
+// and anything else as a question mark.
+
+/// Height of a glyph in pixels.
+pub const HEIGHT: u32 = 7;
+/// Horizontal distance from one glyph to the next, including the gap between them.
+pub const ADVANCE: u32 = 6;
+
+const CHARACTERS: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ :-./_(),?#";
+
+/// Rows of each character in `CHARACTERS`, top to bottom. Bit 4 is the leftmost column.
+const GLYPHS: [[u8; 7]; 47] = [
+    [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+    [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+    [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+    [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+    [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+    [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+    [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+    [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+    [0x0e, 0x11, 0x11, 0x11, 0x1f, 0x11, 0x11],
+    [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e],
+    [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e],
+    [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c],
+    [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f],
+    [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10],
+    [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f],
+    [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+    [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e],
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c],
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f],
+    [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11],
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+    [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+    [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10],
+    [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d],
+    [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11],
+    [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e],
+    [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04],
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a],
+    [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11],
+    [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04],
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f],
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00],
+    [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c],
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f],
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+    [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+    [0x00, 0x00, 0x00, 0x00, 0x0c, 0x04, 0x08],
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    [0x0a, 0x0a, 0x1f, 0x0a, 0x1f, 0x0a, 0x0a],
+];
+
+/// Returns the rows of the glyph drawn for `character`.
+pub fn glyph(character: char) -> [u8; 7] {
+    let character = character.to_ascii_uppercase();
+    let index = CHARACTERS.find(character).or_else(|| CHARACTERS.find('?')).unwrap_or(0);
+    GLYPHS[index]
+}
+
+/// Draws `text` into an RGBA image `width` pixels wide, with its top-left corner at
+/// `(x, y)` and every font pixel drawn as a `scale` by `scale` square. Text past the
+/// right edge is cut off.
+pub fn draw_text(pixels: &mut [u8], width: u32, x: u32, y: u32, scale: u32, text: &str, color: [u8; 4]) {
+    let height = (pixels.len() / (width as usize * 4)) as u32;
+    for (index, character) in text.chars().enumerate() {
+        let left = x + index as u32 * ADVANCE * scale;
+        if left >= width {
+            break;
+        }
+        for (row, bits) in glyph(character).iter().enumerate() {
+            for column in (0..5).filter(|column| bits & (0x10 >> column) != 0) {
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let (px, py) = (left + column * scale + dx, y + row as u32 * scale + dy);
+                        if px < width && py < height {
+                            let offset = (py as usize * width as usize + px as usize) * 4;
+                            pixels[offset..offset + 4].copy_from_slice(&color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use clap::ValueEnum;
+
+// --- Image Formats ---
+
+/// The format screenshots are encoded in. grim encodes the image exactly once, and the
+/// same bytes go to the file, the clipboard, uploads and the history's hash.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    #[value(alias = "jpg")]
+    Jpeg,
+}
+
+impl ImageFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+        }
+    }
+
+    /// Returns the name grim's `-t` option uses for the format.
+    pub fn grim_type(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpeg",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+        }
+    }
+
+    /// Guesses the format of a saved screenshot from its extension.
+    pub fn from_path(path: &str) -> ImageFormat {
+        match path.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase()).as_deref() {
+            Some("jpeg" | "jpg") => ImageFormat::Jpeg,
+            _ => ImageFormat::Png,
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use std::process::Stdio;
+use tokio::process::{Child, Command};
+use tokio::time::{sleep, Duration};
+
+// --- Freezing the Screen ---
+//
+// `--freeze` covers the screen with a still frame of itself while selecting, so menus,
+// tooltips and videos hold still. hyprpicker draws it: on every monitor (`-r`) and without
+// its magnifier (`-z`), it is nothing but the frozen frame. It is only closed after grim
+// is done, so the capture shows the frame that was selected from.
+
+/// Time given to hyprpicker to cover the screen before selecting.
+const FREEZE_SETTLE: Duration = Duration::from_millis(200);
+
+/// Keeps the screen frozen until dropped.
+pub struct Frozen {
+    _hyprpicker: Child,
+}
+
+/// Freezes the screen until the returned guard is dropped.
+pub async fn freeze() -> Option<Frozen> {
+    let spawned = Command::new("hyprpicker")
+    .args(["-r", "-z"])
+    .stdin(Stdio::null())
+    .stdout(Stdio::null())
+    .stderr(Stdio::null())
+    .kill_on_drop(true)
+    .spawn();
+    match spawned {
+        Ok(child) => {
+            sleep(FREEZE_SETTLE).await;
+            Some(Frozen { _hyprpicker: child })
+        }
+        Err(err) => {
+            eprintln!("Warning: Could not freeze the screen, which needs hyprpicker: {}", err);
+            None
+        }
+    }
+}
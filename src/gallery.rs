@@ -0,0 +1,259 @@
+use std::io::Write;
+use anyhow::Result;
+use tokio::io::AsyncReadExt;
+use crate::terminal::{self, base64};
+use crate::{format, history, trash, upload};
+
+// --- `gallery` Subcommand ---
+//
+// A small keyboard-driven browser over the capture history. It draws with plain ANSI
+// escapes, and shows a preview of the selected screenshot on terminals that speak the
+// kitty graphics protocol.
+
+const HELP: &str = "j/k move  enter open  c copy  u upload  f favorite  d delete  q quit";
+
+/// Options for the gallery's actions.
+pub struct GalleryOptions<'a> {
+    pub viewer: &'a str,
+    /// Delete screenshots instead of moving them to the trash.
+    pub purge: bool,
+    pub service: upload::Service,
+    pub imgur_client_id: Option<&'a str>,
+}
+
+/// A key press, decoded from the terminal's input.
+#[derive(Debug, PartialEq)]
+enum Key {
+    Up,
+    Down,
+    Enter,
+    Escape,
+    Char(char),
+}
+
+/// Puts the terminal into raw mode on the alternate screen, and restores it when dropped.
+struct RawTerminal {
+    original: libc::termios,
+}
+
+impl RawTerminal {
+    fn enter() -> Result<RawTerminal> {
+        // SAFETY: `termios` is a plain C struct that tcgetattr fills in completely.
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            anyhow::bail!("The gallery needs an interactive terminal");
+        }
+
+        let mut raw = original;
+        // SAFETY: `raw` is a valid termios obtained from tcgetattr.
+        unsafe {
+            libc::cfmakeraw(&mut raw);
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw);
+        }
+
+        print!("\x1b[?1049h\x1b[?25l");
+        std::io::stdout().flush()?;
+        Ok(RawTerminal { original })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        print!("\x1b_Ga=d,q=2\x1b\\\x1b[?25h\x1b[?1049l");
+        let _ = std::io::stdout().flush();
+        // SAFETY: restores the attributes saved in `enter`.
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
+
+/// Runs the gallery until the user quits.
+pub async fn gallery(options: GalleryOptions<'_>) -> Result<()> {
+    let mut entries = saved_entries().await?;
+    if entries.is_empty() {
+        println!("No saved screenshots in the history yet.");
+        return Ok(());
+    }
+
+    let _terminal = RawTerminal::enter()?;
+    let graphics = terminal::supports_kitty_graphics();
+    let mut stdin = tokio::io::stdin();
+    let mut selected = 0;
+    let mut status = String::new();
+
+    loop {
+        draw(&entries, selected, &status, graphics)?;
+        status.clear();
+
+        let entry = &entries[selected];
+        let path = entry.path.clone().unwrap_or_default();
+        match read_key(&mut stdin).await? {
+            Key::Char('q') | Key::Escape => return Ok(()),
+            Key::Char('j') | Key::Down => selected = (selected + 1).min(entries.len() - 1),
+            Key::Char('k') | Key::Up => selected = selected.saturating_sub(1),
+            Key::Enter | Key::Char('o') => {
+                status = match crate::spawn_with_file(options.viewer, &path) {
+                    Ok(()) => format!("Opened {}", path),
+                    Err(err) => format!("{:#}", err),
+                };
+            }
+            Key::Char('c') => {
+                let result = async {
+                    let buffer = tokio::fs::read(&path).await?;
+                    crate::copy_to_clipboard(&buffer, format::ImageFormat::from_path(&path), Some(&path), None, false).await?;
+                    Ok::<_, anyhow::Error>(())
+                }
+                .await;
+                status = match result {
+                    Ok(()) => "Copied to clipboard".to_string(),
+                    Err(err) => format!("{:#}", err),
+                };
+            }
+            Key::Char('u') => {
+                draw(&entries, selected, "Uploading...", graphics)?;
+                let result = async {
+                    let url = upload::upload_file(&path, options.service, options.imgur_client_id).await?;
+                    crate::copy_text_to_clipboard(&url).await?;
+                    history::set_url(&path, &url).await?;
+                    Ok::<_, anyhow::Error>(url)
+                }
+                .await;
+                status = match result {
+                    Ok(url) => {
+                        entries[selected].url = Some(url.clone());
+                        format!("Uploaded, link copied: {}", url)
+                    }
+                    Err(err) => format!("{:#}", err),
+                };
+            }
+            Key::Char('f') => {
+                status = match history::set_favorite(&path).await {
+                    Ok(()) => {
+                        entries[selected].favorite = true;
+                        "Marked as favorite".to_string()
+                    }
+                    Err(err) => format!("{:#}", err),
+                };
+            }
+            Key::Char('d') => {
+                draw(&entries, selected, &format!("Delete {}? (y/n)", path), graphics)?;
+                if read_key(&mut stdin).await? != Key::Char('y') {
+                    continue;
+                }
+                status = match delete(&entries[selected], options.purge).await {
+                    Ok(message) => {
+                        entries.remove(selected);
+                        if entries.is_empty() {
+                            return Ok(());
+                        }
+                        selected = selected.min(entries.len() - 1);
+                        message
+                    }
+                    Err(err) => format!("{:#}", err),
+                };
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Returns the history entries whose file still exists, newest first.
+async fn saved_entries() -> Result<Vec<history::Entry>> {
+    let mut entries: Vec<history::Entry> = history::load()
+    .await?
+    .into_iter()
+    .filter(|entry| entry.path.as_ref().is_some_and(|path| std::path::Path::new(path).is_file()))
+    .collect();
+    entries.reverse();
+    Ok(entries)
+}
+
+/// Moves a screenshot to the trash, or deletes it, and removes it from the history.
+async fn delete(entry: &history::Entry, purge: bool) -> Result<String> {
+    let path = entry.path.as_deref().unwrap_or_default();
+    let message = trash::remove(path, purge).await?;
+
+    let remaining: Vec<history::Entry> = history::load()
+    .await?
+    .into_iter()
+    .filter(|other| other.path.as_deref() != Some(path))
+    .collect();
+    history::rewrite(&remaining).await?;
+    Ok(message)
+}
+
+/// Redraws the whole screen: the list on the left, the preview on the right.
+fn draw(entries: &[history::Entry], selected: usize, status: &str, graphics: bool) -> Result<()> {
+    let terminal::Size { columns, rows, .. } = terminal::size(libc::STDOUT_FILENO);
+    let list_width = if graphics { columns / 2 } else { columns };
+    let list_rows = rows.saturating_sub(3).max(1);
+    let first = selected.saturating_sub(list_rows - 1);
+
+    let mut out = String::from("\x1b_Ga=d,q=2\x1b\\\x1b[2J\x1b[H");
+    out.push_str(&format!("\x1b[1mLuminaShot gallery\x1b[0m ({} screenshots)\r\n", entries.len()));
+
+    for (index, entry) in entries.iter().enumerate().skip(first).take(list_rows) {
+        let name = entry.path.as_deref().and_then(|path| path.rsplit('/').next()).unwrap_or_default();
+        let mut line = format!(
+            "{} {} {}{}",
+            entry.time.format("%Y-%m-%d %H:%M"),
+            entry.mode,
+            name,
+            if entry.favorite { " *" } else { "" },
+        );
+        line = line.chars().take(list_width.saturating_sub(2)).collect();
+        if index == selected {
+            out.push_str(&format!("\x1b[7m {} \x1b[0m\r\n", line));
+        } else {
+            out.push_str(&format!(" {}\r\n", line));
+        }
+    }
+
+    let entry = &entries[selected];
+    let details = match (&entry.window_title, &entry.url) {
+        (_, Some(url)) => url.clone(),
+        (Some(title), None) => title.clone(),
+        (None, None) => entry.geometry.to_string(),
+    };
+    out.push_str(&format!("\x1b[{};1H\x1b[2m{}\x1b[0m", rows.saturating_sub(1), details.chars().take(columns).collect::<String>()));
+    out.push_str(&format!("\x1b[{};1H{}", rows, if status.is_empty() { HELP } else { status }));
+
+    if graphics {
+        if let Some(path) = &entry.path {
+            // Let the terminal read the file itself and scale it into the right half.
+            out.push_str(&format!(
+                "\x1b[2;{}H\x1b_Ga=T,f=100,t=f,q=2,c={},r={};{}\x1b\\",
+                list_width + 2,
+                columns.saturating_sub(list_width + 2).max(1),
+                list_rows.max(1),
+                base64(path.as_bytes()),
+            ));
+        }
+    }
+
+    let mut stdout = std::io::stdout();
+    stdout.write_all(out.as_bytes())?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Reads one key press.
+async fn read_key(stdin: &mut tokio::io::Stdin) -> Result<Key> {
+    let mut buf = [0u8; 16];
+    loop {
+        let len = stdin.read(&mut buf).await?;
+        if len == 0 {
+            return Ok(Key::Escape);
+        }
+        let key = match &buf[..len] {
+            b"\x1b[A" | b"\x1bOA" => Key::Up,
+            b"\x1b[B" | b"\x1bOB" => Key::Down,
+            b"\x1b" => Key::Escape,
+            b"\r" | b"\n" => Key::Enter,
+            // Ctrl-C doesn't raise SIGINT in raw mode.
+            [3] => Key::Char('q'),
+            [byte] if byte.is_ascii() => Key::Char(*byte as char),
+            _ => continue,
+        };
+        return Ok(key);
+    }
+}
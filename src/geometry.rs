@@ -0,0 +1,217 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+// --- Capture Geometry ---
+
+/// Coordinates beyond this are treated as garbage rather than a real layout position.
+const MAX_COORDINATE: i64 = 1 << 20;
+
+/// How geometries are printed for the user and for scripts.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+pub enum Notation {
+    /// `X,Y WxH`, like slurp and grim
+    #[default]
+    Slurp,
+    /// `WxH+X+Y`, like maim, import and xdotool
+    X11,
+}
+
+static NOTATION: OnceLock<Notation> = OnceLock::new();
+
+/// Sets the notation used by [`Rect::printed`]. Only the first call has an effect.
+pub fn set_notation(notation: Notation) {
+    let _ = NOTATION.set(notation);
+}
+
+/// Returns the notation geometries are printed in.
+pub fn notation() -> Notation {
+    NOTATION.get().copied().unwrap_or_default()
+}
+
+/// A validated capture rectangle in global compositor coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    /// Creates a rectangle, rejecting zero or negative sizes and out-of-range coordinates.
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Result<Rect> {
+        let rect = Rect { x, y, width, height };
+        if width <= 0 || height <= 0 {
+            anyhow::bail!("Invalid geometry {}: width and height must be positive", rect);
+        }
+        let in_range = |n: i64| n.abs() <= MAX_COORDINATE;
+        if !in_range(x as i64) || !in_range(y as i64) || !in_range(x as i64 + width as i64) || !in_range(y as i64 + height as i64) {
+            anyhow::bail!("Invalid geometry {}: coordinates are out of range", rect);
+        }
+        Ok(rect)
+    }
+
+    /// Returns the rectangle grown by the given `[top, right, bottom, left]` margins.
+    pub fn expanded(&self, [top, right, bottom, left]: [i32; 4]) -> Result<Rect> {
+        let [top, right, bottom, left] = [top, right, bottom, left].map(i64::from);
+        let (x, y) = (self.x as i64 - left, self.y as i64 - top);
+        let (width, height) = (self.width as i64 + left + right, self.height as i64 + top + bottom);
+        let fit = |n: i64| i32::try_from(n).map_err(|_| anyhow::anyhow!("Invalid geometry {}: the margins are too large", self));
+        Rect::new(fit(x)?, fit(y)?, fit(width)?, fit(height)?)
+    }
+
+    /// Returns the smallest rectangle covering both rectangles.
+    pub fn union(&self, other: &Rect) -> Result<Rect> {
+        let (left, top) = (self.x.min(other.x), self.y.min(other.y));
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+        Rect::new(left, top, right - left, bottom - top)
+    }
+
+    /// Returns true if the point lies inside the rectangle.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    /// Renders the rectangle in the notation chosen with `--geometry-format`. What is
+    /// passed to grim always uses [`fmt::Display`] instead.
+    pub fn printed(&self) -> String {
+        match notation() {
+            Notation::Slurp => self.to_string(),
+            Notation::X11 => format!("{}x{}+{}+{}", self.width, self.height, self.x, self.y),
+        }
+    }
+}
+
+/// Parses margins for each side, as `[top, right, bottom, left]`. Like CSS margins, and
+/// like Hyprland's gaps, one value applies to all sides, two to vertical and horizontal,
+/// and three to top, horizontal and bottom. Values are separated by commas or spaces.
+pub fn parse_sides(value: &str) -> Result<[i32; 4], String> {
+    let sides = value
+    .split(|c: char| c == ',' || c.is_whitespace())
+    .filter(|part| !part.is_empty())
+    .map(|part| part.parse::<i32>())
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|_| format!("`{}` is not a number of pixels for each side", value))?;
+    if sides.iter().any(|side| side.unsigned_abs() as i64 > MAX_COORDINATE) {
+        return Err(format!("`{}` is more pixels than any screen has", value));
+    }
+
+    match sides[..] {
+        [all] => Ok([all; 4]),
+        [vertical, horizontal] => Ok([vertical, horizontal, vertical, horizontal]),
+        [top, horizontal, bottom] => Ok([top, horizontal, bottom, horizontal]),
+        [top, right, bottom, left] => Ok([top, right, bottom, left]),
+        _ => Err(format!("`{}` should have one to four numbers of pixels", value)),
+    }
+}
+
+/// Renders the `X,Y WxH` format understood by grim and produced by slurp.
+impl fmt::Display for Rect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{} {}x{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+/// Parses the `X,Y WxH` format, or the `WxH+X+Y` format of X11 tools. Negative X11
+/// offsets are written `+-X`, since `-X` would count from the right edge of the screen.
+impl FromStr for Rect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Rect, String> {
+        let invalid = || format!("`{}` is not a valid geometry, expected `X,Y WxH` or `WxH+X+Y`", s.trim());
+
+        let (x, y, size) = match s.trim().split_once(' ') {
+            Some((position, size)) => {
+                let (x, y) = position.split_once(',').ok_or_else(invalid)?;
+                (x, y, size)
+            }
+            None => match s.trim().split('+').collect::<Vec<_>>()[..] {
+                [size, x, y] => (x, y, size),
+                _ => return Err(invalid()),
+            },
+        };
+        let (width, height) = size.trim().split_once('x').ok_or_else(invalid)?;
+
+        let number = |n: &str| n.trim().parse::<i32>().map_err(|_| invalid());
+        Rect::new(number(x)?, number(y)?, number(width)?, number(height)?).map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, width: i32, height: i32) -> Rect {
+        Rect { x, y, width, height }
+    }
+
+    #[test]
+    fn rejects_empty_and_out_of_range_rects() {
+        assert!(Rect::new(0, 0, 0, 10).is_err());
+        assert!(Rect::new(0, 0, 10, -1).is_err());
+        assert!(Rect::new(-(1 << 20), 0, 10, 10).is_ok());
+        assert!(Rect::new(-(1 << 20) - 1, 0, 10, 10).is_err());
+        assert!(Rect::new((1 << 20) - 10, 0, 10, 10).is_ok());
+        assert!(Rect::new((1 << 20) - 10, 0, 11, 10).is_err());
+        // The far edge is computed without overflowing.
+        assert!(Rect::new(i32::MAX, i32::MAX, i32::MAX, i32::MAX).is_err());
+    }
+
+    #[test]
+    fn parses_both_notations() {
+        assert_eq!("10,20 300x400".parse(), Ok(rect(10, 20, 300, 400)));
+        assert_eq!(" -1920,-5  1920x1080 ".parse(), Ok(rect(-1920, -5, 1920, 1080)));
+        assert_eq!("300x400+10+20".parse(), Ok(rect(10, 20, 300, 400)));
+        assert_eq!("300x400+-10+-20".parse(), Ok(rect(-10, -20, 300, 400)));
+        for invalid in ["", "10,20", "10 20x30", "10,20 30", "300x400-10-20", "300x400+10", "a,b cxd", "0,0 0x10"] {
+            assert!(invalid.parse::<Rect>().is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn round_trips_through_display() {
+        let original = rect(-7, 3, 5, 9);
+        assert_eq!(original.to_string(), "-7,3 5x9");
+        assert_eq!(original.to_string().parse(), Ok(original));
+    }
+
+    #[test]
+    fn expands_unions_and_contains() {
+        let base = rect(10, 10, 100, 50);
+        assert_eq!(base.expanded([1, 2, 3, 4]).unwrap(), rect(6, 9, 106, 54));
+        // Negative margins shrink, down to nothing.
+        assert_eq!(base.expanded([-5; 4]).unwrap(), rect(15, 15, 90, 40));
+        assert!(base.expanded([-25, 0, -25, 0]).is_err());
+        // Margins beyond any screen are rejected rather than overflowing.
+        assert!(base.expanded([i32::MAX; 4]).is_err());
+        assert!(base.expanded([i32::MIN, 0, 0, 0]).is_err());
+        assert!(base.expanded([0, i32::MAX, 0, i32::MIN]).is_err());
+
+        assert_eq!(base.union(&rect(-10, 20, 5, 100)).unwrap(), rect(-10, 10, 120, 110));
+        assert_eq!(base.union(&rect(20, 20, 5, 5)).unwrap(), base);
+
+        assert!(base.contains(10, 10));
+        assert!(base.contains(109, 59));
+        assert!(!base.contains(110, 59));
+        assert!(!base.contains(10, 60));
+        assert!(!base.contains(9, 10));
+    }
+
+    #[test]
+    fn parses_sides_like_css() {
+        assert_eq!(parse_sides("8"), Ok([8; 4]));
+        assert_eq!(parse_sides("8,16"), Ok([8, 16, 8, 16]));
+        assert_eq!(parse_sides("1 2 3"), Ok([1, 2, 3, 2]));
+        assert_eq!(parse_sides("1, 2, 3, 4"), Ok([1, 2, 3, 4]));
+        assert_eq!(parse_sides("-4"), Ok([-4; 4]));
+        assert_eq!(parse_sides("-1048576"), Ok([-(1 << 20); 4]));
+        for invalid in ["", " , ", "1,2,3,4,5", "8px", "1.5", "99999999999", "2147483647", "-2147483648", "0 1048577"] {
+            assert!(parse_sides(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+}
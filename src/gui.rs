@@ -0,0 +1,110 @@
+use anyhow::{Context, Result};
+use tokio::process::Command;
+use crate::format::ImageFormat;
+use crate::{cleanup, confirm, dirs, freeze, history, i18n, instance, process, save, sha256, upload};
+
+// --- `gui` Subcommand ---
+//
+// A capture-and-annotate session in the spirit of Flameshot, put together from the pieces
+// LuminaShot already has: the screen is frozen while a region is selected, the capture is
+// opened in swappy to draw on, and a notification with the result asks whether to save,
+// copy or upload it. Clicking the notification itself saves and copies it.
+
+/// What the notification offers besides saving and copying.
+const CHOICES: &[confirm::Choice] = &[confirm::Choice::Save, confirm::Choice::Copy, confirm::Choice::Upload, confirm::Choice::Discard];
+
+/// Options for a gui session.
+pub struct GuiOptions<'a> {
+    /// Name of the symlink to the newest screenshot, empty for none.
+    pub latest_link: &'a str,
+    pub service: upload::Service,
+    pub imgur_client_id: Option<&'a str>,
+}
+
+/// Runs a gui session.
+pub async fn gui(options: GuiOptions<'_>) -> Result<()> {
+    let Some(_instance_lock) = instance::acquire(false).await? else {
+        eprintln!("{}", i18n::tr("Another capture is already in progress."));
+        return Ok(());
+    };
+
+    let frozen = freeze::freeze().await;
+    let Some(rect) = crate::region_mode().await? else {
+        crate::progress(&i18n::tr("Action cancelled."));
+        return Ok(());
+    };
+    let image = crate::capture_geometry_to_buffer(&rect, ImageFormat::Png, None, None).await?;
+    drop(frozen);
+    let image = annotate(&image).await?;
+
+    let choice = confirm::ask(&image, ImageFormat::Png, "Region", CHOICES, crate::NOTIFICATION_ACTION_TIMEOUT).await?;
+    if choice == confirm::Choice::Discard {
+        crate::progress(&i18n::tr("Screenshot discarded."));
+        return Ok(());
+    }
+    let path = match choice {
+        confirm::Choice::Copy => None,
+        _ => Some(save(&image, rect, options.latest_link).await?),
+    };
+    if matches!(choice, confirm::Choice::Keep | confirm::Choice::Copy) {
+        crate::copy_to_clipboard(&image, ImageFormat::Png, path.as_deref(), None, false).await?;
+        crate::progress("Copied to clipboard.");
+    }
+    if let (confirm::Choice::Upload, Some(path)) = (choice, &path) {
+        upload::upload_and_share(path, options.service, options.imgur_client_id).await?;
+    }
+    Ok(())
+}
+
+/// Opens the capture in swappy, and returns it the way it was when swappy was closed.
+async fn annotate(image: &[u8]) -> Result<Vec<u8>> {
+    let stem = format!("{}/luminashot-gui-{}", dirs::runtime_dir(), std::process::id());
+    let input = cleanup::TempPath::new(format!("{}.png", stem));
+    let output = cleanup::TempPath::new(format!("{}-annotated.png", stem));
+    tokio::fs::write(input.path(), image).await?;
+
+    let mut swappy = Command::new("swappy")
+    .arg("-f")
+    .arg(input.path())
+    .arg("-o")
+    .arg(output.path())
+    .kill_on_drop(true)
+    .spawn()
+    .context("Failed to start swappy, which luminashot gui needs for annotating")?;
+    // Annotating takes as long as it takes, like selecting.
+    let status = process::wait_selection("swappy", &mut swappy).await?;
+    if !status.success() {
+        anyhow::bail!("swappy failed with {}", status);
+    }
+    match tokio::fs::read(output.path()).await {
+        Ok(annotated) => Ok(annotated),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(image.to_vec()),
+        Err(err) => Err(err).context("Failed to read the annotated screenshot"),
+    }
+}
+
+/// Saves the annotated capture like any other screenshot, and returns its path.
+async fn save(image: &[u8], rect: crate::Rect, latest_link: &str) -> Result<String> {
+    let path = match save::save_buffer_to_file(image, ImageFormat::Png, "", false).await? {
+        save::SaveOutcome::Saved(path) => path,
+        save::SaveOutcome::Diverted { path, full_dir } => {
+            eprintln!("{}: {}", i18n::tr("Warning"), i18n::tr_with("Not enough space in {dir}, saved the screenshot to {path} instead.", &[("dir", full_dir), ("path", path.clone())]));
+            path
+        }
+        save::SaveOutcome::NoSpace { full_dir } => anyhow::bail!("{}", i18n::tr_with("Not enough space in {dir} to save the screenshot", &[("dir", full_dir)])),
+        save::SaveOutcome::Stdout => unreachable!("only save_buffer_to_path writes to stdout"),
+    };
+    crate::progress(&format!("Saved to {}", path));
+    save::update_latest_link(latest_link, &path).await;
+    history::record(&history::Entry {
+        time: chrono::Local::now(),
+        path: Some(path.clone()),
+        mode: "Region".to_string(),
+        geometry: rect,
+        window_title: None,
+        sha256: sha256::hex_digest(image),
+        favorite: false,
+        url: None,
+    }).await;
+    Ok(path)
+}
@@ -0,0 +1,144 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use crate::{dirs, save};
+use crate::geometry::Rect;
+
+// --- Capture History ---
+//
+// Every capture is appended as one JSON object per line to
+// `$XDG_DATA_HOME/luminashot/history.jsonl`. Appending keeps concurrent captures from
+// clobbering each other's entries, and a damaged line only loses that one entry.
+
+/// One recorded capture.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub time: DateTime<Local>,
+    /// Where the screenshot was saved, or `None` if it was only copied.
+    pub path: Option<String>,
+    /// The mode label of the capture, e.g. "Window".
+    pub mode: String,
+    pub geometry: Rect,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_title: Option<String>,
+    /// SHA-256 of the encoded image, for spotting duplicates.
+    pub sha256: String,
+    /// Favorites are spared by `luminashot clean --keep-favorites`.
+    #[serde(default)]
+    pub favorite: bool,
+    /// Where the screenshot was uploaded to, if it was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+fn history_stem() -> Result<String> {
+    Ok(format!("{}/luminashot/history", dirs::data_home()?))
+}
+
+pub fn history_path() -> Result<String> {
+    Ok(format!("{}.jsonl", history_stem()?))
+}
+
+/// Appends a capture to the history. Failures only produce a warning, since the capture itself succeeded.
+pub async fn record(entry: &Entry) {
+    let result = async {
+        let path = history_path()?;
+        if let Some(parent) = std::path::Path::new(&path).parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        // A single write of the whole line keeps concurrent appends from interleaving.
+        let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(&path).await?;
+        file.write_all(&line).await?;
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+
+    if let Err(err) = result {
+        eprintln!("Warning: could not record the capture in the history: {:#}", err);
+    }
+}
+
+/// Loads the history, oldest first. A missing history file is treated as empty.
+pub async fn load() -> Result<Vec<Entry>> {
+    let path = history_path()?;
+    let contents = match tokio::fs::read_to_string(&path).await {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path)),
+    };
+
+    let mut entries = Vec::new();
+    for (index, line) in contents.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            Err(err) => eprintln!("Warning: skipping damaged history entry at {}:{}: {}", path, index + 1, err),
+        }
+    }
+    Ok(entries)
+}
+
+/// Replaces the whole history, e.g. after pruning it.
+pub async fn rewrite(entries: &[Entry]) -> Result<()> {
+    let mut contents = Vec::new();
+    for entry in entries {
+        contents.extend(serde_json::to_vec(entry)?);
+        contents.push(b'\n');
+    }
+    save::write_file_atomically(&history_stem()?, "jsonl", &contents, true).await?;
+    Ok(())
+}
+
+/// Marks the capture saved at `path` as a favorite.
+pub async fn set_favorite(path: &str) -> Result<()> {
+    let mut entries = load().await?;
+    let entry = entries
+    .iter_mut()
+    .rev()
+    .find(|entry| entry.path.as_deref() == Some(path))
+    .context("The capture is not in the history")?;
+    entry.favorite = true;
+    rewrite(&entries).await
+}
+
+/// Records the upload URL of the capture saved at `path`. Returns false if it isn't in the history.
+pub async fn set_url(path: &str, url: &str) -> Result<bool> {
+    let mut entries = load().await?;
+    let Some(entry) = entries.iter_mut().rev().find(|entry| entry.path.as_deref() == Some(path)) else {
+        return Ok(false);
+    };
+    entry.url = Some(url.to_string());
+    rewrite(&entries).await?;
+    Ok(true)
+}
+
+/// Prints the most recent captures, newest first. A `limit` of 0 prints all of them.
+pub async fn print(limit: usize, as_json: bool) -> Result<()> {
+    let mut entries = load().await?;
+    entries.reverse();
+    if limit > 0 {
+        entries.truncate(limit);
+    }
+
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    for entry in entries {
+        println!(
+            "{}\t{}\t{}\t{}\t{}{}",
+            entry.time.format("%Y-%m-%d %H:%M:%S"),
+            entry.mode,
+            entry.geometry.printed(),
+            entry.path.as_deref().unwrap_or("(clipboard only)"),
+            entry.window_title.as_deref().unwrap_or(""),
+            if entry.favorite { "\t(favorite)" } else { "" },
+        );
+    }
+    Ok(())
+}
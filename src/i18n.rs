@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use crate::{dirs, template};
+
+// --- Translations ---
+//
+// Notifications and the most common messages are looked up in a gettext catalog for the
+// user's language, taken from `LANGUAGE`, `LC_ALL`, `LC_MESSAGES` or `LANG` like gettext
+// does. Catalogs are `.po` files: the ones in `po/` are built in, and a file at
+// `~/.local/share/luminashot/locale/<language>.po` takes precedence, so a language can be
+// added or corrected without rebuilding. Messages without a translation stay in English.
+//
+// Placeholders like `{path}` are filled in after translating, so translations can move
+// them around.
+
+/// Catalogs built into the executable, by language.
+const BUILT_IN: &[(&str, &str)] = &[("de", include_str!("../po/de.po"))];
+
+static CATALOG: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// Returns the translation of `message`, or `message` itself if there is none.
+pub fn tr(message: &str) -> String {
+    CATALOG
+    .get_or_init(load)
+    .get(message)
+    .cloned()
+    .unwrap_or_else(|| message.to_string())
+}
+
+/// Translates `message` and fills in its `{name}` placeholders.
+pub fn tr_with(message: &str, values: &[(&str, String)]) -> String {
+    template::render(&tr(message), values)
+}
+
+/// Loads the catalog of the first preferred language that has one.
+fn load() -> HashMap<String, String> {
+    for language in languages() {
+        let user_catalog = dirs::data_home()
+        .ok()
+        .and_then(|dir| std::fs::read_to_string(format!("{}/luminashot/locale/{}.po", dir, language)).ok());
+        let built_in = BUILT_IN.iter().find(|(name, _)| *name == language).map(|(_, catalog)| catalog.to_string());
+        if let Some(catalog) = user_catalog.or(built_in) {
+            return parse(&catalog);
+        }
+    }
+    HashMap::new()
+}
+
+/// Returns the preferred languages, most preferred first, each followed by the language
+/// without its region: `pt_BR.UTF-8` gives `pt_BR` and `pt`.
+fn languages() -> Vec<String> {
+    // LANGUAGE is a list, and like in gettext only counts if a locale is set at all.
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+    .iter()
+    .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()));
+    let Some(locale) = locale.filter(|locale| locale != "C" && locale != "POSIX") else {
+        return Vec::new();
+    };
+    let preferred = std::env::var("LANGUAGE").ok().filter(|value| !value.is_empty()).unwrap_or(locale);
+
+    let mut languages = Vec::new();
+    for entry in preferred.split(':') {
+        let name = entry.split(['.', '@']).next().unwrap_or_default();
+        let base = name.split('_').next().unwrap_or_default();
+        for language in [name, base] {
+            if !language.is_empty() && !languages.iter().any(|known| known == language) {
+                languages.push(language.to_string());
+            }
+        }
+    }
+    languages
+}
+
+/// Reads the messages of a `.po` file, leaving out untranslated and fuzzy ones, and plural
+/// forms, which no message uses.
+fn parse(catalog: &str) -> HashMap<String, String> {
+    let mut messages = HashMap::new();
+    let (mut id, mut translation) = (String::new(), String::new());
+    let mut skipped = false;
+    // Whether the entry has reached its msgstr, which continuation lines then belong to.
+    let mut in_translation = false;
+
+    let mut finish = |id: &mut String, translation: &mut String, skipped: &mut bool, in_translation: &mut bool| {
+        if !id.is_empty() && !translation.is_empty() && !*skipped {
+            messages.insert(std::mem::take(id), std::mem::take(translation));
+        }
+        id.clear();
+        translation.clear();
+        *skipped = false;
+        *in_translation = false;
+    };
+
+    for line in catalog.lines().map(str::trim) {
+        if let Some(flags) = line.strip_prefix("#,") {
+            finish(&mut id, &mut translation, &mut skipped, &mut in_translation);
+            skipped = flags.split(',').any(|flag| flag.trim() == "fuzzy");
+        } else if let Some(rest) = line.strip_prefix("msgid ") {
+            if in_translation {
+                finish(&mut id, &mut translation, &mut skipped, &mut in_translation);
+            }
+            id = unquote(rest);
+        } else if let Some(rest) = line.strip_prefix("msgstr ") {
+            translation = unquote(rest);
+            in_translation = true;
+        } else if line.starts_with("msgid_plural") || line.starts_with("msgstr[") {
+            skipped = true;
+            in_translation = true;
+        } else if line.starts_with('"') {
+            match in_translation {
+                true => translation.push_str(&unquote(line)),
+                false => id.push_str(&unquote(line)),
+            }
+        }
+    }
+    finish(&mut id, &mut translation, &mut skipped, &mut in_translation);
+    messages
+}
+
+/// Returns the contents of a quoted `.po` string, with its escapes resolved.
+fn unquote(quoted: &str) -> String {
+    let inner = quoted.trim().strip_prefix('"').and_then(|rest| rest.strip_suffix('"')).unwrap_or_default();
+    let mut text = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => text.push('\n'),
+                Some('t') => text.push('\t'),
+                Some(other) => text.push(other),
+                None => {}
+            },
+            c => text.push(c),
+        }
+    }
+    text
+}
@@ -0,0 +1,65 @@
+use anyhow::{Context, Result};
+use crate::dbus::{self, Value};
+use crate::process;
+
+// --- Keeping the Screen Awake ---
+//
+// While a countdown or a recording runs, the screen must not blank or lock, or the lock
+// screen is what gets captured. Idle daemons like hypridle take inhibitors through the
+// org.freedesktop.ScreenSaver interface; where nothing provides it, the desktop portal's
+// Inhibit is asked instead. Both let go of an inhibitor once the connection that took it
+// closes, so one is never left behind, even if luminashot is killed.
+//
+// Having no idle daemon is common and harmless, so failing to inhibit is not reported.
+
+const SCREENSAVER: &str = "org.freedesktop.ScreenSaver";
+const PORTAL: &str = "org.freedesktop.portal.Desktop";
+/// The portal's flag for inhibiting the session from going idle.
+const PORTAL_IDLE: u32 = 8;
+
+/// Keeps the session from going idle until released or dropped.
+pub struct Inhibitor {
+    bus: dbus::Connection,
+    release: Release,
+}
+
+enum Release {
+    /// The cookie returned by `org.freedesktop.ScreenSaver.Inhibit`.
+    Cookie(u32),
+    /// The portal request object standing for the inhibitor.
+    Request(String),
+}
+
+impl Inhibitor {
+    /// Lets the session go idle again.
+    pub async fn release(mut self) {
+        let _ = process::with_timeout("idle inhibitor", Some(process::command_timeout()), async {
+            match &self.release {
+                Release::Cookie(cookie) => self.bus.call(SCREENSAVER, "/org/freedesktop/ScreenSaver", SCREENSAVER, "UnInhibit", vec![Value::U32(*cookie)]).await?,
+                Release::Request(handle) => self.bus.call(PORTAL, handle, "org.freedesktop.portal.Request", "Close", vec![]).await?,
+            };
+            Ok(())
+        })
+        .await;
+    }
+}
+
+/// Keeps the screen from blanking or locking, for `reason`. Returns `None` if nothing
+/// on the session bus can do that.
+pub async fn inhibit(reason: &str) -> Option<Inhibitor> {
+    process::with_timeout("idle inhibitor", Some(process::command_timeout()), take(reason)).await.ok()
+}
+
+async fn take(reason: &str) -> Result<Inhibitor> {
+    let mut bus = dbus::Connection::session().await?;
+
+    let screensaver = bus.call(SCREENSAVER, "/org/freedesktop/ScreenSaver", SCREENSAVER, "Inhibit", vec![Value::str("LuminaShot"), Value::str(reason)]).await;
+    if let Some(cookie) = screensaver.ok().and_then(|reply| reply.first().and_then(Value::as_u32)) {
+        return Ok(Inhibitor { bus, release: Release::Cookie(cookie) });
+    }
+
+    let options = Value::dict(vec![("reason".to_string(), Value::str(reason))]);
+    let reply = bus.call(PORTAL, "/org/freedesktop/portal/desktop", "org.freedesktop.portal.Inhibit", "Inhibit", vec![Value::str(""), Value::U32(PORTAL_IDLE), options]).await?;
+    let handle = reply.first().and_then(Value::as_str).context("The portal returned no request handle")?.to_string();
+    Ok(Inhibitor { bus, release: Release::Request(handle) })
+}
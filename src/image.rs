@@ -0,0 +1,139 @@
+// --- In-Memory Images ---
+
+/// An 8-bit RGBA image.
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// Rows of RGBA pixels, top to bottom, without padding.
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    /// Returns the image scaled to `width`×`height`. Shrinking averages the pixels each
+    /// destination pixel covers, and enlarging interpolates between neighbors.
+    pub fn resized(&self, width: u32, height: u32) -> Image {
+        let columns = weights(self.width, width);
+        let rows = weights(self.height, height);
+        let source_stride = self.width as usize * 4;
+
+        // Horizontally first, into full-precision rows, then vertically.
+        let mut wide = vec![0f32; self.height as usize * width as usize * 4];
+        for (source, target) in self.pixels.chunks_exact(source_stride).zip(wide.chunks_exact_mut(width as usize * 4)) {
+            for (pixel, taps) in target.chunks_exact_mut(4).zip(&columns) {
+                for &(column, weight) in taps {
+                    for (total, &channel) in pixel.iter_mut().zip(&source[column * 4..column * 4 + 4]) {
+                        *total += channel as f32 * weight;
+                    }
+                }
+            }
+        }
+
+        let stride = width as usize * 4;
+        let mut pixels = vec![0u8; height as usize * stride];
+        for (target, taps) in pixels.chunks_exact_mut(stride).zip(&rows) {
+            for (index, channel) in target.iter_mut().enumerate() {
+                let total: f32 = taps.iter().map(|&(row, weight)| wide[row * stride + index] * weight).sum();
+                *channel = total.round().clamp(0.0, 255.0) as u8;
+            }
+        }
+        Image { width, height, pixels }
+    }
+}
+
+/// Returns the source pixels and their weights for every destination pixel along one
+/// axis: a triangle filter, widened by the scale when shrinking so no pixel is skipped.
+fn weights(original: u32, scaled: u32) -> Vec<Vec<(usize, f32)>> {
+    let scale = original as f32 / scaled as f32;
+    let support = scale.max(1.0);
+    (0..scaled)
+    .map(|index| {
+        let center = (index as f32 + 0.5) * scale;
+        let first = (center - support).floor().max(0.0) as usize;
+        let last = ((center + support).ceil() as usize).min(original as usize);
+        let mut taps: Vec<(usize, f32)> = (first..last)
+        .map(|source| (source, (1.0 - ((source as f32 + 0.5 - center) / support).abs()).max(0.0)))
+        .filter(|&(_, weight)| weight > 0.0)
+        .collect();
+        let total: f32 = taps.iter().map(|&(_, weight)| weight).sum();
+        if total > 0.0 {
+            taps.iter_mut().for_each(|(_, weight)| *weight /= total);
+        } else {
+            taps = vec![(((center as usize).min(original as usize - 1)), 1.0)];
+        }
+        taps
+    })
+    .collect()
+}
+
+/// Scales an image down as its rows arrive, top to bottom, so that neither side exceeds
+/// `max_side`. Each destination pixel is the average of the source pixels it covers.
+/// Images that already fit come out unchanged.
+pub struct Downscaler {
+    source_width: u32,
+    source_height: u32,
+    width: u32,
+    height: u32,
+    /// The source columns each destination column covers.
+    columns: Vec<(u32, u32)>,
+    /// The next source row to arrive.
+    source_row: u32,
+    /// Running per-channel totals for the destination row being built.
+    sums: Vec<u64>,
+    pixels: Vec<u8>,
+}
+
+impl Downscaler {
+    pub fn new(source_width: u32, source_height: u32, max_side: u32) -> Downscaler {
+        let scale = (max_side as f64 / source_width.max(source_height).max(1) as f64).min(1.0);
+        let width = ((source_width as f64 * scale).round() as u32).clamp(1, source_width.max(1));
+        let height = ((source_height as f64 * scale).round() as u32).clamp(1, source_height.max(1));
+        Downscaler {
+            source_width,
+            source_height,
+            width,
+            height,
+            columns: (0..width).map(|x| source_span(x, width, source_width)).collect(),
+            source_row: 0,
+            sums: vec![0; width as usize * 4],
+            pixels: Vec::with_capacity(width as usize * height as usize * 4),
+        }
+    }
+
+    /// Adds the next source row of RGBA pixels.
+    pub fn push_row(&mut self, row: &[u8]) {
+        let y = (self.pixels.len() / (self.width as usize * 4)) as u32;
+        if y >= self.height || row.len() < self.source_width as usize * 4 {
+            return;
+        }
+
+        for (sum, &(left, right)) in self.sums.chunks_exact_mut(4).zip(&self.columns) {
+            for pixel in row[left as usize * 4..right as usize * 4].chunks_exact(4) {
+                for (total, &channel) in sum.iter_mut().zip(pixel) {
+                    *total += channel as u64;
+                }
+            }
+        }
+        self.source_row += 1;
+
+        let (top, bottom) = source_span(y, self.height, self.source_height);
+        if self.source_row >= bottom {
+            for (sum, &(left, right)) in self.sums.chunks_exact_mut(4).zip(&self.columns) {
+                let count = ((bottom - top) * (right - left)) as u64;
+                self.pixels.extend(sum.iter().map(|total| (total / count) as u8));
+                sum.fill(0);
+            }
+        }
+    }
+
+    pub fn finish(self) -> Image {
+        Image { width: self.width, height: self.height, pixels: self.pixels }
+    }
+}
+
+/// Returns the range of source pixels that destination pixel `index` covers along one axis.
+fn source_span(index: u32, scaled: u32, original: u32) -> (u32, u32) {
+    let start = (index as u64 * original as u64 / scaled as u64) as u32;
+    let end = ((index as u64 + 1) * original as u64 / scaled as u64) as u32;
+    (start, end.max(start + 1).min(original))
+}
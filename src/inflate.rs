@@ -0,0 +1,324 @@
+use anyhow::{Context, Result};
+
+// --- DEFLATE Decompression (RFC 1950, RFC 1951) ---
+
+pub const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+pub const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+pub const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+pub const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+/// The order in which code length code lengths are stored in a dynamic block header.
+pub const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Reads the bits of a DEFLATE stream, least significant bit first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitReader<'_> {
+    /// Returns the next `n` bits without consuming them. Bits past the end read as zero.
+    fn peek(&mut self, n: u32) -> u32 {
+        while self.count < n && self.pos < self.data.len() {
+            self.buffer |= (self.data[self.pos] as u32) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+        self.buffer & ((1 << n) - 1)
+    }
+
+    fn consume(&mut self, n: u32) -> Result<()> {
+        if self.count < n {
+            anyhow::bail!("Compressed data ends unexpectedly");
+        }
+        self.buffer >>= n;
+        self.count -= n;
+        Ok(())
+    }
+
+    fn bits(&mut self, n: u32) -> Result<u32> {
+        let value = self.peek(n);
+        self.consume(n)?;
+        Ok(value)
+    }
+
+    /// Skips to the next byte boundary. `peek` may have read whole bytes ahead, which are
+    /// given back; the bits left over belong to the current byte.
+    fn align(&mut self) {
+        self.pos -= (self.count / 8) as usize;
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+/// A canonical Huffman code decoded through a lookup table indexed by the next `bits` bits.
+struct Huffman {
+    bits: u32,
+    /// `(symbol, code length)` for every bit pattern; a length of 0 marks an unused pattern.
+    table: Vec<(u16, u8)>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman> {
+        let bits = lengths.iter().copied().max().unwrap_or(0) as u32;
+        if bits > 15 {
+            anyhow::bail!("Invalid Huffman code length {}", bits);
+        }
+
+        let mut length_counts = [0u32; 16];
+        for &length in lengths {
+            length_counts[length as usize] += 1;
+        }
+        length_counts[0] = 0;
+
+        let mut next_code = [0u32; 16];
+        let mut code = 0;
+        for length in 1..16 {
+            code = (code + length_counts[length - 1]) << 1;
+            next_code[length] = code;
+        }
+
+        let mut table = vec![(0u16, 0u8); 1 << bits];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length == 0 {
+                continue;
+            }
+            let code = next_code[length as usize];
+            next_code[length as usize] += 1;
+            if code >= 1 << length {
+                anyhow::bail!("Invalid Huffman code");
+            }
+
+            // Codes are packed most significant bit first, but the stream is read LSB first.
+            let reversed = code.reverse_bits() >> (32 - length as u32);
+            for index in (reversed as usize..table.len()).step_by(1 << length) {
+                table[index] = (symbol as u16, length);
+            }
+        }
+
+        Ok(Huffman { bits, table })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16> {
+        let (symbol, length) = self.table[reader.peek(self.bits) as usize];
+        if length == 0 {
+            anyhow::bail!("Invalid Huffman code in compressed data");
+        }
+        reader.consume(length as u32)?;
+        Ok(symbol)
+    }
+}
+
+/// The furthest back a DEFLATE back-reference can reach.
+pub const WINDOW_SIZE: usize = 32 * 1024;
+/// How much decompressed data is buffered before it is handed to the caller.
+const FLUSH_SIZE: usize = 256 * 1024;
+
+/// The decompressed output. Only the last `WINDOW_SIZE` bytes are kept once the rest
+/// has been handed on, so the whole result never has to fit in memory at once.
+struct Output<'a> {
+    window: Vec<u8>,
+    sink: &'a mut dyn FnMut(&[u8]) -> Result<()>,
+}
+
+impl Output<'_> {
+    fn flush_if_full(&mut self) -> Result<()> {
+        if self.window.len() >= FLUSH_SIZE + WINDOW_SIZE {
+            let done = self.window.len() - WINDOW_SIZE;
+            (self.sink)(&self.window[..done])?;
+            self.window.drain(..done);
+        }
+        Ok(())
+    }
+}
+
+/// Decompresses a zlib stream, as found in PNG image data, handing the output to `sink`
+/// a piece at a time.
+pub fn zlib_decompress(data: &[u8], sink: &mut dyn FnMut(&[u8]) -> Result<()>) -> Result<()> {
+    let [cmf, flg, ..] = *data else {
+        anyhow::bail!("zlib stream is too short");
+    };
+    if cmf & 0x0f != 8 || !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) || flg & 0x20 != 0 {
+        anyhow::bail!("Invalid zlib header");
+    }
+    inflate(&data[2..], sink)
+}
+
+/// Decompresses a raw DEFLATE stream, handing the output to `sink` a piece at a time.
+pub fn inflate(data: &[u8], sink: &mut dyn FnMut(&[u8]) -> Result<()>) -> Result<()> {
+    let mut reader = BitReader { data, pos: 0, buffer: 0, count: 0 };
+    let mut out = Output { window: Vec::with_capacity(FLUSH_SIZE + WINDOW_SIZE), sink };
+
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let header = data.get(reader.pos..reader.pos + 4).context("Compressed data ends unexpectedly")?;
+                let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+                if len != !u16::from_le_bytes([header[2], header[3]]) as usize {
+                    anyhow::bail!("Corrupt stored block length");
+                }
+                let start = reader.pos + 4;
+                out.window.extend_from_slice(data.get(start..start + len).context("Compressed data ends unexpectedly")?);
+                out.flush_if_full()?;
+                reader.pos = start + len;
+            }
+            1 => {
+                let (literals, distances) = fixed_codes()?;
+                inflate_block(&mut reader, &mut out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut out, &literals, &distances)?;
+            }
+            _ => anyhow::bail!("Invalid compressed block type"),
+        }
+        if last {
+            return (out.sink)(&out.window);
+        }
+    }
+}
+
+fn fixed_codes() -> Result<(Huffman, Huffman)> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman)> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    let mut code_length_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_length_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_length_lengths)?;
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code_lengths.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().context("Repeat code without a previous length")?, 3 + reader.bits(2)?),
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count {
+        anyhow::bail!("Code lengths overflow the dynamic block header");
+    }
+
+    Ok((Huffman::new(&lengths[..literal_count])?, Huffman::new(&lengths[literal_count..])?))
+}
+
+fn inflate_block(reader: &mut BitReader, out: &mut Output, literals: &Huffman, distances: &Huffman) -> Result<()> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        match symbol {
+            0..=255 => out.window.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                if index >= LENGTH_BASE.len() {
+                    anyhow::bail!("Invalid length code {}", symbol);
+                }
+                let length = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+
+                let index = distances.decode(reader)? as usize;
+                if index >= DISTANCE_BASE.len() {
+                    anyhow::bail!("Invalid distance code {}", index);
+                }
+                let distance = DISTANCE_BASE[index] as usize + reader.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+                // The window always holds the last WINDOW_SIZE bytes, or everything so far.
+                if distance > out.window.len() {
+                    anyhow::bail!("Back-reference before the start of the data");
+                }
+
+                // The source may overlap the bytes being written, so copy one byte at a time.
+                let start = out.window.len() - distance;
+                for i in 0..length {
+                    out.window.push(out.window[start + i]);
+                }
+                out.flush_if_full()?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inflate_all(data: &[u8]) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        inflate(data, &mut |piece| {
+            out.extend_from_slice(piece);
+            Ok(())
+        })?;
+        Ok(out)
+    }
+
+    #[test]
+    fn inflates_zlib_sync_flush() {
+        // zlib's output for the text below with a sync flush, which ends the dynamic block
+        // with an empty stored one, followed by a final stored block holding "stored".
+        let stream = [
+            0x54, 0x8e, 0xbb, 0x0d, 0xc0, 0x20, 0x10, 0x43, 0x57, 0xf1, 0x00, 0x2e, 0xf8, 0x5e, 0x60, 0x9c,
+            0x48, 0x49, 0x41, 0x99, 0x20, 0xf6, 0xe7, 0x4a, 0x53, 0xbe, 0x27, 0xcb, 0x76, 0xc0, 0xfc, 0xd6,
+            0xfd, 0xbf, 0x0f, 0xc6, 0x44, 0x20, 0xa2, 0x72, 0x24, 0x92, 0x72, 0x21, 0xb2, 0x72, 0x27, 0xca,
+            0x91, 0x37, 0xa2, 0xaa, 0x48, 0x95, 0x30, 0x15, 0xd9, 0x13, 0xd7, 0x51, 0xe9, 0x1d, 0x4d, 0x85,
+            0xf9, 0x48, 0x57, 0xd1, 0xfc, 0xc5, 0x06, 0x00, 0x00, 0xff, 0xff, 0x01, 0x06, 0x00, 0xf9, 0xff,
+            0x73, 0x74, 0x6f, 0x72, 0x65, 0x64,
+        ];
+        assert_eq!((stream[0] >> 1) & 3, 2, "the first block is dynamic");
+
+        let mut expected: String = (0..10).map(|i| format!("{} squared is {}, ", i, i * i)).collect();
+        expected.push_str("stored");
+        assert_eq!(inflate_all(&stream).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn inflates_fixed_block() {
+        // zlib.compress(b"hello hello hello")
+        let stream = [0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x90, 0x00, 0x3a, 0x2e, 0x06, 0x7d];
+        let mut out = Vec::new();
+        zlib_decompress(&stream, &mut |piece| {
+            out.extend_from_slice(piece);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(out, b"hello hello hello");
+    }
+
+    #[test]
+    fn rejects_bad_zlib_headers() {
+        let mut sink = |_: &[u8]| Ok(());
+        assert!(zlib_decompress(&[0x78], &mut sink).is_err());
+        // Not deflate, a bad check value, and a preset dictionary.
+        assert!(zlib_decompress(&[0x79, 0x9c, 0x03, 0x00], &mut sink).is_err());
+        assert!(zlib_decompress(&[0x78, 0x9d, 0x03, 0x00], &mut sink).is_err());
+        assert!(zlib_decompress(&[0x78, 0xbb, 0x03, 0x00], &mut sink).is_err());
+    }
+
+    #[test]
+    fn rejects_corrupt_streams() {
+        // Block type 3 doesn't exist.
+        assert!(inflate_all(&[0x07]).is_err());
+        // A stored block whose length and its complement disagree.
+        assert!(inflate_all(&[0x01, 0x05, 0x00, 0x00, 0x00]).is_err());
+        // A stored block shorter than it says.
+        assert!(inflate_all(&[0x01, 0x05, 0x00, 0xfa, 0xff, b'a', b'b']).is_err());
+        // A fixed block that ends before its end-of-block code.
+        assert!(inflate_all(&[0x03]).is_err());
+        // A fixed block starting with a back-reference: length 3, distance 1.
+        assert!(inflate_all(&[0x03, 0x02, 0x00]).is_err());
+        assert!(inflate_all(&[]).is_err());
+    }
+}
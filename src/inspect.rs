@@ -0,0 +1,111 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use tokio::process::Command;
+use crate::geometry::Rect;
+use crate::{format, i18n, png, process, selector, snapshot, Cli, HyprlandClient, HyprlandMonitor};
+
+// --- Inspect Mode ---
+//
+// `-m inspect` picks single pixels with slurp and prints where they are, their color and
+// the window they belong to, for debugging rendering issues. With `--continuous` it keeps
+// picking until slurp is cancelled with Escape.
+
+#[derive(Serialize)]
+struct Pixel<'a> {
+    x: i32,
+    y: i32,
+    /// `#rrggbbaa`
+    hex: String,
+    rgba: [u8; 4],
+    window: Option<Window<'a>>,
+}
+
+#[derive(Serialize)]
+struct Window<'a> {
+    address: &'a str,
+    class: &'a str,
+    title: &'a str,
+}
+
+/// Prints the pixels the user picks.
+pub async fn inspect(cli: &Cli) -> Result<()> {
+    loop {
+        let Some(point) = pick_point().await? else {
+            if !cli.continuous {
+                crate::progress(&i18n::tr("Action cancelled."));
+            }
+            return Ok(());
+        };
+
+        let rgba = pixel_color(&point).await?;
+        let windows = visible_windows().await?;
+        let window = window_at(&windows, &point);
+        let pixel = Pixel {
+            x: point.x,
+            y: point.y,
+            hex: format!("#{:02x}{:02x}{:02x}{:02x}", rgba[0], rgba[1], rgba[2], rgba[3]),
+            rgba,
+            window: window.map(|window| Window { address: &window.address, class: &window.class, title: &window.title }),
+        };
+
+        if cli.json {
+            println!("{}", serde_json::to_string(&pixel)?);
+        } else {
+            let (class, title) = window.map_or(("-", "-"), |window| (window.class.as_str(), window.title.as_str()));
+            println!("{},{}\t{}\trgba({}, {}, {}, {})\t{}\t{}", pixel.x, pixel.y, pixel.hex, rgba[0], rgba[1], rgba[2], rgba[3], class, title);
+        }
+
+        if !cli.continuous {
+            return Ok(());
+        }
+        // The windows may have moved by the time the next pixel is picked.
+        snapshot::invalidate();
+    }
+}
+
+/// Lets the user click a point with slurp. Returns `None` if it was cancelled.
+async fn pick_point() -> Result<Option<Rect>> {
+    let slurp_output = process::selection_output(Command::new("slurp").args(selector::slurp_args().await).arg("-p")).await?;
+    if !slurp_output.status.success() {
+        return Ok(None);
+    }
+    let selection = String::from_utf8(slurp_output.stdout)?;
+    let point = selection.parse::<Rect>().map_err(|err| anyhow::anyhow!("slurp returned an unexpected point: {}", err))?;
+    Ok(Some(point))
+}
+
+/// Captures the pixel at a point. On scaled monitors, grim returns several pixels for
+/// the point, and the top-left one is used.
+async fn pixel_color(point: &Rect) -> Result<[u8; 4]> {
+    let pixel = Rect::new(point.x, point.y, 1, 1)?;
+    let image = crate::capture_geometry_to_buffer(&pixel, format::ImageFormat::Png, None, None).await?;
+    let mut rgba = None;
+    png::for_each_row(&image, &mut |row| {
+        if rgba.is_none() {
+            rgba = row.get(..4).map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]]);
+        }
+    })
+    .context("Failed to read the captured pixel")?;
+    rgba.context("grim returned an empty image")
+}
+
+/// Returns the windows on the workspaces shown on any monitor.
+async fn visible_windows() -> Result<Vec<HyprlandClient>> {
+    let monitors: Vec<HyprlandMonitor> = snapshot::hyprctl_json("monitors").await?;
+    let shown: Vec<i32> = monitors
+    .iter()
+    .flat_map(|monitor| [monitor.active_workspace.id, monitor.special_workspace.id])
+    .filter(|&id| id != 0)
+    .collect();
+    let clients: Vec<HyprlandClient> = snapshot::hyprctl_json("clients").await?;
+    Ok(clients.into_iter().filter(|client| !client.hidden && shown.contains(&client.workspace.id)).collect())
+}
+
+/// Returns the window drawn at a point. Where windows overlap, the one focused most
+/// recently is taken to be on top.
+fn window_at<'a>(windows: &'a [HyprlandClient], point: &Rect) -> Option<&'a HyprlandClient> {
+    windows
+    .iter()
+    .filter(|window| window.rect().is_ok_and(|rect| rect.contains(point.x, point.y)))
+    .min_by_key(|window| window.focus_history_id)
+}
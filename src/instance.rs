@@ -0,0 +1,70 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::os::fd::AsRawFd;
+use anyhow::{Context, Result};
+use tokio::time::{sleep, Duration};
+use crate::{dirs, process};
+
+// --- Single-Instance Guard ---
+
+/// An exclusive lock on the runtime lock file, held for as long as this value lives.
+pub struct InstanceLock {
+    _file: File,
+}
+
+/// Takes the instance lock so overlapping invocations don't fight over slurp and the clipboard.
+///
+/// If another instance holds the lock, returns `None` unless `replace` is set, in which case
+/// the other instance is sent SIGTERM and the lock is taken over once it has exited.
+pub async fn acquire(replace: bool) -> Result<Option<InstanceLock>> {
+    let path = format!("{}/luminashot.lock", dirs::runtime_dir());
+    let mut file = OpenOptions::new()
+    .read(true)
+    .write(true)
+    .create(true)
+    .truncate(false)
+    .open(&path)
+    .with_context(|| format!("Failed to open lock file {}", path))?;
+
+    if !try_lock(&file)? {
+        if !replace {
+            return Ok(None);
+        }
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        if let Ok(pid) = contents.trim().parse::<libc::pid_t>() {
+            // SAFETY: sending a signal has no memory-safety requirements.
+            unsafe { libc::kill(pid, libc::SIGTERM) };
+        }
+
+        process::with_timeout("previous luminashot instance", Some(process::command_timeout()), async {
+            while !try_lock(&file)? {
+                sleep(Duration::from_millis(20)).await;
+            }
+            Ok(())
+        })
+        .await?;
+    }
+
+    file.set_len(0)?;
+    file.rewind()?;
+    write!(file, "{}", std::process::id())?;
+
+    Ok(Some(InstanceLock { _file: file }))
+}
+
+/// Tries to take an exclusive `flock` on `file` without blocking.
+fn try_lock(file: &File) -> Result<bool> {
+    // SAFETY: the descriptor is owned by `file` and stays open for the duration of the call.
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+
+    let err = std::io::Error::last_os_error();
+    if err.kind() == std::io::ErrorKind::WouldBlock {
+        Ok(false)
+    } else {
+        Err(err).context("Failed to lock the instance lock file")
+    }
+}
@@ -0,0 +1,364 @@
+use anyhow::Result;
+use crate::image::Image;
+
+// --- JPEG Encoding ---
+//
+// grim encodes JPEG screenshots itself, so this is only needed to offer a PNG screenshot
+// on the clipboard as JPEG as well. It is a plain baseline encoder: no chroma subsampling,
+// the example tables from the JPEG standard, and no restart markers. Transparency is left
+// out, so transparent pixels come out in the color they have beneath.
+
+const QUALITY: u32 = 90;
+
+/// The position of each coefficient of a block in the order they are written.
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+const LUMA_QUANT: [u32; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16, 24, 40, 57, 69, 56, 14, 17, 22, 29, 51, 87, 80, 62,
+    18, 22, 37, 56, 68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113, 92, 49, 64, 78, 87, 103, 121, 120, 101, 72, 92, 95, 98, 112, 100, 103, 99,
+];
+
+const CHROMA_QUANT: [u32; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99, 18, 21, 26, 66, 99, 99, 99, 99, 24, 26, 56, 99, 99, 99, 99, 99, 47, 66, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+/// Huffman tables as they are stored: the number of codes of each length from 1 to 16
+/// bits, then the symbols in the order of their codes.
+const DC_LUMA: ([u8; 16], &[u8]) = ([0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0], &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+const DC_CHROMA: ([u8; 16], &[u8]) = ([0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0], &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+const AC_LUMA: ([u8; 16], &[u8]) = ([0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d], &[
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7,
+    0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5,
+    0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2,
+    0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+]);
+const AC_CHROMA: ([u8; 16], &[u8]) = ([0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77], &[
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71,
+    0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33, 0x52, 0xf0,
+    0x15, 0x62, 0x72, 0xd1, 0x0a, 0x16, 0x24, 0x34, 0xe1, 0x25, 0xf1, 0x17, 0x18, 0x19, 0x1a, 0x26,
+    0x27, 0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48,
+    0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68,
+    0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+    0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5,
+    0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3,
+    0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda,
+    0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+]);
+
+/// The code and its length in bits for each symbol of a Huffman table.
+type Codes = [(u16, u8); 256];
+
+/// One of the three color components, with the tables it is encoded with.
+struct Component {
+    quant: [f32; 64],
+    dc: Codes,
+    ac: Codes,
+    previous_dc: i32,
+}
+
+/// Encodes the image as a baseline JPEG.
+pub fn encode(image: &Image) -> Result<Vec<u8>> {
+    if image.width > u16::MAX as u32 || image.height > u16::MAX as u32 {
+        anyhow::bail!("{}×{} is too large for a JPEG", image.width, image.height);
+    }
+    let luma_quant = scaled_quant(&LUMA_QUANT);
+    let chroma_quant = scaled_quant(&CHROMA_QUANT);
+
+    let mut out = vec![0xFF, 0xD8];
+    // JFIF 1.1, without a pixel density or thumbnail.
+    segment(&mut out, 0xE0, b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0");
+    let mut tables = Vec::new();
+    for (id, quant) in [(0, &luma_quant), (1, &chroma_quant)] {
+        tables.push(id);
+        tables.extend(ZIGZAG.iter().map(|&index| quant[index] as u8));
+    }
+    segment(&mut out, 0xDB, &tables);
+    let (width, height) = (image.width as u16, image.height as u16);
+    let mut frame = vec![8];
+    frame.extend_from_slice(&height.to_be_bytes());
+    frame.extend_from_slice(&width.to_be_bytes());
+    frame.extend_from_slice(&[3, 1, 0x11, 0, 2, 0x11, 1, 3, 0x11, 1]);
+    segment(&mut out, 0xC0, &frame);
+    let mut huffman = Vec::new();
+    for (class_and_id, (lengths, symbols)) in [(0x00, DC_LUMA), (0x10, AC_LUMA), (0x01, DC_CHROMA), (0x11, AC_CHROMA)] {
+        huffman.push(class_and_id);
+        huffman.extend_from_slice(&lengths);
+        huffman.extend_from_slice(symbols);
+    }
+    segment(&mut out, 0xC4, &huffman);
+    segment(&mut out, 0xDA, &[3, 1, 0x00, 2, 0x11, 3, 0x11, 0, 63, 0]);
+
+    let quant = |table: &[u32; 64]| std::array::from_fn(|index| table[index] as f32);
+    let mut components = [
+        Component { quant: quant(&luma_quant), dc: codes(DC_LUMA), ac: codes(AC_LUMA), previous_dc: 0 },
+        Component { quant: quant(&chroma_quant), dc: codes(DC_CHROMA), ac: codes(AC_CHROMA), previous_dc: 0 },
+        Component { quant: quant(&chroma_quant), dc: codes(DC_CHROMA), ac: codes(AC_CHROMA), previous_dc: 0 },
+    ];
+    let cosines = cosines();
+    let mut bits = BitWriter { out, buffer: 0, count: 0 };
+    for top in (0..image.height).step_by(8) {
+        for left in (0..image.width).step_by(8) {
+            // Blocks sticking out of the image repeat its last row and column.
+            let pixels: [[f32; 3]; 64] = std::array::from_fn(|index| {
+                let x = (left + index as u32 % 8).min(image.width - 1);
+                let y = (top + index as u32 / 8).min(image.height - 1);
+                let offset = (y as usize * image.width as usize + x as usize) * 4;
+                let [r, g, b] = [0, 1, 2].map(|channel| image.pixels[offset + channel] as f32);
+                [
+                    0.299 * r + 0.587 * g + 0.114 * b - 128.0,
+                    -0.168736 * r - 0.331264 * g + 0.5 * b,
+                    0.5 * r - 0.418688 * g - 0.081312 * b,
+                ]
+            });
+            for (channel, component) in components.iter_mut().enumerate() {
+                let block = pixels.map(|pixel| pixel[channel]);
+                encode_block(&mut bits, &transform(&block, &cosines), component);
+            }
+        }
+    }
+    let mut out = bits.finish();
+    out.extend_from_slice(&[0xFF, 0xD9]);
+    Ok(out)
+}
+
+/// Returns a quantization table scaled for `QUALITY` the way libjpeg does.
+fn scaled_quant(table: &[u32; 64]) -> [u32; 64] {
+    let scale = if QUALITY < 50 { 5000 / QUALITY } else { 200 - QUALITY * 2 };
+    table.map(|value| ((value * scale + 50) / 100).clamp(1, 255))
+}
+
+fn segment(out: &mut Vec<u8>, marker: u8, data: &[u8]) {
+    out.extend_from_slice(&[0xFF, marker]);
+    out.extend_from_slice(&(data.len() as u16 + 2).to_be_bytes());
+    out.extend_from_slice(data);
+}
+
+fn codes((lengths, symbols): ([u8; 16], &[u8])) -> Codes {
+    let mut codes = [(0, 0); 256];
+    let (mut code, mut symbol) = (0u16, 0);
+    for (length, &count) in (1..=16).zip(&lengths) {
+        for _ in 0..count {
+            codes[symbols[symbol] as usize] = (code, length);
+            code += 1;
+            symbol += 1;
+        }
+        code <<= 1;
+    }
+    codes
+}
+
+/// Returns the DCT basis: `cosines[frequency][position]`, with the normalization folded in.
+fn cosines() -> [[f32; 8]; 8] {
+    std::array::from_fn(|frequency| {
+        let normalization = if frequency == 0 { std::f32::consts::FRAC_1_SQRT_2 } else { 1.0 } / 2.0;
+        std::array::from_fn(|position| normalization * ((2 * position + 1) as f32 * frequency as f32 * std::f32::consts::PI / 16.0).cos())
+    })
+}
+
+/// Returns the discrete cosine transform of a block, by rows and then by columns.
+fn transform(block: &[f32; 64], cosines: &[[f32; 8]; 8]) -> [f32; 64] {
+    let mut rows = [0f32; 64];
+    for y in 0..8 {
+        for u in 0..8 {
+            rows[y * 8 + u] = (0..8).map(|x| block[y * 8 + x] * cosines[u][x]).sum();
+        }
+    }
+    let mut coefficients = [0f32; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            coefficients[v * 8 + u] = (0..8).map(|y| rows[y * 8 + u] * cosines[v][y]).sum();
+        }
+    }
+    coefficients
+}
+
+fn encode_block(bits: &mut BitWriter, coefficients: &[f32; 64], component: &mut Component) {
+    let quantized: [i32; 64] = std::array::from_fn(|order| {
+        let index = ZIGZAG[order];
+        (coefficients[index] / component.quant[index]).round() as i32
+    });
+
+    let difference = quantized[0] - component.previous_dc;
+    component.previous_dc = quantized[0];
+    let size = magnitude_bits(difference);
+    bits.write(component.dc[size as usize]);
+    bits.write_value(difference, size);
+
+    let mut zeros = 0;
+    for &value in &quantized[1..] {
+        if value == 0 {
+            zeros += 1;
+            continue;
+        }
+        while zeros > 15 {
+            bits.write(component.ac[0xF0]);
+            zeros -= 16;
+        }
+        let size = magnitude_bits(value);
+        bits.write(component.ac[(zeros << 4 | size) as usize]);
+        bits.write_value(value, size);
+        zeros = 0;
+    }
+    if zeros > 0 {
+        bits.write(component.ac[0x00]);
+    }
+}
+
+/// Returns how many bits the magnitude of a coefficient takes.
+fn magnitude_bits(value: i32) -> u8 {
+    (32 - value.unsigned_abs().leading_zeros()) as u8
+}
+
+/// Writes the entropy-coded data, stuffing a zero byte after every 0xFF.
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u32,
+    count: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, (code, length): (u16, u8)) {
+        self.buffer = (self.buffer << length) | code as u32;
+        self.count += length;
+        while self.count >= 8 {
+            let byte = (self.buffer >> (self.count - 8)) as u8;
+            self.out.push(byte);
+            if byte == 0xFF {
+                self.out.push(0);
+            }
+            self.count -= 8;
+        }
+        self.buffer &= (1 << self.count) - 1;
+    }
+
+    /// Writes a coefficient in `size` bits, negative ones as their ones' complement.
+    fn write_value(&mut self, value: i32, size: u8) {
+        if size > 0 {
+            let bits = if value < 0 { value + (1 << size) - 1 } else { value };
+            self.write((bits as u16, size));
+        }
+    }
+
+    /// Pads the last byte with ones and returns the data.
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            let padding = 8 - self.count;
+            self.write(((1 << padding) - 1, padding));
+        }
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, height: u32) -> Image {
+        let pixels = (0..width * height).flat_map(|index| [(index * 7) as u8, (index * 13) as u8, (index * 29) as u8, 255]).collect();
+        Image { width, height, pixels }
+    }
+
+    /// Returns the markers of the segments before the scan, with their data.
+    fn segments(jpeg: &[u8]) -> (Vec<(u8, &[u8])>, &[u8]) {
+        assert_eq!(jpeg[..2], [0xFF, 0xD8]);
+        let mut segments = Vec::new();
+        let mut pos = 2;
+        loop {
+            assert_eq!(jpeg[pos], 0xFF);
+            let marker = jpeg[pos + 1];
+            let len = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+            segments.push((marker, &jpeg[pos + 4..pos + 2 + len]));
+            pos += 2 + len;
+            if marker == 0xDA {
+                return (segments, &jpeg[pos..]);
+            }
+        }
+    }
+
+    #[test]
+    fn writes_a_baseline_frame() {
+        // Sizes that aren't a multiple of the block size repeat the last row and column.
+        let jpeg = encode(&image(13, 9)).unwrap();
+        assert_eq!(jpeg[jpeg.len() - 2..], [0xFF, 0xD9]);
+
+        let (segments, scan) = segments(&jpeg);
+        let markers: Vec<u8> = segments.iter().map(|(marker, _)| *marker).collect();
+        assert_eq!(markers, [0xE0, 0xDB, 0xC0, 0xC4, 0xDA]);
+        let frame = segments[2].1;
+        assert_eq!(frame[..5], [8, 0, 9, 0, 13]);
+
+        // Every 0xFF in the entropy-coded data is followed by a stuffed zero.
+        let data = &scan[..scan.len() - 2];
+        for (index, &byte) in data.iter().enumerate() {
+            if byte == 0xFF {
+                assert_eq!(data.get(index + 1), Some(&0), "unstuffed 0xFF at {}", index);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_images() {
+        let wide = Image { width: 70_000, height: 1, pixels: vec![0; 70_000 * 4] };
+        assert!(encode(&wide).is_err());
+    }
+
+    #[test]
+    fn huffman_codes_are_prefix_free() {
+        for table in [DC_LUMA, DC_CHROMA, AC_LUMA, AC_CHROMA] {
+            let codes = codes(table);
+            let used: Vec<(u16, u8)> = table.1.iter().map(|&symbol| codes[symbol as usize]).collect();
+            for (i, &(code, length)) in used.iter().enumerate() {
+                for &(other, other_length) in &used[i + 1..] {
+                    let shorter = length.min(other_length);
+                    assert_ne!(code >> (length - shorter), other >> (other_length - shorter));
+                }
+            }
+        }
+        // The standard's first luminance DC codes.
+        let dc = codes(DC_LUMA);
+        assert_eq!(dc[0], (0b00, 2));
+        assert_eq!(dc[1], (0b010, 3));
+        assert_eq!(dc[11], (0b111111110, 9));
+    }
+
+    #[test]
+    fn flat_blocks_have_only_a_dc_coefficient() {
+        let coefficients = transform(&[10.0; 64], &cosines());
+        assert!((coefficients[0] - 80.0).abs() < 1e-3);
+        assert!(coefficients[1..].iter().all(|value| value.abs() < 1e-3));
+    }
+
+    #[test]
+    fn coefficient_bits() {
+        assert_eq!([0, 1, -1, 2, -3, 255, -1024].map(magnitude_bits), [0, 1, 1, 2, 2, 8, 11]);
+
+        let mut bits = BitWriter { out: Vec::new(), buffer: 0, count: 0 };
+        // -3 in two bits is 00, 2 is 10, then padding with ones.
+        bits.write_value(-3, 2);
+        bits.write_value(2, 2);
+        assert_eq!(bits.finish(), [0b0010_1111]);
+
+        let mut bits = BitWriter { out: Vec::new(), buffer: 0, count: 0 };
+        bits.write((0xFF, 8));
+        assert_eq!(bits.finish(), [0xFF, 0x00]);
+    }
+
+    #[test]
+    fn quality_scaling() {
+        // At quality 90 the tables are scaled by a fifth.
+        assert_eq!(scaled_quant(&LUMA_QUANT)[..3], [3, 2, 2]);
+        assert!(scaled_quant(&[1; 64]).iter().all(|&value| value == 1));
+    }
+}
@@ -0,0 +1,101 @@
+use std::ffi::OsString;
+use anyhow::{Context, Result};
+use crate::{daemon, dirs};
+
+// --- `keybinds` Subcommand ---
+//
+// Prints a block of `hyprland.conf` lines binding the common actions, ready to paste or to
+// install as a separate file that hyprland.conf sources. Options given before `keybinds`
+// are added to every command, so `luminashot --copy keybinds` makes all of them copy too.
+
+/// A recommended keybinding.
+struct Bind {
+    /// Modifiers on top of the main one.
+    mods: &'static str,
+    key: &'static str,
+    comment: &'static str,
+    args: &'static [&'static str],
+}
+
+const BINDS: &[Bind] = &[
+    Bind { mods: "", key: "Print", comment: "Capture a region", args: &["--mode", "region"] },
+    Bind { mods: "SHIFT", key: "Print", comment: "Capture a window", args: &["--mode", "window"] },
+    Bind { mods: "CTRL", key: "Print", comment: "Capture the whole monitor", args: &["--mode", "monitor"] },
+    Bind { mods: "ALT", key: "Print", comment: "Capture the previous region again", args: &["--last"] },
+    Bind { mods: "CTRL SHIFT", key: "Print", comment: "Start or stop recording a region", args: &["record", "region", "--toggle"] },
+];
+
+/// Options for the generated keybindings.
+pub struct KeybindsOptions {
+    /// The main modifier, `$mainMod` if hyprland.conf defines it and SUPER otherwise.
+    pub modifier: Option<String>,
+    /// Bind the daemon's global shortcuts instead of running commands directly.
+    pub global: bool,
+    /// Write the lines to `hypr/luminashot.conf` instead of printing them.
+    pub install: bool,
+    pub force: bool,
+}
+
+/// Prints or installs the keybindings.
+pub fn keybinds(options: KeybindsOptions) -> Result<()> {
+    let hypr_dir = format!("{}/hypr", dirs::config_home()?);
+    let hyprland_conf = std::fs::read_to_string(format!("{}/hyprland.conf", hypr_dir)).unwrap_or_default();
+    let modifier = options.modifier.clone().unwrap_or_else(|| default_modifier(&hyprland_conf).to_string());
+
+    let snippet = snippet(&modifier, &crate::args_before("keybinds"), options.global);
+    if !options.install {
+        print!("{}", snippet);
+        return Ok(());
+    }
+
+    let path = format!("{}/luminashot.conf", hypr_dir);
+    if !options.force && std::path::Path::new(&path).exists() {
+        anyhow::bail!("{} already exists; pass --force to overwrite it", path);
+    }
+    std::fs::create_dir_all(&hypr_dir).with_context(|| format!("Failed to create {}", hypr_dir))?;
+    std::fs::write(&path, snippet).with_context(|| format!("Failed to write {}", path))?;
+    println!("Wrote {}", path);
+
+    if hyprland_conf.lines().any(|line| line.trim_start().starts_with("source") && line.contains("luminashot.conf")) {
+        println!("hyprland.conf already sources it; the keybindings are active after `hyprctl reload`.");
+    } else {
+        println!("Add this line to {}/hyprland.conf to use them:\n\nsource = {}", hypr_dir, path);
+    }
+    Ok(())
+}
+
+/// Returns `$mainMod` if hyprland.conf defines it, like the default config does.
+fn default_modifier(hyprland_conf: &str) -> &'static str {
+    let defines_main_mod = hyprland_conf
+    .lines()
+    .any(|line| line.trim_start().strip_prefix("$mainMod").is_some_and(|rest| rest.trim_start().starts_with('=')));
+    if defines_main_mod { "$mainMod" } else { "SUPER" }
+}
+
+/// Builds the `hyprland.conf` lines.
+fn snippet(modifier: &str, base_args: &[OsString], global: bool) -> String {
+    let base: String = base_args.iter().map(|arg| format!(" {}", quote(&arg.to_string_lossy()))).collect();
+
+    let mut out = String::from("# Screenshot keybindings for LuminaShot, generated by `luminashot keybinds`\n");
+    if global {
+        out.push_str(&format!("\nexec-once = luminashot{} daemon\n", base));
+    }
+    for bind in BINDS {
+        let mods = format!("{} {}", modifier, bind.mods);
+        let shortcut = daemon::SHORTCUTS.iter().find(|shortcut| shortcut.args == bind.args);
+        let action = match shortcut {
+            Some(shortcut) if global => format!("global, :{}", shortcut.id),
+            _ => format!("exec, luminashot{} {}", base, bind.args.join(" ")),
+        };
+        out.push_str(&format!("\n# {}\nbind = {}, {}, {}\n", bind.comment, mods.trim(), bind.key, action));
+    }
+    out
+}
+
+/// Single-quotes an argument for the shell Hyprland runs `exec` commands with, when needed.
+fn quote(arg: &str) -> String {
+    if !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_=./:,@%+".contains(c)) {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
@@ -0,0 +1,43 @@
+use anyhow::{Context, Result};
+use crate::{format, history, upload};
+
+// --- `last` Subcommand ---
+
+/// What to do with the most recent capture.
+#[derive(Debug, Clone, Copy)]
+pub enum LastAction {
+    Path,
+    Copy,
+    Open,
+    Upload,
+}
+
+/// Options for the `last` subcommand's actions.
+pub struct LastOptions<'a> {
+    pub viewer: &'a str,
+    pub service: upload::Service,
+    pub imgur_client_id: Option<&'a str>,
+}
+
+/// Runs `action` on the newest capture whose file still exists.
+pub async fn last(action: LastAction, options: LastOptions<'_>) -> Result<()> {
+    let entry = history::load()
+    .await?
+    .into_iter()
+    .rev()
+    .find(|entry| entry.path.as_ref().is_some_and(|path| std::path::Path::new(path).is_file()))
+    .context("No saved screenshot in the history yet")?;
+    let path = entry.path.unwrap_or_default();
+
+    match action {
+        LastAction::Path => println!("{}", path),
+        LastAction::Copy => {
+            let buffer = tokio::fs::read(&path).await.with_context(|| format!("Failed to read {}", path))?;
+            crate::copy_to_clipboard(&buffer, format::ImageFormat::from_path(&path), Some(&path), None, false).await?;
+            println!("Copied {} to the clipboard.", path);
+        }
+        LastAction::Open => crate::spawn_with_file(options.viewer, &path)?,
+        LastAction::Upload => upload::upload_and_share(&path, options.service, options.imgur_client_id).await?,
+    }
+    Ok(())
+}
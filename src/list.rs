@@ -0,0 +1,102 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde_json::json;
+use crate::snapshot::hyprctl_json;
+use crate::{HyprlandClient, HyprlandMonitor};
+
+// --- `list` Subcommand ---
+
+/// The kinds of things `luminashot list` can print.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ListKind {
+    Windows,
+    Monitors,
+    Workspaces,
+}
+
+#[derive(Deserialize, Debug)]
+struct HyprlandWorkspaceInfo {
+    id: i32,
+    name: String,
+    monitor: String,
+    windows: u32,
+}
+
+/// Prints the monitors, windows or workspaces that can be captured, one per line or as JSON.
+pub async fn list(kind: ListKind, as_json: bool) -> Result<()> {
+    let (rows, entries): (Vec<String>, Vec<serde_json::Value>) = match kind {
+        ListKind::Windows => {
+            let active_workspace = crate::get_active_workspace_id().await?;
+            let special_workspaces = crate::shown_special_workspaces().await?;
+            let clients: Vec<HyprlandClient> = hyprctl_json("clients").await?;
+            clients.iter().map(|client| {
+                let geometry = client.rect().ok();
+                // Mirrors the filtering done by window mode, to explain why a window isn't offered.
+                let visible = client.workspace.id == active_workspace || special_workspaces.contains(&client.workspace.id);
+                let offered = geometry.is_some() && !client.hidden && visible;
+                let row = format!(
+                    "{}\t{}\t{}\t{}\t{}{}",
+                    client.address,
+                    client.workspace.name,
+                    geometry.map_or("invalid".to_string(), |rect| rect.printed()),
+                    client.class,
+                    client.title,
+                    if client.hidden { "\t(hidden)" } else { "" },
+                );
+                let entry = json!({
+                    "address": client.address,
+                    "class": client.class,
+                    "title": client.title,
+                    "workspace": { "id": client.workspace.id, "name": client.workspace.name },
+                    "geometry": geometry.map(|rect| rect.printed()),
+                    "hidden": client.hidden,
+                    "offered": offered,
+                });
+                (row, entry)
+            }).unzip()
+        }
+        ListKind::Monitors => {
+            let monitors: Vec<HyprlandMonitor> = hyprctl_json("monitors").await?;
+            monitors.iter().map(|monitor| {
+                let geometry = monitor.rect().ok();
+                let row = format!(
+                    "{}\t{}\t{}{}",
+                    monitor.name,
+                    geometry.map_or("invalid".to_string(), |rect| rect.printed()),
+                    monitor.scale,
+                    if monitor.focused { "\t(focused)" } else { "" },
+                );
+                let entry = json!({
+                    "name": monitor.name,
+                    "geometry": geometry.map(|rect| rect.printed()),
+                    "scale": monitor.scale,
+                    "focused": monitor.focused,
+                });
+                (row, entry)
+            }).unzip()
+        }
+        ListKind::Workspaces => {
+            let workspaces: Vec<HyprlandWorkspaceInfo> = hyprctl_json("workspaces").await?;
+            workspaces.iter().map(|workspace| {
+                let row = format!("{}\t{}\t{}\t{} windows", workspace.id, workspace.name, workspace.monitor, workspace.windows);
+                let entry = json!({
+                    "id": workspace.id,
+                    "name": workspace.name,
+                    "monitor": workspace.monitor,
+                    "windows": workspace.windows,
+                });
+                (row, entry)
+            }).unzip()
+        }
+    };
+
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else {
+        for row in rows {
+            println!("{}", row);
+        }
+    }
+    Ok(())
+}
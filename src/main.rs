@@ -1,24 +1,109 @@
+use std::ffi::OsString;
 use std::process::Stdio;
-use tokio::process::Command;
+use std::sync::OnceLock;
+use std::time::Instant;
+use tokio::process::{Child, ChildStdin, Command};
 use tokio::time::{sleep, Duration};
 use anyhow::{Context, Result};
-use clap::{Parser, ValueEnum};
-use serde::Deserialize;
-use chrono::Local;
-use tokio::io::AsyncWriteExt;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use geometry::Rect;
+
+mod average;
+mod clean;
+mod cleanup;
+mod clipboard;
+mod collage;
+mod compat;
+mod compositor;
+mod config;
+mod confirm;
+mod daemon;
+mod dbus;
+mod deflate;
+mod dirs;
+mod drag;
+mod each_output;
+mod events;
+mod filter;
+mod flash;
+mod font;
+mod freeze;
+mod format;
+mod gallery;
+mod geometry;
+mod gui;
+mod history;
+mod i18n;
+mod idle;
+mod image;
+mod inflate;
+mod inspect;
+mod instance;
+mod jpeg;
+mod keybinds;
+mod last;
+mod list;
+mod montage;
+mod notify;
+mod ocr;
+mod palette;
+mod png;
+mod preset;
+mod print;
+mod process;
+mod record;
+mod redact;
+mod reveal;
+mod save;
+mod search;
+mod selector;
+mod sha256;
+mod snap;
+mod snapshot;
+mod state;
+mod stitch;
+mod target;
+mod template;
+mod terminal;
+mod timings;
+mod trash;
+mod tray;
+mod upload;
+mod wallpaper;
+mod waybar;
+mod wayland;
+mod wizard;
 
 // --- Data Structures for Hyprland's JSON Output ---
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 struct HyprlandClient {
     address: String,
     at: (i32, i32),
     size: (i32, i32),
     workspace: HyprlandWorkspace,
     hidden: bool,
+    class: String,
+    title: String,
+    pid: i32,
+    /// 0 for the focused window, counting up for windows focused longer ago.
+    #[serde(rename = "focusHistoryID", default)]
+    focus_history_id: i32,
+    /// Addresses of all windows in the window's group, empty if it isn't grouped.
+    #[serde(default)]
+    grouped: Vec<String>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+impl HyprlandClient {
+    fn rect(&self) -> Result<Rect> {
+        Rect::new(self.at.0, self.at.1, self.size.0, self.size.1)
+        .with_context(|| format!("Window {} has unusable geometry", self.address))
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
 struct HyprlandWorkspace {
     id: i32,
     name: String,
@@ -26,10 +111,24 @@ struct HyprlandWorkspace {
 
 #[derive(Deserialize, Debug)]
 struct HyprlandMonitor {
+    name: String,
     x: i32,
     y: i32,
     width: i32,
     height: i32,
+    scale: f64,
+    focused: bool,
+    #[serde(rename = "activeWorkspace")]
+    active_workspace: HyprlandWorkspace,
+    /// The special workspace shown on top, with an ID of 0 if there is none.
+    #[serde(rename = "specialWorkspace", default)]
+    special_workspace: HyprlandWorkspace,
+}
+
+impl HyprlandMonitor {
+    fn rect(&self) -> Result<Rect> {
+        Rect::new(self.x, self.y, self.width, self.height)
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -44,6 +143,7 @@ struct HyprlandCursorPos {
 #[command(
 author,
 version,
+args_override_self = true,
 about, // clap will use the crate's description from Cargo.toml
 after_help = r#"EXAMPLES:
 # Capture the current monitor and save it (default action)
@@ -56,165 +156,1390 @@ luminashot -m region -c
 luminashot -m window --copy --save
 
 # A shorter way to do the same as above
-luminashot -m window -cs"#
+luminashot -m window -cs
+
+# List the windows that can be captured
+luminashot list windows"#
 )]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     #[arg(short, long, value_enum, default_value_t = Mode::Monitor, help = "Set the capture mode")]
     mode: Mode,
 
+    #[arg(short, long, value_name = "X,Y WxH", help = "Capture this exact geometry instead of selecting one; WxH+X+Y works too")]
+    geometry: Option<Rect>,
+
+    #[arg(long, value_name = "PIXELS", value_parser = geometry::parse_sides, allow_hyphen_values = true, conflicts_with = "each_output", help = "Grow the captured area on every side, or per side like CSS margins, e.g. 20 or \"10 0 30\"")]
+    expand: Option<[i32; 4]>,
+
+    #[arg(long, value_name = "PIXELS", value_parser = geometry::parse_sides, allow_hyphen_values = true, conflicts_with = "each_output", help = "Shrink the captured area on every side, or per side like CSS margins, e.g. to leave out a window's shadow")]
+    inset: Option<[i32; 4]>,
+
+    #[arg(long, value_name = "CLASS", help = "Capture the window with this class instead of selecting one")]
+    window_class: Option<String>,
+
+    #[arg(long, value_name = "PID", help = "Capture the window of this process instead of selecting one")]
+    pid: Option<i32>,
+
+    #[arg(long, value_enum, value_name = "HOW", help = "How to capture a --window-class or --pid window on another workspace")]
+    other_workspace: Option<target::OtherWorkspace>,
+
+    #[arg(long, help = "Capture a --window-class or --pid window even if it is a hidden tab of a group, by showing it briefly")]
+    reveal_hidden: bool,
+
+    #[arg(long, help = "Capture the same geometry as the previous capture on this workspace, ignoring --mode and --geometry")]
+    last: bool,
+
+    /// Passed by the Retake action: the geometry to capture again as it is, taking
+    /// precedence over every way of choosing one.
+    #[arg(long, value_name = "X,Y WxH", hide = true, allow_hyphen_values = true)]
+    retake: Option<Rect>,
+
+    /// The mode label of the capture being retaken.
+    #[arg(long, value_name = "MODE", hide = true, requires = "retake")]
+    retake_mode: Option<String>,
+
+    #[arg(long, conflicts_with_all = ["copy", "geometry", "last", "window_class", "pid"], help = "Save a separate screenshot of every monitor, all captured at once")]
+    each_output: bool,
+
+    #[arg(long, requires = "each_output", help = "With --each-output, put all monitors into one image, laid out the way they are arranged")]
+    montage: bool,
+
+    #[arg(long, value_name = "COLOR", default_value = "#000000", value_parser = montage::parse_color, help = "Color of the --montage where no monitor is, as #rrggbb or #rrggbbaa")]
+    montage_background: [u8; 4],
+
+    #[arg(long, value_name = "PNG", conflicts_with = "each_output", help = "Add the capture below the image in this file, leaving out rows that repeat its end, and save it there")]
+    append: Option<String>,
+
+    #[arg(long, conflicts_with = "each_output", help = "Only print the size and position of the selection, without capturing; --copy copies it")]
+    measure: bool,
+
+    #[arg(long, help = "In inspect mode, keep picking pixels until Escape is pressed")]
+    continuous: bool,
+
+    #[arg(short, long, value_name = "SECS", default_value_t = 0.0, value_parser = parse_seconds, help = "Wait this long after selecting before capturing, showing a countdown")]
+    delay: f64,
+
     #[arg(short, long, help = "Copy the screenshot to the clipboard")]
     copy: bool,
 
     #[arg(short, long, help = "Save the screenshot to a file (default if no output flag is specified)")]
     save: bool,
+
+    #[arg(long, conflicts_with_all = ["each_output", "append", "silent"], help = "Ask in a notification whether to save, copy, edit or discard the screenshot before doing so")]
+    confirm: bool,
+
+    #[arg(long, requires = "copy", help = "Keep running until the copied screenshot has been pasted once")]
+    wait_for_paste: bool,
+
+    #[arg(long, value_name = "HOLD", default_value = "forever", global = true, value_parser = clipboard::parse_hold, help = "How long the copied screenshot stays on the clipboard: until something else is copied (forever), until it is pasted once (until-paste), or for this many seconds")]
+    clipboard_hold: clipboard::Hold,
+
+    #[arg(long, value_enum, value_name = "BACKEND", default_value_t = clipboard::Backend::WlCopy, global = true, help = "What puts copies on the clipboard; native offers them as PNG, JPEG and the saved file at once")]
+    clipboard_backend: clipboard::Backend,
+
+    #[arg(long, requires = "copy", help = "Also offer the text recognized in the screenshot on the clipboard, so text editors paste the text and chat apps the image")]
+    copy_text: bool,
+
+    #[arg(long, help = "Overwrite an existing file with the same name instead of adding a numeric suffix")]
+    overwrite: bool,
+
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["each_output", "append"], help = "Save the screenshot to this file, replacing it, instead of a new one in the screenshots directory; - writes it to stdout")]
+    file: Option<String>,
+
+    #[arg(long, value_name = "TEMPLATE", default_value = save::DEFAULT_FILE_NAME, help = "Name of saved screenshots, without extension; placeholders: {date}, {time}, {mode}, and {ocr_first_line} and {content_slug} from the recognized text")]
+    filename: String,
+
+    #[arg(long, value_name = "NAME", default_value = "latest", help = "Name of the symlink in the screenshots directory that points to the newest screenshot; empty for none")]
+    latest_link: String,
+
+    #[arg(long, value_enum, default_value_t = format::ImageFormat::Png, help = "Image format of the screenshot")]
+    format: format::ImageFormat,
+
+    #[arg(long, value_name = "0-100", value_parser = clap::value_parser!(u8).range(0..=100), help = "Quality of JPEG screenshots [default: grim's]")]
+    quality: Option<u8>,
+
+    #[arg(long, value_enum, value_name = "FILTER", value_delimiter = ',', help = "Filter to apply to the screenshot; can be repeated, and filters are applied in order")]
+    filter: Vec<filter::Filter>,
+
+    #[arg(long, value_enum, value_name = "DEFICIENCY", help = "Show the screenshot as someone with this color vision deficiency would see it")]
+    simulate: Option<filter::Simulation>,
+
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0, value_parser = parse_factor, help = "Make the screenshot brighter above 1 or darker below it")]
+    brightness: f64,
+
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0, value_parser = parse_factor, help = "Raise the screenshot's contrast above 1 or lower it below it")]
+    contrast: f64,
+
+    #[arg(long, value_name = "GAMMA", default_value_t = 1.0, value_parser = parse_factor, help = "Brighten the midtones above 1 or darken them below it, keeping black and white")]
+    gamma: f64,
+
+    #[arg(long, value_name = "REGEX", value_parser = redact::parse_pattern, conflicts_with = "each_output", help = "Blur text matching this extended regular expression, or email, iban or card; can be repeated")]
+    redact_text: Vec<String>,
+
+    #[arg(long, value_enum, value_name = "PRESET", conflicts_with_all = ["each_output", "append"], help = "Bring the screenshot to standard dimensions for sharing")]
+    size_preset: Option<preset::Preset>,
+
+    #[arg(long, value_enum, default_value_t = preset::Fit::Pad, help = "How --size-preset fits screenshots of another aspect ratio")]
+    size_fit: preset::Fit,
+
+    #[arg(long, value_name = "COLOR", default_value = "#FFFFFF", value_parser = selector::parse_color, help = "Color around the screenshot that --size-preset pads, as #RRGGBB or #RRGGBBAA")]
+    size_fill: String,
+
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "8", value_parser = clap::value_parser!(u16).range(1..=256), conflicts_with = "each_output", help = "Print the screenshot's N dominant colors as hex values [default: 8]")]
+    palette: Option<u16>,
+
+    #[arg(long, value_name = "PATH", requires = "palette", help = "Also write the --palette colors to an image of swatches")]
+    palette_image: Option<String>,
+
+    #[arg(long, value_name = "COMMAND", default_value = "xdg-open", help = "Program used by the notification's Open action")]
+    viewer: String,
+
+    #[arg(long, value_name = "DIR", global = true, help = "Directory screenshots are saved to [default: ~/Pictures/Screenshots]")]
+    dir: Option<String>,
+
+    #[arg(long, value_name = "COMMAND", default_value = "swappy -f", help = "Program used by the notification's Edit action")]
+    editor: String,
+
+    #[arg(long, help = "Open the saved screenshot in the --editor")]
+    edit: bool,
+
+    #[arg(long, conflicts_with = "each_output", help = "Show the screenshot in the terminal, with the kitty graphics protocol or sixel")]
+    preview_term: bool,
+
+    #[arg(long, visible_alias = "dnd", conflicts_with = "each_output", help = "Open a small window to drag the saved screenshot into another app")]
+    drag: bool,
+
+    #[arg(long, value_name = "PRINTER", num_args = 0..=1, require_equals = true, default_missing_value = "", conflicts_with = "each_output", help = "Print the screenshot with lp, on PRINTER or the default printer")]
+    print: Option<String>,
+
+    #[arg(long, conflicts_with = "each_output", help = "Show the saved screenshot in the file manager")]
+    reveal: bool,
+
+    #[arg(long, conflicts_with = "each_output", help = "Make the saved screenshot the wallpaper")]
+    set_wallpaper: bool,
+
+    #[arg(long, value_name = "COMMAND", help = "Program that sets the wallpaper, given the screenshot's path [default: swww, hyprpaper or swaybg, whichever is running]")]
+    wallpaper_command: Option<String>,
+
+    #[arg(long, value_name = "COLOR", global = true, value_parser = selector::parse_color, help = "Tint of the screen outside the selection, as #RRGGBB or #RRGGBBAA [default: #FFFFFF44]")]
+    selection_background: Option<String>,
+
+    #[arg(long, value_name = "COLOR", global = true, value_parser = selector::parse_color, help = "Color of the selection's border [default: slurp's]")]
+    selection_border: Option<String>,
+
+    #[arg(long, value_name = "COLOR", global = true, value_parser = selector::parse_color, help = "Tint of the selection itself [default: slurp's]")]
+    selection_fill: Option<String>,
+
+    #[arg(long, value_enum, value_name = "THEME", global = true, help = "Take the selection colors that aren't set from this color scheme")]
+    selection_theme: Option<selector::Theme>,
+
+    #[arg(long, value_name = "PIXELS", global = true, help = "Width of the selection's border [default: slurp's]")]
+    selection_border_width: Option<u32>,
+
+    #[arg(long, value_name = "FAMILY", global = true, help = "Font of the selection's dimensions [default: slurp's]")]
+    selection_font: Option<String>,
+
+    #[arg(long, value_name = "PIXELS", num_args = 0..=1, require_equals = true, default_missing_value = snap::DEFAULT_DISTANCE, global = true, help = "Snap the edges of a selected region to window borders, monitor edges and the halves and thirds of the screen within PIXELS [default: 12]")]
+    snap: Option<u32>,
+
+    #[arg(long, value_enum, global = true, default_value_t = geometry::Notation::Slurp, help = "How geometries are printed")]
+    geometry_format: geometry::Notation,
+
+    #[arg(long, conflicts_with = "each_output", help = "Write the text recognized in the saved screenshot next to it, to <screenshot>.txt")]
+    ocr_sidecar: bool,
+
+    #[arg(long, value_enum, global = true, default_value_t = ocr::Engine::Tesseract, help = "Where text is recognized")]
+    ocr_engine: ocr::Engine,
+
+    #[arg(long, value_name = "LANG", global = true, value_delimiter = '+', help = "Languages to recognize text in, e.g. jpn or deu+eng; can be repeated [default: the engine's]")]
+    ocr_lang: Vec<String>,
+
+    #[arg(long, value_name = "URL", global = true, help = "Endpoint of the remote OCR engine, sent the image as the multipart field `file`")]
+    ocr_endpoint: Option<String>,
+
+    #[arg(long, value_name = "COMMAND", global = true, help = "Shell command that translates the recognized text on its stdin; the translation is copied, and sidecars keep the original")]
+    translate_command: Option<String>,
+
+    #[arg(long, global = true, help = "Delete screenshots permanently instead of moving them to the trash")]
+    purge: bool,
+
+    #[arg(long, value_enum, default_value_t = OnConflict::Quit, help = "What to do when another luminashot capture is already running")]
+    on_conflict: OnConflict,
+
+    #[arg(long, value_name = "SECS", default_value_t = 10.0, value_parser = parse_seconds, help = "Maximum time to wait for external commands like grim, hyprctl and wl-copy")]
+    timeout: f64,
+
+    #[arg(long, value_name = "SECS", default_value_t = 0.0, value_parser = parse_seconds, help = "Maximum time to wait for an interactive selection (0 waits forever)")]
+    selection_timeout: f64,
+
+    #[arg(long, visible_alias = "no-notify", help = "Don't show a notification or any other capture feedback")]
+    silent: bool,
+
+    #[arg(long, help = "Print a JSON summary of the capture, with how long it took, and progress messages on stderr")]
+    json: bool,
+
+    #[arg(long, conflicts_with = "each_output", help = "Print how long each stage of the capture took, and add them to the --json summary")]
+    timings: bool,
+
+    #[arg(long, help = "Show notifications even when the notification server is in do-not-disturb mode")]
+    ignore_dnd: bool,
+
+    #[arg(long, value_name = "COMMAND", help = "Shell command that exits successfully when do-not-disturb is on, instead of asking the notification server")]
+    dnd_command: Option<String>,
+
+    #[arg(long, help = "Briefly flash the screen after capturing")]
+    flash: bool,
+
+    #[arg(long, help = "Freeze the screen while selecting, so what is selected holds still (requires hyprpicker)")]
+    freeze: bool,
+
+    #[arg(long, help = "Turn off Hyprland animations until the capture is taken, so nothing is caught mid-animation")]
+    no_animations: bool,
+
+    #[arg(long, help = "Turn off blur, shadows and dimming of inactive windows until the capture is taken")]
+    clean: bool,
+
+    #[arg(long, help = "Hide the mouse cursor while the screenshot is taken")]
+    hide_cursor: bool,
+
+    #[arg(long, help = "In window mode, include the window's border in the capture")]
+    with_border: bool,
+
+    #[arg(long, help = "In window mode, include the border and the gaps around the window in the capture")]
+    with_gaps: bool,
+
+    #[arg(long, help = "In window mode, focus the selected window before capturing it, so it is drawn as the active window")]
+    focus: bool,
+
+    #[arg(long, value_name = "NAME", default_value = "LuminaShot", help = "Application name shown in notifications")]
+    app_name: String,
+
+    #[arg(long, value_name = "ICON", help = "Icon name or image path for notifications, instead of the screenshot itself")]
+    notify_icon: Option<String>,
+
+    #[arg(long, help = "Keep each capture's notification instead of replacing the previous one")]
+    stack_notifications: bool,
+
+    #[arg(long, value_name = "MS", help = "Close the notification after this many milliseconds (0 keeps it until dismissed)")]
+    notify_timeout: Option<u32>,
+
+    #[arg(long, value_enum, default_value_t = notify::Urgency::Normal, help = "Urgency of the notification")]
+    urgency: notify::Urgency,
+
+    #[arg(long, value_name = "TEMPLATE", help = "Notification text; supports {path}, {geometry}, {size}, {filesize} and {mode}")]
+    notify_body: Option<String>,
+
+    #[arg(long, value_enum, value_name = "ACTION", value_delimiter = ',', default_values = ["edit", "reveal", "delete", "retake"], help = "Buttons on the capture notification, comma-separated; clicking the notification itself opens the screenshot")]
+    notify_actions: Vec<NotifyAction>,
+}
+
+/// Parses a non-negative number of seconds.
+fn parse_seconds(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(secs) if secs.is_finite() && secs >= 0.0 => Ok(secs),
+        _ => Err(format!("`{}` is not a valid number of seconds", value)),
+    }
+}
+
+/// Parses a positive factor for an image adjustment.
+fn parse_factor(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(factor) if factor.is_finite() && factor > 0.0 => Ok(factor),
+        _ => Err(format!("`{}` is not a valid factor", value)),
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Print the monitors, windows or workspaces that can be captured
+    List {
+        #[arg(value_enum)]
+        kind: list::ListKind,
+
+        #[arg(long, help = "Print JSON instead of tab-separated lines")]
+        json: bool,
+    },
+    /// Print recent captures, newest first
+    History {
+        #[arg(long, value_name = "N", default_value_t = 20, help = "Number of captures to print (0 prints all)")]
+        limit: usize,
+
+        #[arg(long, help = "Print JSON instead of tab-separated lines")]
+        json: bool,
+    },
+    /// Delete old screenshots and history entries
+    Clean {
+        #[arg(long, value_name = "AGE", value_parser = clean::parse_age, help = "Delete captures older than this, e.g. 30d, 12h or 2w")]
+        older_than: Option<chrono::TimeDelta>,
+
+        #[arg(long, value_name = "N", help = "Always keep this many of the newest captures")]
+        keep_last: Option<usize>,
+
+        #[arg(long, help = "Never delete captures marked as favorites")]
+        keep_favorites: bool,
+
+        #[arg(long, help = "Only print what would be deleted")]
+        dry_run: bool,
+    },
+    /// Act on the most recent saved capture; prints its path by default
+    Last {
+        #[arg(short, long, help = "Copy it to the clipboard again")]
+        copy: bool,
+
+        #[arg(short, long, conflicts_with = "copy", help = "Open it with the viewer")]
+        open: bool,
+
+        #[arg(short, long, conflicts_with_all = ["copy", "open"], help = "Print its path")]
+        path: bool,
+
+        #[arg(short, long, conflicts_with_all = ["copy", "open", "path"], help = "Upload it and copy the URL")]
+        upload: bool,
+
+        #[arg(long, value_enum, default_value_t = upload::Service::ZeroXZero, help = "Where --upload sends the screenshot")]
+        service: upload::Service,
+
+        #[arg(long, value_name = "ID", help = "imgur API client ID, needed for --service imgur")]
+        imgur_client_id: Option<String>,
+    },
+    /// Upload an existing screenshot and copy its URL
+    Upload {
+        /// The image file to upload
+        path: String,
+
+        #[arg(long, value_enum, default_value_t = upload::Service::ZeroXZero, help = "Where to upload the image")]
+        service: upload::Service,
+
+        #[arg(long, value_name = "ID", help = "imgur API client ID, needed for --service imgur")]
+        imgur_client_id: Option<String>,
+    },
+    /// Put the newest screenshots side by side in one labelled image
+    Collage {
+        #[arg(long, value_name = "N", default_value_t = 4, value_parser = clap::value_parser!(u16).range(1..), help = "Number of screenshots to put in it")]
+        last: u16,
+
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..), help = "Number of columns [default: about as many as rows]")]
+        cols: Option<u16>,
+
+        #[arg(short, long, help = "Also copy the collage to the clipboard")]
+        copy: bool,
+    },
+    /// Browse past captures in the terminal
+    Gallery {
+        #[arg(long, value_enum, default_value_t = upload::Service::ZeroXZero, help = "Where the upload key sends screenshots")]
+        service: upload::Service,
+
+        #[arg(long, value_name = "ID", help = "imgur API client ID, needed for --service imgur")]
+        imgur_client_id: Option<String>,
+    },
+    /// Select a region, annotate it in swappy, then save, copy or upload it
+    Gui {
+        #[arg(long, value_enum, default_value_t = upload::Service::ZeroXZero, help = "Where the Upload button sends the screenshot")]
+        service: upload::Service,
+
+        #[arg(long, value_name = "ID", help = "imgur API client ID, needed for --service imgur")]
+        imgur_client_id: Option<String>,
+    },
+    /// Print the text in an existing screenshot (requires tesseract)
+    Ocr {
+        /// The image file to read
+        path: String,
+
+        #[arg(short, long, help = "Also copy the text to the clipboard")]
+        copy: bool,
+
+        #[arg(long, value_name = "LANG", value_delimiter = '+', help = "Language codes, e.g. deu or deu+eng; can be repeated [default: --ocr-lang]")]
+        lang: Vec<String>,
+    },
+    /// Find saved captures by the text in them (requires tesseract)
+    Search {
+        /// Words that must all appear in the screenshot
+        query: String,
+    },
+    /// Record the screen to a video file (requires wf-recorder)
+    Record {
+        #[arg(value_enum, default_value_t = Mode::Monitor, help = "What to record")]
+        mode: Mode,
+
+        #[arg(long, help = "Stop the running recording instead, if there is one")]
+        toggle: bool,
+
+        #[arg(long, conflicts_with = "toggle", help = "Pause the running recording")]
+        pause: bool,
+
+        #[arg(long, conflicts_with_all = ["toggle", "pause"], help = "Resume the paused recording")]
+        resume: bool,
+
+        #[arg(long, value_name = "SOURCE", num_args = 0..=1, require_equals = true, default_missing_value = "system", help = "Also record audio: system, mic or a PulseAudio source name [default: system]")]
+        audio: Option<String>,
+
+        #[arg(long, value_enum, default_value_t = record::Encoder::Software, help = "Video encoder; hardware encoders fall back to software when unavailable")]
+        encoder: record::Encoder,
+
+        #[arg(long, value_name = "DEVICE", num_args = 0..=1, require_equals = true, default_missing_value = "", conflicts_with_all = ["audio", "pause", "resume"], help = "Stream to a v4l2loopback device to use as a webcam instead of recording a file [default: the first one]")]
+        to_virtual_cam: Option<String>,
+
+        #[arg(long, conflicts_with_all = ["toggle", "pause", "resume"], help = "Print whether a recording is running as JSON for status bars like waybar")]
+        status: bool,
+
+        #[arg(long, requires = "status", help = "Keep printing the --status every second")]
+        watch: bool,
+    },
+    /// Select a region and print its average color, without saving an image
+    AverageColor {
+        #[arg(short, long, help = "Also copy the hex value to the clipboard")]
+        copy: bool,
+
+        #[arg(long, help = "Print JSON instead of the hex and rgb() values")]
+        json: bool,
+    },
+    /// Print the state of LuminaShot as JSON for a waybar custom module
+    Waybar {
+        #[arg(long, help = "Keep running and print it again whenever it changes")]
+        watch: bool,
+    },
+    /// Register global shortcuts with the compositor and capture when they are pressed
+    Daemon {
+        #[arg(long, help = "Also show an icon in the system tray with the shortcuts and recent captures")]
+        tray: bool,
+    },
+    /// Print recommended Hyprland keybindings, or install them into a file hyprland.conf sources
+    Keybinds {
+        #[arg(long, value_name = "MOD", help = "Main modifier of the bindings [default: $mainMod if hyprland.conf defines it, otherwise SUPER]")]
+        modifier: Option<String>,
+
+        #[arg(long, help = "Bind the global shortcuts of `luminashot daemon` instead of running commands")]
+        global: bool,
+
+        #[arg(long, help = "Write them to ~/.config/hypr/luminashot.conf instead of printing them")]
+        install: bool,
+
+        #[arg(long, requires = "install", help = "Overwrite an existing luminashot.conf")]
+        force: bool,
+    },
+    /// Create, inspect or check the config file
+    Config {
+        #[command(subcommand)]
+        action: config::ConfigAction,
+    },
 }
 
 #[derive(ValueEnum, Clone, Debug)]
 enum Mode {
     Region,
     Window,
+    #[value(alias = "output")]
     Monitor,
+    /// Print the position, color and window of picked pixels instead of capturing
+    Inspect,
+}
+
+/// The buttons the capture notification can offer.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum NotifyAction {
+    Edit,
+    /// Show the screenshot in the file manager
+    Reveal,
+    Delete,
+    Favorite,
+    Wallpaper,
+    Copy,
+    Print,
+    Retake,
+}
+
+impl NotifyAction {
+    /// Returns the action key and the untranslated button label.
+    fn button(self) -> (&'static str, &'static str) {
+        match self {
+            NotifyAction::Edit => ("edit", "Edit"),
+            NotifyAction::Reveal => ("reveal", "Show in Folder"),
+            NotifyAction::Delete => ("delete", "Delete"),
+            NotifyAction::Favorite => ("favorite", "Favorite"),
+            NotifyAction::Wallpaper => ("wallpaper", "Set as Wallpaper"),
+            NotifyAction::Copy => ("copy", "Copy"),
+            NotifyAction::Print => ("print", "Print"),
+            NotifyAction::Retake => ("retake", "Retake"),
+        }
+    }
+
+    /// Returns true if the action works on the saved file, so it is left out when nothing was saved.
+    fn needs_file(self) -> bool {
+        !matches!(self, NotifyAction::Copy | NotifyAction::Print | NotifyAction::Retake)
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug, PartialEq)]
+enum OnConflict {
+    /// Exit quietly and leave the running capture alone
+    Quit,
+    /// Cancel the running capture and start a new one
+    Replace,
 }
 
 // --- Main Application Logic ---
 
-#[tokio::main]
+/// How long to keep listening for clicks on the capture notification's buttons.
+const NOTIFICATION_ACTION_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Time given to the notification server to take the countdown off the screen before capturing.
+const COUNTDOWN_CLOSE_GRACE: Duration = Duration::from_millis(300);
+
+/// Options that take a template, and the placeholders they support.
+const TEMPLATE_OPTIONS: config::TemplateOptions = &[
+    ("notify-body", &["path", "geometry", "size", "filesize", "mode"]),
+];
+
+/// Time given to the compositor to draw a frame without the cursor before capturing it.
+const CURSOR_HIDE_GRACE: Duration = Duration::from_millis(50);
+
+/// Time given to the compositor to redraw borders and dimming after focusing a window.
+const FOCUS_SETTLE_DELAY: Duration = Duration::from_millis(200);
+
+/// Largest side of the preview sent inline with clipboard-only notifications.
+const NOTIFICATION_PREVIEW_SIZE: u32 = 256;
+
+/// The command line this run acts on: the process arguments, or the ones a standby process
+/// was handed by the daemon.
+static COMMAND_LINE: OnceLock<Vec<OsString>> = OnceLock::new();
+
+/// When this run was asked for, which is where latencies are measured from.
+static TRIGGERED: OnceLock<Instant> = OnceLock::new();
+
+/// Whether progress messages go to stderr, keeping stdout for the `--json` summary or the
+/// image itself.
+static JSON_OUTPUT: OnceLock<bool> = OnceLock::new();
+
+/// What `--json` prints once a capture is done.
+#[derive(Serialize)]
+struct CaptureSummary<'a> {
+    path: Option<&'a str>,
+    copied: bool,
+    geometry: String,
+    mode: &'a str,
+    window_title: Option<&'a str>,
+    bytes: usize,
+    /// From starting luminashot, or pressing a daemon shortcut, until the image was saved
+    /// and copied. Includes the time spent selecting.
+    latency_ms: f64,
+    /// From the end of the selection and any delay until the image was saved and copied.
+    capture_latency_ms: f64,
+    /// With `--timings`, how long each stage took.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timings: Option<timings::Timings>,
+    /// With `--palette`, the dominant colors.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    palette: Option<&'a [palette::Swatch]>,
+}
+
+// Everything here waits on child processes and sockets, so a single thread is plenty, and
+// every keypress is spared starting a pool of worker threads.
+#[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<()> {
-    let mut cli = Cli::parse();
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    // A standby process has already paid for starting up, and only waits for the daemon
+    // to tell it what to do.
+    if args.get(1).is_some_and(|arg| arg == daemon::STANDBY_ARG) {
+        match daemon::wait_for_activation()? {
+            Some(handed_over) => args = handed_over,
+            None => return Ok(()),
+        }
+    }
+    if args.get(1).is_some_and(|arg| arg == clipboard::SERVE_ARG) {
+        return clipboard::serve(&args[2..]);
+    }
+    // A notification listener is started with the command line of the run that showed the
+    // notification, whose options the actions follow.
+    let watching = args.get(1).is_some_and(|arg| arg == notify::WATCH_ARG);
+    if watching {
+        args.remove(1);
+    }
+    let _ = TRIGGERED.set(Instant::now());
+    let Some(args) = compat::translate(args).await? else {
+        return Ok(());
+    };
+    let _ = COMMAND_LINE.set(args.clone());
+
+    // Defaults from the config file are inserted ahead of the real arguments.
+    let cli = Cli::parse_from(config::args_with_config(&Cli::command(), args)?);
+
+    let result = tokio::select! {
+        result = run(cli, watching) => result,
+        signal = cleanup::shutdown_signal() => {
+            // The capture future has been dropped at this point, which killed any
+            // running slurp/grim/wl-copy children and removed partial temp files.
+            let signal = signal?;
+            eprintln!("{}", i18n::tr_with("Interrupted by {signal}, exiting.", &[("signal", signal.to_string())]));
+            std::process::exit(cleanup::exit_code(signal));
+        }
+    };
+    // Reported the way returning the error from main would, but with the prefix translated.
+    if let Err(err) = result {
+        eprintln!("{}: {:?}", i18n::tr("Error"), err);
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+async fn run(mut cli: Cli, watching: bool) -> Result<()> {
+    process::set_timeouts(process::Timeouts {
+        command: Duration::from_secs_f64(cli.timeout),
+        selection: (cli.selection_timeout > 0.0).then(|| Duration::from_secs_f64(cli.selection_timeout)),
+    });
+    geometry::set_notation(cli.geometry_format);
+    selector::set_style(selector::Style {
+        background: cli.selection_background.clone(),
+        border: cli.selection_border.clone(),
+        fill: cli.selection_fill.clone(),
+        border_width: cli.selection_border_width,
+        font: cli.selection_font.clone(),
+        theme: cli.selection_theme,
+    });
+    snap::set_distance(cli.snap);
+    save::set_screenshots_dir(cli.dir.clone());
+    clipboard::set_hold(cli.clipboard_hold);
+    clipboard::set_backend(cli.clipboard_backend);
+    if watching {
+        return watch_notification(&cli).await;
+    }
+
+    match cli.command.take() {
+        Some(Commands::List { kind, json }) => list::list(kind, json).await,
+        Some(Commands::History { limit, json }) => history::print(limit, json).await,
+        Some(Commands::Search { query }) => search::search(&query, &ocr_options(&cli)).await,
+        Some(Commands::Gallery { service, imgur_client_id }) => {
+            gallery::gallery(gallery::GalleryOptions {
+                viewer: &cli.viewer,
+                purge: cli.purge,
+                service,
+                imgur_client_id: imgur_client_id.as_deref(),
+            }).await
+        }
+        Some(Commands::Gui { service, imgur_client_id }) => {
+            notify::set_app_name(cli.app_name.clone());
+            gui::gui(gui::GuiOptions {
+                latest_link: &cli.latest_link,
+                service,
+                imgur_client_id: imgur_client_id.as_deref(),
+            }).await
+        }
+        Some(Commands::Last { copy, open, path: _, upload, service, imgur_client_id }) => {
+            let action = match (copy, open, upload) {
+                (true, _, _) => last::LastAction::Copy,
+                (_, true, _) => last::LastAction::Open,
+                (_, _, true) => last::LastAction::Upload,
+                _ => last::LastAction::Path,
+            };
+            last::last(action, last::LastOptions {
+                viewer: &cli.viewer,
+                service,
+                imgur_client_id: imgur_client_id.as_deref(),
+            }).await
+        }
+        Some(Commands::Collage { last, cols, copy }) => {
+            collage::collage(collage::CollageOptions { last: last as usize, columns: cols.map(usize::from), copy }).await
+        }
+        Some(Commands::Ocr { path, copy, lang }) => {
+            let mut options = ocr_options(&cli);
+            if !lang.is_empty() {
+                options.langs = lang;
+            }
+            ocr::ocr_file(&path, &options, copy).await
+        }
+        Some(Commands::Upload { path, service, imgur_client_id }) => upload::upload_and_share(&path, service, imgur_client_id.as_deref()).await,
+        Some(Commands::Clean { older_than, keep_last, keep_favorites, dry_run }) => {
+            clean::clean(clean::Retention { older_than, keep_last, keep_favorites }, dry_run, cli.purge).await
+        }
+        Some(Commands::Record { status: true, watch, .. }) => record::status(watch).await,
+        Some(Commands::Record { mode, toggle, pause, resume, audio, encoder, to_virtual_cam, .. }) => {
+            notify::set_app_name(cli.app_name.clone());
+            record::record(record::RecordOptions {
+                mode,
+                toggle,
+                pause,
+                resume,
+                audio: audio.as_deref(),
+                encoder,
+                virtual_cam: to_virtual_cam.as_deref(),
+                silent: cli.silent,
+                ignore_dnd: cli.ignore_dnd,
+                dnd_command: cli.dnd_command.as_deref(),
+            }).await
+        }
+        Some(Commands::AverageColor { copy, json }) => {
+            average::average_color(average::AverageOptions { geometry: cli.geometry, copy, json }).await
+        }
+        Some(Commands::Waybar { watch }) => waybar::waybar(watch).await,
+        Some(Commands::Daemon { tray }) => daemon::daemon(daemon::DaemonOptions { tray, viewer: &cli.viewer }).await,
+        Some(Commands::Keybinds { modifier, global, install, force }) => {
+            keybinds::keybinds(keybinds::KeybindsOptions { modifier, global, install, force })
+        }
+        Some(Commands::Config { action }) => match action {
+            config::ConfigAction::Init { force, interactive } => config::init(&Cli::command(), force, interactive),
+            config::ConfigAction::Show => config::show(&Cli::command(), TEMPLATE_OPTIONS),
+            config::ConfigAction::Validate => config::validate(&Cli::command(), TEMPLATE_OPTIONS),
+        },
+        None => capture(cli).await,
+    }
+}
 
+/// Takes a screenshot, the default when no subcommand is given.
+async fn capture(mut cli: Cli) -> Result<()> {
     // Default action is to save if no output flag is specified.
-    if !cli.copy && !cli.save {
+    if (!cli.copy && !cli.save) || cli.file.is_some() {
         cli.save = true;
     }
+    let to_stdout = cli.file.as_deref() == Some("-");
+    if to_stdout && (cli.json || cli.palette.is_some()) {
+        anyhow::bail!("--file - can't be combined with --json or --palette, which print to stdout too");
+    }
+
+    notify::set_app_name(cli.app_name.clone());
+    let _ = JSON_OUTPUT.set(cli.json || to_stdout);
+    filter::check_format(cli.format, &edits(&cli))?;
+    if !cli.redact_text.is_empty() && cli.format != format::ImageFormat::Png {
+        anyhow::bail!("--redact-text only works with --format png");
+    }
+    if cli.size_preset.is_some() && cli.format != format::ImageFormat::Png {
+        anyhow::bail!("--size-preset only works with --format png");
+    }
+    if cli.palette.is_some() && cli.format != format::ImageFormat::Png {
+        anyhow::bail!("--palette only works with --format png");
+    }
+    if cli.preview_term && cli.format != format::ImageFormat::Png {
+        anyhow::bail!("--preview-term only works with --format png");
+    }
+    if cli.montage && cli.format != format::ImageFormat::Png {
+        anyhow::bail!("--montage only works with --format png");
+    }
+    if cli.edit && !cli.save {
+        anyhow::bail!("--edit needs the screenshot to be saved; add --save");
+    }
+    if cli.drag && !cli.save {
+        anyhow::bail!("--drag needs the screenshot to be saved; add --save");
+    }
+    if cli.reveal && !cli.save {
+        anyhow::bail!("--reveal needs the screenshot to be saved; add --save");
+    }
+    if cli.set_wallpaper && !cli.save {
+        anyhow::bail!("--set-wallpaper needs the screenshot to be saved; add --save");
+    }
+    if let Some(path) = &cli.append {
+        if cli.format != format::ImageFormat::Png {
+            anyhow::bail!("--append only works with --format png");
+        }
+        stitch::check_path(path)?;
+    }
 
-    let geometry = match cli.mode {
-        Mode::Region => region_mode().await?,
-        Mode::Window => window_mode().await?,
-        Mode::Monitor => monitor_mode().await?,
+    let Some(instance_lock) = instance::acquire(cli.on_conflict == OnConflict::Replace).await? else {
+        eprintln!("{}", i18n::tr("Another capture is already in progress."));
+        return Ok(());
     };
 
+    if !compositor::is_hyprland() {
+        let needs_hyprland = [
+            (matches!(cli.mode, Mode::Window), "--mode window"),
+            (matches!(cli.mode, Mode::Inspect), "--mode inspect"),
+            (cli.window_class.is_some(), "--window-class"),
+            (cli.pid.is_some(), "--pid"),
+            (cli.each_output, "--each-output"),
+        ];
+        if let Some((_, option)) = needs_hyprland.iter().find(|(given, _)| *given) {
+            anyhow::bail!("{}", i18n::tr_with("{option} needs Hyprland, but HYPRLAND_INSTANCE_SIGNATURE is not set. On other compositors, use --mode region, --mode monitor or --geometry.", &[("option", option.to_string())]));
+        }
+    }
+    compositor::restore_stale_overrides().await;
+
+    if matches!(cli.mode, Mode::Inspect) {
+        return inspect::inspect(&cli).await;
+    }
+
+    // Applied before selecting, so slurp's own fade-out can't end up in the image either,
+    // and what is selected looks the way it will be captured.
+    let overrides = compositor_overrides(&cli).await;
+
+    if cli.each_output {
+        return each_output::capture(&cli, overrides).await;
+    }
+
+    // Started before selecting, and only closed once grim has captured the frozen frame.
+    let frozen = if cli.freeze { freeze::freeze().await } else { None };
+
+    let mut window_title: Option<String> = None;
+    let mut reverts = Vec::new();
+    let (geometry, mode_label) = if let Some(rect) = cli.retake {
+        (Some(rect), cli.retake_mode.clone().unwrap_or_else(|| "Geometry".to_string()))
+    } else if cli.last {
+        let last = state::load_last_capture().await?;
+        (Some(last.geometry), last.mode)
+    } else if let Some(rect) = cli.geometry {
+        (Some(rect), "Geometry".to_string())
+    } else if cli.window_class.is_some() || cli.pid.is_some() {
+        let query = target::WindowQuery { class: cli.window_class.as_deref(), pid: cli.pid };
+        let (mut window, window_reverts) = target::find_window(&query, cli.other_workspace, cli.reveal_hidden).await?;
+        reverts = window_reverts;
+        if cli.focus {
+            window = focus_window(window).await?;
+        }
+        window_title = Some(window.title.clone());
+        (Some(window_rect(&cli, &window).await?), "Window".to_string())
+    } else {
+        let geometry = match cli.mode {
+            Mode::Region => region_mode().await?,
+            Mode::Window => match window_mode().await? {
+                Some(mut window) => {
+                    if cli.focus {
+                        window = focus_window(window).await?;
+                    }
+                    window_title = Some(window.title.clone());
+                    Some(window_rect(&cli, &window).await?)
+                }
+                None => None,
+            },
+            Mode::Monitor => monitor_mode().await?,
+            Mode::Inspect => unreachable!("inspect mode doesn't capture"),
+        };
+        (geometry, format!("{:?}", cli.mode))
+    };
+
+    // `--last` and retakes repeat the capture as it was, margins included.
+    let geometry = match geometry {
+        Some(rect) if !cli.last && cli.retake.is_none() => Some(with_margins(&cli, rect)?),
+        geometry => geometry,
+    };
+
+    if cli.measure {
+        return match geometry {
+            Some(rect) => measure(&cli, &rect).await,
+            None => {
+                progress(&i18n::tr("Action cancelled."));
+                Ok(())
+            }
+        };
+    }
+
     if let Some(geom) = geometry {
-        println!("Capturing geometry: {}", geom);
+        // During a presentation, no notification may pop up over the shared screen.
+        let do_not_disturb = !cli.silent && !cli.ignore_dnd && notify::do_not_disturb(cli.dnd_command.as_deref()).await;
+
+        if cli.delay > 0.0 {
+            countdown(Duration::from_secs_f64(cli.delay), cli.silent || do_not_disturb).await;
+        }
+
+        // Only hidden now, since selecting needs the cursor.
+        let hidden_cursor = if cli.hide_cursor { hide_cursor().await } else { None };
+
+        progress(&i18n::tr_with("Capturing geometry: {geometry}", &[("geometry", geom.printed())]));
+        let capture_started = Instant::now();
+
+        // wl-copy is fed while grim is still encoding, instead of once the whole image is in
+        // memory. Filtered images are only ready once grim is done, and the native backend
+        // and a hold in seconds take a clipboard owner of our own.
+        let streams = cli.copy && !cli.copy_text && !cli.confirm && edits(&cli).is_empty() && cli.redact_text.is_empty() && cli.size_preset.is_none() && cli.append.is_none()
+        && !clipboard::needs_owner();
+        let mut clipboard = if streams { Some(ClipboardCopy::start(cli.format, cli.wait_for_paste)?) } else { None };
+        let streamed_to_clipboard = clipboard.is_some();
+        let image_buffer = capture_geometry_to_buffer(&geom, cli.format, cli.quality, clipboard.as_mut()).await?;
+        let mut image_buffer = filter::apply(image_buffer, &edits(&cli))?;
+        // The text is recognized here anyway, so it is reused for what else needs it.
+        let mut redacted_text = None;
+        if !cli.redact_text.is_empty() {
+            let redacted = redact::redact(image_buffer, &cli.redact_text, &ocr_options(&cli)).await.context("Could not redact the screenshot, so it was not kept")?;
+            if redacted.words > 0 {
+                progress(&i18n::tr_with("Blurred {count} word(s) matching --redact-text", &[("count", redacted.words.to_string())]));
+            }
+            image_buffer = redacted.image;
+            redacted_text = Some(redacted.text);
+        }
+        if let Some(size_preset) = cli.size_preset {
+            image_buffer = preset::apply(&image_buffer, size_preset, cli.size_fit, &cli.size_fill)?;
+        }
+        if let Some(path) = &cli.append {
+            let appended = stitch::append(path, &image_buffer).await?;
+            progress(&i18n::tr_with("Appended to {path}, now {height} pixels tall ({rows} repeated rows left out)", &[
+                ("path", path.clone()),
+                ("height", appended.height.to_string()),
+                ("rows", appended.overlap.to_string()),
+            ]));
+            image_buffer = appended.image;
+        }
+        drop(frozen);
+        drop(hidden_cursor);
+        // Recorded while the workspace an --other-workspace window was captured on still shows.
+        let captured = state::LastCapture { geometry: geom, mode: mode_label.clone() };
+        state::save_last_capture(&captured).await;
+        drop(reverts);
+        drop(overrides);
+
+        // The flash runs alongside saving and copying; grim is done, so it can't end up in the image.
+        let flash = (cli.flash && !cli.silent).then(|| tokio::spawn(flash::flash()));
+
+        let mut edit_after = cli.edit;
+        if cli.confirm {
+            let choices = [confirm::Choice::Save, confirm::Choice::Copy, confirm::Choice::Edit, confirm::Choice::Discard];
+            match confirm::ask(&image_buffer, cli.format, &mode_label, &choices, NOTIFICATION_ACTION_TIMEOUT).await? {
+                confirm::Choice::Keep => {}
+                confirm::Choice::Save => (cli.save, cli.copy) = (true, false),
+                confirm::Choice::Copy => (cli.save, cli.copy) = (false, true),
+                confirm::Choice::Edit => (cli.save, edit_after) = (true, true),
+                confirm::Choice::Upload => unreachable!("--confirm doesn't offer uploading"),
+                confirm::Choice::Discard => {
+                    progress(&i18n::tr("Screenshot discarded."));
+                    return Ok(());
+                }
+            }
+        }
+
+        // Recognized ahead of saving, since the file name may need it.
+        let names_by_content = cli.save && cli.file.is_none() && cli.append.is_none()
+        && template::placeholders(&cli.filename).iter().any(|name| save::CONTENT_PLACEHOLDERS.contains(name));
+        let wants_text = (cli.copy && cli.copy_text) || (cli.save && cli.ocr_sidecar) || names_by_content;
+        let text = match (wants_text, redacted_text) {
+            (false, _) => None,
+            (true, Some(text)) => Some(text).filter(|text| !text.trim().is_empty()),
+            (true, None) => recognized_text(&image_buffer, &cli).await,
+        };
 
-        // Capture the image data into a buffer in memory first.
-        let image_buffer = capture_geometry_to_buffer(&geom).await?;
         let mut file_path: Option<String> = None;
+        let mut note: Option<String> = None;
+
+        // The file is written while wl-copy takes over the clipboard.
+        let (outcome, copied) = tokio::join!(
+            async {
+                if let Some(path) = &cli.append {
+                    let written = timings::time(timings::Stage::Save, stitch::write(path, &image_buffer)).await;
+                    Some(written.map(save::SaveOutcome::Saved))
+                } else if let (true, Some(path)) = (cli.save, &cli.file) {
+                    Some(timings::time(timings::Stage::Save, save::save_buffer_to_path(&image_buffer, cli.format, path)).await)
+                } else if cli.save {
+                    let file_name = save::file_name(&cli.filename, &mode_label, text.as_deref());
+                    Some(timings::time(timings::Stage::Save, save::save_buffer_named(&image_buffer, cli.format, &file_name, cli.overwrite)).await)
+                } else {
+                    None
+                }
+            },
+            async {
+                if let Some(clipboard) = clipboard {
+                    Some(timings::time(timings::Stage::Copy, clipboard.finish()).await)
+                } else {
+                    None
+                }
+            },
+        );
+        let mut clipboard_owner = copied.transpose()?.flatten();
+
+        if let Some(outcome) = outcome.transpose()? {
+            file_path = outcome.path().map(String::from);
+            if let (Some(path), None, None) = (&file_path, &cli.append, &cli.file) {
+                save::update_latest_link(&cli.latest_link, path).await;
+            }
+
+            // Don't lose the capture when the disk is full: make sure it is at least on the clipboard.
+            match &outcome {
+                save::SaveOutcome::Saved(_) | save::SaveOutcome::Stdout => {}
+                save::SaveOutcome::Diverted { full_dir, .. } => {
+                    note = Some(i18n::tr_with("Not enough space in {dir}.", &[("dir", full_dir.clone())]));
+                    cli.copy = true;
+                }
+                save::SaveOutcome::NoSpace { full_dir } => {
+                    note = Some(i18n::tr_with("Not enough space in {dir}, the screenshot was not saved.", &[("dir", full_dir.clone())]));
+                    cli.copy = true;
+                }
+            }
+            if let Some(note) = &note {
+                eprintln!("{}: {}", i18n::tr("Warning"), note);
+            }
+        }
 
-        if cli.save {
-            let path = save_buffer_to_file(&image_buffer).await?;
-            file_path = Some(path);
+        let copied_text = match (&text, &cli.translate_command) {
+            (Some(text), Some(command)) if cli.copy && cli.copy_text => match ocr::translate(text, command).await {
+                Ok(translated) => Some(translated),
+                Err(err) => {
+                    eprintln!("{}: {}: {:#}", i18n::tr("Warning"), i18n::tr("Copying the text untranslated"), err);
+                    Some(text.clone())
+                }
+            },
+            _ => text.clone(),
+        };
+        if cli.copy && !streamed_to_clipboard {
+            let text = copied_text.as_deref().filter(|_| cli.copy_text);
+            clipboard_owner = timings::time(timings::Stage::Copy, copy_to_clipboard(&image_buffer, cli.format, file_path.as_deref(), text, cli.wait_for_paste)).await?;
+        }
+        let done = Instant::now();
+
+        let digest = sha256::hex_digest(&image_buffer);
+        history::record(&history::Entry {
+            time: chrono::Local::now(),
+            path: file_path.clone(),
+            mode: mode_label.clone(),
+            geometry: geom,
+            window_title: window_title.clone(),
+            sha256: digest.clone(),
+            favorite: false,
+            url: None,
+        }).await;
+        // What was recognized anyway spares searching from recognizing it again.
+        if let (Some(path), Some(text)) = (&file_path, &text) {
+            if cli.ocr_sidecar {
+                if let Err(err) = ocr::write_sidecar(path, text).await {
+                    eprintln!("{}: {}: {:#}", i18n::tr("Warning"), i18n::tr("Could not write the recognized text"), err);
+                }
+            }
+            if let Err(err) = search::index(&digest, text).await {
+                eprintln!("{}: {}: {:#}", i18n::tr("Warning"), i18n::tr("Could not add the recognized text to the search index"), err);
+            }
         }
 
-        if cli.copy {
-            copy_buffer_to_clipboard(&image_buffer).await?;
+        if let Some(printer) = &cli.print {
+            if let Err(err) = print::print(&image_buffer, printer).await {
+                eprintln!("{}: {}: {:#}", i18n::tr("Warning"), i18n::tr("Could not print the screenshot"), err);
+            }
+        }
+        if edit_after {
+            if let Some(path) = &file_path {
+                if let Err(err) = spawn_with_file(&cli.editor, path) {
+                    eprintln!("{}: {}: {:#}", i18n::tr("Warning"), i18n::tr("Could not open the editor"), err);
+                }
+            }
+        }
+        if cli.preview_term {
+            if let Err(err) = terminal::preview(&image_buffer) {
+                eprintln!("{}: {}: {:#}", i18n::tr("Warning"), i18n::tr("Could not show the preview"), err);
+            }
+        }
+        if cli.drag {
+            if let Some(path) = &file_path {
+                if let Err(err) = drag::drag(path) {
+                    eprintln!("{}: {}: {:#}", i18n::tr("Warning"), i18n::tr("Could not open the drag window"), err);
+                }
+            }
+        }
+        if cli.reveal {
+            if let Some(path) = &file_path {
+                if let Err(err) = reveal::reveal(path).await {
+                    eprintln!("{}: {}: {:#}", i18n::tr("Warning"), i18n::tr("Could not show the screenshot in the file manager"), err);
+                }
+            }
+        }
+        if cli.set_wallpaper {
+            if let Some(path) = &file_path {
+                if let Err(err) = wallpaper::set(cli.wallpaper_command.as_deref(), path).await {
+                    eprintln!("{}: {}: {:#}", i18n::tr("Warning"), i18n::tr("Could not set the wallpaper"), err);
+                }
+            }
         }
 
         // Send a notification based on the actions performed.
-        send_notification(cli.copy, file_path.as_deref(), &cli.mode).await?;
+        let delivered = if cli.silent {
+            None
+        } else {
+            timings::time(
+                timings::Stage::Notification,
+                send_notification(&cli, &geom, &image_buffer, file_path.as_deref(), &mode_label, note.as_deref(), do_not_disturb),
+            ).await
+        };
+
+        let swatches = match cli.palette {
+            Some(count) => Some(palette::dominant_colors(&image_buffer, count as usize)?),
+            None => None,
+        };
+        if let (Some(swatches), Some(path)) = (&swatches, &cli.palette_image) {
+            tokio::fs::write(path, palette::swatch_image(swatches)).await.with_context(|| format!("Failed to write {}", path))?;
+        }
+
+        // Printed once the notification is out, so its time is part of the timings.
+        if cli.json {
+            let summary = CaptureSummary {
+                path: file_path.as_deref(),
+                copied: cli.copy,
+                geometry: geom.printed(),
+                mode: &mode_label,
+                window_title: window_title.as_deref(),
+                bytes: image_buffer.len(),
+                latency_ms: TRIGGERED.get().map_or(0.0, |triggered| (done - *triggered).as_secs_f64() * 1000.0),
+                capture_latency_ms: (done - capture_started).as_secs_f64() * 1000.0,
+                timings: cli.timings.then(timings::current),
+                palette: swatches.as_deref(),
+            };
+            println!("{}", serde_json::to_string(&summary)?);
+        } else if let Some(swatches) = &swatches {
+            for swatch in swatches {
+                println!("{}\t{:.1}%", swatch.color, swatch.share * 100.0);
+            }
+        }
+        if cli.timings {
+            progress(&timings::table());
+        }
+
+        if let Some(flash) = flash {
+            if let Ok(Err(err)) = flash.await {
+                eprintln!("{}: {}: {:#}", i18n::tr("Warning"), i18n::tr("Could not flash the screen"), err);
+            }
+        }
+
+        // Waiting for a paste shouldn't block the next capture.
+        drop(instance_lock);
+
+        if let Some(delivered) = delivered {
+            if let Err(err) = watch_in_background(delivered, file_path.as_deref(), Some(&captured), &image_buffer).await {
+                eprintln!("{}: {}: {:#}", i18n::tr("Warning"), i18n::tr("The notification's buttons won't work"), err);
+            }
+        }
+
+        if let Some(mut wl_copy) = clipboard_owner {
+            progress(&i18n::tr("Waiting for the screenshot to be pasted..."));
+            wl_copy.wait().await?;
+        }
 
     } else {
-        println!("Action cancelled.");
+        progress(&i18n::tr("Action cancelled."));
     }
 
     Ok(())
 }
 
+/// Prints the size and position of a selection for `--measure`, along with its size in
+/// physical pixels on a scaled monitor.
+async fn measure(cli: &Cli, rect: &Rect) -> Result<()> {
+    let measured = match geometry::notation() {
+        geometry::Notation::Slurp => format!("{}x{} at {},{}", rect.width, rect.height, rect.x, rect.y),
+        geometry::Notation::X11 => rect.printed(),
+    };
+    // Without Hyprland the scale is unknown, so selections are measured as they are.
+    let monitors: Vec<HyprlandMonitor> = match compositor::is_hyprland() {
+        true => snapshot::hyprctl_json("monitors").await?,
+        false => Vec::new(),
+    };
+    let scale = monitors
+    .iter()
+    .find(|monitor| monitor.rect().is_ok_and(|monitor_rect| monitor_rect.contains(rect.x, rect.y)))
+    .map_or(1.0, |monitor| monitor.scale);
+    let physical = ((rect.width as f64 * scale).round() as i64, (rect.height as f64 * scale).round() as i64);
+
+    if cli.json {
+        let summary = serde_json::json!({
+            "x": rect.x,
+            "y": rect.y,
+            "width": rect.width,
+            "height": rect.height,
+            "scale": scale,
+            "physical_width": physical.0,
+            "physical_height": physical.1,
+        });
+        println!("{}", summary);
+    } else if scale != 1.0 {
+        println!("{} ({}x{} physical pixels at {}x scale)", measured, physical.0, physical.1, scale);
+    } else {
+        println!("{}", measured);
+    }
+
+    if cli.copy {
+        copy_text_to_clipboard(&measured).await?;
+    }
+    Ok(())
+}
+
+/// Recognizes the text in a capture for `--copy-text`, `--ocr-sidecar` and `--filename`, warning instead of failing the capture.
+async fn recognized_text(image: &[u8], cli: &Cli) -> Option<String> {
+    match ocr::recognize(image, cli.format, &ocr_options(cli)).await {
+        Ok(text) if !text.trim().is_empty() => Some(text.trim().to_string()),
+        Ok(_) => {
+            eprintln!("{}: {}", i18n::tr("Warning"), i18n::tr("No text was recognized in the screenshot."));
+            None
+        }
+        Err(err) => {
+            eprintln!("{}: {}: {:#}", i18n::tr("Warning"), i18n::tr("Text recognition failed"), err);
+            None
+        }
+    }
+}
+
+/// Returns the text recognition settings from the command line.
+fn ocr_options(cli: &Cli) -> ocr::Options {
+    ocr::Options {
+        engine: cli.ocr_engine,
+        langs: cli.ocr_lang.clone(),
+        endpoint: cli.ocr_endpoint.clone(),
+        translate_command: cli.translate_command.clone(),
+    }
+}
+
+/// Returns the edits requested on the command line.
+fn edits(cli: &Cli) -> filter::Edits {
+    filter::Edits {
+        adjustments: filter::Adjustments { brightness: cli.brightness, contrast: cli.contrast, gamma: cli.gamma },
+        filters: cli.filter.clone(),
+        simulate: cli.simulate,
+    }
+}
+
+/// Changes the Hyprland options requested on the command line for the duration of the capture.
+/// Options that can't be changed only produce a warning.
+async fn compositor_overrides(cli: &Cli) -> Vec<compositor::OptionOverride> {
+    let mut wanted: Vec<&[&str]> = Vec::new();
+    if cli.no_animations {
+        wanted.push(&["animations:enabled"]);
+    }
+    if cli.clean {
+        // Hyprland 0.45 moved drop_shadow into its own shadow category.
+        wanted.push(&["decoration:blur:enabled"]);
+        wanted.push(&["decoration:shadow:enabled", "decoration:drop_shadow"]);
+        wanted.push(&["decoration:dim_inactive"]);
+    }
+
+    let mut overrides = Vec::new();
+    if !wanted.is_empty() && !compositor::is_hyprland() {
+        eprintln!("{}: {}", i18n::tr("Warning"), i18n::tr("--no-animations and --clean only work on Hyprland."));
+        return overrides;
+    }
+    for names in wanted {
+        match compositor::override_renamed_option(names, "0").await {
+            Ok(guard) => overrides.push(guard),
+            Err(err) => eprintln!("{}: {}: {:#}", i18n::tr("Warning"), i18n::tr_with("Could not turn off {option}", &[("option", names[0].to_string())]), err),
+        }
+    }
+    overrides
+}
+
+/// Makes the cursor invisible until the returned guard is dropped.
+async fn hide_cursor() -> Option<compositor::OptionOverride> {
+    match compositor::override_option("cursor:invisible", "true").await {
+        Ok(guard) => {
+            sleep(CURSOR_HIDE_GRACE).await;
+            Some(guard)
+        }
+        Err(err) => {
+            eprintln!("{}: {}: {:#}", i18n::tr("Warning"), i18n::tr("Could not hide the cursor"), err);
+            None
+        }
+    }
+}
+
 // --- Screenshot Mode Implementations ---
 
 /// Simple region selection mode.
-async fn region_mode() -> Result<Option<String>> {
-    let slurp_output = Command::new("slurp")
-    .arg("-b")
-    .arg("#FFFFFF44")
-    .output()
-    .await?;
+async fn region_mode() -> Result<Option<Rect>> {
+    let slurp_output = process::selection_output(
+        Command::new("slurp")
+        .args(selector::slurp_args().await)
+    ).await?;
 
     if slurp_output.status.success() {
-        Ok(Some(String::from_utf8(slurp_output.stdout)?.trim().to_string()))
+        let selection = String::from_utf8(slurp_output.stdout)?;
+        let rect = selection.parse::<Rect>().map_err(|err| anyhow::anyhow!("slurp returned an unexpected selection: {}", err))?;
+        Ok(Some(snap::snap(rect).await))
     } else {
         Ok(None)
     }
 }
 
-/// Auto-detects the monitor under the cursor.
-async fn monitor_mode() -> Result<Option<String>> {
-    let cursor_pos_output = Command::new("hyprctl")
-    .arg("cursorpos")
-    .arg("-j")
-    .output()
-    .await?;
+/// Auto-detects the monitor under the cursor. Elsewhere than on Hyprland, which can't
+/// tell where the cursor is, the monitor is clicked on instead.
+async fn monitor_mode() -> Result<Option<Rect>> {
+    if !compositor::is_hyprland() {
+        return select_output().await;
+    }
+    let cursor_pos_output = process::output(
+        Command::new("hyprctl")
+        .arg("cursorpos")
+        .arg("-j")
+    ).await?;
 
     let cursor_pos: HyprlandCursorPos = serde_json::from_slice(&cursor_pos_output.stdout)?;
 
-    let monitors_output = Command::new("hyprctl")
-    .arg("monitors")
-    .arg("-j")
-    .output()
-    .await?;
-
-    let monitors: Vec<HyprlandMonitor> = serde_json::from_slice(&monitors_output.stdout)?;
+    let monitors: Vec<HyprlandMonitor> = snapshot::hyprctl_json("monitors").await?;
 
+    // A disabled or headless output without a size doesn't contain the cursor.
     for monitor in monitors {
-        if cursor_pos.x >= monitor.x && cursor_pos.x < monitor.x + monitor.width &&
-            cursor_pos.y >= monitor.y && cursor_pos.y < monitor.y + monitor.height {
-                return Ok(Some(format!("{},{} {}x{}", monitor.x, monitor.y, monitor.width, monitor.height)));
-            }
+        let Ok(rect) = monitor.rect() else {
+            continue;
+        };
+        if rect.contains(cursor_pos.x, cursor_pos.y) {
+            return Ok(Some(rect));
+        }
     }
 
     anyhow::bail!("Could not find a monitor under the cursor.");
 }
 
-/// Implements the full reactive "monitor and restart" window selection using polling.
-async fn window_mode() -> Result<Option<String>> {
+/// Lets the user click on a monitor, with slurp alone.
+async fn select_output() -> Result<Option<Rect>> {
+    let slurp_output = process::selection_output(
+        Command::new("slurp")
+        .args(selector::slurp_args().await)
+        .args(["-o", "-r"])
+    ).await?;
+
+    if slurp_output.status.success() {
+        let selection = String::from_utf8(slurp_output.stdout)?;
+        let rect = selection.parse::<Rect>().map_err(|err| anyhow::anyhow!("slurp returned an unexpected selection: {}", err))?;
+        Ok(Some(rect))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Implements the full reactive "monitor and restart" window selection: the picker is
+/// restarted whenever the windows it offers change.
+async fn window_mode() -> Result<Option<HyprlandClient>> {
     loop {
+        // Listening before the windows are listed, so a change in between isn't missed.
+        let events = events::Events::connect().await;
         let initial_workspace_id = get_active_workspace_id().await?;
         let windows = get_windows_on_workspace(initial_workspace_id).await?;
 
         if windows.is_empty() {
-            println!("No windows on active workspace. Waiting for a window or workspace change...");
-            monitor_workspace_changes_by_polling(initial_workspace_id).await?;
+            progress(&i18n::tr("No windows on active workspace. Waiting for a window or workspace change..."));
+            wait_for_window_changes(events, initial_workspace_id, &windows).await?;
             continue;
         }
 
+        // Zero-sized windows can't be captured, so they are never offered in the picker.
         let slurp_input = windows
         .iter()
-        .map(|w| format!("{},{} {}x{} {}", w.at.0, w.at.1, w.size.0, w.size.1, w.address))
+        .filter_map(|w| w.rect().ok().map(|rect| format!("{} {}", rect, w.address)))
         .collect::<Vec<_>>()
         .join("\n");
 
         let mut slurp_process = Command::new("slurp")
-        .args(["-r", "-b", "#FFFFFF44", "-f", "%l"])
+        .args(selector::slurp_args().await)
+        .args(["-r", "-f", "%l"])
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
+        .kill_on_drop(true)
         .spawn()
         .context("Failed to spawn slurp")?;
 
         if let Some(mut stdin) = slurp_process.stdin.take() {
-            stdin.write_all(slurp_input.as_bytes()).await?;
+            process::io("slurp", stdin.write_all(slurp_input.as_bytes())).await?;
         }
 
-        let slurp_pid = slurp_process.id().context("Failed to get slurp PID")?;
+        let mut slurp_stdout = slurp_process.stdout.take().context("Failed to get slurp stdout")?;
 
         let mut monitor_handle = tokio::spawn(async move {
-            monitor_workspace_changes_by_polling(initial_workspace_id).await
+            wait_for_window_changes(events, initial_workspace_id, &windows).await
         });
 
         tokio::select! {
-            slurp_result = slurp_process.wait_with_output() => {
+            slurp_result = process::wait_selection("slurp", &mut slurp_process) => {
                 monitor_handle.abort();
-                let output = slurp_result?;
-                if output.status.success() {
-                    let selected_address = String::from_utf8(output.stdout)?.trim().to_string();
-                    let final_geom = get_geometry_for_address(&selected_address).await?;
-                    return Ok(Some(final_geom));
+                let status = slurp_result?;
+                if status.success() {
+                    let mut selected_address = String::new();
+                    slurp_stdout.read_to_string(&mut selected_address).await?;
+                    let selected_address = selected_address.trim();
+                    return Ok(Some(get_window_for_address(selected_address).await?));
                 } else {
                     return Ok(None);
                 }
             },
             monitor_result = &mut monitor_handle => {
-                let _ = Command::new("kill").arg(slurp_pid.to_string()).status().await;
-                if monitor_result.is_ok() {
-                    println!("Workspace changed, restarting selection...");
+                // Kills slurp through its handle and reaps it, so it can't linger as a zombie.
+                let _ = slurp_process.kill().await;
+                if let Ok(Ok(change)) = monitor_result {
+                    progress(&format!("{}, restarting selection...", change));
                 }
             }
         }
@@ -223,142 +1548,506 @@ async fn window_mode() -> Result<Option<String>> {
 
 // --- Helper Functions ---
 
+/// Returns the area to capture for a window, grown by its border and gaps if requested.
+async fn window_rect(cli: &Cli, window: &HyprlandClient) -> Result<Rect> {
+    let mut rect = window.rect()?;
+    // The border is drawn just outside the window's geometry, and the gaps outside the border.
+    if cli.with_border || cli.with_gaps {
+        let border = compositor::get_sides_option("general:border_size").await?[0];
+        rect = rect.expanded([border; 4])?;
+    }
+    if cli.with_gaps {
+        rect = rect.expanded(compositor::get_sides_option("general:gaps_in").await?)?;
+    }
+    Ok(rect)
+}
+
+/// Returns the area to capture grown by `--expand` and shrunk by `--inset`.
+fn with_margins(cli: &Cli, rect: Rect) -> Result<Rect> {
+    let rect = rect.expanded(cli.expand.unwrap_or_default())?;
+    rect.expanded(cli.inset.unwrap_or_default().map(|side| -side))
+    .context("--inset leaves nothing to capture")
+}
+
+/// Focuses a window and returns it as it is once the compositor has settled.
+async fn focus_window(window: HyprlandClient) -> Result<HyprlandClient> {
+    compositor::dispatch("focuswindow", &format!("address:{}", window.address)).await?;
+    sleep(FOCUS_SETTLE_DELAY).await;
+    // Focusing can change the geometry, for example of a window in a group.
+    get_window_for_address(&window.address).await
+}
+
 /// Gets the ID of the currently active workspace.
 async fn get_active_workspace_id() -> Result<i32> {
-    let output = Command::new("hyprctl")
-    .arg("activeworkspace")
-    .arg("-j")
-    .output()
-    .await?;
-    let workspace: HyprlandWorkspace = serde_json::from_slice(&output.stdout)?;
+    let workspace: HyprlandWorkspace = snapshot::hyprctl_json("activeworkspace").await?;
     Ok(workspace.id)
 }
 
+/// Gets the IDs of the special workspaces currently shown on any monitor.
+async fn shown_special_workspaces() -> Result<Vec<i32>> {
+    let monitors: Vec<HyprlandMonitor> = snapshot::hyprctl_json("monitors").await?;
+    Ok(monitors
+    .into_iter()
+    .map(|monitor| monitor.special_workspace.id)
+    .filter(|&id| id != 0)
+    .collect())
+}
+
 /// Gets the list of all visible windows on a specific workspace ID.
 async fn get_windows_on_workspace(workspace_id: i32) -> Result<Vec<HyprlandClient>> {
-    let clients_output = Command::new("hyprctl")
-    .arg("clients")
-    .arg("-j")
-    .output()
-    .await?;
-    let all_clients: Vec<HyprlandClient> = serde_json::from_slice(&clients_output.stdout)?;
+    let all_clients: Vec<HyprlandClient> = snapshot::hyprctl_json("clients").await?;
 
+    // Windows on a special workspace shown on top of this one are visible too.
+    let special_workspaces = shown_special_workspaces().await?;
     let visible_clients = all_clients
     .into_iter()
-    .filter(|c| !c.hidden && c.workspace.id == workspace_id)
+    .filter(|c| !c.hidden && (c.workspace.id == workspace_id || special_workspaces.contains(&c.workspace.id)))
     .collect();
 
     Ok(visible_clients)
 }
 
-/// Monitors for workspace changes by polling `hyprctl`.
-async fn monitor_workspace_changes_by_polling(initial_id: i32) -> Result<()> {
+/// Waits until the windows offered for selection are out of date, because the workspace
+/// changed or a window opened, closed, moved or was resized. Returns what happened.
+/// `events` is connected before `windows` are listed, and polled instead if it failed.
+async fn wait_for_window_changes(events: Result<events::Events>, initial_id: i32, windows: &[HyprlandClient]) -> Result<&'static str> {
+    let mut events = match events {
+        Ok(events) => events,
+        Err(err) => {
+            eprintln!("{}: {}", i18n::tr("Warning"), i18n::tr_with("{error}, polling for changes instead.", &[("error", format!("{:#}", err))]));
+            return poll_window_changes(initial_id, windows).await;
+        }
+    };
+
+    loop {
+        let (name, _) = events.next().await?;
+        let change = match name.as_str() {
+            "workspace" | "workspacev2" | "focusedmon" | "activespecial" => "Workspace changed",
+            "openwindow" => "Window opened",
+            "closewindow" => "Window closed",
+            "movewindow" | "movewindowv2" | "changefloatingmode" | "fullscreen" => "Window moved",
+            "resizewindow" => "Window resized",
+            _ => continue,
+        };
+        snapshot::invalidate();
+        return Ok(change);
+    }
+}
+
+/// Watches for the same changes by polling `hyprctl`, for when the event socket is unavailable.
+async fn poll_window_changes(initial_id: i32, windows: &[HyprlandClient]) -> Result<&'static str> {
+    let layout = |windows: &[HyprlandClient]| {
+        let mut layout: Vec<_> = windows.iter().map(|w| (w.address.clone(), w.at, w.size)).collect();
+        layout.sort();
+        layout
+    };
+    let offered = layout(windows);
+
     loop {
         sleep(Duration::from_millis(200)).await;
-        if let Ok(current_id) = get_active_workspace_id().await {
-            if current_id != initial_id {
-                return Ok(());
+        snapshot::invalidate();
+        let Ok(current_id) = get_active_workspace_id().await else { continue };
+        if current_id != initial_id {
+            return Ok("Workspace changed");
+        }
+        if let Ok(current) = get_windows_on_workspace(current_id).await {
+            if layout(&current) != offered {
+                return Ok("Windows changed");
             }
         }
     }
 }
 
-/// After a window is selected, this gets its final, most up-to-date geometry.
-async fn get_geometry_for_address(address: &str) -> Result<String> {
-    let clients_output = Command::new("hyprctl")
-    .arg("clients")
-    .arg("-j")
-    .output()
-    .await?;
-    let all_clients: Vec<HyprlandClient> = serde_json::from_slice(&clients_output.stdout)?;
+/// After a window is selected, this gets its final geometry and title. The snapshot the picker
+/// was built from is still current, since any change since then would have restarted it.
+async fn get_window_for_address(address: &str) -> Result<HyprlandClient> {
+    let all_clients: Vec<HyprlandClient> = snapshot::hyprctl_json("clients").await?;
 
     for client in all_clients {
         if client.address == address {
-            return Ok(format!("{},{} {}x{}", client.at.0, client.at.1, client.size.0, client.size.1));
+            return Ok(client);
         }
     }
 
     anyhow::bail!("Could not find window with address {} after selection", address);
 }
 
-/// Runs grim and captures the output to a byte buffer in memory.
-async fn capture_geometry_to_buffer(geometry: &str) -> Result<Vec<u8>> {
-    let output = Command::new("grim")
-    .arg("-g")
-    .arg(geometry)
+/// Runs grim and captures the output to a byte buffer in memory, passing it on to
+/// `clipboard` as it arrives.
+async fn capture_geometry_to_buffer(geometry: &Rect, format: format::ImageFormat, quality: Option<u8>, mut clipboard: Option<&mut ClipboardCopy>) -> Result<Vec<u8>> {
+    let mut grim = Command::new("grim");
+    grim.arg("-g").arg(geometry.to_string()).arg("-t").arg(format.grim_type());
+    if let (format::ImageFormat::Jpeg, Some(quality)) = (format, quality) {
+        grim.arg("-q").arg(quality.to_string());
+    }
+
+    let mut grim = grim
     .arg("-") // Output to stdout
-    .output()
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .kill_on_drop(true)
+    .spawn()
+    .context("Failed to spawn grim")?;
+
+    let mut stdout = grim.stdout.take().context("Failed to get grim stdout")?;
+    let mut stderr = grim.stderr.take().context("Failed to get grim stderr")?;
+
+    let mut buffer = Vec::new();
+    let mut chunk = vec![0u8; 64 * 1024];
+    let mut errors = String::new();
+    // grim writes the image as it encodes it, so its first byte marks the end of the capture.
+    let started = Instant::now();
+    let mut first_byte = None;
+    let status = process::with_timeout("grim", Some(process::command_timeout()), async {
+        let (read, _) = tokio::join!(
+            async {
+                loop {
+                    let len = stdout.read(&mut chunk).await?;
+                    if len == 0 {
+                        return Ok::<_, anyhow::Error>(());
+                    }
+                    first_byte.get_or_insert_with(Instant::now);
+                    buffer.extend_from_slice(&chunk[..len]);
+                    if let Some(clipboard) = clipboard.as_mut() {
+                        clipboard.write(&chunk[..len]).await?;
+                    }
+                }
+            },
+            stderr.read_to_string(&mut errors),
+        );
+        read?;
+        Ok(grim.wait().await?)
+    })
     .await?;
-
-    if !output.status.success() {
-        anyhow::bail!("grim command failed!");
+    let first_byte = first_byte.unwrap_or_else(Instant::now);
+    timings::add(timings::Stage::Capture, first_byte - started);
+    timings::add(timings::Stage::Encode, first_byte.elapsed());
+    if !status.success() {
+        anyhow::bail!("grim failed to capture {}: {}", geometry, errors.trim());
     }
 
-    Ok(output.stdout)
+    Ok(buffer)
 }
 
-/// Takes an image buffer and saves it to a file.
-async fn save_buffer_to_file(buffer: &[u8]) -> Result<String> {
-    let timestamp = Local::now().format("%Y-%m-%d_%H-%M-%S");
-    let pictures_dir = std::env::var("XDG_PICTURES_DIR").unwrap_or_else(|_| format!("{}/Pictures", std::env::var("HOME").unwrap()));
+/// A wl-copy process that is being fed an image.
+///
+/// By default wl-copy forks into the background and keeps serving the image after
+/// luminashot exits, until another application takes over the clipboard, or with
+/// `Hold::UntilPaste` until the image has been pasted. With `wait_for_paste` it stays
+/// in the foreground instead, and exits once the image has been pasted, which also
+/// clears the clipboard. wl-copy can't hold the image for a while only.
+struct ClipboardCopy {
+    child: Child,
+    stdin: ChildStdin,
+    wait_for_paste: bool,
+}
+
+impl ClipboardCopy {
+    fn start(format: format::ImageFormat, wait_for_paste: bool) -> Result<ClipboardCopy> {
+        let mut wl_copy = Command::new("wl-copy");
+        wl_copy.args(["--type", format.mime_type()]);
+        if wait_for_paste {
+            wl_copy.args(["--foreground", "--paste-once"]);
+        } else if clipboard::hold() == clipboard::Hold::UntilPaste {
+            wl_copy.arg("--paste-once");
+        }
 
-    let save_dir = format!("{}/Screenshots", pictures_dir);
-    tokio::fs::create_dir_all(&save_dir).await?;
+        let mut child = wl_copy
+        .stdin(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to spawn wl-copy")?;
 
-    let file_path = format!("{}/{}-luminashot.png", save_dir, timestamp);
-    tokio::fs::write(&file_path, buffer).await?;
+        let stdin = child.stdin.take().context("Failed to get wl-copy stdin")?;
+        Ok(ClipboardCopy { child, stdin, wait_for_paste })
+    }
 
-    Ok(file_path)
+    async fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        process::io("wl-copy", self.stdin.write_all(bytes)).await
+    }
+
+    /// Closes wl-copy's input, and waits for it to take over the clipboard. With
+    /// `wait_for_paste`, returns the wl-copy process that exits once the image is pasted.
+    async fn finish(self) -> Result<Option<Child>> {
+        let ClipboardCopy { mut child, stdin, wait_for_paste } = self;
+        drop(stdin); // Close stdin to signal end of data
+
+        if wait_for_paste {
+            return Ok(Some(child));
+        }
+
+        let status = process::wait("wl-copy", &mut child).await?;
+        if !status.success() {
+            anyhow::bail!("wl-copy command failed!");
+        }
+        Ok(None)
+    }
 }
 
-/// Takes an image buffer and pipes it to wl-copy.
-async fn copy_buffer_to_clipboard(buffer: &[u8]) -> Result<()> {
-    let mut wl_copy_cmd = Command::new("wl-copy")
-    .stdin(Stdio::piped())
-    .spawn()
-    .context("Failed to spawn wl-copy")?;
+/// Puts an image buffer on the clipboard, along with the text recognized in it and, with
+/// the native backend, the file it was saved to. That takes a clipboard owner of our own,
+/// and is left to wl-copy otherwise, or if the owner fails. See [`ClipboardCopy`] for what
+/// is returned with `wait_for_paste`.
+async fn copy_to_clipboard(buffer: &[u8], format: format::ImageFormat, path: Option<&str>, text: Option<&str>, wait_for_paste: bool) -> Result<Option<Child>> {
+    if text.is_some() || clipboard::needs_owner() {
+        let native = clipboard::backend() == clipboard::Backend::Native;
+        let image_types: &[&str] = match (native, format) {
+            (true, format::ImageFormat::Png) => &["image/png", "image/jpeg"],
+            _ => &[format.mime_type()],
+        };
+        let uri = path
+        .filter(|_| native)
+        .and_then(|path| std::path::absolute(path).ok())
+        .map(|path| format!("file://{}\r\n", dirs::encode_path(&path.to_string_lossy())));
+
+        let mut items = vec![clipboard::Item { mime_types: image_types, data: buffer }];
+        if let Some(text) = text {
+            items.push(clipboard::Item { mime_types: clipboard::TEXT_TYPES, data: text.as_bytes() });
+        }
+        if let Some(uri) = &uri {
+            items.push(clipboard::Item { mime_types: clipboard::URI_TYPES, data: uri.as_bytes() });
+        }
+        match clipboard::offer(&items, wait_for_paste).await {
+            Ok(owner) => return Ok(owner),
+            Err(err) => eprintln!("{}: {}: {:#}", i18n::tr("Warning"), i18n::tr("Copying only the image, through wl-copy"), err),
+        }
+    }
+
+    let mut clipboard = ClipboardCopy::start(format, wait_for_paste)?;
+    clipboard.write(buffer).await?;
+    clipboard.finish().await
+}
 
-    let mut wl_copy_stdin = wl_copy_cmd.stdin.take().context("Failed to get wl-copy stdin")?;
+/// Waits for `delay`, counting down the remaining seconds in a notification that is
+/// updated in place and closed again before the capture fires.
+async fn countdown(delay: Duration, silent: bool) {
+    let total = delay.as_secs_f64().ceil() as u64;
+    let countdown_notification = |remaining: u64| notify::Notification {
+        summary: "LuminaShot".to_string(),
+        body: i18n::tr_with("Capturing in {seconds}...", &[("seconds", remaining.to_string())]),
+        icon: "camera-photo".to_string(),
+        urgency: notify::Urgency::Low,
+        progress: Some((100 - 100 * remaining / total) as u8),
+        transient: true,
+        ..Default::default()
+    };
 
-    // Write the buffer to wl-copy's stdin
-    wl_copy_stdin.write_all(buffer).await?;
-    drop(wl_copy_stdin); // Close stdin to signal end of data
+    // The screen locking during the wait would have the lock screen captured.
+    let inhibitor = idle::inhibit("Waiting to take a screenshot").await;
+    let mut remaining = delay;
+    let mut shown: Option<notify::Delivered> = None;
 
-    let wl_copy_status = wl_copy_cmd.wait().await?;
-    if !wl_copy_status.success() {
-        anyhow::bail!("wl-copy command failed!");
+    while !remaining.is_zero() {
+        let secs = remaining.as_secs_f64().ceil() as u64;
+        progress(&i18n::tr_with("Capturing in {seconds}...", &[("seconds", secs.to_string())]));
+
+        if !silent {
+            let notification = countdown_notification(secs);
+            match &mut shown {
+                Some(delivered) => {
+                    let _ = delivered.update(&notification).await;
+                }
+                // Without the notification server, updates would stack up as separate notifications.
+                None if secs < total => {}
+                None => shown = notify::show(&notification).await,
+            }
+        }
+
+        // Sleep until the displayed number of seconds is due to drop by one.
+        let step = remaining.saturating_sub(Duration::from_secs(secs - 1));
+        sleep(step).await;
+        remaining -= step;
+    }
+
+    if let Some(delivered) = shown {
+        if delivered.close().await.is_ok() {
+            sleep(COUNTDOWN_CLOSE_GRACE).await;
+        }
+    }
+    if let Some(inhibitor) = inhibitor {
+        inhibitor.release().await;
     }
+}
+
+/// Copies text, such as an upload URL, to the clipboard.
+async fn copy_text_to_clipboard(text: &str) -> Result<()> {
+    let status = process::status(
+        Command::new("wl-copy")
+        .arg("--")
+        .arg(text)
+        .stdin(Stdio::null())
+    ).await?;
 
+    if !status.success() {
+        anyhow::bail!("wl-copy command failed!");
+    }
     Ok(())
 }
 
 /// Sends a desktop notification summarizing the actions taken.
-async fn send_notification(copied: bool, file_path: Option<&str>, mode: &Mode) -> Result<()> {
-    let mode_str = format!("{:?}", mode);
-    let summary = format!("LuminaShot - {} Mode", mode_str);
-
-    let body = match (copied, file_path) {
-        (true, Some(path)) => format!("Copied and saved to {}", path),
-        (true, None) => "Copied to clipboard.".to_string(),
-        (false, Some(path)) => format!("Saved to {}", path),
-        (false, None) => return Ok(()), // Should not happen with current logic
+async fn send_notification(cli: &Cli, geometry: &Rect, image_buffer: &[u8], file_path: Option<&str>, mode_label: &str, note: Option<&str>, do_not_disturb: bool) -> Option<notify::Delivered> {
+    let summary = i18n::tr_with("LuminaShot - {mode} Mode", &[("mode", i18n::tr(mode_label))]);
+
+    let mut body = match (cli.copy, file_path) {
+        (true, Some(path)) => i18n::tr_with("Copied and saved to {path}", &[("path", path.to_string())]),
+        (true, None) => i18n::tr("Copied to clipboard."),
+        (false, Some(path)) => i18n::tr_with("Saved to {path}", &[("path", path.to_string())]),
+        (false, None) => return None, // Should not happen with current logic
     };
 
-    let mut notify_cmd = Command::new("notify-send");
-    notify_cmd.arg(&summary).arg(&body);
+    if let Some(body_template) = &cli.notify_body {
+        body = template::render(body_template, &[
+            ("path", file_path.unwrap_or_default().to_string()),
+            ("geometry", geometry.printed()),
+            ("size", format!("{}x{}", geometry.width, geometry.height)),
+            ("filesize", template::human_size(image_buffer.len() as u64)),
+            ("mode", mode_label.to_string()),
+        ]);
+    }
+
+    if let Some(note) = note {
+        body = format!("{}\n{}", note, body);
+    }
+
+    if do_not_disturb {
+        eprintln!("{}: {}", summary, body);
+        return None;
+    }
 
     // Use a file path for the icon if available, otherwise use a generic icon for copy.
-    if let Some(path) = file_path {
-        notify_cmd.arg("-i").arg(path);
-    } else {
-        notify_cmd.arg("-i").arg("edit-copy");
+    let icon = cli.notify_icon.as_deref().or(file_path).unwrap_or("edit-copy").to_string();
+
+    // Without a file to point at, the preview is sent inline with the notification.
+    let image = match (file_path, &cli.notify_icon, cli.format) {
+        (None, None, format::ImageFormat::Png) => png::decode_scaled(image_buffer, NOTIFICATION_PREVIEW_SIZE).ok(),
+        _ => None,
+    };
+
+    let mut actions = Vec::new();
+    if file_path.is_some() {
+        // "default" is invoked by clicking the notification itself on most servers.
+        actions.push(("default", "Open"));
     }
+    for action in &cli.notify_actions {
+        if file_path.is_some() || !action.needs_file() {
+            actions.push(action.button());
+        }
+    }
+
+    notify::show(&notify::Notification {
+        summary,
+        body,
+        icon,
+        image,
+        actions: actions.into_iter().map(|(key, label)| (key.to_string(), i18n::tr(label))).collect(),
+        urgency: cli.urgency,
+        timeout: cli.notify_timeout,
+        replace_previous: !cli.stack_notifications,
+        ..Default::default()
+    }).await
+}
 
-    let status = notify_cmd.status().await?;
+/// What the notification listener is told about the capture or recording it acts on.
+#[derive(Serialize, Deserialize)]
+struct Watched {
+    file_path: Option<String>,
+    captured: Option<state::LastCapture>,
+}
 
-    if !status.success() {
-        anyhow::bail!("notify-send command failed");
+/// Leaves listening for the notification's actions to a new process, which runs them with
+/// this run's options, so this one can exit.
+async fn watch_in_background(delivered: notify::Delivered, file_path: Option<&str>, captured: Option<&state::LastCapture>, image_buffer: &[u8]) -> Result<()> {
+    let watched = Watched { file_path: file_path.map(String::from), captured: captured.cloned() };
+    let mut input = serde_json::to_vec(&watched)?;
+    input.push(b'\n');
+    input.extend_from_slice(image_buffer);
+    delivered.hand_over(command_line().get(1..).unwrap_or_default(), &input).await
+}
+
+/// Waits for an action on the notification handed over to this process, and runs it.
+async fn watch_notification(cli: &Cli) -> Result<()> {
+    let (delivered, input) = notify::Delivered::adopt().await?;
+    let newline = input.iter().position(|&byte| byte == b'\n').context("Malformed notification handover")?;
+    let watched: Watched = serde_json::from_slice(&input[..newline])?;
+    let image_buffer = &input[newline + 1..];
+
+    match delivered.wait_for_action(NOTIFICATION_ACTION_TIMEOUT).await {
+        Some(action) => handle_notification_action(&action, watched.file_path.as_deref(), image_buffer, watched.captured.as_ref(), cli).await,
+        None => Ok(()),
     }
+}
+
+/// Runs the action picked from the capture notification.
+async fn handle_notification_action(action: &str, file_path: Option<&str>, image_buffer: &[u8], captured: Option<&state::LastCapture>, cli: &Cli) -> Result<()> {
+    match (action, file_path) {
+        ("default", Some(path)) => spawn_with_file(&cli.viewer, path),
+        ("edit", Some(path)) => spawn_with_file(&cli.editor, path),
+        ("reveal", Some(path)) => reveal::reveal(path).await,
+        ("delete", Some(path)) => {
+            println!("{}", trash::remove(path, cli.purge).await?);
+            Ok(())
+        }
+        ("copy", _) => {
+            if let Some(mut wl_copy) = copy_to_clipboard(image_buffer, cli.format, file_path, None, cli.wait_for_paste).await? {
+                wl_copy.wait().await?;
+            }
+            Ok(())
+        }
+        ("print", _) => print::print(image_buffer, cli.print.as_deref().unwrap_or_default()).await,
+        ("retake", _) => captured.map_or(Ok(()), retake),
+        ("favorite", Some(path)) => history::set_favorite(path).await,
+        ("wallpaper", Some(path)) => wallpaper::set(cli.wallpaper_command.as_deref(), path).await,
+        _ => Ok(()),
+    }
+}
+
+/// Starts a new capture of the same geometry with the same options as this one.
+fn retake(captured: &state::LastCapture) -> Result<()> {
+    let exe = std::env::current_exe().context("Failed to locate the luminashot executable")?;
+
+    // The previous invocation's arguments are reused as-is; `--retake` takes precedence
+    // over any selection they contain, and over what `--last` would pick by now.
+    std::process::Command::new(exe)
+    .args(command_line().iter().skip(1))
+    .arg(format!("--retake={}", captured.geometry))
+    .arg(format!("--retake-mode={}", captured.mode))
+    .spawn()
+    .context("Failed to start a new capture")?;
+
+    Ok(())
+}
+
+/// Returns the options given before `subcommand` on the command line, which are passed
+/// along to the commands it runs on the user's behalf.
+fn args_before(subcommand: &str) -> Vec<OsString> {
+    command_line().iter().skip(1).take_while(|arg| *arg != subcommand).cloned().collect()
+}
+
+/// Returns the command line this run acts on, including the program name.
+fn command_line() -> &'static [OsString] {
+    COMMAND_LINE.get().map_or(&[], Vec::as_slice)
+}
+
+/// Prints a progress message, on stderr with `--json` or `--file -` so stdout only carries
+/// the summary or the image.
+fn progress(message: &str) {
+    if JSON_OUTPUT.get().copied().unwrap_or(false) {
+        eprintln!("{}", message);
+    } else {
+        println!("{}", message);
+    }
+}
+
+/// Starts a command line with a file path appended, without waiting for it to exit.
+fn spawn_with_file(command_line: &str, path: &str) -> Result<()> {
+    let mut parts = command_line.split_whitespace();
+    let program = parts.next().context("Empty command line")?;
+
+    Command::new(program)
+    .args(parts)
+    .arg(path)
+    .spawn()
+    .with_context(|| format!("Failed to start {}", program))?;
 
     Ok(())
 }
@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+use crate::geometry::Rect;
+use crate::image::Image;
+use crate::png;
+
+// --- Montage of Every Monitor ---
+//
+// `--each-output --montage` puts the screenshots of all monitors into one image, each at
+// its place in the monitor layout, so gaps and monitors of different sizes look the way
+// they are arranged. The parts of the bounding box that no monitor covers are filled with
+// the background color. Monitors with fewer pixels per unit of layout than the densest one
+// are scaled up to match it, so nothing loses detail.
+
+/// A monitor's place in the layout and its screenshot, as a PNG.
+pub struct Shot {
+    pub rect: Rect,
+    pub image: Vec<u8>,
+}
+
+/// Parses a `#rrggbb` or `#rrggbbaa` color.
+pub fn parse_color(value: &str) -> Result<[u8; 4], String> {
+    let invalid = || format!("`{}` is not a valid color, expected #rrggbb or #rrggbbaa", value);
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if !matches!(hex.len(), 6 | 8) || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let channels: Vec<u8> = (0..hex.len() / 2).map(|index| u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16)).collect::<Result<_, _>>().map_err(|_| invalid())?;
+    Ok([channels[0], channels[1], channels[2], channels.get(3).copied().unwrap_or(255)])
+}
+
+/// Returns the rectangle covering every shot.
+pub fn bounds(shots: &[Shot]) -> Result<Rect> {
+    let left = shots.iter().map(|shot| shot.rect.x).min().context("There are no monitors")?;
+    let top = shots.iter().map(|shot| shot.rect.y).min().unwrap_or(0);
+    let right = shots.iter().map(|shot| shot.rect.x + shot.rect.width).max().unwrap_or(0);
+    let bottom = shots.iter().map(|shot| shot.rect.y + shot.rect.height).max().unwrap_or(0);
+    Rect::new(left, top, right - left, bottom - top)
+}
+
+/// Puts the shots together at their places in the layout and encodes the result as a PNG.
+pub fn compose(shots: &[Shot], background: [u8; 4]) -> Result<Vec<u8>> {
+    let bounds = bounds(shots)?;
+    let images: Vec<Image> = shots.iter().map(|shot| png::decode(&shot.image)).collect::<Result<_>>()?;
+    // Pixels per unit of layout; grim captures scaled monitors at their full resolution.
+    let density = shots
+    .iter()
+    .zip(&images)
+    .map(|(shot, image)| image.width as f64 / shot.rect.width as f64)
+    .fold(1.0, f64::max);
+    let to_pixels = |length: i32| (length as f64 * density).round() as usize;
+
+    let (width, height) = (to_pixels(bounds.width), to_pixels(bounds.height));
+    let mut pixels = background.repeat(width * height);
+    for (shot, image) in shots.iter().zip(&images) {
+        let (left, top) = (to_pixels(shot.rect.x - bounds.x), to_pixels(shot.rect.y - bounds.y));
+        let target_width = to_pixels(shot.rect.width).min(width - left);
+        let target_height = to_pixels(shot.rect.height).min(height - top);
+        for y in 0..target_height {
+            let source_y = y * image.height as usize / target_height;
+            let source_row = &image.pixels[source_y * image.width as usize * 4..][..image.width as usize * 4];
+            let start = ((top + y) * width + left) * 4;
+            for (x, target) in pixels[start..start + target_width * 4].chunks_exact_mut(4).enumerate() {
+                let source_x = x * image.width as usize / target_width;
+                target.copy_from_slice(&source_row[source_x * 4..source_x * 4 + 4]);
+            }
+        }
+    }
+
+    let alpha = pixels.iter().skip(3).step_by(4).any(|&alpha| alpha != 255);
+    let mut encoder = png::Encoder::new(width as u32, height as u32, alpha);
+    for row in pixels.chunks_exact(width * 4) {
+        encoder.push_row(row);
+    }
+    Ok(encoder.finish())
+}
@@ -0,0 +1,420 @@
+use std::ffi::OsString;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::process::Stdio;
+use std::sync::OnceLock;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+use tokio::time::Duration;
+use crate::dbus::{self, Value};
+use crate::dirs;
+use crate::image::Image;
+use crate::process;
+
+// --- Desktop Notifications ---
+
+const NOTIFICATIONS_INTERFACE: &str = "org.freedesktop.Notifications";
+
+/// The name of the desktop entry installed alongside the binary.
+const DESKTOP_ENTRY: &str = "luminashot";
+
+/// Marks the luminashot process that keeps listening for a notification's actions once
+/// the one that showed it is done.
+pub const WATCH_ARG: &str = "--watch-notification";
+
+/// The descriptor that process finds the notification's bus connection at. The server
+/// may send `ActionInvoked` to the connection that showed the notification only, so the
+/// connection itself is handed over rather than a new one subscribing.
+const WATCH_FD: RawFd = 3;
+
+static APP_NAME: OnceLock<String> = OnceLock::new();
+
+/// Sets the application name shown by notification centers. Only the first call has an effect.
+pub fn set_app_name(name: String) {
+    let _ = APP_NAME.set(name);
+}
+
+fn app_name() -> &'static str {
+    APP_NAME.get().map_or("LuminaShot", String::as_str)
+}
+
+/// A desktop notification.
+#[derive(Debug, Clone, Default)]
+pub struct Notification {
+    pub summary: String,
+    pub body: String,
+    /// An icon name or path to an image file.
+    pub icon: String,
+    /// An image sent inline with the notification, shown instead of the icon.
+    pub image: Option<Image>,
+    /// Action buttons as `(key, label)` pairs. Only shown by servers supporting actions.
+    pub actions: Vec<(String, String)>,
+    pub urgency: Urgency,
+    /// Milliseconds before the notification expires. `None` leaves it to the server, 0 never expires.
+    pub timeout: Option<u32>,
+    /// Progress from 0 to 100, shown as a bar by servers supporting the `value` hint.
+    pub progress: Option<u8>,
+    /// Transient notifications are not kept in the server's history.
+    pub transient: bool,
+    /// Replace the previous grouped notification instead of adding another one.
+    pub replace_previous: bool,
+}
+
+/// Notification urgency levels from the Desktop Notifications specification.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Urgency {
+    Low,
+    #[default]
+    Normal,
+    Critical,
+}
+
+impl Urgency {
+    fn name(self) -> &'static str {
+        match self {
+            Urgency::Low => "low",
+            Urgency::Normal => "normal",
+            Urgency::Critical => "critical",
+        }
+    }
+}
+
+/// Returns the `expire_timeout` argument of `Notify`, where -1 means the server's default.
+fn expire_timeout(notification: &Notification) -> i32 {
+    notification.timeout.map_or(-1, |ms| ms.min(i32::MAX as u32) as i32)
+}
+
+/// A notification delivered over D-Bus, which can still report button clicks.
+pub struct Delivered {
+    bus: dbus::Connection,
+    id: u32,
+    supports_actions: bool,
+    grouped: bool,
+}
+
+impl Delivered {
+    /// Replaces the content of the notification in place.
+    pub async fn update(&mut self, notification: &Notification) -> Result<()> {
+        process::with_timeout("notification server", Some(process::command_timeout()), async {
+            self.id = notify(&mut self.bus, notification, self.id).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Closes the notification.
+    pub async fn close(mut self) -> Result<()> {
+        process::with_timeout("notification server", Some(process::command_timeout()), async {
+            self.bus.call(NOTIFICATIONS_INTERFACE, "/org/freedesktop/Notifications", NOTIFICATIONS_INTERFACE, "CloseNotification", vec![Value::U32(self.id)]).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns true if the server shows the notification's action buttons.
+    pub fn supports_actions(&self) -> bool {
+        self.supports_actions
+    }
+
+    /// Waits until the user clicks one of the notification's actions and returns its key.
+    /// Returns `None` once the notification is closed, or after `limit` has passed.
+    pub async fn wait_for_action(mut self, limit: Duration) -> Option<String> {
+        if !self.supports_actions {
+            return None;
+        }
+
+        let wait = async {
+            loop {
+                let message = self.bus.next_message().await.ok()?;
+                if message.body.first().and_then(Value::as_u32) != Some(self.id) {
+                    continue;
+                }
+                if message.is_signal(NOTIFICATIONS_INTERFACE, "ActionInvoked") {
+                    // A later capture may have taken over the notification, along with its buttons.
+                    if self.grouped && group_owner().await != Some((self.id, std::process::id())) {
+                        return None;
+                    }
+                    return message.body.get(1).and_then(Value::as_str).map(String::from);
+                }
+                if message.is_signal(NOTIFICATIONS_INTERFACE, "NotificationClosed") {
+                    return None;
+                }
+            }
+        };
+
+        tokio::time::timeout(limit, wait).await.ok().flatten()
+    }
+
+    /// Hands the notification over to a new luminashot process, started with `WATCH_ARG`
+    /// and `args` and given `input` on stdin, so this one can exit while the buttons keep
+    /// working. Does nothing if there are no buttons to listen for.
+    pub async fn hand_over(self, args: &[OsString], input: &[u8]) -> Result<()> {
+        if !self.supports_actions {
+            return Ok(());
+        }
+        let (socket, serial, pending) = self.bus.detach().await?;
+        let fd = socket.as_raw_fd();
+
+        let exe = std::env::current_exe().context("Failed to locate the luminashot executable")?;
+        let mut command = Command::new(exe);
+        command
+        .arg(WATCH_ARG)
+        .args(args)
+        .stdin(Stdio::piped())
+        // Like the clipboard owner, it must not keep a piped caller's output open.
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0);
+        // SAFETY: dup2 and fcntl are async-signal-safe. The copy dup2 makes stays open
+        // across exec; if the socket already is at WATCH_FD, its close-on-exec flag is
+        // cleared instead.
+        unsafe {
+            command.pre_exec(move || {
+                let result = match fd == WATCH_FD {
+                    true => libc::fcntl(fd, libc::F_SETFD, 0),
+                    false => libc::dup2(fd, WATCH_FD),
+                };
+                if result < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let mut child = command.spawn().context("Failed to start the notification listener")?;
+        drop(socket);
+
+        // Done before it gets its input, so it can't see a click before owning the group.
+        if self.grouped && group_owner().await == Some((self.id, std::process::id())) {
+            if let Some(pid) = child.id() {
+                set_group_owner(self.id, pid).await;
+            }
+        }
+
+        let mut payload = format!("{} {} {} {}\n", self.id, serial, self.grouped, pending.len()).into_bytes();
+        payload.extend_from_slice(&pending);
+        payload.extend_from_slice(input);
+        let mut stdin = child.stdin.take().context("The notification listener has no stdin")?;
+        process::io("the notification listener", stdin.write_all(&payload)).await?;
+        Ok(())
+    }
+
+    /// Takes over the notification handed to this process with `hand_over`, and returns
+    /// it with the input that came along.
+    pub async fn adopt() -> Result<(Delivered, Vec<u8>)> {
+        let mut payload = Vec::new();
+        tokio::io::stdin().read_to_end(&mut payload).await?;
+
+        let newline = payload.iter().position(|&byte| byte == b'\n').context("Malformed notification handover")?;
+        let header = std::str::from_utf8(&payload[..newline])?;
+        let [id, serial, grouped, pending_len] = header.split(' ').collect::<Vec<_>>()[..] else {
+            anyhow::bail!("Malformed notification handover: {}", header);
+        };
+        let (pending, input) = payload[newline + 1..]
+        .split_at_checked(pending_len.parse()?)
+        .context("Truncated notification handover")?;
+
+        // SAFETY: the process handing the notification over put its bus connection at
+        // WATCH_FD, and nothing else in this process uses that descriptor.
+        let socket = unsafe { OwnedFd::from_raw_fd(WATCH_FD) };
+        let bus = dbus::Connection::adopt(socket, serial.parse()?, pending).await?;
+        let delivered = Delivered { bus, id: id.parse()?, supports_actions: true, grouped: grouped.parse()? };
+        Ok((delivered, input.to_vec()))
+    }
+}
+
+/// Returns true if notifications should be held back because do-not-disturb is on.
+///
+/// `command` is run through `sh -c` and signals do-not-disturb by exiting successfully.
+/// Without one, the running notification server is identified and asked directly.
+pub async fn do_not_disturb(command: Option<&str>) -> bool {
+    if let Some(command) = command {
+        return process::status(Command::new("sh").arg("-c").arg(command)).await.is_ok_and(|status| status.success());
+    }
+
+    let Ok(server) = server_name().await else {
+        return false;
+    };
+    let server = server.to_lowercase();
+
+    let (program, args, paused): (&str, &[&str], fn(&str) -> bool) = if server.contains("dunst") {
+        ("dunstctl", &["is-paused"], |out| out.trim() == "true")
+    } else if server.contains("mako") {
+        ("makoctl", &["mode"], |out| out.lines().any(|mode| mode.trim() == "do-not-disturb"))
+    } else if server.contains("swaync") || server.contains("swaynotificationcenter") {
+        ("swaync-client", &["--get-dnd"], |out| out.trim() == "true")
+    } else {
+        return false;
+    };
+
+    match process::output(Command::new(program).args(args)).await {
+        Ok(output) if output.status.success() => paused(&String::from_utf8_lossy(&output.stdout)),
+        _ => false,
+    }
+}
+
+/// Returns the name the notification server reports in `GetServerInformation`.
+async fn server_name() -> Result<String> {
+    process::with_timeout("notification server", Some(process::command_timeout()), async {
+        let mut bus = dbus::Connection::session().await?;
+        let info = bus.call(NOTIFICATIONS_INTERFACE, "/org/freedesktop/Notifications", NOTIFICATIONS_INTERFACE, "GetServerInformation", vec![]).await?;
+        Ok(info.first().and_then(Value::as_str).unwrap_or_default().to_string())
+    })
+    .await
+}
+
+/// Shows a notification. Notifications are best-effort: if neither the notification
+/// server nor `notify-send` is available, the message is printed to stderr instead.
+///
+/// Returns a handle for waiting on actions if the notification was delivered over D-Bus.
+pub async fn show(notification: &Notification) -> Option<Delivered> {
+    let dbus_error = match send_dbus(notification).await {
+        Ok(delivered) => return Some(delivered),
+        Err(err) => err,
+    };
+
+    if let Err(err) = send_notify_send(notification).await {
+        eprintln!("Could not show a notification ({:#}; {:#})", dbus_error, err);
+        eprintln!("{}: {}", notification.summary, notification.body);
+    }
+    None
+}
+
+/// Sends a notification directly to `org.freedesktop.Notifications`.
+async fn send_dbus(notification: &Notification) -> Result<Delivered> {
+    process::with_timeout("notification server", Some(process::command_timeout()), async {
+        let mut bus = dbus::Connection::session().await?;
+
+        let capabilities = bus.call(NOTIFICATIONS_INTERFACE, "/org/freedesktop/Notifications", NOTIFICATIONS_INTERFACE, "GetCapabilities", vec![]).await?;
+        let supports_actions = match capabilities.first() {
+            Some(Value::Array(_, caps)) => caps.iter().any(|cap| cap.as_str() == Some("actions")),
+            _ => false,
+        };
+
+        // Subscribe before sending, so a click can't arrive before we are listening.
+        if supports_actions && !notification.actions.is_empty() {
+            bus.add_match(&format!("type='signal',interface='{}'", NOTIFICATIONS_INTERFACE)).await?;
+        }
+
+        let replaces = if notification.replace_previous {
+            group_owner().await.map_or(0, |(id, _)| id)
+        } else {
+            0
+        };
+        let id = notify(&mut bus, notification, replaces).await?;
+        if notification.replace_previous {
+            set_group_owner(id, std::process::id()).await;
+        }
+
+        Ok(Delivered {
+            bus,
+            id,
+            supports_actions: supports_actions && !notification.actions.is_empty(),
+            grouped: notification.replace_previous,
+        })
+    })
+    .await
+}
+
+/// Calls `Notify`, replacing the notification `replaces` unless it is 0, and returns the new ID.
+async fn notify(bus: &mut dbus::Connection, notification: &Notification, replaces: u32) -> Result<u32> {
+    let mut hints = vec![
+        ("urgency".to_string(), Value::Byte(notification.urgency as u8)),
+        ("desktop-entry".to_string(), Value::str(DESKTOP_ENTRY)),
+    ];
+    if let Some(progress) = notification.progress {
+        hints.push(("value".to_string(), Value::I32(progress.min(100) as i32)));
+    }
+    if notification.transient {
+        hints.push(("transient".to_string(), Value::Bool(true)));
+    }
+    if let Some(image) = &notification.image {
+        hints.push(("image-data".to_string(), image_data(image)));
+    }
+
+    let actions = notification.actions.iter().flat_map(|(key, label)| [key.clone(), label.clone()]);
+    let reply = bus.call(
+        NOTIFICATIONS_INTERFACE,
+        "/org/freedesktop/Notifications",
+        NOTIFICATIONS_INTERFACE,
+        "Notify",
+        vec![
+            Value::str(app_name()),
+            Value::U32(replaces),
+            Value::str(&notification.icon),
+            Value::str(&notification.summary),
+            Value::str(&notification.body),
+            Value::string_array(actions),
+            Value::dict(hints),
+            Value::I32(expire_timeout(notification)),
+        ],
+    ).await?;
+
+    Ok(reply.first().and_then(Value::as_u32).unwrap_or_default())
+}
+
+// --- Notification Grouping ---
+
+fn group_path() -> String {
+    format!("{}/luminashot-notification", dirs::runtime_dir())
+}
+
+/// Returns the ID of the latest grouped notification and the PID of the process handling it.
+async fn group_owner() -> Option<(u32, u32)> {
+    let contents = tokio::fs::read_to_string(group_path()).await.ok()?;
+    let (id, pid) = contents.trim().split_once(' ')?;
+    Some((id.parse().ok()?, pid.parse().ok()?))
+}
+
+/// Records the process `pid` as the owner of the grouped notification `id`.
+async fn set_group_owner(id: u32, pid: u32) {
+    if let Err(err) = tokio::fs::write(group_path(), format!("{} {}", id, pid)).await {
+        eprintln!("Warning: Failed to record the notification ID: {}", err);
+    }
+}
+
+/// Builds the `(iiibiiay)` structure of the `image-data` hint.
+fn image_data(image: &Image) -> Value {
+    Value::Struct(vec![
+        Value::I32(image.width as i32),
+        Value::I32(image.height as i32),
+        Value::I32(image.width as i32 * 4),
+        Value::Bool(true),
+        Value::I32(8),
+        Value::I32(4),
+        Value::bytes(&image.pixels),
+    ])
+}
+
+/// Falls back to `notify-send` for setups where talking to the bus directly fails.
+async fn send_notify_send(notification: &Notification) -> Result<()> {
+    let mut notify_send = Command::new("notify-send");
+    notify_send
+    .arg("-a")
+    .arg(app_name())
+    .arg("-h")
+    .arg(format!("string:desktop-entry:{}", DESKTOP_ENTRY))
+    .arg(&notification.summary)
+    .arg(&notification.body)
+    .arg("-i")
+    .arg(&notification.icon)
+    .arg("-u")
+    .arg(notification.urgency.name());
+    if let Some(ms) = notification.timeout {
+        notify_send.arg("-t").arg(ms.to_string());
+    }
+    if let Some(progress) = notification.progress {
+        notify_send.arg("-h").arg(format!("int:value:{}", progress.min(100)));
+    }
+    if notification.transient {
+        notify_send.arg("-e");
+    }
+
+    let status = process::status(&mut notify_send).await?;
+
+    if !status.success() {
+        anyhow::bail!("notify-send command failed");
+    }
+
+    Ok(())
+}
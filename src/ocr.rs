@@ -0,0 +1,258 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::time::Duration;
+use crate::format::ImageFormat;
+use crate::{cleanup, dirs, process};
+
+// --- Text Recognition ---
+//
+// Text is recognized by the tesseract binary, or by a remote endpoint that is sent the
+// image as the multipart field `file`, and the languages as `lang` if any were given. The
+// endpoint answers with the plain text, or with JSON carrying it in a `text` field.
+
+/// OCR of a large screenshot takes far longer than the usual external command.
+const OCR_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Where text is recognized.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+pub enum Engine {
+    /// The tesseract command
+    #[default]
+    Tesseract,
+    /// An HTTP endpoint set with --ocr-endpoint
+    Remote,
+}
+
+/// Settings for a text recognition run.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub engine: Engine,
+    /// Language codes, e.g. `eng` and `deu`. Empty uses the engine's default.
+    pub langs: Vec<String>,
+    /// URL of the remote engine.
+    pub endpoint: Option<String>,
+    /// Shell command that translates the text on its stdin, for copying.
+    pub translate_command: Option<String>,
+}
+
+impl Options {
+    /// Returns the languages in tesseract's `deu+eng` form, if any were given.
+    fn lang(&self) -> Option<String> {
+        (!self.langs.is_empty()).then(|| self.langs.join("+"))
+    }
+}
+
+/// Returns true if the OCR engine is installed, or configured.
+pub async fn is_available(options: &Options) -> bool {
+    match options.engine {
+        Engine::Tesseract => {
+            process::status(Command::new("tesseract").arg("--version").stdout(std::process::Stdio::null()))
+            .await
+            .is_ok_and(|status| status.success())
+        }
+        Engine::Remote => options.endpoint.is_some(),
+    }
+}
+
+/// Recognizes the text in an image file.
+pub async fn recognize_file(path: &str, options: &Options) -> Result<String> {
+    match options.engine {
+        Engine::Tesseract => tesseract(path, options, None).await,
+        Engine::Remote => remote(path, options).await,
+    }
+}
+
+/// A recognized word, with its box in pixels.
+#[derive(Debug, Clone)]
+pub struct Word {
+    pub text: String,
+    /// Page, block, paragraph and line number, the same for all words on a line.
+    pub line: [u32; 4],
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Recognizes the text in an image that is only in memory, like a fresh capture.
+pub async fn recognize(image: &[u8], format: ImageFormat, options: &Options) -> Result<String> {
+    let temp = write_temp(image, format).await?;
+    recognize_file(&temp.path().to_string_lossy(), options).await
+}
+
+/// Recognizes the words in an image in reading order, with where they are. Only tesseract
+/// tells where the words are.
+pub async fn words(image: &[u8], format: ImageFormat, options: &Options) -> Result<Vec<Word>> {
+    if options.engine != Engine::Tesseract {
+        anyhow::bail!("Finding words in the image needs --ocr-engine tesseract; remote engines only return the text");
+    }
+    let temp = write_temp(image, format).await?;
+    let tsv = tesseract(&temp.path().to_string_lossy(), options, Some("tsv")).await?;
+
+    // Columns: level, page, block, paragraph, line, word, left, top, width, height, confidence, text.
+    let mut words = Vec::new();
+    for row in tsv.lines().skip(1) {
+        let columns: Vec<&str> = row.splitn(12, '\t').collect();
+        let [level, page, block, paragraph, line, _, left, top, width, height, _, text] = columns[..] else {
+            continue;
+        };
+        if level != "5" || text.trim().is_empty() {
+            continue;
+        }
+        let number = |column: &str| column.parse::<u32>().with_context(|| format!("Malformed tesseract output: {}", row));
+        words.push(Word {
+            text: text.trim().to_string(),
+            line: [number(page)?, number(block)?, number(paragraph)?, number(line)?],
+            x: number(left)?,
+            y: number(top)?,
+            width: number(width)?,
+            height: number(height)?,
+        });
+    }
+    Ok(words)
+}
+
+async fn write_temp(image: &[u8], format: ImageFormat) -> Result<cleanup::TempPath> {
+    let temp = cleanup::TempPath::new(format!("{}/luminashot-ocr-{}.{}", dirs::runtime_dir(), std::process::id(), format.extension()));
+    tokio::fs::write(temp.path(), image).await.context("Failed to write the screenshot for text recognition")?;
+    Ok(temp)
+}
+
+/// Runs tesseract on a file, with an output config like `tsv` instead of plain text.
+async fn tesseract(path: &str, options: &Options, config: Option<&str>) -> Result<String> {
+    let mut tesseract = Command::new("tesseract");
+    tesseract
+    .arg(path)
+    .arg("stdout")
+    .kill_on_drop(true);
+    if let Some(lang) = options.lang() {
+        tesseract.arg("-l").arg(lang);
+    }
+    tesseract.args(config);
+
+    let output = process::with_timeout("tesseract", Some(OCR_TIMEOUT), async {
+        tesseract.output().await.context("Failed to run tesseract")
+    })
+    .await?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("tesseract failed on {}: {}", path, stderr.trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+async fn remote(path: &str, options: &Options) -> Result<String> {
+    let endpoint = options.endpoint.as_deref().context("The remote OCR engine needs a URL, set one with --ocr-endpoint")?;
+    let mut curl = Command::new("curl");
+    curl
+    .args(["--silent", "--show-error", "--fail-with-body"])
+    .arg("--max-time")
+    .arg(OCR_TIMEOUT.as_secs().to_string())
+    .arg("-F")
+    .arg(format!("file=@{}", path))
+    .kill_on_drop(true);
+    if let Some(lang) = options.lang() {
+        curl.arg("-F").arg(format!("lang={}", lang));
+    }
+    curl.arg(endpoint);
+
+    let output = process::with_timeout("curl", Some(OCR_TIMEOUT), async {
+        curl.output().await.context("Failed to run curl")
+    })
+    .await?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("Text recognition at {} failed: {} {}", endpoint, stderr.trim(), stdout.trim());
+    }
+    match serde_json::from_str::<serde_json::Value>(&stdout) {
+        Ok(serde_json::Value::Object(reply)) => reply
+        .get("text")
+        .and_then(|text| text.as_str())
+        .map(String::from)
+        .with_context(|| format!("The reply from {} has no `text` field", endpoint)),
+        _ => Ok(stdout.into_owned()),
+    }
+}
+
+/// Pipes recognized text through `--translate-command`, and returns what it prints.
+pub async fn translate(text: &str, command: &str) -> Result<String> {
+    let mut child = Command::new("sh")
+    .arg("-c")
+    .arg(command)
+    .stdin(std::process::Stdio::piped())
+    .stdout(std::process::Stdio::piped())
+    .stderr(std::process::Stdio::piped())
+    .kill_on_drop(true)
+    .spawn()
+    .with_context(|| format!("Failed to run {}", command))?;
+    let mut stdin = child.stdin.take().context("The translate command has no stdin")?;
+    process::io("the translate command", stdin.write_all(text.as_bytes())).await?;
+    drop(stdin);
+
+    // Translation services take about as long as remote OCR.
+    let output = process::with_timeout("the translate command", Some(OCR_TIMEOUT), async {
+        Ok(child.wait_with_output().await?)
+    })
+    .await?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match (output.status.success(), stderr.trim()) {
+        (true, _) => {}
+        (false, "") => anyhow::bail!("The translate command failed with {}", output.status),
+        (false, stderr) => anyhow::bail!("The translate command failed: {}", stderr),
+    }
+    let translated = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if translated.is_empty() {
+        anyhow::bail!("The translate command printed nothing");
+    }
+    Ok(translated)
+}
+
+/// Returns the path of the text file `--ocr-sidecar` writes next to a screenshot.
+pub fn sidecar_path(path: &str) -> String {
+    format!("{}.txt", path)
+}
+
+/// Writes the text recognized in a screenshot next to it.
+pub async fn write_sidecar(path: &str, text: &str) -> Result<()> {
+    let sidecar = sidecar_path(path);
+    tokio::fs::write(&sidecar, format!("{}\n", text)).await.with_context(|| format!("Failed to write {}", sidecar))
+}
+
+/// Reads the text written next to a screenshot, if there is any.
+pub async fn read_sidecar(path: &str) -> Option<String> {
+    tokio::fs::read_to_string(sidecar_path(path)).await.ok()
+}
+
+/// Prints the text in an image file, optionally copying it to the clipboard. With a
+/// translate command, the translation is printed after it, and copied instead.
+pub async fn ocr_file(path: &str, options: &Options, copy: bool) -> Result<()> {
+    if !std::path::Path::new(path).is_file() {
+        anyhow::bail!("{} does not exist", path);
+    }
+
+    let text = recognize_file(path, options).await?;
+    let text = text.trim();
+    if text.is_empty() {
+        anyhow::bail!("No text was recognized in {}", path);
+    }
+
+    println!("{}", text);
+    let Some(command) = &options.translate_command else {
+        if copy {
+            crate::copy_text_to_clipboard(text).await?;
+        }
+        return Ok(());
+    };
+
+    let translated = translate(text, command).await?;
+    println!("\n{}", translated);
+    if copy {
+        crate::copy_text_to_clipboard(&translated).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use crate::png;
+
+// --- Dominant Colors ---
+//
+// `--palette` finds the capture's dominant colors by median cut: the colors are counted
+// at 5 bits per channel, then the box of colors that is most worth splitting is cut in
+// half along its widest channel until there are as many boxes as colors asked for. Each
+// color is the average of its box, weighted by how often its colors occur.
+
+/// Bits kept of each channel when counting colors.
+const BITS: u32 = 5;
+/// Side of each color's square in the swatch image.
+const SWATCH_SIZE: u32 = 64;
+
+/// A dominant color and the share of the image it stands for.
+#[derive(Debug, Serialize)]
+pub struct Swatch {
+    /// `#rrggbb`
+    pub color: String,
+    pub share: f64,
+    #[serde(skip)]
+    rgb: [u8; 3],
+}
+
+/// A color at `BITS` bits per channel and how many pixels have it.
+#[derive(Clone, Copy)]
+struct Bin {
+    color: [u8; 3],
+    count: u64,
+}
+
+/// Colors that will be represented by one swatch.
+struct ColorBox {
+    bins: Vec<Bin>,
+}
+
+impl ColorBox {
+    fn population(&self) -> u64 {
+        self.bins.iter().map(|bin| bin.count).sum()
+    }
+
+    /// Returns the channel the colors differ most in, and by how much.
+    fn widest_channel(&self) -> (usize, u8) {
+        (0..3)
+        .map(|channel| {
+            let values = self.bins.iter().map(|bin| bin.color[channel]);
+            (channel, values.clone().max().unwrap_or(0) - values.min().unwrap_or(0))
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap_or((0, 0))
+    }
+
+    /// Splits the box along its widest channel, with about half of its pixels on each side.
+    fn split(mut self) -> (ColorBox, ColorBox) {
+        let (channel, _) = self.widest_channel();
+        self.bins.sort_unstable_by_key(|bin| bin.color[channel]);
+        let half = self.population() / 2;
+        let mut below = 0;
+        let mut at = 1;
+        for (index, bin) in self.bins.iter().enumerate() {
+            below += bin.count;
+            if below >= half {
+                at = index + 1;
+                break;
+            }
+        }
+        let upper = self.bins.split_off(at.clamp(1, self.bins.len() - 1));
+        (self, ColorBox { bins: upper })
+    }
+
+    fn average(&self) -> [u8; 3] {
+        let population = self.population().max(1);
+        std::array::from_fn(|channel| {
+            let sum: u64 = self.bins.iter().map(|bin| (((bin.color[channel] as u64) << (8 - BITS)) + (1 << (7 - BITS))) * bin.count).sum();
+            (sum / population) as u8
+        })
+    }
+}
+
+/// Returns up to `count` dominant colors of a PNG image, the most common first.
+/// Fully transparent pixels are left out.
+pub fn dominant_colors(image: &[u8], count: usize) -> Result<Vec<Swatch>> {
+    let mut histogram = vec![0u64; 1 << (3 * BITS)];
+    png::for_each_row(image, &mut |row| {
+        for pixel in row.chunks_exact(4).filter(|pixel| pixel[3] > 0) {
+            let [r, g, b] = [pixel[0] >> (8 - BITS), pixel[1] >> (8 - BITS), pixel[2] >> (8 - BITS)];
+            histogram[((r as usize) << (2 * BITS)) | ((g as usize) << BITS) | b as usize] += 1;
+        }
+    })
+    .context("Failed to read the image's colors")?;
+
+    let mask = (1 << BITS) - 1;
+    let bins: Vec<Bin> = histogram
+    .iter()
+    .enumerate()
+    .filter(|(_, &count)| count > 0)
+    .map(|(index, &count)| Bin {
+        color: [(index >> (2 * BITS)) as u8, ((index >> BITS) & mask) as u8, (index & mask) as u8],
+        count,
+    })
+    .collect();
+    if bins.is_empty() {
+        return Ok(Vec::new());
+    }
+    let total: u64 = bins.iter().map(|bin| bin.count).sum();
+
+    let mut boxes = vec![ColorBox { bins }];
+    while boxes.len() < count {
+        // Big boxes with very different colors are split first.
+        let widest = boxes
+        .iter()
+        .enumerate()
+        .filter(|(_, color_box)| color_box.bins.len() > 1)
+        .max_by_key(|(_, color_box)| color_box.population() * (color_box.widest_channel().1 as u64 + 1));
+        let Some((index, _)) = widest else { break };
+        let (lower, upper) = boxes.swap_remove(index).split();
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+
+    boxes.sort_by_key(|color_box| std::cmp::Reverse(color_box.population()));
+    Ok(boxes
+    .iter()
+    .map(|color_box| {
+        let rgb = color_box.average();
+        Swatch {
+            color: format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2]),
+            share: color_box.population() as f64 / total as f64,
+            rgb,
+        }
+    })
+    .collect())
+}
+
+/// Renders the colors as a row of squares, as a PNG.
+pub fn swatch_image(swatches: &[Swatch]) -> Vec<u8> {
+    let width = SWATCH_SIZE * swatches.len().max(1) as u32;
+    let mut encoder = png::Encoder::new(width, SWATCH_SIZE, false);
+    let row: Vec<u8> = swatches
+    .iter()
+    .flat_map(|swatch| std::iter::repeat_n([swatch.rgb[0], swatch.rgb[1], swatch.rgb[2], 255], SWATCH_SIZE as usize))
+    .flatten()
+    .collect();
+    let row = if row.is_empty() { vec![0; width as usize * 4] } else { row };
+    for _ in 0..SWATCH_SIZE {
+        encoder.push_row(&row);
+    }
+    encoder.finish()
+}
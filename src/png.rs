@@ -0,0 +1,521 @@
+use anyhow::{Context, Result};
+use crate::image::{Downscaler, Image};
+use crate::deflate::ZlibEncoder;
+use crate::inflate;
+
+// --- PNG Decoding ---
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Decodes a non-interlaced greyscale or truecolor PNG, as written by grim, scaling it
+/// down so neither side exceeds `max_side`.
+///
+/// Rows are scaled as they are decompressed, so a capture of a whole multi-monitor desktop
+/// never sits in memory at full size; a 3×4K screenshot alone is over 100 MB of RGBA.
+pub fn decode_scaled(data: &[u8], max_side: u32) -> Result<Image> {
+    let (header, compressed) = read_chunks(data)?;
+    let mut scaler = Downscaler::new(header.width, header.height, max_side);
+    let rows = header.decode_rows(&compressed, &mut |row| scaler.push_row(row))?;
+    if rows < header.height {
+        anyhow::bail!("PNG image data is truncated");
+    }
+    Ok(scaler.finish())
+}
+
+/// Decodes a non-interlaced greyscale or truecolor PNG into RGBA, all of it at once.
+pub fn decode(data: &[u8]) -> Result<Image> {
+    let mut pixels = Vec::new();
+    let (width, height) = for_each_row(data, &mut |row| pixels.extend_from_slice(row))?;
+    Ok(Image { width, height, pixels })
+}
+
+/// Decodes a PNG like [`decode_scaled`] and hands every row of RGBA pixels to `row_sink`
+/// without keeping them. Returns the image's width and height.
+pub fn for_each_row(data: &[u8], row_sink: &mut dyn FnMut(&mut [u8])) -> Result<(u32, u32)> {
+    let (header, compressed) = read_chunks(data)?;
+    let rows = header.decode_rows(&compressed, row_sink)?;
+    if rows < header.height {
+        anyhow::bail!("PNG image data is truncated");
+    }
+    Ok((header.width, header.height))
+}
+
+/// Decodes a PNG like [`decode_scaled`], passes every row of RGBA pixels through `edit`,
+/// and encodes the result again, a row at a time. Images without an alpha channel don't
+/// get one.
+pub fn transform(data: &[u8], edit: &mut dyn FnMut(&mut [u8])) -> Result<Vec<u8>> {
+    let (header, compressed) = read_chunks(data)?;
+    let alpha = header.channels == 2 || header.channels == 4;
+    let mut encoder = Encoder::new(header.width, header.height, alpha);
+    let rows = header.decode_rows(&compressed, &mut |row| {
+        edit(row);
+        encoder.push_row(row);
+    })?;
+    if rows < header.height {
+        anyhow::bail!("PNG image data is truncated");
+    }
+    Ok(encoder.finish())
+}
+
+/// Returns the header and the concatenated, still compressed image data.
+fn read_chunks(data: &[u8]) -> Result<(Header, Vec<u8>)> {
+    if !data.starts_with(&SIGNATURE) {
+        anyhow::bail!("Not a PNG image");
+    }
+
+    let mut header = None;
+    let mut compressed = Vec::new();
+    let mut pos = SIGNATURE.len();
+    while pos + 8 <= data.len() {
+        let len = u32::from_be_bytes(data[pos..pos + 4].try_into()?) as usize;
+        let kind = &data[pos + 4..pos + 8];
+        let body = data.get(pos + 8..pos + 8 + len).context("PNG chunk is truncated")?;
+        match kind {
+            b"IHDR" => header = Some(Header::parse(body)?),
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        // Skip the chunk body and its CRC.
+        pos += 12 + len;
+    }
+
+    Ok((header.context("PNG has no IHDR chunk")?, compressed))
+}
+
+/// The image properties from the IHDR chunk.
+struct Header {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    channels: usize,
+}
+
+impl Header {
+    fn parse(body: &[u8]) -> Result<Header> {
+        if body.len() < 13 {
+            anyhow::bail!("PNG header is truncated");
+        }
+        let width = u32::from_be_bytes(body[0..4].try_into()?);
+        let height = u32::from_be_bytes(body[4..8].try_into()?);
+        let (bit_depth, color_type, interlace) = (body[8], body[9], body[12]);
+
+        let channels = match color_type {
+            0 => 1,
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => anyhow::bail!("Unsupported PNG color type {}", color_type),
+        };
+        if bit_depth != 8 && bit_depth != 16 {
+            anyhow::bail!("Unsupported PNG bit depth {}", bit_depth);
+        }
+        if interlace != 0 {
+            anyhow::bail!("Interlaced PNGs are not supported");
+        }
+
+        Ok(Header { width, height, bit_depth, channels })
+    }
+
+    /// Decompresses the image data, reverses the per-row filters and hands each row to
+    /// `row_sink` as 8-bit RGBA. Returns the number of rows decoded.
+    fn decode_rows(&self, compressed: &[u8], row_sink: &mut dyn FnMut(&mut [u8])) -> Result<u32> {
+        let sample_bytes = self.bit_depth as usize / 8;
+        let pixel_bytes = self.channels * sample_bytes;
+        let stride = self.width as usize * pixel_bytes;
+
+        // A row is its filter type byte followed by `stride` bytes of samples.
+        let mut line = Vec::with_capacity(stride + 1);
+        let mut previous = vec![0u8; stride];
+        let mut rgba = Vec::with_capacity(self.width as usize * 4);
+        let mut rows = 0;
+
+        inflate::zlib_decompress(compressed, &mut |mut data| {
+            while !data.is_empty() && rows < self.height {
+                let take = (stride + 1 - line.len()).min(data.len());
+                line.extend_from_slice(&data[..take]);
+                data = &data[take..];
+                if line.len() < stride + 1 {
+                    break;
+                }
+
+                let filter = line[0];
+                let row = &mut line[1..];
+                unfilter(filter, row, &previous, pixel_bytes)?;
+
+                rgba.clear();
+                for pixel in row.chunks_exact(pixel_bytes) {
+                    // 16-bit samples are big-endian, so their first byte is the 8-bit approximation.
+                    let sample = |channel: usize| pixel[channel * sample_bytes];
+                    match self.channels {
+                        1 => rgba.extend_from_slice(&[sample(0), sample(0), sample(0), 255]),
+                        2 => rgba.extend_from_slice(&[sample(0), sample(0), sample(0), sample(1)]),
+                        3 => rgba.extend_from_slice(&[sample(0), sample(1), sample(2), 255]),
+                        _ => rgba.extend_from_slice(&[sample(0), sample(1), sample(2), sample(3)]),
+                    }
+                }
+                row_sink(&mut rgba);
+                rows += 1;
+
+                previous.copy_from_slice(row);
+                line.clear();
+            }
+            Ok(())
+        })
+        .context("Failed to decompress PNG image data")?;
+
+        Ok(rows)
+    }
+}
+
+/// Reverses one of the five PNG row filters in place.
+fn unfilter(filter: u8, row: &mut [u8], previous: &[u8], pixel_bytes: usize) -> Result<()> {
+    match filter {
+        0 => {}
+        1 => {
+            for i in pixel_bytes..row.len() {
+                row[i] = row[i].wrapping_add(row[i - pixel_bytes]);
+            }
+        }
+        2 => {
+            for (byte, above) in row.iter_mut().zip(previous) {
+                *byte = byte.wrapping_add(*above);
+            }
+        }
+        3 => {
+            for i in 0..row.len() {
+                let left = if i >= pixel_bytes { row[i - pixel_bytes] } else { 0 };
+                row[i] = row[i].wrapping_add(((left as u16 + previous[i] as u16) / 2) as u8);
+            }
+        }
+        4 => {
+            for i in 0..row.len() {
+                let (left, upper_left) = if i >= pixel_bytes { (row[i - pixel_bytes], previous[i - pixel_bytes]) } else { (0, 0) };
+                row[i] = row[i].wrapping_add(paeth(left, previous[i], upper_left));
+            }
+        }
+        _ => anyhow::bail!("Invalid PNG filter type {}", filter),
+    }
+    Ok(())
+}
+
+fn paeth(left: u8, above: u8, upper_left: u8) -> u8 {
+    let estimate = left as i16 + above as i16 - upper_left as i16;
+    let distance = |value: u8| (estimate - value as i16).abs();
+    if distance(left) <= distance(above) && distance(left) <= distance(upper_left) {
+        left
+    } else if distance(above) <= distance(upper_left) {
+        above
+    } else {
+        upper_left
+    }
+}
+
+// --- PNG Encoding ---
+
+/// Compressed image data collected before it is written out as an IDAT chunk.
+const IDAT_SIZE: usize = 256 * 1024;
+
+/// Writes an 8-bit truecolor PNG a row at a time.
+pub struct Encoder {
+    pixel_bytes: usize,
+    zlib: ZlibEncoder,
+    previous: Vec<u8>,
+    row: Vec<u8>,
+    /// The row as every filter type would store it, to pick the smallest from.
+    filtered: [Vec<u8>; 5],
+    /// Compressed image data not written to a chunk yet.
+    idat: Vec<u8>,
+    out: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new(width: u32, height: u32, alpha: bool) -> Encoder {
+        let pixel_bytes = if alpha { 4 } else { 3 };
+        let stride = width as usize * pixel_bytes;
+
+        let mut out = SIGNATURE.to_vec();
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        // 8 bits per sample, truecolor with or without alpha, no interlacing.
+        header.extend_from_slice(&[8, if alpha { 6 } else { 2 }, 0, 0, 0]);
+        write_chunk(&mut out, b"IHDR", &header);
+
+        Encoder {
+            pixel_bytes,
+            zlib: ZlibEncoder::new(),
+            previous: vec![0; stride],
+            row: Vec::with_capacity(stride),
+            filtered: std::array::from_fn(|_| Vec::with_capacity(stride)),
+            idat: Vec::with_capacity(IDAT_SIZE),
+            out,
+        }
+    }
+
+    /// Adds the next row of RGBA pixels. Without alpha, their alpha is left out.
+    pub fn push_row(&mut self, rgba: &[u8]) {
+        self.row.clear();
+        if self.pixel_bytes == 4 {
+            self.row.extend_from_slice(rgba);
+        } else {
+            for pixel in rgba.chunks_exact(4) {
+                self.row.extend_from_slice(&pixel[..3]);
+            }
+        }
+        self.row.resize(self.previous.len(), 0);
+
+        // Like libpng, pick the filter whose output is closest to zero overall, which
+        // tends to compress best.
+        for (filter, filtered) in self.filtered.iter_mut().enumerate() {
+            filter_row(filter as u8, &self.row, &self.previous, self.pixel_bytes, filtered);
+        }
+        let (filter, filtered) = self
+        .filtered
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, filtered)| filtered.iter().map(|&byte| (byte as i8).unsigned_abs() as u64).sum::<u64>())
+        .expect("there are five filters");
+
+        self.zlib.write(&[filter as u8]);
+        self.zlib.write(filtered);
+        std::mem::swap(&mut self.previous, &mut self.row);
+
+        let compressed = self.zlib.take_output();
+        self.idat.extend_from_slice(&compressed);
+        if self.idat.len() >= IDAT_SIZE {
+            write_chunk(&mut self.out, b"IDAT", &self.idat);
+            self.idat.clear();
+        }
+    }
+
+    /// Finishes the image data and returns the whole PNG.
+    pub fn finish(self) -> Vec<u8> {
+        let Encoder { zlib, mut idat, mut out, .. } = self;
+        idat.extend_from_slice(&zlib.finish());
+        write_chunk(&mut out, b"IDAT", &idat);
+        write_chunk(&mut out, b"IEND", &[]);
+        out
+    }
+}
+
+/// Stores a row with one of the five PNG row filters, the reverse of [`unfilter`].
+fn filter_row(filter: u8, row: &[u8], previous: &[u8], pixel_bytes: usize, out: &mut Vec<u8>) {
+    out.clear();
+    // The first pixel has no left neighbour, so each filter is written as a pass over it
+    // followed by one over the rest, which keeps the loops free of branches.
+    let (first, rest) = row.split_at(pixel_bytes.min(row.len()));
+    let (first_above, rest_above) = previous.split_at(first.len());
+    let left = row.iter();
+    let upper_left = previous.iter();
+    match filter {
+        0 => out.extend_from_slice(row),
+        1 => {
+            out.extend_from_slice(first);
+            out.extend(rest.iter().zip(left).map(|(&byte, &left)| byte.wrapping_sub(left)));
+        }
+        2 => out.extend(row.iter().zip(previous).map(|(&byte, &above)| byte.wrapping_sub(above))),
+        3 => {
+            out.extend(first.iter().zip(first_above).map(|(&byte, &above)| byte.wrapping_sub(above / 2)));
+            out.extend(rest.iter().zip(rest_above).zip(left).map(|((&byte, &above), &left)| {
+                byte.wrapping_sub(((left as u16 + above as u16) / 2) as u8)
+            }));
+        }
+        _ => {
+            out.extend(first.iter().zip(first_above).map(|(&byte, &above)| byte.wrapping_sub(above)));
+            out.extend(rest.iter().zip(rest_above).zip(left.zip(upper_left)).map(|((&byte, &above), (&left, &upper_left))| {
+                byte.wrapping_sub(paeth(left, above, upper_left))
+            }));
+        }
+    }
+}
+
+/// Appends a chunk with its length and CRC.
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+    let crc = crc32(kind, body);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
+
+/// The CRC-32 of a chunk's type and body.
+fn crc32(kind: &[u8], body: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for &byte in kind.iter().chain(body) {
+        crc = CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc ^ 0xffffffff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RGBA pixels from a fixed linear congruential generator.
+    fn noise(width: u32, height: u32) -> Vec<u8> {
+        let mut state = 1u32;
+        (0..width * height * 4)
+        .map(|_| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect()
+    }
+
+    fn encode(width: u32, height: u32, pixels: &[u8], alpha: bool) -> Vec<u8> {
+        let mut encoder = Encoder::new(width, height, alpha);
+        for row in pixels.chunks_exact(width as usize * 4) {
+            encoder.push_row(row);
+        }
+        encoder.finish()
+    }
+
+    /// Builds a PNG by hand, with `raw` stored uncompressed in blocks of `block` bytes and
+    /// split over two IDAT chunks.
+    fn stored_png(width: u32, height: u32, bit_depth: u8, color_type: u8, raw: &[u8], block: usize) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+
+        let mut zlib = vec![0x78, 0x01];
+        let blocks: Vec<&[u8]> = raw.chunks(block).collect();
+        for (index, data) in blocks.iter().enumerate() {
+            zlib.push((index + 1 == blocks.len()) as u8);
+            zlib.extend_from_slice(&(data.len() as u16).to_le_bytes());
+            zlib.extend_from_slice(&(!(data.len() as u16)).to_le_bytes());
+            zlib.extend_from_slice(data);
+        }
+        // The checksum isn't verified.
+        zlib.extend_from_slice(&[0; 4]);
+
+        let mut out = SIGNATURE.to_vec();
+        write_chunk(&mut out, b"IHDR", &header);
+        let (first, second) = zlib.split_at(zlib.len() / 2);
+        write_chunk(&mut out, b"IDAT", first);
+        write_chunk(&mut out, b"tEXt", b"Comment\0ignored");
+        write_chunk(&mut out, b"IDAT", second);
+        write_chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    #[test]
+    fn round_trips() {
+        let pixels = noise(37, 11);
+        let image = decode(&encode(37, 11, &pixels, true)).unwrap();
+        assert_eq!((image.width, image.height), (37, 11));
+        assert_eq!(image.pixels, pixels);
+
+        // Without alpha, every pixel comes back opaque.
+        let image = decode(&encode(37, 11, &pixels, false)).unwrap();
+        let opaque: Vec<u8> = pixels.chunks_exact(4).flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255]).collect();
+        assert_eq!(image.pixels, opaque);
+    }
+
+    #[test]
+    fn round_trips_over_many_chunks_and_blocks() {
+        // Noise barely compresses, so this spans several IDAT chunks and DEFLATE blocks.
+        let pixels = noise(400, 400);
+        let png = encode(400, 400, &pixels, true);
+        assert!(png.windows(4).filter(|window| window == b"IDAT").count() > 1);
+        assert_eq!(decode(&png).unwrap().pixels, pixels);
+
+        // Flat areas are compressed into back-references instead.
+        let mut pixels = vec![0u8; 300 * 200 * 4];
+        pixels[..300 * 100 * 4].copy_from_slice(&noise(300, 100));
+        assert_eq!(decode(&encode(300, 200, &pixels, true)).unwrap().pixels, pixels);
+    }
+
+    #[test]
+    fn decodes_every_filter_from_stored_blocks() {
+        let (width, height) = (5u32, 10u32);
+        let pixels = noise(width, height);
+        let stride = width as usize * 4;
+        let mut raw = Vec::new();
+        let mut previous = vec![0u8; stride];
+        let mut filtered = Vec::new();
+        for (index, row) in pixels.chunks_exact(stride).enumerate() {
+            let filter = index as u8 % 5;
+            filter_row(filter, row, &previous, 4, &mut filtered);
+            raw.push(filter);
+            raw.extend_from_slice(&filtered);
+            previous.copy_from_slice(row);
+        }
+
+        let image = decode(&stored_png(width, height, 8, 6, &raw, 7)).unwrap();
+        assert_eq!(image.pixels, pixels);
+    }
+
+    #[test]
+    fn decodes_greyscale_and_16_bit() {
+        // Two pixels of 16-bit grey with alpha, whose high bytes are kept.
+        let raw = [0, 0x12, 0x34, 0xff, 0x00, 0xab, 0xcd, 0x80, 0x01];
+        let image = decode(&stored_png(2, 1, 16, 4, &raw, 100)).unwrap();
+        assert_eq!(image.pixels, [0x12, 0x12, 0x12, 0xff, 0xab, 0xab, 0xab, 0x80]);
+
+        let raw = [0, 7, 200];
+        let image = decode(&stored_png(2, 1, 8, 0, &raw, 100)).unwrap();
+        assert_eq!(image.pixels, [7, 7, 7, 255, 200, 200, 200, 255]);
+    }
+
+    #[test]
+    fn scales_and_transforms() {
+        let pixels = noise(64, 32);
+        let png = encode(64, 32, &pixels, true);
+
+        let scaled = decode_scaled(&png, 16).unwrap();
+        assert_eq!((scaled.width, scaled.height), (16, 8));
+        assert_eq!(scaled.pixels.len(), 16 * 8 * 4);
+
+        let inverted = transform(&png, &mut |row| {
+            for byte in row {
+                *byte = !*byte;
+            }
+        })
+        .unwrap();
+        let expected: Vec<u8> = pixels.iter().map(|byte| !byte).collect();
+        assert_eq!(decode(&inverted).unwrap().pixels, expected);
+    }
+
+    #[test]
+    fn rejects_broken_images() {
+        assert!(decode(b"GIF89a").is_err());
+        assert!(decode(&SIGNATURE).is_err());
+
+        let png = encode(8, 8, &noise(8, 8), true);
+        // Cut off in the middle of the image data.
+        assert!(decode(&png[..png.len() / 2]).is_err());
+
+        // An invalid filter type, and an interlaced image.
+        assert!(decode(&stored_png(1, 1, 8, 0, &[9, 0], 100)).is_err());
+        let mut interlaced = stored_png(1, 1, 8, 0, &[0, 0], 100);
+        interlaced[8 + 8 + 12] = 1;
+        assert!(decode(&interlaced).is_err());
+        // Palette images aren't supported.
+        assert!(decode(&stored_png(1, 1, 8, 3, &[0, 0], 100)).is_err());
+    }
+
+    #[test]
+    fn crc_of_iend() {
+        let mut out = Vec::new();
+        write_chunk(&mut out, b"IEND", &[]);
+        assert_eq!(out, [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]);
+    }
+}
@@ -0,0 +1,96 @@
+use std::time::Instant;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use crate::image::Image;
+use crate::{png, timings};
+
+// --- Size Presets ---
+//
+// `--size-preset` brings a capture to the dimensions a platform expects. By default it is
+// scaled to fit, centered, and the rest is filled with `--size-fill`; with `--size-fit
+// crop` it is scaled to cover the whole size instead, and what sticks out is cut off.
+
+/// Standard dimensions to bring a capture to.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Preset {
+    /// 1200×630, for Open Graph link previews
+    OgImage,
+    /// 1200×628, for large Twitter/X cards
+    TwitterCard,
+    /// 1920×1080
+    #[value(name = "1080p")]
+    FullHd,
+    /// 1080×1080, for square posts
+    Square,
+}
+
+impl Preset {
+    pub fn size(self) -> (u32, u32) {
+        match self {
+            Preset::OgImage => (1200, 630),
+            Preset::TwitterCard => (1200, 628),
+            Preset::FullHd => (1920, 1080),
+            Preset::Square => (1080, 1080),
+        }
+    }
+}
+
+/// How a capture of another aspect ratio is made to fit.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Default)]
+pub enum Fit {
+    /// Show all of it, filling the sides with --size-fill
+    #[default]
+    Pad,
+    /// Fill the whole size, cutting off the sides
+    Crop,
+}
+
+/// Returns the PNG image brought to the preset's size.
+pub fn apply(data: &[u8], preset: Preset, fit: Fit, fill: &str) -> Result<Vec<u8>> {
+    let started = Instant::now();
+    let image = png::decode(data).context("Failed to read the screenshot for --size-preset")?;
+    let (width, height) = preset.size();
+    let fill = rgba(fill);
+
+    let (horizontal, vertical) = (width as f64 / image.width as f64, height as f64 / image.height as f64);
+    let scale = match fit {
+        Fit::Pad => horizontal.min(vertical),
+        Fit::Crop => horizontal.max(vertical),
+    };
+    let scaled_width = ((image.width as f64 * scale).round() as u32).max(1);
+    let scaled_height = ((image.height as f64 * scale).round() as u32).max(1);
+    let scaled = image.resized(scaled_width, scaled_height);
+
+    // Where the scaled image goes on the canvas, negative where it is cropped.
+    let left = (width as i64 - scaled_width as i64) / 2;
+    let top = (height as i64 - scaled_height as i64) / 2;
+    let mut canvas = Image { width, height, pixels: fill.repeat(width as usize * height as usize) };
+    for y in 0..height as i64 {
+        let source_y = y - top;
+        if source_y < 0 || source_y >= scaled_height as i64 {
+            continue;
+        }
+        let first = left.max(0);
+        let last = (left + scaled_width as i64).min(width as i64);
+        let target = (y as usize * width as usize + first as usize) * 4;
+        let source = (source_y as usize * scaled_width as usize + (first - left) as usize) * 4;
+        let length = (last - first) as usize * 4;
+        canvas.pixels[target..target + length].copy_from_slice(&scaled.pixels[source..source + length]);
+    }
+
+    let alpha = canvas.pixels.chunks_exact(4).any(|pixel| pixel[3] != 255);
+    let mut encoder = png::Encoder::new(width, height, alpha);
+    for row in canvas.pixels.chunks_exact(width as usize * 4) {
+        encoder.push_row(row);
+    }
+    timings::add(timings::Stage::Filter, started.elapsed());
+    Ok(encoder.finish())
+}
+
+/// Returns the channels of a `#RRGGBB` or `#RRGGBBAA` color, as validated by
+/// [`crate::selector::parse_color`].
+fn rgba(color: &str) -> [u8; 4] {
+    let hex = color.trim_start_matches('#');
+    let channel = |index: usize| hex.get(index * 2..index * 2 + 2).and_then(|digits| u8::from_str_radix(digits, 16).ok());
+    [channel(0).unwrap_or(0), channel(1).unwrap_or(0), channel(2).unwrap_or(0), channel(3).unwrap_or(255)]
+}
@@ -0,0 +1,48 @@
+use std::process::Stdio;
+use anyhow::{Context, Result};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use crate::process;
+
+// --- Printing ---
+//
+// `--print` and the notification's Print action send the screenshot to CUPS with `lp`,
+// scaled to fit the page inside half-inch margins. CUPS turns landscape screenshots
+// itself, so wide dialogs come out as large as possible.
+
+/// Page margins in points.
+const MARGIN: u32 = 36;
+
+/// Prints an image on `printer`, or on the default printer if it is empty.
+pub async fn print(image: &[u8], printer: &str) -> Result<()> {
+    let mut command = Command::new("lp");
+    if !printer.is_empty() {
+        command.args(["-d", printer]);
+    }
+    command.args(["-t", "LuminaShot screenshot", "-o", "fit-to-page"]);
+    for side in ["left", "right", "top", "bottom"] {
+        command.args(["-o", &format!("page-{}={}", side, MARGIN)]);
+    }
+
+    let mut child = command
+    .arg("-")
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .context("Failed to run lp; printing needs CUPS")?;
+    let mut stdin = child.stdin.take().context("lp has no stdin")?;
+    stdin.write_all(image).await.context("Failed to send the screenshot to lp")?;
+    drop(stdin);
+
+    let output = process::with_timeout("lp", Some(process::command_timeout()), async {
+        Ok(child.wait_with_output().await?)
+    })
+    .await?;
+    if !output.status.success() {
+        anyhow::bail!("lp failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    // Like "request id is office-42 (0 file(s))".
+    crate::progress(String::from_utf8_lossy(&output.stdout).trim());
+    Ok(())
+}
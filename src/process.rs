@@ -0,0 +1,131 @@
+use std::future::Future;
+use std::process::{ExitStatus, Output};
+use std::sync::OnceLock;
+use anyhow::{Context, Result};
+use tokio::process::{Child, Command};
+use tokio::time::{timeout, Duration};
+use crate::timings::{self, Stage};
+
+// --- Timeout Configuration ---
+
+/// Limits applied to external commands for the lifetime of the process.
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    /// Limit for non-interactive commands such as grim, hyprctl and wl-copy.
+    pub command: Duration,
+    /// Limit for interactive selection with slurp, `None` to wait indefinitely.
+    pub selection: Option<Duration>,
+}
+
+static TIMEOUTS: OnceLock<Timeouts> = OnceLock::new();
+
+/// Sets the timeouts used by all helpers in this module. Only the first call has an effect.
+pub fn set_timeouts(timeouts: Timeouts) {
+    let _ = TIMEOUTS.set(timeouts);
+}
+
+/// Returns the limit for non-interactive commands.
+pub fn command_timeout() -> Duration {
+    timeouts().command
+}
+
+fn timeouts() -> Timeouts {
+    *TIMEOUTS.get_or_init(|| Timeouts {
+        command: Duration::from_secs(10),
+        selection: None,
+    })
+}
+
+// --- Command Helpers ---
+
+/// Returns the program name of a command for use in error messages.
+pub fn program_name(command: &Command) -> String {
+    command.as_std().get_program().to_string_lossy().into_owned()
+}
+
+/// Returns true if `program` is an executable in `$PATH`.
+pub fn in_path(program: &str) -> bool {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&path).any(|dir| {
+        let candidate = dir.join(program);
+        std::fs::metadata(&candidate).is_ok_and(|metadata| metadata.is_file() && std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o111 != 0)
+    })
+}
+
+/// Awaits `future`, failing with an error naming `name` if it takes longer than `limit`.
+pub async fn with_timeout<T>(name: &str, limit: Option<Duration>, future: impl Future<Output = Result<T>>) -> Result<T> {
+    match limit {
+        Some(limit) => match timeout(limit, future).await {
+            Ok(result) => result,
+            Err(_) => anyhow::bail!("`{}` did not finish within {}s", name, limit.as_secs_f32()),
+        },
+        None => future.await,
+    }
+}
+
+/// The stage a non-interactive command's run time counts towards, if any.
+fn stage_of(name: &str) -> Option<Stage> {
+    (name == "hyprctl").then_some(Stage::Compositor)
+}
+
+/// Awaits `future`, adding the time it took to `stage` if there is one.
+async fn timed<T>(stage: Option<Stage>, future: impl Future<Output = T>) -> T {
+    match stage {
+        Some(stage) => timings::time(stage, future).await,
+        None => future.await,
+    }
+}
+
+/// Runs a non-interactive command to completion and collects its output.
+pub async fn output(command: &mut Command) -> Result<Output> {
+    let name = program_name(command);
+    command.kill_on_drop(true);
+    timed(stage_of(&name), with_timeout(&name, Some(timeouts().command), async {
+        command.output().await.with_context(|| format!("Failed to run {}", name))
+    }))
+    .await
+}
+
+/// Runs a non-interactive command to completion and returns its exit status.
+pub async fn status(command: &mut Command) -> Result<ExitStatus> {
+    let name = program_name(command);
+    command.kill_on_drop(true);
+    timed(stage_of(&name), with_timeout(&name, Some(timeouts().command), async {
+        command.status().await.with_context(|| format!("Failed to run {}", name))
+    }))
+    .await
+}
+
+/// Runs an interactive selection command, limited by the selection timeout.
+pub async fn selection_output(command: &mut Command) -> Result<Output> {
+    let name = program_name(command);
+    command.kill_on_drop(true);
+    timings::time(Stage::Selection, with_timeout(&name, timeouts().selection, async {
+        command.output().await.with_context(|| format!("Failed to run {}", name))
+    }))
+    .await
+}
+
+/// Waits for an interactive selection child to exit.
+pub async fn wait_selection(name: &str, child: &mut Child) -> Result<ExitStatus> {
+    timings::time(Stage::Selection, with_timeout(name, timeouts().selection, async {
+        Ok(child.wait().await?)
+    }))
+    .await
+}
+
+/// Waits for a non-interactive child to exit.
+pub async fn wait(name: &str, child: &mut Child) -> Result<ExitStatus> {
+    with_timeout(name, Some(timeouts().command), async {
+        Ok(child.wait().await?)
+    })
+    .await
+}
+
+/// Awaits a single step of talking to a non-interactive child, such as writing to its stdin.
+pub async fn io<T>(name: &str, future: impl Future<Output = std::io::Result<T>>) -> Result<T> {
+    with_timeout(name, Some(timeouts().command), async {
+        Ok(future.await?)
+    })
+    .await
+}
@@ -0,0 +1,595 @@
+use std::process::Stdio;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use tokio::process::{Child, Command};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time::{sleep, Duration, Instant};
+use crate::geometry::Rect;
+use crate::{dirs, i18n, idle, notify, process, template, Mode};
+
+// --- `record` Subcommand ---
+//
+// Screen recordings are made with wf-recorder. While one is running, the process ID of the
+// `luminashot record` that started it and the output file are kept in a state file in the
+// runtime directory, which is how a second `luminashot record --toggle`, `--pause` or
+// `--resume` finds the recording. It writes its request into the state file and sends
+// SIGUSR1 to the first invocation, which stays around until the recording is stopped, then
+// reports the finished file.
+//
+// wf-recorder can't pause, so pausing stops it and resuming starts another one on a new
+// part of the file. The parts are joined with ffmpeg when the recording stops.
+//
+// With `--to-virtual-cam`, wf-recorder streams raw frames into a v4l2loopback device
+// instead, which video call apps offer as a webcam, until the stream is stopped.
+//
+// `--status` reads the state file to tell status bars whether a recording is running.
+
+/// Options for a recording.
+pub struct RecordOptions<'a> {
+    pub mode: Mode,
+    /// Stop the running recording instead of refusing to start a second one.
+    pub toggle: bool,
+    /// Pause the running recording.
+    pub pause: bool,
+    /// Resume the paused recording.
+    pub resume: bool,
+    /// The audio source to record along with the screen, if any; see [`audio_device`].
+    pub audio: Option<&'a str>,
+    pub encoder: Encoder,
+    /// Stream to a v4l2loopback device instead of recording to a file. An empty string
+    /// picks the first loopback device.
+    pub virtual_cam: Option<&'a str>,
+    pub silent: bool,
+    pub ignore_dnd: bool,
+    pub dnd_command: Option<&'a str>,
+}
+
+/// How the video is encoded.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Encoder {
+    /// x264 on the CPU.
+    Software,
+    /// The GPU's encoder through VA-API, on Intel and AMD graphics.
+    Vaapi,
+    /// The GPU's encoder on NVIDIA graphics.
+    Nvenc,
+}
+
+/// What is being recorded right now.
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordingState {
+    /// The process ID of the `luminashot record` running wf-recorder.
+    pid: u32,
+    path: String,
+    started: DateTime<Local>,
+    #[serde(default)]
+    paused: bool,
+    /// Whether `path` is a virtual camera being streamed to rather than a file.
+    #[serde(default)]
+    virtual_cam: bool,
+    /// Seconds recorded before the part being recorded now, or before the pause.
+    #[serde(default)]
+    recorded_secs: f64,
+    /// When the part being recorded now was started.
+    #[serde(default = "Local::now")]
+    part_started: DateTime<Local>,
+    /// What another invocation asked the recording to do, picked up on SIGUSR1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    request: Option<Request>,
+}
+
+/// Something a recording can be asked to do by another invocation.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Request {
+    Pause,
+    Resume,
+    Stop,
+}
+
+/// A running wf-recorder. Dropping it, for example on SIGINT, stops the recording
+/// gracefully so the file is still finalized.
+struct Recorder {
+    child: Child,
+}
+
+impl Recorder {
+    /// Stops wf-recorder and waits until it has finished writing its file.
+    async fn stop(mut self) -> Result<()> {
+        interrupt(&self.child);
+        self.child.wait().await.context("Failed to wait for wf-recorder")?;
+        Ok(())
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            interrupt(&self.child);
+        }
+    }
+}
+
+/// Makes wf-recorder finish its file and exit, just like on Ctrl-C.
+fn interrupt(child: &Child) {
+    if let Some(pid) = child.id() {
+        // SAFETY: sending a signal has no memory-safety requirements.
+        unsafe { libc::kill(pid as libc::pid_t, libc::SIGINT) };
+    }
+}
+
+/// Removes the state file when dropped, however the recording ends.
+struct StateFile;
+
+impl Drop for StateFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(state_path());
+    }
+}
+
+fn state_path() -> String {
+    format!("{}/luminashot-recording.json", dirs::runtime_dir())
+}
+
+/// Starts a recording, or stops, pauses or resumes the running one.
+pub async fn record(options: RecordOptions<'_>) -> Result<()> {
+    if let Some(state) = running_recording().await {
+        if options.pause || options.resume {
+            return pause_or_resume(state, if options.pause { Request::Pause } else { Request::Resume }).await;
+        }
+        if !options.toggle {
+            anyhow::bail!("A recording to {} is already running; use `luminashot record --toggle` to stop it", state.path);
+        }
+        return stop(state).await;
+    }
+    if options.pause || options.resume {
+        anyhow::bail!("No recording is running");
+    }
+
+    let geometry = match options.mode {
+        Mode::Region => crate::region_mode().await?,
+        Mode::Window => crate::window_mode().await?.map(|window| window.rect()).transpose()?,
+        Mode::Monitor => crate::monitor_mode().await?,
+        Mode::Inspect => anyhow::bail!("Only a region, window or monitor can be recorded"),
+    };
+    let Some(geometry) = geometry else {
+        println!("{}", i18n::tr("Action cancelled."));
+        return Ok(());
+    };
+
+    let do_not_disturb = !options.silent && !options.ignore_dnd && notify::do_not_disturb(options.dnd_command).await;
+    let quiet = options.silent || do_not_disturb;
+
+    // Listening before the state file exists, so no request can be missed.
+    let mut requests = signal(SignalKind::user_defined1()).context("Failed to listen for pause and stop requests")?;
+    let path = match options.virtual_cam {
+        Some("") => loopback_device()?,
+        Some(device) => device.to_string(),
+        None => {
+            let dir = recordings_dir();
+            tokio::fs::create_dir_all(&dir).await.with_context(|| format!("Failed to create {}", dir))?;
+            format!("{}/{}-luminashot.mp4", dir, Local::now().format("%Y-%m-%d_%H-%M-%S"))
+        }
+    };
+    let mut recorder = Some(start(&geometry, &path, &options)?);
+    let mut state = RecordingState {
+        pid: std::process::id(),
+        path,
+        started: Local::now(),
+        paused: false,
+        virtual_cam: options.virtual_cam.is_some(),
+        recorded_secs: 0.0,
+        part_started: Local::now(),
+        request: None,
+    };
+    let state_file = StateFile;
+    write_state(&state).await?;
+    if state.virtual_cam {
+        println!("Streaming {} to the virtual camera {}", geometry, state.path);
+    } else {
+        println!("Recording {} to {}", geometry, state.path);
+    }
+
+    let started = if quiet {
+        None
+    } else {
+        notify::show(&notify::Notification {
+            summary: i18n::tr(if state.virtual_cam { "LuminaShot - Streaming" } else { "LuminaShot - Recording" }),
+            body: i18n::tr("Run `luminashot record --toggle` again to stop."),
+            icon: "media-record".to_string(),
+            urgency: notify::Urgency::Low,
+            transient: true,
+            ..Default::default()
+        }).await
+    };
+
+    // Without input, the screen would blank or lock in the middle of the recording.
+    let inhibitor = idle::inhibit(if state.virtual_cam { "Streaming the screen" } else { "Recording the screen" }).await;
+    let mut parts = vec![state.path.clone()];
+    let mut recorded = Duration::ZERO;
+    let mut part_started = Instant::now();
+    loop {
+        let request = tokio::select! {
+            // wf-recorder exited on its own, so the recording is over.
+            status = wait(&mut recorder) => {
+                let status = status.context("Failed to wait for wf-recorder")?;
+                recorded += part_started.elapsed();
+                if state.virtual_cam {
+                    anyhow::bail!("wf-recorder stopped streaming to {} with {}", state.path, status);
+                }
+                // After resuming, it was writing the latest part rather than the recording itself.
+                let part = parts.last().unwrap_or(&state.path);
+                if !tokio::fs::metadata(part).await.is_ok_and(|metadata| metadata.len() > 0) {
+                    anyhow::bail!("wf-recorder exited with {} without writing {}", status, part);
+                }
+                break;
+            }
+            _ = requests.recv() => take_request().await,
+        };
+
+        match request {
+            Some(Request::Pause) => {
+                if let Some(running) = recorder.take() {
+                    running.stop().await?;
+                    recorded += part_started.elapsed();
+                    state.paused = true;
+                    state.recorded_secs = recorded.as_secs_f64();
+                    write_state(&state).await?;
+                    println!("Paused recording to {}", state.path);
+                }
+            }
+            Some(Request::Resume) if recorder.is_none() => {
+                let part = part_path(&state.path, parts.len() + 1);
+                recorder = Some(start(&geometry, &part, &options)?);
+                parts.push(part);
+                part_started = Instant::now();
+                state.paused = false;
+                state.part_started = Local::now();
+                write_state(&state).await?;
+                println!("Resumed recording to {}", state.path);
+            }
+            Some(Request::Stop) => {
+                if let Some(running) = recorder.take() {
+                    running.stop().await?;
+                    recorded += part_started.elapsed();
+                }
+                break;
+            }
+            Some(Request::Resume) | None => {}
+        }
+    }
+    if let Some(inhibitor) = inhibitor {
+        inhibitor.release().await;
+    }
+    if let Some(started) = started {
+        let _ = started.close().await;
+    }
+
+    if state.virtual_cam {
+        println!("Stopped streaming to {}", state.path);
+        return Ok(());
+    }
+    if parts.len() > 1 {
+        join_parts(&parts, &state.path).await;
+    }
+    drop(state_file);
+
+    let size = tokio::fs::metadata(&state.path).await.map_or(0, |metadata| metadata.len());
+    let elapsed = recorded.as_secs();
+    let duration = format!("{}:{:02}", elapsed / 60, elapsed % 60);
+    println!("Saved {} recording to {}", duration, state.path);
+
+    if quiet {
+        return Ok(());
+    }
+    let delivered = notify::show(&notify::Notification {
+        summary: i18n::tr("LuminaShot - Recording Saved"),
+        body: i18n::tr_with("Saved to {path} ({duration}, {size})", &[
+            ("path", state.path.clone()),
+            ("duration", duration),
+            ("size", template::human_size(size)),
+        ]),
+        icon: "video-x-generic".to_string(),
+        actions: vec![("default".to_string(), i18n::tr("Open"))],
+        ..Default::default()
+    }).await;
+
+    // Clicking it opens the recording with `--viewer`, from a process of its own, so
+    // stopping returns right away.
+    if let Some(delivered) = delivered {
+        if let Err(err) = crate::watch_in_background(delivered, Some(&state.path), None, &[]).await {
+            eprintln!("Warning: The notification's buttons won't work: {:#}", err);
+        }
+    }
+    Ok(())
+}
+
+/// What `--status` prints, in the format of waybar's custom modules.
+#[derive(Serialize)]
+pub struct Status {
+    pub text: String,
+    pub tooltip: String,
+    /// `recording`, `paused` or `idle`, for styling the module.
+    pub class: &'static str,
+    pub alt: &'static str,
+    /// Seconds recorded so far, leaving out pauses.
+    elapsed: u64,
+    path: Option<String>,
+}
+
+impl Status {
+    pub async fn current() -> Status {
+        let Some(state) = running_recording().await else {
+            return Status { text: String::new(), tooltip: "Not recording".to_string(), class: "idle", alt: "idle", elapsed: 0, path: None };
+        };
+        let mut elapsed = state.recorded_secs;
+        if !state.paused {
+            elapsed += (Local::now() - state.part_started).num_milliseconds().max(0) as f64 / 1000.0;
+        }
+        let elapsed = elapsed as u64;
+        let duration = format!("{}:{:02}", elapsed / 60, elapsed % 60);
+        let (class, symbol, doing) = match (state.paused, state.virtual_cam) {
+            (true, _) => ("paused", "⏸", "Paused recording to"),
+            (false, true) => ("recording", "●", "Streaming to"),
+            (false, false) => ("recording", "●", "Recording to"),
+        };
+        Status {
+            text: format!("{} {}", symbol, duration),
+            tooltip: format!("{} {} ({})", doing, state.path, duration),
+            class,
+            alt: class,
+            elapsed,
+            path: Some(state.path),
+        }
+    }
+}
+
+/// Prints whether a recording is running as JSON for status bars, once, or every second
+/// with `watch` for waybar's continuous `exec` modules.
+pub async fn status(watch: bool) -> Result<()> {
+    loop {
+        println!("{}", serde_json::to_string(&Status::current().await)?);
+        if !watch {
+            return Ok(());
+        }
+        sleep(Duration::from_secs(1)).await;
+    }
+}
+
+/// Waits for wf-recorder to exit, or forever while paused.
+async fn wait(recorder: &mut Option<Recorder>) -> std::io::Result<std::process::ExitStatus> {
+    match recorder {
+        Some(recorder) => recorder.child.wait().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Returns the path of a part of a recording after the first one, which is at `path`.
+fn part_path(path: &str, part: usize) -> String {
+    let stem = path.strip_suffix(".mp4").unwrap_or(path);
+    format!("{}-part{}.mp4", stem, part)
+}
+
+/// Joins the parts of a paused recording into the first one, at `path`. If that fails,
+/// the parts are left as they are.
+async fn join_parts(parts: &[String], path: &str) {
+    let list_path = format!("{}/luminashot-recording-parts.txt", dirs::runtime_dir());
+    let joined = format!("{}-joined.mp4", path.strip_suffix(".mp4").unwrap_or(path));
+    let result = async {
+        // The concat demuxer's list quotes paths in single quotes.
+        let list: String = parts.iter().map(|part| format!("file '{}'\n", part.replace('\'', "'\\''"))).collect();
+        tokio::fs::write(&list_path, list).await.context("Failed to write the list of parts")?;
+        let status = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-f", "concat", "-safe", "0", "-i"])
+        .arg(&list_path)
+        .args(["-c", "copy"])
+        .arg(&joined)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .status()
+        .await
+        .context("Failed to run ffmpeg; is it installed?")?;
+        if !status.success() {
+            anyhow::bail!("ffmpeg exited with {}", status);
+        }
+        tokio::fs::rename(&joined, path).await.with_context(|| format!("Failed to move the joined recording to {}", path))?;
+        for part in &parts[1..] {
+            let _ = tokio::fs::remove_file(part).await;
+        }
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+
+    let _ = tokio::fs::remove_file(&list_path).await;
+    if let Err(err) = result {
+        let _ = tokio::fs::remove_file(&joined).await;
+        eprintln!("Warning: could not join the {} parts of the recording: {:#}. They were saved as {}.", parts.len(), err, parts.join(", "));
+    }
+}
+
+/// Returns the recording in progress, clearing out the state left behind by one that died.
+async fn running_recording() -> Option<RecordingState> {
+    let contents = tokio::fs::read(state_path()).await.ok()?;
+    match serde_json::from_slice::<RecordingState>(&contents) {
+        Ok(state) if is_running(state.pid) => Some(state),
+        _ => {
+            let _ = tokio::fs::remove_file(state_path()).await;
+            None
+        }
+    }
+}
+
+/// Returns the PulseAudio source wf-recorder should record for `--audio`. PipeWire's
+/// PulseAudio server understands the same names.
+fn audio_device(audio: &str) -> &str {
+    match audio {
+        // What is playing on the default output, for tutorials and demos.
+        "system" => "@DEFAULT_MONITOR@",
+        "mic" => "@DEFAULT_SOURCE@",
+        source => source,
+    }
+}
+
+/// Returns wf-recorder's arguments for `encoder`, or for software encoding with a warning
+/// if the hardware it needs isn't there.
+fn encoder_args(encoder: Encoder) -> Vec<String> {
+    let software = || vec!["-c".to_string(), "libx264".to_string()];
+    match encoder {
+        Encoder::Software => software(),
+        Encoder::Vaapi => match render_node() {
+            Some(device) => vec!["-c".to_string(), "h264_vaapi".to_string(), "-d".to_string(), device],
+            None => {
+                eprintln!("Warning: no GPU render node in /dev/dri, recording with the software encoder instead.");
+                software()
+            }
+        },
+        Encoder::Nvenc => {
+            if std::path::Path::new("/proc/driver/nvidia").exists() {
+                vec!["-c".to_string(), "h264_nvenc".to_string()]
+            } else {
+                eprintln!("Warning: the NVIDIA driver isn't loaded, recording with the software encoder instead.");
+                software()
+            }
+        }
+    }
+}
+
+/// Returns the first GPU render node, which VA-API encodes on.
+fn render_node() -> Option<String> {
+    let mut nodes: Vec<String> = std::fs::read_dir("/dev/dri")
+    .ok()?
+    .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+    .filter(|name| name.starts_with("renderD"))
+    .collect();
+    nodes.sort();
+    nodes.first().map(|name| format!("/dev/dri/{}", name))
+}
+
+/// Returns the first v4l2loopback device. Unlike real cameras, they are virtual devices,
+/// so they show up under `/sys/devices/virtual`.
+fn loopback_device() -> Result<String> {
+    let mut devices: Vec<String> = std::fs::read_dir("/sys/class/video4linux")
+    .into_iter()
+    .flatten()
+    .filter_map(|entry| {
+        let entry = entry.ok()?;
+        let target = std::fs::canonicalize(entry.path()).ok()?;
+        target.starts_with("/sys/devices/virtual").then(|| entry.file_name().into_string().ok())?
+    })
+    .collect();
+    // video10 comes after video9.
+    devices.sort_by_key(|name| (name.len(), name.clone()));
+    devices
+    .first()
+    .map(|name| format!("/dev/{}", name))
+    .context("No v4l2loopback device found; load the module with `sudo modprobe v4l2loopback exclusive_caps=1`")
+}
+
+/// Spawns wf-recorder to record `geometry` to `path`.
+fn start(geometry: &Rect, path: &str, options: &RecordOptions<'_>) -> Result<Recorder> {
+    let mut command = Command::new("wf-recorder");
+    command
+    .arg("-g")
+    .arg(geometry.to_string())
+    .arg("-f")
+    .arg(path);
+    if options.virtual_cam.is_some() {
+        // Raw frames in the pixel format webcams use, which every app can read.
+        command.args(["--muxer=v4l2", "--codec=rawvideo", "--pixel-format=yuv420p"]);
+    } else {
+        command.args(encoder_args(options.encoder));
+    }
+    if let Some(audio) = options.audio {
+        // wf-recorder only takes the device attached to the flag.
+        command.arg(format!("--audio={}", audio_device(audio)));
+    }
+    let child = command
+    .stdin(Stdio::null())
+    .stdout(Stdio::null())
+    .spawn()
+    .context("Failed to spawn wf-recorder")?;
+
+    Ok(Recorder { child })
+}
+
+async fn write_state(state: &RecordingState) -> Result<()> {
+    tokio::fs::write(state_path(), serde_json::to_vec(state)?)
+    .await
+    .context("Failed to write the recording state file")
+}
+
+/// Takes the request another invocation left in the state file.
+async fn take_request() -> Option<Request> {
+    let contents = tokio::fs::read(state_path()).await.ok()?;
+    let mut state = serde_json::from_slice::<RecordingState>(&contents).ok()?;
+    let request = state.request.take();
+    let _ = write_state(&state).await;
+    request
+}
+
+/// Asks the recording to do something.
+async fn send_request(mut state: RecordingState, request: Request) -> Result<RecordingState> {
+    state.request = Some(request);
+    write_state(&state).await?;
+    // SAFETY: sending a signal has no memory-safety requirements.
+    unsafe { libc::kill(state.pid as libc::pid_t, libc::SIGUSR1) };
+    Ok(state)
+}
+
+/// Pauses or resumes a recording started by another invocation.
+async fn pause_or_resume(state: RecordingState, request: Request) -> Result<()> {
+    if state.virtual_cam {
+        anyhow::bail!("Streaming to a virtual camera can't be paused");
+    }
+    let pausing = request == Request::Pause;
+    if state.paused == pausing {
+        println!("The recording to {} is already {}", state.path, if pausing { "paused" } else { "running" });
+        return Ok(());
+    }
+    let state = send_request(state, request).await?;
+
+    process::with_timeout("wf-recorder", Some(process::command_timeout()), async {
+        while running_recording().await.is_some_and(|running| running.paused != pausing) {
+            sleep(Duration::from_millis(50)).await;
+        }
+        Ok(())
+    })
+    .await?;
+
+    println!("{} recording to {}", if pausing { "Paused" } else { "Resumed" }, state.path);
+    Ok(())
+}
+
+/// Stops a recording started by another invocation and waits for its file to be finalized.
+async fn stop(state: RecordingState) -> Result<()> {
+    let state = send_request(state, Request::Stop).await?;
+
+    process::with_timeout("wf-recorder", Some(process::command_timeout()), async {
+        while is_running(state.pid) && std::path::Path::new(&state_path()).exists() {
+            sleep(Duration::from_millis(50)).await;
+        }
+        Ok(())
+    })
+    .await?;
+
+    println!("Stopped {} to {}", if state.virtual_cam { "streaming" } else { "recording" }, state.path);
+    Ok(())
+}
+
+/// Returns whether a process with this ID exists.
+fn is_running(pid: u32) -> bool {
+    // SAFETY: signal 0 only checks for the process, it doesn't deliver anything.
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+/// Returns the directory recordings are saved to.
+fn recordings_dir() -> String {
+    let videos_dir = dirs::videos_dir().unwrap_or_else(|err| {
+        let fallback = dirs::temp_dir();
+        eprintln!("Warning: {:#}, using {} instead.", err, fallback);
+        fallback
+    });
+    format!("{}/Recordings", videos_dir)
+}
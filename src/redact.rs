@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use anyhow::{Context, Result};
+use crate::format::ImageFormat;
+use crate::image::Image;
+use crate::{ocr, png};
+
+// --- Redacting Text ---
+//
+// `--redact-text` recognizes the words in a capture together with their positions, and
+// blurs every word that is part of a match before the capture is saved or copied. The
+// patterns are POSIX extended regular expressions, matched against each line of text
+// with its words joined by single spaces, so patterns like IBANs may span several words.
+//
+// Redaction fails closed: if the text can't be recognized, the capture isn't kept.
+
+/// Patterns that can be given by name.
+const NAMED_PATTERNS: &[(&str, &str)] = &[
+    ("email", "[[:alnum:]._%+-]+@[[:alnum:].-]+\\.[[:alpha:]]{2,}"),
+    ("iban", "[A-Z]{2}[0-9]{2}( ?[A-Z0-9]{4}){2,7}( ?[A-Z0-9]{1,3})?"),
+    ("card", "[0-9]{4}([ -]?[0-9]{4}){3}"),
+];
+
+/// Pixels added around every word's box, so no edge of a letter stays sharp.
+const PADDING: u32 = 2;
+/// Box blur passes; three come close to a gaussian blur.
+const PASSES: usize = 3;
+
+/// A capture with the matching words blurred.
+pub struct Redacted {
+    pub image: Vec<u8>,
+    /// The recognized text, with `[redacted]` in place of the blurred words.
+    pub text: String,
+    pub words: usize,
+}
+
+/// Validates a `--redact-text` pattern for clap, replacing a pattern's name with the pattern.
+pub fn parse_pattern(value: &str) -> Result<String, String> {
+    let pattern = NAMED_PATTERNS.iter().find(|(name, _)| *name == value).map_or(value, |(_, pattern)| pattern);
+    Regex::new(pattern)?;
+    Ok(pattern.to_string())
+}
+
+/// Blurs the words of a PNG image that match any of `patterns`.
+pub async fn redact(image: Vec<u8>, patterns: &[String], options: &ocr::Options) -> Result<Redacted> {
+    let regexes = patterns.iter().map(|pattern| Regex::new(pattern)).collect::<Result<Vec<_>, _>>().map_err(anyhow::Error::msg)?;
+    let words = ocr::words(&image, ImageFormat::Png, options).await?;
+
+    let mut lines: BTreeMap<[u32; 4], Vec<&ocr::Word>> = BTreeMap::new();
+    for word in &words {
+        lines.entry(word.line).or_default().push(word);
+    }
+
+    let mut boxes = Vec::new();
+    let mut text = Vec::new();
+    for words in lines.values() {
+        let mut joined = String::new();
+        let mut spans = Vec::with_capacity(words.len());
+        for word in words {
+            if !joined.is_empty() {
+                joined.push(' ');
+            }
+            spans.push((joined.len(), joined.len() + word.text.len()));
+            joined.push_str(&word.text);
+        }
+        let matches: Vec<(usize, usize)> = regexes.iter().flat_map(|regex| regex.find_all(&joined)).collect();
+
+        let mut line = Vec::new();
+        for (word, (start, end)) in words.iter().zip(spans) {
+            if matches.iter().any(|&(match_start, match_end)| match_start < end && start < match_end) {
+                boxes.push(*word);
+                if line.last() != Some(&"[redacted]") {
+                    line.push("[redacted]");
+                }
+            } else {
+                line.push(word.text.as_str());
+            }
+        }
+        text.push(line.join(" "));
+    }
+
+    let redacted = match boxes.is_empty() {
+        true => image,
+        false => blur_boxes(&image, &boxes)?,
+    };
+    Ok(Redacted { image: redacted, text: text.join("\n"), words: boxes.len() })
+}
+
+/// Returns the PNG image with the words' boxes blurred.
+fn blur_boxes(data: &[u8], words: &[&ocr::Word]) -> Result<Vec<u8>> {
+    let mut image = png::decode(data)?;
+    for word in words {
+        let x = word.x.saturating_sub(PADDING);
+        let y = word.y.saturating_sub(PADDING);
+        let width = (word.x + word.width + PADDING).min(image.width).saturating_sub(x);
+        let height = (word.y + word.height + PADDING).min(image.height).saturating_sub(y);
+        if width > 0 && height > 0 {
+            blur(&mut image, x, y, width, height);
+        }
+    }
+
+    // Re-encoded through `transform`, which keeps whether the image has an alpha channel.
+    let stride = image.width as usize * 4;
+    let mut rows = image.pixels.chunks_exact(stride);
+    png::transform(data, &mut |row| {
+        if let Some(blurred) = rows.next() {
+            row.copy_from_slice(blurred);
+        }
+    })
+    .context("Failed to blur the matching text")
+}
+
+/// Blurs a rectangle of the image so strongly that the text in it can't be read, without
+/// touching pixels outside of it. The radius is the rectangle's height, the size of a letter.
+fn blur(image: &mut Image, x: u32, y: u32, width: u32, height: u32) {
+    let radius = height as usize;
+    let stride = image.width as usize * 4;
+    let (x, y, width, height) = (x as usize, y as usize, width as usize, height as usize);
+    let index = |column: usize, row: usize| (y + row) * stride + (x + column) * 4;
+
+    let mut line = Vec::new();
+    for _ in 0..PASSES {
+        for row in 0..height {
+            line.clear();
+            line.extend((0..width).map(|column| index(column, row)));
+            box_blur(&mut image.pixels, &line, radius);
+        }
+        for column in 0..width {
+            line.clear();
+            line.extend((0..height).map(|row| index(column, row)));
+            box_blur(&mut image.pixels, &line, radius);
+        }
+    }
+}
+
+/// Replaces the color of each pixel at `offsets` by the average of its neighbors within
+/// `radius` along them, repeating the pixels at either end.
+fn box_blur(pixels: &mut [u8], offsets: &[usize], radius: usize) {
+    let last = offsets.len() - 1;
+    let source: Vec<[u32; 3]> = offsets.iter().map(|&offset| std::array::from_fn(|channel| pixels[offset + channel] as u32)).collect();
+    let at = |position: isize| source[position.clamp(0, last as isize) as usize];
+    let window = 2 * radius as u32 + 1;
+
+    let mut sum = [0u32; 3];
+    for position in -(radius as isize)..=radius as isize {
+        let pixel = at(position);
+        for channel in 0..3 {
+            sum[channel] += pixel[channel];
+        }
+    }
+    for (position, &offset) in offsets.iter().enumerate() {
+        for channel in 0..3 {
+            pixels[offset + channel] = (sum[channel] / window) as u8;
+        }
+        let (leaving, entering) = (at(position as isize - radius as isize), at(position as isize + radius as isize + 1));
+        for channel in 0..3 {
+            sum[channel] = sum[channel] + entering[channel] - leaving[channel];
+        }
+    }
+}
+
+// --- POSIX Regular Expressions ---
+
+/// A compiled extended regular expression, from the C library.
+struct Regex {
+    compiled: Box<libc::regex_t>,
+}
+
+impl Regex {
+    fn new(pattern: &str) -> Result<Regex, String> {
+        let source = CString::new(pattern).map_err(|_| "The pattern contains a NUL byte".to_string())?;
+        // SAFETY: `regex_t` is a plain C struct that regcomp fills in.
+        let mut compiled: Box<libc::regex_t> = Box::new(unsafe { std::mem::zeroed() });
+        // SAFETY: `source` is NUL-terminated and `compiled` is valid for writes.
+        let code = unsafe { libc::regcomp(&mut *compiled, source.as_ptr(), libc::REG_EXTENDED) };
+        if code != 0 {
+            let mut message = [0u8; 256];
+            // SAFETY: regerror writes at most `message.len()` bytes, NUL-terminated.
+            unsafe { libc::regerror(code, &*compiled, message.as_mut_ptr().cast(), message.len()) };
+            let end = message.iter().position(|&byte| byte == 0).unwrap_or(message.len());
+            return Err(format!("`{}` is not a valid regular expression: {}", pattern, String::from_utf8_lossy(&message[..end])));
+        }
+        Ok(Regex { compiled })
+    }
+
+    /// Returns the byte ranges of all matches in `text`, left to right, without overlaps.
+    fn find_all(&self, text: &str) -> Vec<(usize, usize)> {
+        let Ok(subject) = CString::new(text) else {
+            return Vec::new();
+        };
+        let mut matches = Vec::new();
+        let mut start = 0;
+        while start <= text.len() {
+            let mut found = libc::regmatch_t { rm_so: 0, rm_eo: 0 };
+            let flags = if start > 0 { libc::REG_NOTBOL } else { 0 };
+            // SAFETY: `start` is within the NUL-terminated `subject`, and `found` holds one match.
+            let code = unsafe { libc::regexec(&*self.compiled, subject.as_ptr().add(start), 1, &mut found, flags) };
+            if code != 0 {
+                break;
+            }
+            let (begin, end) = (start + found.rm_so as usize, start + found.rm_eo as usize);
+            if end > begin {
+                matches.push((begin, end));
+            }
+            start = end.max(begin + 1);
+        }
+        matches
+    }
+}
+
+impl Drop for Regex {
+    fn drop(&mut self) {
+        // SAFETY: `compiled` was filled in by a successful regcomp.
+        unsafe { libc::regfree(&mut *self.compiled) };
+    }
+}
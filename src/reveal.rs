@@ -0,0 +1,36 @@
+use std::process::Stdio;
+use anyhow::{Context, Result};
+use tokio::process::Command;
+use crate::dbus::{self, Value};
+use crate::dirs;
+
+// --- Reveal in File Manager ---
+//
+// `--reveal` and the notification's Show in Folder action open the screenshot's folder
+// with the screenshot selected, through the org.freedesktop.FileManager1 interface that
+// Nautilus, Dolphin, Nemo, Thunar and others implement. Without such a file manager, the
+// folder is opened with xdg-open instead.
+
+const FILE_MANAGER: &str = "org.freedesktop.FileManager1";
+const FILE_MANAGER_PATH: &str = "/org/freedesktop/FileManager1";
+
+/// Shows the file at `path` in the file manager.
+pub async fn reveal(path: &str) -> Result<()> {
+    let path = std::path::absolute(path)?;
+    let uri = format!("file://{}", dirs::encode_path(&path.to_string_lossy()));
+    let shown = async {
+        let mut bus = dbus::Connection::session().await?;
+        bus.call(FILE_MANAGER, FILE_MANAGER_PATH, FILE_MANAGER, "ShowItems", vec![Value::string_array([uri]), Value::str("")]).await
+    };
+    if shown.await.is_ok() {
+        return Ok(());
+    }
+
+    let folder = path.parent().context("The screenshot has no folder")?;
+    Command::new("xdg-open")
+    .arg(folder)
+    .stdin(Stdio::null())
+    .spawn()
+    .context("Failed to start xdg-open")?;
+    Ok(())
+}
@@ -0,0 +1,271 @@
+use std::path::Path;
+use std::sync::OnceLock;
+use anyhow::{Context, Result};
+use chrono::Local;
+use tokio::io::AsyncWriteExt;
+use crate::format::ImageFormat;
+use crate::{cleanup, dirs};
+
+// --- Saving Screenshots ---
+
+/// Extra room kept free on the target filesystem beyond the image itself.
+const FREE_SPACE_MARGIN: u64 = 1024 * 1024;
+
+static SCREENSHOTS_DIR: OnceLock<Option<String>> = OnceLock::new();
+
+/// Where a screenshot ended up after trying to save it.
+#[derive(Debug)]
+pub enum SaveOutcome {
+    /// Saved to the screenshots directory.
+    Saved(String),
+    /// The screenshots directory was too full, so the file was saved to the temp directory.
+    Diverted { path: String, full_dir: String },
+    /// Neither the screenshots directory nor the temp directory had enough space.
+    NoSpace { full_dir: String },
+    /// Written to stdout instead of a file.
+    Stdout,
+}
+
+impl SaveOutcome {
+    /// Returns the path of the saved file, if any.
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            SaveOutcome::Saved(path) | SaveOutcome::Diverted { path, .. } => Some(path),
+            SaveOutcome::NoSpace { .. } | SaveOutcome::Stdout => None,
+        }
+    }
+}
+
+/// The `--filename` template that gives the usual names.
+pub const DEFAULT_FILE_NAME: &str = "{date}_{time}-luminashot";
+
+/// Placeholders of `--filename` that need the text in the screenshot.
+pub const CONTENT_PLACEHOLDERS: &[&str] = &["ocr_first_line", "content_slug"];
+
+/// Longest text a content placeholder is replaced with, in characters.
+const CONTENT_LENGTH: usize = 48;
+
+/// Renders a `--filename` template, with `text` recognized in the screenshot if any.
+/// Separators left dangling by empty placeholders are trimmed off.
+pub fn file_name(template: &str, mode: &str, text: Option<&str>) -> String {
+    let now = Local::now();
+    let first_line = text.and_then(|text| text.lines().map(str::trim).find(|line| !line.is_empty())).unwrap_or("");
+    let name = crate::template::render(template, &[
+        ("date", now.format("%Y-%m-%d").to_string()),
+        ("time", now.format("%H-%M-%S").to_string()),
+        ("mode", mode.to_lowercase()),
+        ("ocr_first_line", truncate(&first_line.replace(|c: char| c == '/' || c.is_control(), " "))),
+        ("content_slug", truncate(&slug(first_line))),
+    ]);
+    let name = name.replace('/', "-");
+    let name = name.trim_matches(|c: char| matches!(c, '-' | '_' | ' ' | '.'));
+    if name.is_empty() { "luminashot".to_string() } else { name.to_string() }
+}
+
+/// Lowercases `text` and joins its words with hyphens, like `invoice-summary-q3`.
+fn slug(text: &str) -> String {
+    text
+    .split(|c: char| !c.is_alphanumeric())
+    .filter(|word| !word.is_empty())
+    .map(str::to_lowercase)
+    .collect::<Vec<_>>()
+    .join("-")
+}
+
+/// Cuts `text` down to `CONTENT_LENGTH` characters, at a word boundary where there is one.
+fn truncate(text: &str) -> String {
+    if text.chars().count() <= CONTENT_LENGTH {
+        return text.trim().to_string();
+    }
+    let cut: String = text.chars().take(CONTENT_LENGTH).collect();
+    let cut = match cut.rfind(['-', ' ']) {
+        Some(end) if end > 0 => &cut[..end],
+        _ => &cut,
+    };
+    cut.trim().to_string()
+}
+
+/// Takes an image buffer and saves it to a file, with `suffix` appended to the usual name.
+///
+/// Free space is checked up front, so a full disk results in a clear [`SaveOutcome`]
+/// instead of a cryptic I/O error after a partial write.
+pub async fn save_buffer_to_file(buffer: &[u8], format: ImageFormat, suffix: &str, overwrite: bool) -> Result<SaveOutcome> {
+    let timestamp = Local::now().format("%Y-%m-%d_%H-%M-%S");
+    save_buffer_named(buffer, format, &format!("{}-luminashot{}", timestamp, suffix), overwrite).await
+}
+
+/// Like [`save_buffer_to_file`], with `file_name`, without extension, in place of the usual name.
+pub async fn save_buffer_named(buffer: &[u8], format: ImageFormat, file_name: &str, overwrite: bool) -> Result<SaveOutcome> {
+    let save_dir = screenshots_dir();
+    tokio::fs::create_dir_all(&save_dir).await?;
+    let needed = buffer.len() as u64 + FREE_SPACE_MARGIN;
+
+    if has_free_space(&save_dir, needed) {
+        let stem = format!("{}/{}", save_dir, file_name);
+        return Ok(SaveOutcome::Saved(write_file_atomically(&stem, format.extension(), buffer, overwrite).await?));
+    }
+
+    let temp_dir = dirs::temp_dir();
+    if has_free_space(&temp_dir, needed) {
+        let stem = format!("{}/{}", temp_dir, file_name);
+        let path = write_file_atomically(&stem, format.extension(), buffer, overwrite).await?;
+        return Ok(SaveOutcome::Diverted { path, full_dir: save_dir });
+    }
+
+    Ok(SaveOutcome::NoSpace { full_dir: save_dir })
+}
+
+/// Saves an image buffer to `path`, replacing any file there, or writes it to stdout if
+/// `path` is `-`. Paths without an extension get the format's. Free space is checked up
+/// front as well, but the screenshot is never moved somewhere the caller didn't ask for.
+pub async fn save_buffer_to_path(buffer: &[u8], format: ImageFormat, path: &str) -> Result<SaveOutcome> {
+    if path == "-" {
+        let mut stdout = tokio::io::stdout();
+        stdout.write_all(buffer).await.context("Failed to write the screenshot to stdout")?;
+        stdout.flush().await?;
+        return Ok(SaveOutcome::Stdout);
+    }
+
+    // Absolute, so the history and the latest link still find it from anywhere.
+    let path = std::path::absolute(path)?;
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await.with_context(|| format!("Failed to create {}", dir.display()))?;
+        let dir = dir.to_string_lossy();
+        if !has_free_space(&dir, buffer.len() as u64 + FREE_SPACE_MARGIN) {
+            return Ok(SaveOutcome::NoSpace { full_dir: dir.into_owned() });
+        }
+    }
+    let (stem, extension) = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => (path.with_extension(""), extension),
+        None => (path.clone(), format.extension()),
+    };
+    Ok(SaveOutcome::Saved(write_file_atomically(&stem.to_string_lossy(), extension, buffer, true).await?))
+}
+
+/// Sets the directory given with `--dir`. Only the first call has an effect.
+pub fn set_screenshots_dir(dir: Option<String>) {
+    let _ = SCREENSHOTS_DIR.set(dir);
+}
+
+/// Returns the directory screenshots are saved to: the one given with `--dir`, or else the
+/// Screenshots folder in the Pictures directory.
+pub fn screenshots_dir() -> String {
+    if let Some(dir) = SCREENSHOTS_DIR.get().and_then(Option::as_deref).filter(|dir| !dir.is_empty()) {
+        // Written by hand in the config file more often than not.
+        return match (dir.strip_prefix("~/"), dirs::home_dir()) {
+            (Some(rest), Ok(home)) => format!("{}/{}", home, rest),
+            _ => dir.to_string(),
+        };
+    }
+    let pictures_dir = dirs::pictures_dir().unwrap_or_else(|err| {
+        let fallback = dirs::temp_dir();
+        eprintln!("Warning: {:#}, using {} instead.", err, fallback);
+        fallback
+    });
+    format!("{}/Screenshots", pictures_dir)
+}
+
+/// Points the symlink `name` in the screenshots directory at `path`, the newest screenshot,
+/// so scripts can refer to it by a fixed name. The link gets the screenshot's extension.
+pub async fn update_latest_link(name: &str, path: &str) {
+    if name.is_empty() {
+        return;
+    }
+    let dir = screenshots_dir();
+    let path = Path::new(path);
+    let extension = path.extension().map(|extension| extension.to_string_lossy().into_owned()).unwrap_or_default();
+    let link = format!("{}/{}.{}", dir, name, extension);
+
+    // A file of the user's own under that name is left alone.
+    if tokio::fs::symlink_metadata(&link).await.is_ok_and(|metadata| !metadata.is_symlink()) {
+        eprintln!("Warning: {} is not a symlink, not replacing it with a link to the newest screenshot", link);
+        return;
+    }
+    // A relative link keeps working when the whole directory is moved.
+    let target = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(file_name)) if parent == Path::new(&dir) => Path::new(file_name),
+        _ => path,
+    };
+    let tmp = format!("{}.{}.tmp", link, std::process::id());
+    let result = async {
+        tokio::fs::symlink(target, &tmp).await?;
+        tokio::fs::rename(&tmp, &link).await
+    }
+    .await;
+    if let Err(err) = result {
+        let _ = tokio::fs::remove_file(&tmp).await;
+        eprintln!("Warning: Could not update {}: {}", link, err);
+    }
+}
+
+/// Returns whether the filesystem containing `dir` has at least `needed` bytes available.
+/// If free space can't be determined, assumes there is enough.
+fn has_free_space(dir: &str, needed: u64) -> bool {
+    let Ok(path) = std::ffi::CString::new(dir) else {
+        return true;
+    };
+
+    // SAFETY: `path` is a valid NUL-terminated string and `stat` is a properly sized out-parameter.
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return true;
+    }
+
+    (stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64) >= needed
+}
+
+/// Writes a buffer to `<stem>.<extension>` via a temp file, so a crash or full disk never
+/// leaves a truncated file under the final name. Unless `overwrite` is set, an existing
+/// file is never replaced and `-1`, `-2`, ... is appended to the stem instead.
+pub async fn write_file_atomically(stem: &str, extension: &str, buffer: &[u8], overwrite: bool) -> Result<String> {
+    let first_path = format!("{}.{}", stem, extension);
+    let tmp = cleanup::TempPath::new(format!("{}.{}.tmp", first_path, std::process::id()));
+    let tmp_path = tmp.path();
+
+    let result: std::io::Result<String> = async {
+        let mut file = tokio::fs::File::create(tmp_path).await?;
+        file.write_all(buffer).await?;
+        file.sync_all().await?;
+
+        if overwrite {
+            tokio::fs::rename(tmp_path, &first_path).await?;
+            return Ok(first_path.clone());
+        }
+
+        for suffix in 0.. {
+            let path = match suffix {
+                0 => first_path.clone(),
+                n => format!("{}-{}.{}", stem, n, extension),
+            };
+            if claim_path(tmp_path, Path::new(&path)).await? {
+                return Ok(path);
+            }
+        }
+        unreachable!()
+    }
+    .await;
+
+    result.with_context(|| format!("Failed to write {}", first_path))
+}
+
+/// Moves `tmp_path` to `path` unless `path` already exists. Returns whether the move happened.
+async fn claim_path(tmp_path: &Path, path: &Path) -> std::io::Result<bool> {
+    // A hard link fails atomically if the target exists, so two captures racing for the
+    // same name can never clobber each other.
+    match tokio::fs::hard_link(tmp_path, path).await {
+        Ok(()) => {
+            tokio::fs::remove_file(tmp_path).await?;
+            Ok(true)
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => Ok(false),
+        // Filesystems like FAT don't support hard links; fall back to a plain existence check.
+        Err(_) => {
+            if tokio::fs::try_exists(path).await? {
+                return Ok(false);
+            }
+            tokio::fs::rename(tmp_path, path).await?;
+            Ok(true)
+        }
+    }
+}
+
@@ -0,0 +1,140 @@
+use std::collections::{HashMap, HashSet};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use crate::{dirs, history, ocr, save};
+
+// --- Text Search ---
+//
+// Recognized text is kept in `$XDG_DATA_HOME/luminashot/text-index.jsonl`, keyed by the
+// capture's hash. Screenshots are indexed the first time a search needs them, so only
+// searching pays for OCR. Captures that were recognized when they were taken, for
+// `--copy-text` or `--ocr-sidecar`, are indexed right away.
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexedText {
+    sha256: String,
+    text: String,
+}
+
+fn index_stem() -> Result<String> {
+    Ok(format!("{}/luminashot/text-index", dirs::data_home()?))
+}
+
+fn index_path() -> Result<String> {
+    Ok(format!("{}.jsonl", index_stem()?))
+}
+
+/// Prints the saved captures containing every word of `query`, newest first.
+pub async fn search(query: &str, options: &ocr::Options) -> Result<()> {
+    let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    if words.is_empty() {
+        anyhow::bail!("The search query is empty");
+    }
+
+    let mut index = load_index().await?;
+
+    let captures: Vec<(history::Entry, String)> = history::load()
+    .await?
+    .into_iter()
+    .rev()
+    .filter_map(|entry| {
+        let path = entry.path.clone()?;
+        std::path::Path::new(&path).exists().then_some((entry, path))
+    })
+    .collect();
+
+    // Text written by `--ocr-sidecar` is taken as it is, without recognizing it again.
+    let mut unindexed = Vec::new();
+    for capture @ (entry, path) in &captures {
+        if index.contains_key(&entry.sha256) {
+            continue;
+        }
+        match ocr::read_sidecar(path).await {
+            Some(text) => {
+                self::index(&entry.sha256, &text).await?;
+                index.insert(entry.sha256.clone(), text.to_lowercase());
+            }
+            None => unindexed.push(capture),
+        }
+    }
+    if !unindexed.is_empty() {
+        if ocr::is_available(options).await {
+            eprintln!("Recognizing text in {} new screenshot(s)...", unindexed.len());
+            for (entry, path) in unindexed {
+                match ocr::recognize_file(path, options).await {
+                    Ok(text) => {
+                        self::index(&entry.sha256, &text).await?;
+                        index.insert(entry.sha256.clone(), text.to_lowercase());
+                    }
+                    Err(err) => eprintln!("Warning: {:#}", err),
+                }
+            }
+        } else {
+            eprintln!("Warning: The OCR engine is not available, {} screenshot(s) could not be searched", unindexed.len());
+        }
+    }
+
+    for (entry, path) in &captures {
+        if let Some(text) = index.get(&entry.sha256) {
+            if words.iter().all(|word| text.contains(word.as_str())) {
+                println!("{}", path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Adds the text recognized in a capture to the index.
+pub async fn index(sha256: &str, text: &str) -> Result<()> {
+    append_to_index(&IndexedText { sha256: sha256.to_string(), text: text.to_string() }).await
+}
+
+/// Drops the recognized text of captures that are no longer in the history.
+pub async fn prune_index(keep: &HashSet<&str>) -> Result<()> {
+    let path = index_path()?;
+    let contents = match tokio::fs::read_to_string(&path).await {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path)),
+    };
+
+    let mut pruned = Vec::new();
+    for line in contents.lines() {
+        if serde_json::from_str::<IndexedText>(line).is_ok_and(|indexed| keep.contains(indexed.sha256.as_str())) {
+            pruned.extend_from_slice(line.as_bytes());
+            pruned.push(b'\n');
+        }
+    }
+    save::write_file_atomically(&index_stem()?, "jsonl", &pruned, true).await?;
+    Ok(())
+}
+
+/// Loads the recognized text of every indexed capture, lowercased for matching.
+async fn load_index() -> Result<HashMap<String, String>> {
+    let path = index_path()?;
+    let contents = match tokio::fs::read_to_string(&path).await {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path)),
+    };
+
+    Ok(contents
+    .lines()
+    .filter_map(|line| serde_json::from_str::<IndexedText>(line).ok())
+    .map(|indexed| (indexed.sha256, indexed.text.to_lowercase()))
+    .collect())
+}
+
+async fn append_to_index(indexed: &IndexedText) -> Result<()> {
+    let path = index_path()?;
+    if let Some(parent) = std::path::Path::new(&path).parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    let mut line = serde_json::to_vec(indexed)?;
+    line.push(b'\n');
+    let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(&path).await?;
+    file.write_all(&line).await.with_context(|| format!("Failed to write {}", path))?;
+    Ok(())
+}
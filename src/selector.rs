@@ -0,0 +1,146 @@
+use std::sync::OnceLock;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde_json::Value;
+use tokio::sync::OnceCell;
+use crate::{compositor, dirs};
+
+// --- Selection Appearance ---
+//
+// slurp draws every interactive selection: regions, windows and the points of `inspect`.
+// How it looks is set once at startup, like the geometry notation, and passed to every
+// slurp that is started. Whatever isn't set is taken from the theme, if there is one, and
+// otherwise left to slurp's own defaults.
+
+/// The screen is tinted this much while selecting, unless set otherwise.
+pub const DEFAULT_BACKGROUND: &str = "#FFFFFF44";
+
+/// Colors, as `#RRGGBB` or `#RRGGBBAA`, and sizes of the selection.
+#[derive(Debug, Clone, Default)]
+pub struct Style {
+    /// Tint of the screen outside the selection.
+    pub background: Option<String>,
+    pub border: Option<String>,
+    /// Tint of the selection itself.
+    pub fill: Option<String>,
+    pub border_width: Option<u32>,
+    /// Font family of the selection's dimensions.
+    pub font: Option<String>,
+    /// Where the colors that aren't set come from.
+    pub theme: Option<Theme>,
+}
+
+/// A color scheme the selection's colors can be taken from.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum Theme {
+    /// pywal's ~/.cache/wal/colors.json
+    Wal,
+    /// matugen's JSON output, saved to ~/.cache/matugen/colors.json
+    Matugen,
+    /// Hyprland's general:col.active_border
+    Hyprland,
+}
+
+/// Selection colors taken from a theme.
+#[derive(Debug, Clone, Default)]
+struct ThemeColors {
+    background: Option<String>,
+    border: Option<String>,
+    fill: Option<String>,
+}
+
+/// How much of the theme's colors the tints let through.
+const BACKGROUND_ALPHA: &str = "66";
+const FILL_ALPHA: &str = "33";
+
+static STYLE: OnceLock<Style> = OnceLock::new();
+/// The theme's colors, read the first time something is selected.
+static THEME_COLORS: OnceCell<ThemeColors> = OnceCell::const_new();
+
+/// Sets the style of every selection. Only the first call has an effect.
+pub fn set_style(style: Style) {
+    let _ = STYLE.set(style);
+}
+
+/// Returns slurp's arguments for the selection style.
+pub async fn slurp_args() -> Vec<String> {
+    let style = STYLE.get().cloned().unwrap_or_default();
+    let theme = match style.theme {
+        Some(theme) => THEME_COLORS.get_or_init(|| async move {
+            theme_colors(theme).await.unwrap_or_else(|err| {
+                eprintln!("Warning: Could not read the selection colors from the theme: {:#}", err);
+                ThemeColors::default()
+            })
+        }).await.clone(),
+        None => ThemeColors::default(),
+    };
+    let background = style.background.or(theme.background).unwrap_or_else(|| DEFAULT_BACKGROUND.to_string());
+    let mut args = vec!["-b".to_string(), background];
+    let (border, fill) = (style.border.or(theme.border), style.fill.or(theme.fill));
+    for (flag, value) in [("-c", border), ("-s", fill), ("-w", style.border_width.map(|width| width.to_string())), ("-F", style.font)] {
+        if let Some(value) = value {
+            args.extend([flag.to_string(), value]);
+        }
+    }
+    args
+}
+
+/// Validates a color for clap, as `#RRGGBB` or `#RRGGBBAA`; the `#` may be left out.
+pub fn parse_color(value: &str) -> Result<String, String> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if matches!(hex.len(), 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(format!("#{}", hex.to_uppercase()))
+    } else {
+        Err(format!("`{}` is not a color like #RRGGBB or #RRGGBBAA", value))
+    }
+}
+
+/// Reads the selection colors from a theme: its accent for the border, and for a light
+/// tint of the selection, and its background for the tint of the rest of the screen.
+async fn theme_colors(theme: Theme) -> Result<ThemeColors> {
+    let (accent, background) = match theme {
+        Theme::Wal => {
+            let colors = read_json(&format!("{}/wal/colors.json", dirs::cache_home()?)).await?;
+            (colors.pointer("/colors/color4").cloned(), colors.pointer("/special/background").cloned())
+        }
+        // Older matugen versions group the colors by scheme, newer ones each color's schemes.
+        Theme::Matugen => {
+            let colors = read_json(&format!("{}/matugen/colors.json", dirs::cache_home()?)).await?;
+            let color = |name: &str| {
+                colors
+                .pointer(&format!("/colors/dark/{}", name))
+                .or_else(|| colors.pointer(&format!("/colors/{}/dark", name)))
+                .cloned()
+            };
+            (color("primary"), color("surface"))
+        }
+        // Like `ee33ccff 99ff00ee 45deg`, with colors as ARGB in hex; the first one is used.
+        Theme::Hyprland => {
+            let value = compositor::get_option("general:col.active_border").await?;
+            let color = value
+            .split_whitespace()
+            .next()
+            .and_then(|first| u32::from_str_radix(first, 16).ok())
+            .map(|argb| Value::String(format!("#{:06X}{:02X}", argb & 0xFFFFFF, argb >> 24)));
+            (color, None)
+        }
+    };
+
+    let color = |value: Option<Value>| value.and_then(|value| value.as_str().and_then(|color| parse_color(color).ok()));
+    let accent = color(accent).context("The theme has no usable accent color")?;
+    Ok(ThemeColors {
+        background: color(background).map(|color| with_alpha(&color, BACKGROUND_ALPHA)),
+        fill: Some(with_alpha(&accent, FILL_ALPHA)),
+        border: Some(accent),
+    })
+}
+
+async fn read_json(path: &str) -> Result<Value> {
+    let contents = tokio::fs::read_to_string(path).await.with_context(|| format!("Failed to read {}", path))?;
+    serde_json::from_str(&contents).with_context(|| format!("{} is not valid JSON", path))
+}
+
+/// Returns a `#RRGGBB[AA]` color with its alpha replaced.
+fn with_alpha(color: &str, alpha: &str) -> String {
+    format!("{}{}", &color[..7], alpha)
+}
@@ -0,0 +1,95 @@
+use std::sync::OnceLock;
+use anyhow::Result;
+use crate::geometry::Rect;
+use crate::snapshot::hyprctl_json;
+use crate::{HyprlandClient, HyprlandMonitor};
+
+// --- Snapping Selections ---
+//
+// slurp can't snap while dragging, so with `--snap` the selected region is snapped
+// afterwards: each edge moves to the nearest guide within the distance. The guides are
+// the edges of the visible windows and of the monitors, and the halves and thirds of each
+// monitor, so a window pane or the left half of a screen is easy to select exactly by hand.
+//
+// A guide only counts for an edge if it runs alongside the selection, so a window at the
+// other end of the screen doesn't pull an edge that happens to line up with it.
+
+/// How far an edge moves to reach a guide, if `--snap` is given without a distance.
+pub const DEFAULT_DISTANCE: &str = "12";
+
+static DISTANCE: OnceLock<Option<u32>> = OnceLock::new();
+
+/// A line an edge can snap to, at `position` from `start` to `end` along the other axis.
+struct Guide {
+    position: i32,
+    start: i32,
+    end: i32,
+}
+
+/// Sets how far selections snap, or that they don't. Only the first call has an effect.
+pub fn set_distance(distance: Option<u32>) {
+    let _ = DISTANCE.set(distance);
+}
+
+/// Returns the selection with its edges snapped to the nearby guides, or as it is if
+/// snapping is off or the guides can't be read.
+pub async fn snap(rect: Rect) -> Rect {
+    let Some(distance) = DISTANCE.get().copied().flatten() else {
+        return rect;
+    };
+    let (vertical, horizontal) = match guides().await {
+        Ok(guides) => guides,
+        Err(err) => {
+            eprintln!("Warning: Could not snap the selection: {:#}", err);
+            return rect;
+        }
+    };
+
+    let distance = distance as i32;
+    let (right, bottom) = (rect.x + rect.width, rect.y + rect.height);
+    let across = (rect.y, bottom);
+    let along = (rect.x, right);
+    let left = nearest(&vertical, rect.x, across, distance);
+    let right = nearest(&vertical, right, across, distance);
+    let top = nearest(&horizontal, rect.y, along, distance);
+    let bottom = nearest(&horizontal, bottom, along, distance);
+    // Edges snapping past each other would leave nothing to capture.
+    Rect::new(left, top, right - left, bottom - top).unwrap_or(rect)
+}
+
+/// Returns the guide closest to `edge` that runs alongside `span`, if one is within `distance`.
+fn nearest(guides: &[Guide], edge: i32, (start, end): (i32, i32), distance: i32) -> i32 {
+    guides
+    .iter()
+    .filter(|guide| guide.start < end + distance && start - distance < guide.end)
+    .map(|guide| guide.position)
+    .filter(|position| (position - edge).abs() <= distance)
+    .min_by_key(|position| (position - edge).abs())
+    .unwrap_or(edge)
+}
+
+/// Returns the vertical guides, for the left and right edges, and the horizontal ones.
+async fn guides() -> Result<(Vec<Guide>, Vec<Guide>)> {
+    let monitors: Vec<HyprlandMonitor> = hyprctl_json("monitors").await?;
+    let clients: Vec<HyprlandClient> = hyprctl_json("clients").await?;
+    let (mut vertical, mut horizontal) = (Vec::new(), Vec::new());
+    let mut add = |rect: Rect, fractions: &[(i32, i32)]| {
+        for &(numerator, denominator) in fractions {
+            vertical.push(Guide { position: rect.x + rect.width * numerator / denominator, start: rect.y, end: rect.y + rect.height });
+            horizontal.push(Guide { position: rect.y + rect.height * numerator / denominator, start: rect.x, end: rect.x + rect.width });
+        }
+    };
+
+    // Every monitor shows its own workspace, and maybe a special one on top.
+    let mut workspaces = Vec::new();
+    for monitor in &monitors {
+        add(monitor.rect()?, &[(0, 1), (1, 3), (1, 2), (2, 3), (1, 1)]);
+        workspaces.extend([monitor.active_workspace.id, monitor.special_workspace.id]);
+    }
+    for client in clients.iter().filter(|client| !client.hidden && workspaces.contains(&client.workspace.id)) {
+        if let Ok(rect) = client.rect() {
+            add(rect, &[(0, 1), (1, 1)]);
+        }
+    }
+    Ok((vertical, horizontal))
+}
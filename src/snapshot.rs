@@ -0,0 +1,40 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use anyhow::Result;
+use tokio::process::Command;
+use crate::{compositor, process};
+
+// --- Per-Run Snapshot of hyprctl Queries ---
+//
+// A single capture asks for the same clients, monitors and active workspace several times:
+// for the picker, for the selected window's final geometry, and to watch for changes.
+// Each query runs once and its reply is reused until something changes the compositor's
+// state, like a dispatcher or a window event, and invalidates the snapshot.
+
+/// Replies to `hyprctl <command> -j`, keyed by command.
+static REPLIES: Mutex<BTreeMap<String, Vec<u8>>> = Mutex::new(BTreeMap::new());
+
+/// Runs `hyprctl <command> -j` and parses its output, reusing the reply from earlier in
+/// this run if the snapshot is still valid.
+pub async fn hyprctl_json<T: serde::de::DeserializeOwned>(command: &str) -> Result<T> {
+    compositor::require_hyprland()?;
+    let cached = REPLIES.lock().unwrap().get(command).cloned();
+    let reply = match cached {
+        Some(reply) => reply,
+        None => {
+            let output = process::output(
+                Command::new("hyprctl")
+                .arg(command)
+                .arg("-j")
+            ).await?;
+            REPLIES.lock().unwrap().insert(command.to_string(), output.stdout.clone());
+            output.stdout
+        }
+    };
+    Ok(serde_json::from_slice(&reply)?)
+}
+
+/// Forgets every reply, so the next queries see the compositor's current state.
+pub fn invalidate() {
+    REPLIES.lock().unwrap().clear();
+}
@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use crate::{dirs, i18n};
+use crate::geometry::Rect;
+use crate::snapshot::hyprctl_json;
+use crate::HyprlandMonitor;
+
+// --- Persisted State ---
+//
+// Besides the last capture overall, the last capture on each workspace is kept, keyed by
+// the monitor it was taken on and the workspace that monitor showed, so `--last` repeats
+// the region used where the user is now. Workspaces without a capture of their own fall
+// back to the last capture overall.
+
+/// What was captured last, so it can be captured again with `--last` or the Retake action.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastCapture {
+    pub geometry: Rect,
+    /// The mode label of the original capture, e.g. "Window".
+    pub mode: String,
+}
+
+fn last_capture_path() -> Result<String> {
+    Ok(format!("{}/luminashot/last-capture.json", dirs::state_home()?))
+}
+
+fn last_by_workspace_path() -> Result<String> {
+    Ok(format!("{}/luminashot/last-capture-by-workspace.json", dirs::state_home()?))
+}
+
+/// Loads the last capture on the current workspace, or else the last capture overall,
+/// failing with a clear message if nothing has been captured yet.
+pub async fn load_last_capture() -> Result<LastCapture> {
+    let focused = |monitor: &HyprlandMonitor| monitor.focused;
+    if let Some(workspace) = workspace(focused).await {
+        if let Some(capture) = load_by_workspace().await.remove(&workspace) {
+            return Ok(capture);
+        }
+    }
+    let path = last_capture_path()?;
+    let contents = tokio::fs::read(&path)
+    .await
+    .with_context(|| i18n::tr_with("No previous capture to repeat ({path} is missing)", &[("path", path.clone())]))?;
+    serde_json::from_slice(&contents).with_context(|| format!("Failed to parse {}", path))
+}
+
+/// Records the last capture. Failures only produce a warning, since the capture itself succeeded.
+pub async fn save_last_capture(capture: &LastCapture) {
+    let result = async {
+        let path = last_capture_path()?;
+        if let Some(parent) = std::path::Path::new(&path).parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&path, serde_json::to_vec_pretty(capture)?).await?;
+
+        // Taken on the monitor it is on, which isn't always the focused one.
+        let (x, y) = (capture.geometry.x + capture.geometry.width / 2, capture.geometry.y + capture.geometry.height / 2);
+        let captured_on = |monitor: &HyprlandMonitor| monitor.rect().is_ok_and(|rect| rect.contains(x, y));
+        if let Some(workspace) = workspace(captured_on).await {
+            let mut by_workspace = load_by_workspace().await;
+            by_workspace.insert(workspace, capture.clone());
+            tokio::fs::write(last_by_workspace_path()?, serde_json::to_vec_pretty(&by_workspace)?).await?;
+        }
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+
+    if let Err(err) = result {
+        eprintln!("Warning: could not record the last capture: {:#}", err);
+    }
+}
+
+/// Loads the last capture on each workspace. A missing or unreadable file counts as empty,
+/// since it only refines what `--last` repeats.
+async fn load_by_workspace() -> BTreeMap<String, LastCapture> {
+    let Ok(path) = last_by_workspace_path() else {
+        return BTreeMap::new();
+    };
+    match tokio::fs::read(&path).await {
+        Ok(contents) => serde_json::from_slice(&contents).unwrap_or_default(),
+        Err(_) => BTreeMap::new(),
+    }
+}
+
+/// Returns the first monitor matching `pick` and the workspace it shows, like `DP-1/3`, with
+/// a special workspace shown on top taking the place of the one below. None if Hyprland
+/// can't tell.
+async fn workspace(pick: impl Fn(&HyprlandMonitor) -> bool) -> Option<String> {
+    let monitors: Vec<HyprlandMonitor> = hyprctl_json("monitors").await.ok()?;
+    let monitor = monitors.iter().find(|monitor| pick(monitor))?;
+    let workspace = match monitor.special_workspace.id {
+        0 => &monitor.active_workspace.name,
+        _ => &monitor.special_workspace.name,
+    };
+    Some(format!("{}/{}", monitor.name, workspace))
+}
@@ -0,0 +1,95 @@
+use anyhow::{Context, Result};
+use crate::image::Image;
+use crate::{png, save};
+
+// --- Stitching ---
+//
+// `--append` builds a long screenshot out of captures of the same area taken while
+// scrolling: each capture is added below the image so far. Where the top of a capture
+// repeats the bottom of the image, as it does when the page was scrolled by less than
+// its height, the repeated rows are only kept once.
+
+/// Columns at the right edge that are left out when comparing rows, since that is where
+/// a scrollbar moves between captures.
+const SCROLLBAR_WIDTH: u32 = 24;
+/// Shorter overlaps are too likely to be a coincidence, like a few blank rows.
+const MIN_OVERLAP: usize = 8;
+
+/// The result of adding a capture to an image.
+pub struct Appended {
+    pub image: Vec<u8>,
+    /// Rows of the capture that repeated the end of the image and were left out.
+    pub overlap: usize,
+    pub height: u32,
+}
+
+/// Fails unless `path` can hold the appended image, which is always a PNG.
+pub fn check_path(path: &str) -> Result<()> {
+    match path.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && extension.eq_ignore_ascii_case("png") => Ok(()),
+        _ => anyhow::bail!("--append needs a .png file, not {}", path),
+    }
+}
+
+/// Writes the appended image back to `path`, replacing the image that was there.
+pub async fn write(path: &str, image: &[u8]) -> Result<String> {
+    let (stem, extension) = path.rsplit_once('.').context("--append needs a .png file")?;
+    save::write_file_atomically(stem, extension, image, true).await
+}
+
+/// Adds a PNG capture below the PNG image at `path`. If there is no image there yet, the
+/// capture starts it.
+pub async fn append(path: &str, capture: &[u8]) -> Result<Appended> {
+    let existing = match tokio::fs::read(path).await {
+        Ok(existing) => existing,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            let height = png::decode(capture)?.height;
+            return Ok(Appended { image: capture.to_vec(), overlap: 0, height });
+        }
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path)),
+    };
+
+    let top = png::decode(&existing).with_context(|| format!("Failed to read {}", path))?;
+    let bottom = png::decode(capture)?;
+    if top.width != bottom.width {
+        anyhow::bail!("The capture is {} pixels wide but {} is {}; --append needs the same width every time", bottom.width, path, top.width);
+    }
+
+    let overlap = overlap(&top, &bottom);
+    let stride = top.width as usize * 4;
+    let rows = top.pixels.chunks_exact(stride).chain(bottom.pixels.chunks_exact(stride).skip(overlap));
+    let height = top.height + bottom.height - overlap as u32;
+    let alpha = top.pixels.iter().chain(&bottom.pixels).skip(3).step_by(4).any(|&alpha| alpha != 255);
+
+    let mut encoder = png::Encoder::new(top.width, height, alpha);
+    for row in rows {
+        encoder.push_row(row);
+    }
+    Ok(Appended { image: encoder.finish(), overlap, height })
+}
+
+/// Returns how many rows at the top of `bottom` repeat the last rows of `top`.
+fn overlap(top: &Image, bottom: &Image) -> usize {
+    let top_rows = row_signatures(top);
+    let bottom_rows = row_signatures(bottom);
+    // The longest overlap wins; a capture can't overlap entirely, or nothing was scrolled.
+    let longest = top_rows.len().min(bottom_rows.len().saturating_sub(1));
+    (MIN_OVERLAP..=longest)
+    .rev()
+    .find(|&rows| top_rows[top_rows.len() - rows..] == bottom_rows[..rows])
+    .unwrap_or(0)
+}
+
+/// Hashes every row, without the columns a scrollbar could be in.
+fn row_signatures(image: &Image) -> Vec<u64> {
+    let stride = image.width as usize * 4;
+    let compared = image.width.saturating_sub(SCROLLBAR_WIDTH).max(1) as usize * 4;
+    image
+    .pixels
+    .chunks_exact(stride)
+    .map(|row| {
+        // FNV-1a
+        row[..compared.min(row.len())].iter().fold(0xcbf29ce484222325u64, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+    })
+    .collect()
+}
@@ -0,0 +1,144 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use tokio::time::{sleep, Duration};
+use crate::compositor::{self, Revert};
+use crate::snapshot::hyprctl_json;
+use crate::{HyprlandClient, HyprlandMonitor};
+
+// --- Capturing a Window by Class or PID ---
+
+/// Time given to the compositor to finish a workspace switch or move before capturing.
+const WORKSPACE_SETTLE_DELAY: Duration = Duration::from_millis(300);
+
+/// How to capture a matching window that is on another workspace.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum OtherWorkspace {
+    /// Switch to the window's workspace, and back afterwards
+    Switch,
+    /// Move the window to the current workspace, and back afterwards
+    Move,
+}
+
+/// Which window to capture without asking.
+pub struct WindowQuery<'a> {
+    pub class: Option<&'a str>,
+    pub pid: Option<i32>,
+}
+
+impl WindowQuery<'_> {
+    fn matches(&self, client: &HyprlandClient) -> bool {
+        self.class.is_none_or(|class| client.class.eq_ignore_ascii_case(class))
+        && self.pid.is_none_or(|pid| client.pid == pid)
+    }
+
+    fn describe(&self) -> String {
+        match (self.class, self.pid) {
+            (Some(class), Some(pid)) => format!("window of class {} with PID {}", class, pid),
+            (Some(class), None) => format!("window of class {}", class),
+            (None, Some(pid)) => format!("window with PID {}", pid),
+            (None, None) => "window".to_string(),
+        }
+    }
+}
+
+/// Finds the window matching `query`, bringing it into view if it is on another workspace
+/// or, with `reveal_hidden`, hidden behind another tab of its group.
+///
+/// Windows on the active workspace are preferred, then the most recently focused one. The
+/// returned guards put everything back the way it was when dropped.
+pub async fn find_window(query: &WindowQuery<'_>, other_workspace: Option<OtherWorkspace>, reveal_hidden: bool) -> Result<(HyprlandClient, Vec<Revert>)> {
+    let active_workspace = crate::get_active_workspace_id().await?;
+    let mut visible_workspaces = crate::shown_special_workspaces().await?;
+    visible_workspaces.push(active_workspace);
+    let clients: Vec<HyprlandClient> = hyprctl_json("clients").await?;
+
+    let window = clients
+    .iter()
+    .filter(|client| query.matches(client))
+    .min_by_key(|client| (!visible_workspaces.contains(&client.workspace.id), client.focus_history_id))
+    .with_context(|| format!("No {} is open", query.describe()))?;
+    if window.hidden && !reveal_hidden {
+        anyhow::bail!("The {} is hidden in a group; pass --reveal-hidden to capture it", query.describe());
+    }
+
+    // Guards are pushed in the order the changes are made, and undone in reverse.
+    let mut reverts = Vec::new();
+    if !visible_workspaces.contains(&window.workspace.id) {
+        match other_workspace {
+            None => anyhow::bail!(
+                "The {} is on workspace {}; pass --other-workspace switch or move to capture it",
+                query.describe(),
+                window.workspace.name,
+            ),
+            Some(OtherWorkspace::Switch) => switch_to_workspace(window, active_workspace, &mut reverts).await?,
+            Some(OtherWorkspace::Move) => {
+                let back = workspace_selector(window.workspace.id, &window.workspace.name);
+                reverts.push(Revert::new("movetoworkspacesilent", format!("{},address:{}", back, window.address)));
+                compositor::dispatch("movetoworkspacesilent", &format!("{},address:{}", active_workspace, window.address)).await?;
+            }
+        }
+    }
+    if window.hidden {
+        reveal_in_group(window, &clients, &mut reverts).await?;
+    }
+    reverts.reverse();
+
+    if reverts.is_empty() {
+        return Ok((window.clone(), reverts));
+    }
+    sleep(WORKSPACE_SETTLE_DELAY).await;
+    // The window has been laid out again, so its geometry has to be read again as well.
+    Ok((crate::get_window_for_address(&window.address).await?, reverts))
+}
+
+/// Switches to the window's workspace, adding guards that switch every monitor back.
+async fn switch_to_workspace(window: &HyprlandClient, focused: i32, reverts: &mut Vec<Revert>) -> Result<()> {
+    // Special workspaces are shown on top of the current one rather than switched to.
+    if let Some(name) = window.workspace.name.strip_prefix("special:") {
+        reverts.push(Revert::new("togglespecialworkspace", name.to_string()));
+        return compositor::dispatch("togglespecialworkspace", name).await;
+    }
+
+    let before: Vec<HyprlandMonitor> = hyprctl_json("monitors").await?;
+    // Created before the switch, so a failed switch still restores focus.
+    reverts.push(Revert::new("workspace", focused.to_string()));
+    compositor::dispatch("workspace", &window.workspace.id.to_string()).await?;
+
+    // The monitor now showing the window's workspace was showing something else before,
+    // which has to be put back before focus returns.
+    let after: Vec<HyprlandMonitor> = hyprctl_json("monitors").await?;
+    let previous = after
+    .iter()
+    .find(|monitor| monitor.active_workspace.id == window.workspace.id)
+    .and_then(|shown_on| before.iter().find(|monitor| monitor.name == shown_on.name));
+    if let Some(monitor) = previous {
+        if monitor.active_workspace.id != focused {
+            reverts.push(Revert::new("workspace", workspace_selector(monitor.active_workspace.id, &monitor.active_workspace.name)));
+        }
+    }
+    Ok(())
+}
+
+/// Makes a window the visible tab of its group, adding guards that bring back the tab that
+/// was shown before and the window that had focus.
+async fn reveal_in_group(window: &HyprlandClient, clients: &[HyprlandClient], reverts: &mut Vec<Revert>) -> Result<()> {
+    let focused = clients.iter().find(|client| client.focus_history_id == 0);
+    let shown = clients.iter().find(|client| !client.hidden && window.grouped.contains(&client.address));
+    if let Some(focused) = focused {
+        reverts.push(Revert::new("focuswindow", format!("address:{}", focused.address)));
+    }
+    if let Some(shown) = shown.filter(|shown| focused.is_none_or(|focused| focused.address != shown.address)) {
+        reverts.push(Revert::new("focuswindow", format!("address:{}", shown.address)));
+    }
+    compositor::dispatch("focuswindow", &format!("address:{}", window.address)).await
+}
+
+/// Returns how a workspace is named in dispatcher arguments.
+fn workspace_selector(id: i32, name: &str) -> String {
+    // Special workspaces are addressed by name, which already starts with `special:`.
+    if id < 0 {
+        name.to_string()
+    } else {
+        id.to_string()
+    }
+}
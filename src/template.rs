@@ -0,0 +1,79 @@
+// --- Text Templates ---
+
+/// Replaces `{name}` placeholders in `template` with their values.
+///
+/// Unknown placeholders are left untouched, and `{{` / `}}` produce literal braces.
+pub fn render(template: &str, values: &[(&str, String)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find(['{', '}']) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            out.push_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+
+        let placeholder = rest.strip_prefix('{').and_then(|inner| {
+            let end = inner.find('}')?;
+            let value = values.iter().find(|(name, _)| *name == &inner[..end])?;
+            Some((&value.1, end + 2))
+        });
+
+        match placeholder {
+            Some((value, len)) => {
+                out.push_str(value);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push_str(&rest[..1]);
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Returns the names of the `{name}` placeholders used in `template`.
+pub fn placeholders(template: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find(['{', '}']) {
+        rest = &rest[start..];
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            rest = &rest[2..];
+            continue;
+        }
+        match rest.strip_prefix('{').and_then(|inner| inner.find('}').map(|end| (inner, end))) {
+            Some((inner, end)) if !inner[..end].contains('{') => {
+                names.push(&inner[..end]);
+                rest = &inner[end + 1..];
+            }
+            _ => rest = &rest[1..],
+        }
+    }
+
+    names
+}
+
+/// Formats a byte count the way file managers do, e.g. `1.4 MB`.
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["kB", "MB", "GB", "TB"];
+
+    if bytes < 1000 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1000.0;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
@@ -0,0 +1,237 @@
+use std::io::{Read, Write};
+use std::os::fd::AsRawFd;
+use anyhow::{Context, Result};
+use crate::png;
+
+// --- Terminal Graphics ---
+//
+// Helpers for drawing images in the terminal, shared by the gallery and `--preview-term`.
+// Terminals speaking the kitty graphics protocol (kitty, WezTerm, Ghostty, Konsole) are
+// recognized by their environment variables and get the PNG as is. Others are asked for
+// their device attributes, and are sent sixels if they report support for them, like
+// foot, xterm and mlterm do.
+
+/// Longest time to wait for the terminal to answer a query.
+const QUERY_TIMEOUT_MS: i32 = 300;
+/// Share of the terminal's height a preview may take.
+const PREVIEW_HEIGHT: f64 = 0.6;
+/// Bytes of base64 in each chunk of a kitty graphics transmission.
+const KITTY_CHUNK: usize = 4096;
+
+/// Size of the terminal, in cells and, if the terminal reports it, in pixels.
+pub struct Size {
+    pub columns: usize,
+    pub rows: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Returns the size of the terminal behind `fd`.
+pub fn size(fd: i32) -> Size {
+    // SAFETY: `winsize` is a plain C struct that the ioctl fills in on success.
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } != 0 || size.ws_col == 0 {
+        return Size { columns: 80, rows: 24, width: 0, height: 0 };
+    }
+    Size {
+        columns: size.ws_col as usize,
+        rows: size.ws_row as usize,
+        width: size.ws_xpixel as usize,
+        height: size.ws_ypixel as usize,
+    }
+}
+
+/// Guesses whether the terminal understands the kitty graphics protocol.
+pub fn supports_kitty_graphics() -> bool {
+    let term = std::env::var("TERM").unwrap_or_default();
+    let program = std::env::var("TERM_PROGRAM").unwrap_or_default();
+    std::env::var_os("KITTY_WINDOW_ID").is_some()
+    || std::env::var_os("KONSOLE_VERSION").is_some()
+    || term.contains("kitty")
+    || term.contains("ghostty")
+    || program == "WezTerm"
+    || program == "ghostty"
+}
+
+/// Encodes bytes as standard base64 with padding.
+pub fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+        for (i, shift) in [18, 12, 6, 0].into_iter().enumerate() {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> shift & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Draws a PNG image in the terminal LuminaShot was started from, below the cursor and
+/// scaled to fit. Does nothing, with a warning, if the terminal can't show images.
+pub fn preview(image: &[u8]) -> Result<()> {
+    let Ok(mut tty) = std::fs::OpenOptions::new().read(true).write(true).open("/dev/tty") else {
+        eprintln!("Warning: --preview-term needs to be run from a terminal");
+        return Ok(());
+    };
+    let fd = tty.as_raw_fd();
+    let kitty = supports_kitty_graphics();
+    if !kitty && !supports_sixel(&mut tty) {
+        eprintln!("Warning: The terminal supports neither the kitty graphics protocol nor sixel, so there is no preview");
+        return Ok(());
+    }
+
+    // Fit the image into the width of the terminal and part of its height, assuming cells
+    // twice as high as wide where the terminal doesn't report its size in pixels.
+    let size = size(fd);
+    let (cell_width, cell_height) = match (size.width, size.height) {
+        (0, _) | (_, 0) => (10.0, 20.0),
+        (width, height) => (width as f64 / size.columns as f64, height as f64 / size.rows as f64),
+    };
+    let (image_width, image_height) = png_size(image)?;
+    let scale = (size.columns as f64 * cell_width / image_width as f64)
+    .min(size.rows as f64 * PREVIEW_HEIGHT * cell_height / image_height as f64)
+    .min(1.0);
+    let (width, height) = (image_width as f64 * scale, image_height as f64 * scale);
+
+    let mut out = Vec::new();
+    if kitty {
+        let columns = ((width / cell_width).ceil() as usize).max(1);
+        let data = base64(image);
+        let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+        for (index, chunk) in chunks.iter().enumerate() {
+            let more = (index + 1 < chunks.len()) as u8;
+            match index {
+                0 => write!(out, "\x1b_Ga=T,f=100,q=2,c={},m={};", columns, more)?,
+                _ => write!(out, "\x1b_Gm={};", more)?,
+            }
+            out.extend_from_slice(chunk);
+            out.extend_from_slice(b"\x1b\\");
+        }
+    } else {
+        let scaled = png::decode_scaled(image, width.max(height).round().max(1.0) as u32)?;
+        sixel(&mut out, &scaled.pixels, scaled.width as usize, scaled.height as usize);
+    }
+    out.push(b'\n');
+    tty.write_all(&out)?;
+    tty.flush()?;
+    Ok(())
+}
+
+/// Reads the width and height from a PNG's header.
+fn png_size(image: &[u8]) -> Result<(u32, u32)> {
+    let header = image.get(16..24).context("The screenshot is not a PNG image")?;
+    let width = u32::from_be_bytes(header[..4].try_into()?);
+    let height = u32::from_be_bytes(header[4..].try_into()?);
+    if width == 0 || height == 0 {
+        anyhow::bail!("The screenshot is empty");
+    }
+    Ok((width, height))
+}
+
+/// Asks the terminal for its primary device attributes, where 4 means sixel graphics.
+fn supports_sixel(tty: &mut std::fs::File) -> bool {
+    let fd = tty.as_raw_fd();
+    // SAFETY: `termios` is a plain C struct that tcgetattr fills in completely.
+    let mut original: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+        return false;
+    }
+    let mut raw = original;
+    // SAFETY: `raw` is a valid termios obtained from tcgetattr.
+    unsafe {
+        libc::cfmakeraw(&mut raw);
+        libc::tcsetattr(fd, libc::TCSANOW, &raw);
+    }
+
+    let mut response = Vec::new();
+    if tty.write_all(b"\x1b[c").and_then(|_| tty.flush()).is_ok() {
+        // The answer looks like `ESC [ ? 62 ; 4 ; 22 c`.
+        while !response.ends_with(b"c") {
+            let mut poll = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+            // SAFETY: `poll` points to one valid pollfd.
+            if unsafe { libc::poll(&mut poll, 1, QUERY_TIMEOUT_MS) } <= 0 {
+                break;
+            }
+            let mut byte = [0u8; 1];
+            match tty.read(&mut byte) {
+                Ok(1) => response.push(byte[0]),
+                _ => break,
+            }
+        }
+    }
+    // SAFETY: restores the attributes saved above.
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+
+    let response = String::from_utf8_lossy(&response);
+    response
+    .rsplit_once("[?")
+    .and_then(|(_, attributes)| attributes.strip_suffix('c'))
+    .is_some_and(|attributes| attributes.split(';').any(|attribute| attribute == "4"))
+}
+
+/// Appends RGBA pixels as sixels, in the 216 colors of a 6x6x6 color cube. Mostly
+/// transparent pixels are left out, showing the terminal's background.
+fn sixel(out: &mut Vec<u8>, pixels: &[u8], width: usize, height: usize) {
+    let level = |channel: u8| (channel as usize * 5 + 127) / 255;
+    let colors: Vec<Option<usize>> = pixels
+    .chunks_exact(4)
+    .map(|pixel| (pixel[3] >= 128).then(|| level(pixel[0]) * 36 + level(pixel[1]) * 6 + level(pixel[2])))
+    .collect();
+
+    // P2 = 1 keeps unset pixels transparent.
+    let _ = write!(out, "\x1bP0;1q\"1;1;{};{}", width, height);
+    for color in 0..216 {
+        let _ = write!(out, "#{};2;{};{};{}", color, color / 36 * 20, color / 6 % 6 * 20, color % 6 * 20);
+    }
+
+    for band in (0..height).step_by(6) {
+        let rows = (height - band).min(6);
+        let mut used = [false; 216];
+        for row in band..band + rows {
+            for color in colors[row * width..(row + 1) * width].iter().flatten() {
+                used[*color] = true;
+            }
+        }
+        for color in (0..216).filter(|&color| used[color]) {
+            let _ = write!(out, "#{}", color);
+            let mut run: Option<(u8, usize)> = None;
+            for x in 0..width {
+                let bits = (0..rows).fold(0u8, |bits, row| match colors[(band + row) * width + x] == Some(color) {
+                    true => bits | 1 << row,
+                    false => bits,
+                });
+                let symbol = b'?' + bits;
+                run = match run {
+                    Some((previous, count)) if previous == symbol => Some((symbol, count + 1)),
+                    Some((previous, count)) => {
+                        push_run(out, previous, count);
+                        Some((symbol, 1))
+                    }
+                    None => Some((symbol, 1)),
+                };
+            }
+            if let Some((symbol, count)) = run {
+                push_run(out, symbol, count);
+            }
+            // Back to the start of the band for the next color.
+            out.push(b'$');
+        }
+        out.push(b'-');
+    }
+    out.extend_from_slice(b"\x1b\\");
+}
+
+/// Appends `count` repetitions of a sixel, shortened to `!count` when that is smaller.
+fn push_run(out: &mut Vec<u8>, symbol: u8, count: usize) {
+    if count > 3 {
+        let _ = write!(out, "!{}", count);
+        out.push(symbol);
+    } else {
+        out.extend(std::iter::repeat_n(symbol, count));
+    }
+}
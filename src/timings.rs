@@ -0,0 +1,107 @@
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+
+// --- Stage Timings ---
+//
+// Every capture keeps a running total of the time spent in each of its stages, which
+// `--timings` prints and adds to the `--json` summary. Stages that run more than once,
+// like the compositor queries or a window picker that is restarted, add up.
+
+#[derive(Debug, Clone, Copy)]
+pub enum Stage {
+    /// Waiting for slurp, including the user making the selection.
+    Selection,
+    /// hyprctl calls, cached or not.
+    Compositor,
+    /// From starting grim until it writes the first byte of the image.
+    Capture,
+    /// From grim's first byte until its last; grim encodes as it writes.
+    Encode,
+    /// Decoding, editing and re-encoding the image for `--filter` and the adjustments.
+    Filter,
+    Save,
+    Copy,
+    /// Sending the notification, not waiting for its actions.
+    Notification,
+}
+
+const STAGES: [(Stage, &str); 8] = [
+    (Stage::Selection, "Selection"),
+    (Stage::Compositor, "Compositor queries"),
+    (Stage::Capture, "Capture"),
+    (Stage::Encode, "Encode"),
+    (Stage::Filter, "Filters"),
+    (Stage::Save, "Save"),
+    (Stage::Copy, "Copy"),
+    (Stage::Notification, "Notification"),
+];
+
+static TOTALS: Mutex<[Option<Duration>; 8]> = Mutex::new([None; 8]);
+
+/// Adds `elapsed` to the total of `stage`.
+pub fn add(stage: Stage, elapsed: Duration) {
+    let mut totals = TOTALS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let total = &mut totals[stage as usize];
+    *total = Some(total.unwrap_or_default() + elapsed);
+}
+
+/// Awaits `future`, adding the time it took to `stage`. The time is counted even if the
+/// future is dropped before it finishes, like a window picker that is restarted.
+pub async fn time<T>(stage: Stage, future: impl Future<Output = T>) -> T {
+    let _timer = Timer { stage, started: Instant::now() };
+    future.await
+}
+
+struct Timer {
+    stage: Stage,
+    started: Instant,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        add(self.stage, self.started.elapsed());
+    }
+}
+
+/// The stage totals in milliseconds, `null` for stages that didn't run.
+#[derive(Debug, Serialize)]
+pub struct Timings {
+    selection_ms: Option<f64>,
+    compositor_ms: Option<f64>,
+    capture_ms: Option<f64>,
+    encode_ms: Option<f64>,
+    filter_ms: Option<f64>,
+    save_ms: Option<f64>,
+    copy_ms: Option<f64>,
+    notification_ms: Option<f64>,
+}
+
+/// Returns the totals so far.
+pub fn current() -> Timings {
+    let totals = *TOTALS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let ms = |stage: Stage| totals[stage as usize].map(|total| total.as_secs_f64() * 1000.0);
+    Timings {
+        selection_ms: ms(Stage::Selection),
+        compositor_ms: ms(Stage::Compositor),
+        capture_ms: ms(Stage::Capture),
+        encode_ms: ms(Stage::Encode),
+        filter_ms: ms(Stage::Filter),
+        save_ms: ms(Stage::Save),
+        copy_ms: ms(Stage::Copy),
+        notification_ms: ms(Stage::Notification),
+    }
+}
+
+/// Formats the stages that ran as an aligned table, one per line.
+pub fn table() -> String {
+    let totals = *TOTALS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut out = String::from("Timings:");
+    for (stage, label) in STAGES {
+        if let Some(total) = totals[stage as usize] {
+            out.push_str(&format!("\n  {:<20}{:>9.1} ms", label, total.as_secs_f64() * 1000.0));
+        }
+    }
+    out
+}
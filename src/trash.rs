@@ -0,0 +1,94 @@
+use std::io::ErrorKind;
+use anyhow::{Context, Result};
+use crate::{dirs, ocr};
+
+// --- Trash ---
+//
+// Screenshots deleted from the notification, the gallery or `clean` are moved to the
+// trash, following the freedesktop.org Trash specification, so they can be restored from
+// a file manager. `--purge` deletes them for good instead.
+//
+// Everything goes to the home trash. Files on another filesystem are copied there and
+// then removed, which the specification allows in place of a per-volume trash.
+
+/// Moves a file to the trash, or deletes it if `purge` is set. Returns a message saying
+/// which, for printing.
+pub async fn remove(path: &str, purge: bool) -> Result<String> {
+    let message = remove_file(path, purge).await?;
+    // The text `--ocr-sidecar` wrote next to a screenshot goes along with it.
+    let sidecar = ocr::sidecar_path(path);
+    if tokio::fs::try_exists(&sidecar).await.unwrap_or(false) {
+        if let Err(err) = remove_file(&sidecar, purge).await {
+            eprintln!("Warning: {:#}", err);
+        }
+    }
+    Ok(message)
+}
+
+async fn remove_file(path: &str, purge: bool) -> Result<String> {
+    if purge {
+        tokio::fs::remove_file(path).await.with_context(|| format!("Failed to delete {}", path))?;
+        return Ok(format!("Deleted {}", path));
+    }
+    move_to_trash(path).await.with_context(|| format!("Failed to move {} to the trash", path))?;
+    Ok(format!("Moved {} to the trash", path))
+}
+
+async fn move_to_trash(path: &str) -> Result<()> {
+    let path = std::path::absolute(path)?;
+    let trash = format!("{}/Trash", dirs::data_home()?);
+    for dir in ["files", "info"] {
+        tokio::fs::create_dir_all(format!("{}/{}", trash, dir)).await?;
+    }
+
+    let info = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        dirs::encode_path(&path.to_string_lossy()),
+        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S"),
+    );
+    let name = path.file_name().context("Not a file")?.to_string_lossy().into_owned();
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem.to_string(), format!(".{}", extension)),
+        _ => (name.clone(), String::new()),
+    };
+
+    // Creating the info file first claims the name, so two deletions can't pick the same one.
+    let mut attempt = 1;
+    loop {
+        let trashed_name = match attempt {
+            1 => name.clone(),
+            n => format!("{}_{}{}", stem, n, extension),
+        };
+        attempt += 1;
+        let target = format!("{}/files/{}", trash, trashed_name);
+        if tokio::fs::try_exists(&target).await? {
+            continue;
+        }
+        let info_path = format!("{}/info/{}.trashinfo", trash, trashed_name);
+        let created = tokio::fs::OpenOptions::new().write(true).create_new(true).open(&info_path).await;
+        match created {
+            Ok(_) => {}
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err).with_context(|| format!("Failed to create {}", info_path)),
+        }
+        tokio::fs::write(&info_path, &info).await?;
+
+        if let Err(err) = move_file(&path, &target).await {
+            let _ = tokio::fs::remove_file(&info_path).await;
+            return Err(err);
+        }
+        return Ok(());
+    }
+}
+
+/// Renames a file, or copies and removes it if it is on another filesystem.
+async fn move_file(path: &std::path::Path, target: &str) -> Result<()> {
+    match tokio::fs::rename(path, target).await {
+        Err(err) if err.raw_os_error() == Some(libc::EXDEV) => {
+            tokio::fs::copy(path, target).await?;
+            tokio::fs::remove_file(path).await?;
+            Ok(())
+        }
+        result => Ok(result?),
+    }
+}
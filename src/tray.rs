@@ -0,0 +1,272 @@
+use anyhow::Result;
+use crate::daemon::{Shortcut, SHORTCUTS};
+use crate::dbus::{self, MessageType, Value};
+use crate::history;
+
+// --- Tray Icon ---
+//
+// `daemon --tray` shows an icon in the system tray through the StatusNotifierItem
+// protocol, for desktops where the tray is where people look for a screenshot tool.
+// Clicking the icon captures a region and middle-clicking it captures a window; its menu
+// has the daemon's shortcuts and the most recent captures.
+//
+// The item is exported on the daemon's own bus connection, and its menu through the
+// com.canonical.dbusmenu interface that SNI hosts like waybar and KDE read menus from.
+// Trays that start after the daemon, or restart, are picked up when they claim the
+// watcher name.
+
+const WATCHER: &str = "org.kde.StatusNotifierWatcher";
+const WATCHER_PATH: &str = "/StatusNotifierWatcher";
+const ITEM: &str = "org.kde.StatusNotifierItem";
+const ITEM_PATH: &str = "/StatusNotifierItem";
+const MENU_PATH: &str = "/MenuBar";
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+const ITEM_PROPERTIES: &[&str] = &["Category", "Id", "Title", "Status", "IconName", "ItemIsMenu", "Menu", "ToolTip"];
+const MENU_PROPERTIES: &[&str] = &["Version", "TextDirection", "Status", "IconThemePath"];
+const UNKNOWN_METHOD: &str = "org.freedesktop.DBus.Error.UnknownMethod";
+
+/// How many captures the Recent Captures submenu lists.
+const RECENT: usize = 8;
+/// Menu id of the Recent Captures submenu; the captures in it follow.
+const RECENT_MENU: i32 = 100;
+const SEPARATOR: i32 = 99;
+
+/// What a click in the tray asks the daemon to do.
+pub enum Action {
+    Run(&'static Shortcut),
+    Open(String),
+}
+
+/// The exported tray icon and the state of its menu.
+pub struct Tray {
+    name: String,
+    /// Bumped whenever the menu changes, so hosts know to fetch it again.
+    revision: u32,
+    recent: Vec<String>,
+}
+
+impl Tray {
+    /// Claims a bus name for the item and registers it with the tray, if one is running.
+    pub async fn register(bus: &mut dbus::Connection) -> Result<Tray> {
+        let name = format!("{}-{}-1", ITEM, std::process::id());
+        bus.request_name(&name).await?;
+        bus.add_match(&format!("type='signal',interface='org.freedesktop.DBus',member='NameOwnerChanged',arg0='{}'", WATCHER)).await?;
+
+        let mut tray = Tray { name, revision: 1, recent: Vec::new() };
+        tray.recent = recent_captures().await;
+        if let Err(err) = tray.announce(bus).await {
+            eprintln!("Warning: No system tray is running yet, the icon will show up once one starts ({:#})", err);
+        }
+        Ok(tray)
+    }
+
+    /// Tells the StatusNotifierWatcher about the item.
+    async fn announce(&self, bus: &mut dbus::Connection) -> Result<()> {
+        bus.call(WATCHER, WATCHER_PATH, WATCHER, "RegisterStatusNotifierItem", vec![Value::str(&self.name)]).await?;
+        Ok(())
+    }
+
+    /// Handles a message for the tray. Returns what a click asked for, if anything.
+    pub async fn handle(&mut self, bus: &mut dbus::Connection, message: &dbus::Message) -> Result<Option<Action>> {
+        if message.is_signal("org.freedesktop.DBus", "NameOwnerChanged") {
+            // A tray (re)started: arguments are the name, its old owner and its new one.
+            let started = message.body.first().and_then(Value::as_str) == Some(WATCHER)
+            && message.body.get(2).and_then(Value::as_str).is_some_and(|owner| !owner.is_empty());
+            if started {
+                if let Err(err) = self.announce(bus).await {
+                    eprintln!("Warning: Failed to add the tray icon: {:#}", err);
+                }
+            }
+            return Ok(None);
+        }
+        if message.kind != MessageType::MethodCall {
+            return Ok(None);
+        }
+
+        let member = message.member.as_deref().unwrap_or_default();
+        let (reply, action) = match (message.path.as_deref(), message.interface.as_deref()) {
+            (Some(ITEM_PATH), Some(PROPERTIES)) => (properties(message, member, ITEM_PROPERTIES, |name| self.item_property(name)), None),
+            (Some(MENU_PATH), Some(PROPERTIES)) => (properties(message, member, MENU_PROPERTIES, menu_property), None),
+            (Some(ITEM_PATH), _) => match member {
+                "Activate" => (Some(vec![]), Some(Action::Run(&SHORTCUTS[0]))),
+                "SecondaryActivate" => (Some(vec![]), Some(Action::Run(&SHORTCUTS[1]))),
+                "ContextMenu" | "Scroll" => (Some(vec![]), None),
+                _ => (None, None),
+            },
+            (Some(MENU_PATH), _) => self.menu_call(message, member).await,
+            _ => (None, None),
+        };
+
+        match reply {
+            Some(body) => bus.reply(message, body).await?,
+            None => bus.reply_error(message, UNKNOWN_METHOD, &format!("No method {} on {}", member, message.path.as_deref().unwrap_or_default())).await?,
+        }
+        Ok(action)
+    }
+
+    fn item_property(&self, name: &str) -> Option<Value> {
+        let tooltip = match self.recent.first() {
+            Some(path) => format!("Last capture: {}", path),
+            None => "No captures yet".into(),
+        };
+        Some(match name {
+            "Category" => Value::str("ApplicationStatus"),
+            "Id" => Value::str("luminashot"),
+            "Title" => Value::str("LuminaShot"),
+            "Status" => Value::str("Active"),
+            "IconName" => Value::str("camera-photo"),
+            "ItemIsMenu" => Value::Bool(false),
+            "Menu" => Value::Path(MENU_PATH.into()),
+            "ToolTip" => Value::Struct(vec![
+                Value::str("camera-photo"),
+                Value::Array("(iiay)".into(), vec![]),
+                Value::str("LuminaShot"),
+                Value::str(tooltip),
+            ]),
+            _ => return None,
+        })
+    }
+
+    /// Answers a com.canonical.dbusmenu call.
+    async fn menu_call(&mut self, message: &dbus::Message, member: &str) -> (Option<Vec<Value>>, Option<Action>) {
+        let id = match message.body.first() {
+            Some(Value::I32(id)) => *id,
+            _ => 0,
+        };
+        match member {
+            "GetLayout" => (Some(vec![Value::U32(self.revision), self.layout(id)]), None),
+            "GetGroupProperties" => {
+                let ids: Vec<i32> = message.body.first().and_then(Value::as_array).unwrap_or_default().iter().filter_map(as_i32).collect();
+                let items = ids
+                .into_iter()
+                .filter_map(|id| Some(Value::Struct(vec![Value::I32(id), Value::dict(self.item(id)?)])))
+                .collect();
+                (Some(vec![Value::Array("(ia{sv})".into(), items)]), None)
+            }
+            "GetProperty" => {
+                let name = message.body.get(1).and_then(Value::as_str).unwrap_or_default();
+                let value = self.item(id).and_then(|properties| properties.into_iter().find(|(key, _)| key == name));
+                (value.map(|(_, value)| vec![Value::variant(value)]), None)
+            }
+            "Event" => {
+                let clicked = message.body.get(1).and_then(Value::as_str) == Some("clicked");
+                (Some(vec![]), clicked.then(|| self.clicked(id)).flatten())
+            }
+            "EventGroup" => {
+                // Only the first click of a group is acted on; hosts send one at a time anyway.
+                let events = message.body.first().and_then(Value::as_array).unwrap_or_default();
+                let action = events.iter().find_map(|event| match event {
+                    Value::Struct(fields) if fields.get(1).and_then(Value::as_str) == Some("clicked") => fields.first().and_then(as_i32).and_then(|id| self.clicked(id)),
+                    _ => None,
+                });
+                (Some(vec![Value::Array("i".into(), vec![])]), action)
+            }
+            "AboutToShow" => (Some(vec![Value::Bool(self.refresh().await)]), None),
+            "AboutToShowGroup" => {
+                let updates = if self.refresh().await { vec![Value::I32(0)] } else { vec![] };
+                (Some(vec![Value::Array("i".into(), updates), Value::Array("i".into(), vec![])]), None)
+            }
+            _ => (None, None),
+        }
+    }
+
+    /// Reloads the recent captures. Returns true if the menu changed.
+    async fn refresh(&mut self) -> bool {
+        let recent = recent_captures().await;
+        if recent == self.recent {
+            return false;
+        }
+        self.recent = recent;
+        self.revision += 1;
+        true
+    }
+
+    /// Returns the properties of a menu item, or `None` if there is no item with that id.
+    fn item(&self, id: i32) -> Option<Vec<(String, Value)>> {
+        let label = |text: &str| ("label".to_string(), Value::str(text));
+        match id {
+            0 => Some(vec![("children-display".into(), Value::str("submenu"))]),
+            SEPARATOR => Some(vec![("type".into(), Value::str("separator"))]),
+            RECENT_MENU => Some(vec![
+                label("Recent Captures"),
+                ("children-display".into(), Value::str("submenu")),
+                ("enabled".into(), Value::Bool(!self.recent.is_empty())),
+            ]),
+            _ if id > RECENT_MENU => {
+                let path = self.recent.get((id - RECENT_MENU - 1) as usize)?;
+                let file_name = std::path::Path::new(path).file_name().map_or(path.clone(), |name| name.to_string_lossy().into_owned());
+                // Underscores mark mnemonics in menu labels.
+                Some(vec![label(&file_name.replace('_', "__"))])
+            }
+            _ => SHORTCUTS.get((id - 1) as usize).map(|shortcut| vec![label(shortcut.description)]),
+        }
+    }
+
+    /// Returns the ids of the items directly below a menu item.
+    fn children(&self, id: i32) -> Vec<i32> {
+        match id {
+            0 => (1..=SHORTCUTS.len() as i32).chain([SEPARATOR, RECENT_MENU]).collect(),
+            RECENT_MENU => (1..=self.recent.len() as i32).map(|index| RECENT_MENU + index).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Builds the `(ia{sv}av)` layout of a menu item and everything below it.
+    fn layout(&self, id: i32) -> Value {
+        let children = self.children(id).into_iter().map(|child| Value::variant(self.layout(child))).collect();
+        Value::Struct(vec![
+            Value::I32(id),
+            Value::dict(self.item(id).unwrap_or_default()),
+            Value::Array("v".into(), children),
+        ])
+    }
+
+    fn clicked(&self, id: i32) -> Option<Action> {
+        if id > RECENT_MENU {
+            return self.recent.get((id - RECENT_MENU - 1) as usize).cloned().map(Action::Open);
+        }
+        SHORTCUTS.get(usize::try_from(id - 1).ok()?).map(Action::Run)
+    }
+}
+
+fn menu_property(name: &str) -> Option<Value> {
+    Some(match name {
+        "Version" => Value::U32(3),
+        "TextDirection" => Value::str("ltr"),
+        "Status" => Value::str("normal"),
+        "IconThemePath" => Value::Array("s".into(), vec![]),
+        _ => return None,
+    })
+}
+
+/// Answers an org.freedesktop.DBus.Properties call for an object with the given properties.
+fn properties(message: &dbus::Message, member: &str, names: &[&str], property: impl Fn(&str) -> Option<Value>) -> Option<Vec<Value>> {
+    match member {
+        "Get" => {
+            let name = message.body.get(1).and_then(Value::as_str)?;
+            Some(vec![Value::variant(property(name)?)])
+        }
+        "GetAll" => Some(vec![Value::dict(names.iter().filter_map(|&name| Some((name.to_string(), property(name)?))).collect())]),
+        _ => None,
+    }
+}
+
+fn as_i32(value: &Value) -> Option<i32> {
+    match value {
+        Value::I32(n) => Some(*n),
+        Value::Variant(inner) => as_i32(inner),
+        _ => None,
+    }
+}
+
+/// Returns the paths of the newest captures that still exist, newest first.
+async fn recent_captures() -> Vec<String> {
+    let entries = history::load().await.unwrap_or_default();
+    entries
+    .into_iter()
+    .rev()
+    .filter_map(|entry| entry.path)
+    .filter(|path| std::path::Path::new(path).is_file())
+    .take(RECENT)
+    .collect()
+}
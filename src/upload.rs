@@ -0,0 +1,117 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+use tokio::process::Command;
+use tokio::time::Duration;
+use crate::{history, i18n, notify, process};
+
+// --- Uploading ---
+
+/// Uploads can take much longer than the usual external command.
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Image hosts screenshots can be uploaded to.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Service {
+    /// Anonymous upload to imgur.com (needs an API client ID)
+    Imgur,
+    /// The 0x0.st null pointer
+    #[value(name = "0x0")]
+    ZeroXZero,
+    /// catbox.moe
+    Catbox,
+}
+
+#[derive(Deserialize)]
+struct ImgurResponse {
+    data: ImgurImage,
+}
+
+#[derive(Deserialize)]
+struct ImgurImage {
+    link: String,
+}
+
+/// Uploads an image file and returns its public URL.
+pub async fn upload_file(path: &str, service: Service, imgur_client_id: Option<&str>) -> Result<String> {
+    if !std::path::Path::new(path).is_file() {
+        anyhow::bail!("{} does not exist", path);
+    }
+
+    let file_field = |name: &str| format!("{}=@{}", name, path);
+    let mut curl = Command::new("curl");
+    curl
+    .args(["--silent", "--show-error", "--fail-with-body"])
+    .arg("--max-time")
+    .arg(UPLOAD_TIMEOUT.as_secs().to_string())
+    .kill_on_drop(true);
+
+    match service {
+        Service::Imgur => {
+            let client_id = imgur_client_id.context("Uploading to imgur needs an API client ID, set one with --imgur-client-id")?;
+            curl
+            .arg("-H")
+            .arg(format!("Authorization: Client-ID {}", client_id))
+            .arg("-F")
+            .arg(file_field("image"))
+            .arg("https://api.imgur.com/3/image");
+        }
+        Service::ZeroXZero => {
+            curl
+            .arg("-F")
+            .arg(file_field("file"))
+            .arg("https://0x0.st");
+        }
+        Service::Catbox => {
+            curl
+            .args(["-F", "reqtype=fileupload", "-F"])
+            .arg(file_field("fileToUpload"))
+            .arg("https://catbox.moe/user/api.php");
+        }
+    }
+
+    let output = process::with_timeout("curl", Some(UPLOAD_TIMEOUT), async {
+        curl.output().await.context("Failed to run curl")
+    })
+    .await?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("Upload failed: {} {}", stderr.trim(), stdout.trim());
+    }
+
+    let url = match service {
+        Service::Imgur => serde_json::from_str::<ImgurResponse>(&stdout).context("Unexpected response from imgur")?.data.link,
+        Service::ZeroXZero | Service::Catbox => stdout.trim().to_string(),
+    };
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        anyhow::bail!("Unexpected response from the upload service: {}", url);
+    }
+    Ok(url)
+}
+
+/// Uploads a file, copies the URL to the clipboard and records it in the history.
+pub async fn upload_and_share(path: &str, service: Service, imgur_client_id: Option<&str>) -> Result<()> {
+    let path = std::fs::canonicalize(path)
+    .with_context(|| format!("{} does not exist", path))?
+    .to_string_lossy()
+    .into_owned();
+
+    println!("Uploading {}...", path);
+    let url = upload_file(&path, service, imgur_client_id).await?;
+    println!("{}", url);
+
+    crate::copy_text_to_clipboard(&url).await?;
+    if !history::set_url(&path, &url).await? {
+        println!("{} is not in the capture history, so the URL was not recorded.", path);
+    }
+
+    notify::show(&notify::Notification {
+        summary: i18n::tr("LuminaShot - Uploaded"),
+        body: i18n::tr_with("Link copied to clipboard: {url}", &[("url", url.clone())]),
+        icon: path,
+        ..Default::default()
+    }).await;
+    Ok(())
+}
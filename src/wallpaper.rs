@@ -0,0 +1,71 @@
+use std::process::Stdio;
+use anyhow::{Context, Result};
+use tokio::process::Command;
+use crate::process;
+
+// --- Set as Wallpaper ---
+//
+// `--set-wallpaper` and the notification's Wallpaper action hand the saved screenshot to
+// the wallpaper daemon. Without `--wallpaper-command`, the running one is used: swww,
+// hyprpaper or swaybg, asked in that order. A command given with `--wallpaper-command`
+// is run with the path appended, like the viewer and editor.
+
+/// Makes the screenshot at `path` the wallpaper of every monitor.
+pub async fn set(command: Option<&str>, path: &str) -> Result<()> {
+    if let Some(command_line) = command {
+        let mut parts = command_line.split_whitespace();
+        let program = parts.next().context("Empty --wallpaper-command")?;
+        return run(Command::new(program).args(parts).arg(path)).await;
+    }
+
+    if !running("swww-daemon").is_empty() {
+        run(Command::new("swww").args(["img", path])).await
+    } else if !running("hyprpaper").is_empty() {
+        run(Command::new("hyprctl").args(["hyprpaper", "preload", path])).await?;
+        run(Command::new("hyprctl").args(["hyprpaper", "wallpaper"]).arg(format!(",{}", path))).await?;
+        // Frees the previous wallpaper, which hyprpaper otherwise keeps in memory.
+        run(Command::new("hyprctl").args(["hyprpaper", "unload", "unused"])).await
+    } else if let pids @ [_, ..] = running("swaybg").as_slice() {
+        // swaybg can't switch images, so the new one is started before the old one is stopped.
+        Command::new("swaybg")
+        .args(["--mode", "fill", "--image", path])
+        .stdin(Stdio::null())
+        .spawn()
+        .context("Failed to start swaybg")?;
+        for &pid in pids {
+            // SAFETY: kill has no memory-safety preconditions.
+            unsafe { libc::kill(pid, libc::SIGTERM) };
+        }
+        Ok(())
+    } else {
+        anyhow::bail!("No wallpaper daemon is running; start swww, hyprpaper or swaybg, or set --wallpaper-command")
+    }
+}
+
+/// Runs a wallpaper command and checks that it succeeded.
+async fn run(command: &mut Command) -> Result<()> {
+    let name = process::program_name(command);
+    let output = process::output(command.stdin(Stdio::null())).await?;
+    if !output.status.success() {
+        anyhow::bail!("{} failed: {}", name, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Returns the ids of our processes running the program `name`.
+fn running(name: &str) -> Vec<i32> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    // SAFETY: getuid never fails and has no preconditions.
+    let uid = unsafe { libc::getuid() };
+    entries
+    .flatten()
+    .filter_map(|entry| {
+        let pid: i32 = entry.file_name().to_str()?.parse().ok()?;
+        let comm = std::fs::read_to_string(entry.path().join("comm")).ok()?;
+        let owner = std::os::unix::fs::MetadataExt::uid(&entry.metadata().ok()?);
+        (comm.trim_end() == name && owner == uid).then_some(pid)
+    })
+    .collect()
+}
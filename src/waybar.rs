@@ -0,0 +1,55 @@
+use anyhow::Result;
+use serde::Serialize;
+use tokio::time::{sleep, Duration};
+use crate::{history, record};
+
+// --- `waybar` Subcommand ---
+//
+// Prints the state of LuminaShot as a waybar custom module: a camera normally, with the
+// last capture in the tooltip, and the elapsed time while recording. Clicks are handled by
+// waybar itself, with `on-click` running a capture; see the README for a config.
+
+/// What the module shows when nothing is being recorded.
+const IDLE_TEXT: &str = "📷";
+
+#[derive(Serialize, PartialEq)]
+struct Module {
+    text: String,
+    tooltip: String,
+    class: &'static str,
+    alt: &'static str,
+}
+
+/// Prints the module once, or again whenever it changes with `watch`.
+pub async fn waybar(watch: bool) -> Result<()> {
+    let mut shown = None;
+    loop {
+        let module = module().await?;
+        if shown.as_ref() != Some(&module) {
+            println!("{}", serde_json::to_string(&module)?);
+            shown = Some(module);
+        }
+        if !watch {
+            return Ok(());
+        }
+        sleep(Duration::from_secs(1)).await;
+    }
+}
+
+async fn module() -> Result<Module> {
+    let recording = record::Status::current().await;
+    if recording.class != "idle" {
+        return Ok(Module { text: recording.text, tooltip: recording.tooltip, class: recording.class, alt: recording.alt });
+    }
+
+    let last = history::load()
+    .await?
+    .into_iter()
+    .rev()
+    .find_map(|entry| entry.path.filter(|path| std::path::Path::new(path).is_file()));
+    let tooltip = match last {
+        Some(path) => format!("Last capture: {}", path),
+        None => "No captures yet".to_string(),
+    };
+    Ok(Module { text: IDLE_TEXT.to_string(), tooltip, class: "idle", alt: "idle" })
+}
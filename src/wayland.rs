@@ -0,0 +1,327 @@
+use std::collections::VecDeque;
+use std::io::Write;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::net::UnixStream;
+use anyhow::{Context, Result};
+use crate::dirs;
+
+// A minimal, blocking Wayland client: just enough of the wire protocol to bind globals,
+// send requests and read events with the file descriptors that come with them. Object
+// IDs are handed out in order and never reused, which is fine for the few objects a
+// single-purpose client creates.
+
+/// The `wl_display` singleton.
+const DISPLAY: u32 = 1;
+/// Most file descriptors the compositor sends in one message.
+const MAX_FDS: usize = 28;
+
+/// An argument of a request. New IDs and objects are passed as `Uint`.
+pub enum Arg<'a> {
+    Uint(u32),
+    Str(&'a str),
+}
+
+/// A global advertised by the compositor's registry.
+#[derive(Debug, Clone)]
+pub struct Global {
+    pub name: u32,
+    pub interface: String,
+    pub version: u32,
+}
+
+/// An event, with its arguments still encoded.
+pub struct Event {
+    pub object: u32,
+    pub opcode: u16,
+    args: Vec<u8>,
+    position: usize,
+}
+
+impl Event {
+    pub fn uint(&mut self) -> Result<u32> {
+        let bytes = self.args.get(self.position..self.position + 4).context("Wayland event is too short")?;
+        self.position += 4;
+        Ok(u32::from_ne_bytes(bytes.try_into()?))
+    }
+
+    pub fn string(&mut self) -> Result<String> {
+        let length = self.uint()? as usize;
+        let bytes = self.args.get(self.position..self.position + length).context("Wayland event is too short")?;
+        self.position += length.div_ceil(4) * 4;
+        Ok(String::from_utf8_lossy(bytes.strip_suffix(&[0]).unwrap_or(bytes)).into_owned())
+    }
+}
+
+// --- Connection ---
+
+pub struct Connection {
+    stream: UnixStream,
+    next_id: u32,
+    registry: u32,
+    buffer: Vec<u8>,
+    fds: VecDeque<OwnedFd>,
+}
+
+impl Connection {
+    /// Connects to the compositor named by `$WAYLAND_DISPLAY`.
+    pub fn connect() -> Result<Connection> {
+        let display = std::env::var("WAYLAND_DISPLAY").unwrap_or_else(|_| "wayland-0".into());
+        let path = match display.starts_with('/') {
+            true => display,
+            false => format!("{}/{}", dirs::runtime_dir(), display),
+        };
+        let stream = UnixStream::connect(&path).with_context(|| format!("Failed to connect to the compositor at {}", path))?;
+        Ok(Connection { stream, next_id: 2, registry: 0, buffer: Vec::new(), fds: VecDeque::new() })
+    }
+
+    /// Returns a fresh object ID.
+    pub fn new_id(&mut self) -> u32 {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    pub fn request(&mut self, object: u32, opcode: u16, args: &[Arg]) -> Result<()> {
+        let mut body = Vec::new();
+        for arg in args {
+            match arg {
+                Arg::Uint(value) => body.extend_from_slice(&value.to_ne_bytes()),
+                Arg::Str(text) => {
+                    body.extend_from_slice(&(text.len() as u32 + 1).to_ne_bytes());
+                    body.extend_from_slice(text.as_bytes());
+                    body.resize(body.len() + 4 - text.len() % 4, 0);
+                }
+            }
+        }
+        let mut message = Vec::with_capacity(8 + body.len());
+        message.extend_from_slice(&object.to_ne_bytes());
+        message.extend_from_slice(&(((8 + body.len() as u32) << 16) | opcode as u32).to_ne_bytes());
+        message.extend_from_slice(&body);
+        self.stream.write_all(&message).context("Lost the connection to the compositor")
+    }
+
+    /// Waits for the next event. Protocol errors end the connection with their message.
+    pub fn read_event(&mut self) -> Result<Event> {
+        loop {
+            if self.buffer.len() >= 8 {
+                let size = (u32::from_ne_bytes(self.buffer[4..8].try_into()?) >> 16) as usize;
+                if size < 8 {
+                    anyhow::bail!("The compositor sent a malformed message");
+                }
+                if self.buffer.len() >= size {
+                    let message: Vec<u8> = self.buffer.drain(..size).collect();
+                    let mut event = Event {
+                        object: u32::from_ne_bytes(message[..4].try_into()?),
+                        opcode: (u32::from_ne_bytes(message[4..8].try_into()?) & 0xffff) as u16,
+                        args: message[8..].to_vec(),
+                        position: 0,
+                    };
+                    if event.object == DISPLAY && event.opcode == 0 {
+                        let (object, code, text) = (event.uint()?, event.uint()?, event.string()?);
+                        anyhow::bail!("The compositor reported error {} on object {}: {}", code, object, text);
+                    }
+                    return Ok(event);
+                }
+            }
+            self.receive()?;
+        }
+    }
+
+    /// Takes the next file descriptor that came with the events read so far.
+    pub fn take_fd(&mut self) -> Result<OwnedFd> {
+        self.fds.pop_front().context("The compositor didn't send a file descriptor")
+    }
+
+    /// Waits until the compositor has handled every request sent so far. Events arriving
+    /// in the meantime are given to `handle`.
+    pub fn roundtrip(&mut self, mut handle: impl FnMut(&mut Connection, Event) -> Result<()>) -> Result<()> {
+        let callback = self.new_id();
+        self.request(DISPLAY, 0, &[Arg::Uint(callback)])?;
+        loop {
+            let event = self.read_event()?;
+            if event.object == callback {
+                return Ok(());
+            }
+            handle(self, event)?;
+        }
+    }
+
+    /// Returns the globals the compositor offers.
+    pub fn globals(&mut self) -> Result<Vec<Global>> {
+        self.registry = self.new_id();
+        self.request(DISPLAY, 1, &[Arg::Uint(self.registry)])?;
+        let registry = self.registry;
+        let mut globals = Vec::new();
+        self.roundtrip(|_, mut event| {
+            if event.object == registry && event.opcode == 0 {
+                globals.push(Global { name: event.uint()?, interface: event.string()?, version: event.uint()? });
+            }
+            Ok(())
+        })?;
+        Ok(globals)
+    }
+
+    /// Binds a global at `version`, which must not be above the one it is offered at.
+    pub fn bind(&mut self, global: &Global, version: u32) -> Result<u32> {
+        let id = self.new_id();
+        self.request(self.registry, 0, &[Arg::Uint(global.name), Arg::Str(&global.interface), Arg::Uint(version.min(global.version)), Arg::Uint(id)])?;
+        Ok(id)
+    }
+
+    /// Reads whatever the compositor has sent, with any file descriptors.
+    fn receive(&mut self) -> Result<()> {
+        let mut data = vec![0u8; 4096];
+        let mut control = vec![0u8; unsafe { libc::CMSG_SPACE((MAX_FDS * size_of::<i32>()) as u32) } as usize];
+        let mut iov = libc::iovec { iov_base: data.as_mut_ptr().cast(), iov_len: data.len() };
+        // SAFETY: `msghdr` is a plain C struct; the pointers set below stay valid for the call.
+        let mut header: libc::msghdr = unsafe { std::mem::zeroed() };
+        header.msg_iov = &mut iov;
+        header.msg_iovlen = 1;
+        header.msg_control = control.as_mut_ptr().cast();
+        header.msg_controllen = control.len();
+
+        // SAFETY: `header` describes the two buffers above.
+        let received = unsafe { libc::recvmsg(self.stream.as_raw_fd(), &mut header, libc::MSG_CMSG_CLOEXEC) };
+        if received < 0 {
+            return Err(std::io::Error::last_os_error()).context("Lost the connection to the compositor");
+        }
+        if received == 0 {
+            anyhow::bail!("The compositor closed the connection");
+        }
+        self.buffer.extend_from_slice(&data[..received as usize]);
+
+        // SAFETY: the control messages were filled in by recvmsg, within `msg_controllen`.
+        unsafe {
+            let mut message = libc::CMSG_FIRSTHDR(&header);
+            while !message.is_null() {
+                if (*message).cmsg_level == libc::SOL_SOCKET && (*message).cmsg_type == libc::SCM_RIGHTS {
+                    let count = ((*message).cmsg_len as usize - libc::CMSG_LEN(0) as usize) / size_of::<i32>();
+                    let fds = libc::CMSG_DATA(message).cast::<i32>();
+                    for index in 0..count {
+                        self.fds.push_back(OwnedFd::from_raw_fd(fds.add(index).read_unaligned()));
+                    }
+                }
+                message = libc::CMSG_NXTHDR(&header, message);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn pair() -> (Connection, UnixStream) {
+        let (ours, peer) = UnixStream::pair().unwrap();
+        (Connection { stream: ours, next_id: 2, registry: 0, buffer: Vec::new(), fds: VecDeque::new() }, peer)
+    }
+
+    /// Encodes an event the way the compositor sends it.
+    fn event(object: u32, opcode: u16, args: &[Arg]) -> Vec<u8> {
+        let (mut connection, mut peer) = pair();
+        connection.request(object, opcode, args).unwrap();
+        drop(connection);
+        let mut message = Vec::new();
+        peer.read_to_end(&mut message).unwrap();
+        message
+    }
+
+    #[test]
+    fn encodes_requests() {
+        let message = event(5, 2, &[Arg::Uint(7), Arg::Str("abc"), Arg::Str("wl_seat")]);
+        let words: Vec<u32> = message.chunks_exact(4).map(|word| u32::from_ne_bytes(word.try_into().unwrap())).collect();
+        // Strings carry their terminating zero and are padded to whole words.
+        assert_eq!(words.len(), 2 + 1 + 2 + 3);
+        assert_eq!(words[..3], [5, (32 << 16) | 2, 7]);
+        assert_eq!(&message[12..20], b"\x04\0\0\0abc\0");
+        assert_eq!(&message[20..32], b"\x08\0\0\0wl_seat\0");
+    }
+
+    #[test]
+    fn reads_globals() {
+        let (mut connection, mut peer) = pair();
+        // The registry is the first new ID, the roundtrip's callback the second.
+        let mut events = event(2, 0, &[Arg::Uint(1), Arg::Str("wl_seat"), Arg::Uint(7)]);
+        events.extend(event(2, 0, &[Arg::Uint(2), Arg::Str("ext_data_control_manager_v1"), Arg::Uint(1)]));
+        events.extend(event(9, 0, &[]));
+        events.extend(event(3, 0, &[Arg::Uint(0)]));
+        // Arriving a byte at a time must not matter.
+        let writer = std::thread::spawn(move || {
+            for byte in events {
+                peer.write_all(&[byte]).unwrap();
+            }
+            peer
+        });
+
+        let globals = connection.globals().unwrap();
+        let _peer = writer.join().unwrap();
+        let found: Vec<(u32, &str, u32)> = globals.iter().map(|global| (global.name, global.interface.as_str(), global.version)).collect();
+        assert_eq!(found, [(1, "wl_seat", 7), (2, "ext_data_control_manager_v1", 1)]);
+        assert_eq!(connection.bind(&globals[0], 9).unwrap(), 4);
+    }
+
+    #[test]
+    fn reports_protocol_errors() {
+        let (mut connection, mut peer) = pair();
+        peer.write_all(&event(DISPLAY, 0, &[Arg::Uint(4), Arg::Uint(1), Arg::Str("invalid object")])).unwrap();
+        let err = connection.read_event().err().unwrap().to_string();
+        assert_eq!(err, "The compositor reported error 1 on object 4: invalid object");
+    }
+
+    #[test]
+    fn rejects_malformed_and_short_events() {
+        let (mut connection, mut peer) = pair();
+        peer.write_all(&[1, 0, 0, 0, 0, 0, 4, 0]).unwrap();
+        assert!(connection.read_event().is_err());
+
+        let (mut connection, mut peer) = pair();
+        peer.write_all(&event(3, 1, &[Arg::Uint(5)])).unwrap();
+        let mut received = connection.read_event().unwrap();
+        assert_eq!((received.object, received.opcode), (3, 1));
+        assert_eq!(received.uint().unwrap(), 5);
+        assert!(received.uint().is_err());
+        assert!(received.string().is_err());
+
+        // The connection closing halfway through a message.
+        let (mut connection, mut peer) = pair();
+        peer.write_all(&event(3, 1, &[Arg::Uint(5)])[..10]).unwrap();
+        drop(peer);
+        assert!(connection.read_event().is_err());
+        assert!(connection.take_fd().is_err());
+    }
+
+    #[test]
+    fn receives_file_descriptors() {
+        let (mut connection, peer) = pair();
+        let (sent, mut other_end) = UnixStream::pair().unwrap();
+        let message = event(3, 0, &[Arg::Str("text/plain")]);
+
+        let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(size_of::<i32>() as u32) } as usize];
+        let mut iov = libc::iovec { iov_base: message.as_ptr() as *mut _, iov_len: message.len() };
+        // SAFETY: as in `receive`, the header points at the buffers above for the call.
+        unsafe {
+            let mut header: libc::msghdr = std::mem::zeroed();
+            header.msg_iov = &mut iov;
+            header.msg_iovlen = 1;
+            header.msg_control = control.as_mut_ptr().cast();
+            header.msg_controllen = control.len();
+            let cmsg = libc::CMSG_FIRSTHDR(&header);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(size_of::<i32>() as u32) as usize;
+            libc::CMSG_DATA(cmsg).cast::<i32>().write_unaligned(sent.as_raw_fd());
+            assert_eq!(libc::sendmsg(peer.as_raw_fd(), &header, 0), message.len() as isize);
+        }
+        drop(sent);
+
+        let mut event = connection.read_event().unwrap();
+        assert_eq!(event.string().unwrap(), "text/plain");
+        let mut received = UnixStream::from(connection.take_fd().unwrap());
+        received.write_all(b"pasted").unwrap();
+        drop(received);
+        let mut text = String::new();
+        other_end.read_to_string(&mut text).unwrap();
+        assert_eq!(text, "pasted");
+    }
+}
@@ -0,0 +1,150 @@
+use std::io::{BufRead, Write};
+use anyhow::Result;
+use clap::{Arg, Command};
+use crate::config::{self, Entry};
+use crate::{compositor, process};
+
+// --- Setup Wizard ---
+//
+// `config init --interactive` asks about the settings people change first, checking that
+// the programs they need are installed along the way. The answers are written into the
+// commented config file in place of the defaults; pressing Enter keeps a default, which
+// then stays commented out like the rest.
+
+/// Programs every capture needs.
+const REQUIRED_PROGRAMS: &[&str] = &["grim", "slurp", "wl-copy"];
+
+/// The default of `--dir`, as it is shown.
+const DEFAULT_DIR: &str = "~/Pictures/Screenshots";
+
+/// Asks the questions and returns the answers that differ from the defaults.
+pub fn ask(command: &Command) -> Result<Vec<Entry>> {
+    let mut answers = Vec::new();
+    let mut set = |section: Option<&str>, key: &str, value: String| {
+        answers.push(Entry { line: 0, section: section.map(String::from), key: key.to_string(), value });
+    };
+
+    println!("Setting up LuminaShot. Press Enter to keep the default in brackets.");
+    for program in REQUIRED_PROGRAMS.iter().filter(|program| !process::in_path(program)) {
+        warn(&format!("{} is not installed, and LuminaShot needs it to take screenshots.", program));
+    }
+    if !compositor::is_hyprland() {
+        warn("Hyprland is not running. On other compositors only region and monitor captures work.");
+    }
+
+    let dir = question("Where should screenshots be saved?", DEFAULT_DIR)?;
+    if dir != DEFAULT_DIR {
+        set(None, "dir", dir);
+    }
+
+    for (key, prompt) in [("mode", "What should be captured when no mode is given?"), ("format", "Which image format should screenshots have?")] {
+        if let Some(value) = choice(option(command, key)?, prompt)? {
+            set(None, key, value);
+        }
+    }
+
+    if confirm("Copy screenshots to the clipboard?", false)? {
+        set(None, "copy", "true".to_string());
+        // Saving is only the default when no other output is asked for.
+        if confirm("Save them to a file as well?", true)? {
+            set(None, "save", "true".to_string());
+        }
+    }
+
+    let editor = option(command, "editor")?;
+    let default_editor = config::default_value(editor);
+    let editor = question("Which program should the notification's Edit button open?", &default_editor)?;
+    check_program(&editor);
+    if editor != default_editor {
+        set(None, "editor", editor);
+    }
+
+    // Every subcommand that uploads gets the same service.
+    let uploaders: Vec<&Command> = command.get_subcommands().filter(|sub| sub.get_arguments().any(|arg| arg.get_long() == Some("service"))).collect();
+    if let Some(first) = uploaders.first() {
+        let service = choice(option(first, "service")?, "Where should screenshots be uploaded to?")?;
+        let client_id = match service.as_deref() {
+            Some("imgur") => Some(question("Which imgur API client ID should be used? Register one at https://api.imgur.com/oauth2/addclient.", "")?),
+            _ => None,
+        };
+        if client_id.as_deref() == Some("") {
+            warn("Uploads to imgur need a client ID; set imgur-client-id before uploading.");
+        }
+        for sub in &uploaders {
+            if let Some(service) = &service {
+                set(Some(sub.get_name()), "service", service.clone());
+            }
+            if let Some(client_id) = client_id.as_ref().filter(|id| !id.is_empty()) {
+                set(Some(sub.get_name()), "imgur-client-id", client_id.clone());
+            }
+        }
+        check_program("curl");
+    }
+
+    Ok(answers)
+}
+
+/// Returns the option with the long name `key`.
+fn option<'a>(command: &'a Command, key: &str) -> Result<&'a Arg> {
+    command
+    .get_arguments()
+    .find(|arg| arg.get_long() == Some(key))
+    .ok_or_else(|| anyhow::anyhow!("`{}` has no option `{}`", command.get_name(), key))
+}
+
+/// Asks for one of an option's values. Returns `None` if the default is kept.
+fn choice(arg: &Arg, prompt: &str) -> Result<Option<String>> {
+    let values = arg.get_possible_values();
+    let names = values.iter().map(|value| value.get_name()).collect::<Vec<_>>().join(", ");
+    let default = config::default_value(arg);
+    loop {
+        let answer = question(&format!("{} ({})", prompt, names), &default)?;
+        match values.iter().find(|value| value.matches(&answer, true)) {
+            Some(value) if value.get_name() == default => return Ok(None),
+            Some(value) => return Ok(Some(value.get_name().to_string())),
+            None => println!("`{}` is not one of {}.", answer, names),
+        }
+    }
+}
+
+/// Asks a yes-or-no question.
+fn confirm(prompt: &str, default: bool) -> Result<bool> {
+    loop {
+        let answer = question(&format!("{} (yes, no)", prompt), if default { "yes" } else { "no" })?;
+        match answer.to_ascii_lowercase().as_str() {
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!("Please answer yes or no."),
+        }
+    }
+}
+
+/// Asks a question and returns the answer, or `default` if nothing was typed.
+fn question(prompt: &str, default: &str) -> Result<String> {
+    match default.is_empty() {
+        true => print!("\n{}\n> ", prompt),
+        false => print!("\n{} [{}]\n> ", prompt, default),
+    }
+    std::io::stdout().flush()?;
+
+    let mut answer = String::new();
+    if std::io::stdin().lock().read_line(&mut answer)? == 0 {
+        println!();
+        anyhow::bail!("No answer given, so the config file was not written");
+    }
+    let answer = answer.trim();
+    Ok(if answer.is_empty() { default } else { answer }.to_string())
+}
+
+/// Warns if the program a command starts isn't installed.
+fn check_program(command: &str) {
+    if let Some(program) = command.split_whitespace().next() {
+        if !process::in_path(program) {
+            warn(&format!("{} is not installed yet.", program));
+        }
+    }
+}
+
+fn warn(message: &str) {
+    println!("Warning: {}", message);
+}